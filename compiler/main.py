@@ -1,9 +1,13 @@
+import decimal
 import struct
 from dataclasses import dataclass
 
 VAL_NUMBER = 0x01
 VAL_BOOLEAN = 0x02
 VAL_STRING = 0x03
+VAL_BIGINT = 0x04
+VAL_DECIMAL = 0x05
+VAL_CHAR = 0x06
 
 OP_PUSH_CONST = 0x01
 OP_ADD = 0x10
@@ -16,17 +20,31 @@ OP_AND = 0x20
 OP_OR = 0x21
 OP_NOT = 0x22
 OP_EQUAL = 0x30
+OP_IDENTICAL = 0x31
 OP_JUMP = 0x40
 OP_JUMP_IF_TRUE = 0x41
 OP_JUMP_IF_FALSE = 0x42
 OP_PRINT = 0x60
+OP_FORMAT = 0x61
 OP_GET_LOCAL = 0x70
 OP_SET_LOCAL = 0x71
 OP_CALL = 0x80
 OP_RETURN = 0x81
+OP_CALL_NATIVE = 0x82
+OP_MAKE_TUPLE = 0x90
+OP_TUPLE_GET = 0x91
+OP_TUPLE_UNPACK = 0x92
+OP_MAKE_ERROR = 0xA0
+OP_IS_ERROR = 0xA1
+OP_TRY = 0xA2
 OP_HALT = 0xFF
 
 
+@dataclass
+class Char:
+    value: str
+
+
 @dataclass
 class Instruction:
     opcode: int
@@ -49,6 +67,22 @@ class BinaryData:
     def f64(self, value):
         self.data += struct.pack('<d', value)
 
+    def bigint(self, value):
+        encoded_int = value.to_bytes((value.bit_length() + 8) // 8, 'little', signed=True)
+        self.u16(len(encoded_int))
+        self.data += encoded_int
+
+    def decimal(self, value):
+        sign, digits, exponent = value.as_tuple()
+        mantissa = int(''.join(map(str, digits)))
+        if exponent > 0:
+            mantissa *= 10 ** exponent
+            exponent = 0
+        self.u32((sign << 31) | (-exponent << 16))
+        self.u32(mantissa & 0xFFFFFFFF)
+        self.u32((mantissa >> 32) & 0xFFFFFFFF)
+        self.u32((mantissa >> 64) & 0xFFFFFFFF)
+
     def string(self, value):
         encoded_str = value.encode('utf-8')
         self.u16(len(encoded_str))
@@ -99,6 +133,15 @@ class BytecodeBuilder:
             elif isinstance(const, bool):
                 bytecode.u8(VAL_BOOLEAN)
                 bytecode.u8(int(const))
+            elif isinstance(const, int):
+                bytecode.u8(VAL_BIGINT)
+                bytecode.bigint(const)
+            elif isinstance(const, decimal.Decimal):
+                bytecode.u8(VAL_DECIMAL)
+                bytecode.decimal(const)
+            elif isinstance(const, Char):
+                bytecode.u8(VAL_CHAR)
+                bytecode.u32(ord(const.value))
             elif isinstance(const, str):
                 bytecode.u8(VAL_STRING)
                 bytecode.string(const)
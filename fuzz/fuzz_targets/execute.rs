@@ -0,0 +1,182 @@
+#![no_main]
+
+// Builds a program from the fuzzer's input, loads it, and runs it with a
+// small step limit. Loading must accept or refuse it without panicking, and
+// running it must either finish or stop with a runtime error.
+//
+// Runtime errors are panics with a `RuntimeError` payload, such as "Invalid
+// operand types for add.". Any other panic, like an index out of bounds or an
+// unwrapped None, is a bug in the VM and is reported as a crash.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Once;
+
+use arbitrary::{Result, Unstructured};
+use libfuzzer_sys::fuzz_target;
+use zircon::{
+    Bytecode, Count, Instruction, Opcode, OperandKind, RuntimeError, VirtualMachine, VmOptions,
+};
+
+const STEP_LIMIT: u64 = 1000;
+
+static HOOK: Once = Once::new();
+
+// Lets runtime errors unwind quietly, and passes every other panic to the
+// fuzzer's hook, which aborts.
+fn install_hook() {
+    HOOK.call_once(|| {
+        let fuzzer_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if !info.payload().is::<RuntimeError>() {
+                fuzzer_hook(info);
+            }
+        }));
+    });
+}
+
+struct Header {
+    num_args: usize,
+    num_locals: usize,
+    defaults: Vec<u16>,
+}
+
+fn constant(u: &mut Unstructured, out: &mut Vec<u8>, depth: usize) -> Result<()> {
+    match u.int_in_range(0..=4)? {
+        0 => {
+            out.push(0x01);
+            out.extend(u.arbitrary::<f64>()?.to_le_bytes());
+        }
+        1 => out.extend([0x02, u.arbitrary::<bool>()? as u8]),
+        2 => {
+            let s: String = u.arbitrary()?;
+            let s = &s[..s.floor_char_boundary(32)];
+            out.push(0x03);
+            out.extend((s.len() as u16).to_le_bytes());
+            out.extend(s.as_bytes());
+        }
+        3 => {
+            out.push(0x06);
+            out.extend((u.arbitrary::<char>()? as u32).to_le_bytes());
+        }
+        _ if depth < 2 => {
+            let len = u.int_in_range(0..=3)?;
+            out.push(0x07);
+            out.extend((len as u16).to_le_bytes());
+            for _ in 0..len {
+                constant(u, out, depth + 1)?;
+            }
+        }
+        _ => out.extend([0x02, 0]),
+    }
+    Ok(())
+}
+
+// Picks instructions whose stack effect fits the depth so far, using the
+// opcode table, so that most programs get past the verifier. Jumps can
+// still join paths at different depths, which the loader refuses.
+fn instructions(
+    u: &mut Unstructured,
+    headers: &[Header],
+    header: &Header,
+    constants: usize,
+) -> Result<Vec<Instruction>> {
+    let len = u.int_in_range(1..=32)?;
+    let mut instructions = Vec::with_capacity(len);
+    let mut depth = 0;
+    while instructions.len() < len - 1 {
+        let opcode = *u.choose(Opcode::ALL)?;
+        if matches!(
+            opcode,
+            Opcode::Return | Opcode::Halt | Opcode::CallNative | Opcode::Import
+        ) {
+            continue;
+        }
+        let operand = match opcode.operand_kind() {
+            OperandKind::None => None,
+            OperandKind::Constant => Some(u.choose_index(constants)?),
+            OperandKind::Target => Some(u.choose_index(len)?),
+            OperandKind::Local | OperandKind::LocalConstant if header.num_locals == 0 => continue,
+            OperandKind::Local => Some(u.choose_index(header.num_locals)?),
+            OperandKind::LocalConstant => {
+                let slot = u.choose_index(header.num_locals.min(256))?;
+                Some(slot << 8 | u.choose_index(constants.min(256))?)
+            }
+            OperandKind::Function => Some(u.choose_index(headers.len())?),
+            OperandKind::Count | OperandKind::Element => Some(u.int_in_range(0..=3)?),
+        };
+        let count = |count| match count {
+            Count::Fixed(n) => n,
+            Count::Operand(n) => operand.unwrap_or(0) + n,
+            Count::Arguments => headers[operand.unwrap_or(0)].num_args,
+            Count::Native | Count::Rest => 0,
+        };
+        let effect = opcode.stack_effect();
+        let (pops, pushes) = (count(effect.pops), count(effect.pushes));
+        if pops > depth {
+            continue;
+        }
+        depth = depth - pops + pushes;
+        instructions.push(Instruction::new(opcode, operand.map(|n| n as u16)));
+    }
+    if depth <= 1 && u.arbitrary()? {
+        instructions.push(Instruction::new(Opcode::Return, None));
+    } else {
+        instructions.push(Instruction::new(Opcode::Halt, None));
+    }
+    Ok(instructions)
+}
+
+fn program(u: &mut Unstructured) -> Result<Vec<u8>> {
+    let mut out = b"ZRCN".to_vec();
+    out.push(3);
+    let constants = u.int_in_range(1..=8)?;
+    out.extend((constants as u32).to_le_bytes());
+    for _ in 0..constants {
+        constant(u, &mut out, 0)?;
+    }
+    let mut headers = Vec::new();
+    for _ in 0..u.int_in_range(1..=4)? {
+        let num_args = u.int_in_range(0..=3)?;
+        let mut defaults = Vec::new();
+        for _ in 0..u.int_in_range(0..=num_args)? {
+            defaults.push(u.choose_index(constants)? as u16);
+        }
+        headers.push(Header {
+            num_args,
+            num_locals: num_args + u.int_in_range(0..=3)?,
+            defaults,
+        });
+    }
+    out.extend((headers.len() as u32).to_le_bytes());
+    for header in &headers {
+        let instructions = instructions(u, &headers, header, constants)?;
+        out.extend((instructions.len() as u32).to_le_bytes());
+        out.extend((header.num_args as u32).to_le_bytes());
+        out.extend((header.num_locals as u32).to_le_bytes());
+        out.extend((header.defaults.len() as u16).to_le_bytes());
+        for default in &header.defaults {
+            out.extend(default.to_le_bytes());
+        }
+        for instruction in instructions {
+            instruction.encode(&mut out).unwrap();
+        }
+    }
+    Ok(out)
+}
+
+fuzz_target!(|data: &[u8]| {
+    install_hook();
+    let mut u = Unstructured::new(data);
+    let bytes = match program(&mut u) {
+        Ok(bytes) => bytes,
+        Err(_) => return,
+    };
+    let bytecode = match Bytecode::read(&mut bytes.as_slice()) {
+        Ok(bytecode) => bytecode,
+        Err(_) => return,
+    };
+    // Entry functions with parameters are refused before anything runs.
+    let options = VmOptions::deterministic().step_limit(STEP_LIMIT);
+    let mut vm = VirtualMachine::with_options(&bytecode, options);
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| vm.run()));
+});
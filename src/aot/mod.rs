@@ -0,0 +1,500 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::process::{self, Command};
+
+use cranelift_codegen::ir::{
+    self, types, AbiParam, Block, FuncRef, InstBuilder, Signature, UserFuncName,
+};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_module::{default_libcall_names, DataDescription, FuncId, Linkage, Module};
+use cranelift_object::{ObjectBuilder, ObjectModule};
+
+use crate::bytecode::{Bytecode, Function, Opcode, Value};
+use crate::transpile::{block_starts, c, is_terminator, local_count};
+
+// Appended to the C transpiler's value runtime and linked into every
+// executable.
+const SHIM: &str = include_str!("runtime.c");
+
+// Constant table tags, matching the value tags of the C runtime.
+const TAG_NUMBER: u8 = 2;
+const TAG_BOOLEAN: u8 = 3;
+const TAG_CHAR: u8 = 4;
+const TAG_STR: u8 = 5;
+
+#[derive(Debug)]
+pub struct AotError {
+    pub message: String,
+}
+
+impl fmt::Display for AotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for AotError {}
+
+fn error<T>(message: String) -> Result<T, AotError> {
+    Err(AotError { message })
+}
+
+fn backend_error(e: impl fmt::Display) -> AotError {
+    AotError {
+        message: e.to_string(),
+    }
+}
+
+// Compiles a program to native code for the host and links it with the
+// runtime using the system C compiler (`cc`, or `$CC`). An output path
+// ending in `.o` produces a relocatable object with the runtime included
+// instead of an executable.
+pub fn compile_native(bytecode: &Bytecode, output: &Path) -> Result<(), AotError> {
+    let object = object_code(bytecode)?;
+
+    let dir = env::temp_dir().join(format!("zircon-aot-{}", process::id()));
+    fs::create_dir_all(&dir).map_err(backend_error)?;
+    let result = link(&dir, &object, output);
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+fn link(dir: &Path, object: &[u8], output: &Path) -> Result<(), AotError> {
+    let program = dir.join("program.o");
+    let runtime = dir.join("runtime.c");
+    fs::write(&program, object).map_err(backend_error)?;
+    fs::write(&runtime, format!("{}\n{}", c::RUNTIME, SHIM)).map_err(backend_error)?;
+
+    let compiler = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let run = |command: &mut Command| -> Result<(), AotError> {
+        let status = command
+            .status()
+            .map_err(|e| backend_error(format!("Failed to run '{}': {}", compiler, e)))?;
+        if !status.success() {
+            return error(format!("'{}' failed with {}", compiler, status));
+        }
+        Ok(())
+    };
+
+    if output.extension().is_some_and(|e| e == "o") {
+        let runtime_object = dir.join("runtime.o");
+        run(Command::new(&compiler)
+            .args(["-std=c99", "-O2", "-c"])
+            .arg(&runtime)
+            .arg("-o")
+            .arg(&runtime_object))?;
+        run(Command::new(&compiler)
+            .args(["-r", "-nostdlib"])
+            .arg(&program)
+            .arg(&runtime_object)
+            .arg("-o")
+            .arg(output))
+    } else {
+        run(Command::new(&compiler)
+            .args(["-std=c99", "-O2"])
+            .arg(&program)
+            .arg(&runtime)
+            .arg("-o")
+            .arg(output)
+            .arg("-lm"))
+    }
+}
+
+// Builds an object file defining `zircon_main`, `zircon_constants`, and one
+// native function per bytecode function.
+fn object_code(bytecode: &Bytecode) -> Result<Vec<u8>, AotError> {
+    let mut flags = settings::builder();
+    flags.set("is_pic", "true").map_err(backend_error)?;
+    flags.set("opt_level", "speed").map_err(backend_error)?;
+    let isa = cranelift_native::builder()
+        .map_err(backend_error)?
+        .finish(settings::Flags::new(flags))
+        .map_err(backend_error)?;
+    let builder =
+        ObjectBuilder::new(isa, "zircon", default_libcall_names()).map_err(backend_error)?;
+    let mut module = ObjectModule::new(builder);
+
+    let constants = module
+        .declare_data("zircon_constants", Linkage::Export, false, false)
+        .map_err(backend_error)?;
+    let mut description = DataDescription::new();
+    description.define(constant_table(bytecode)?.into_boxed_slice());
+    module
+        .define_data(constants, &description)
+        .map_err(backend_error)?;
+
+    let pointer = module.target_config().pointer_type();
+    let mut signature = module.make_signature();
+    signature.params.push(AbiParam::new(pointer));
+    let mut ids = Vec::new();
+    for index in 0..bytecode.functions().len() {
+        let id = module
+            .declare_function(
+                &format!("zircon_function_{}", index),
+                Linkage::Local,
+                &signature,
+            )
+            .map_err(backend_error)?;
+        ids.push(id);
+    }
+
+    let mut context = module.make_context();
+    let mut builder_context = FunctionBuilderContext::new();
+    for (index, function) in bytecode.functions().iter().enumerate() {
+        context.func.signature = signature.clone();
+        context.func.name = UserFuncName::user(0, ids[index].as_u32());
+        let translator = Translator {
+            module: &mut module,
+            builder: FunctionBuilder::new(&mut context.func, &mut builder_context),
+            functions: &ids,
+            imports: HashMap::new(),
+            blocks: HashMap::new(),
+            invalid: Block::from_u32(0),
+            pointer,
+        };
+        translator
+            .function(bytecode, function)
+            .map_err(|e| AotError {
+                message: format!("Function {}, {}", index, e.message),
+            })?;
+        module
+            .define_function(ids[index], &mut context)
+            .map_err(backend_error)?;
+        module.clear_context(&mut context);
+    }
+
+    // The C runtime's `main` loads the constants and then calls this.
+    let entry = module
+        .declare_function("zircon_main", Linkage::Export, &module.make_signature())
+        .map_err(backend_error)?;
+    context.func.signature = module.make_signature();
+    context.func.name = UserFuncName::user(0, entry.as_u32());
+    {
+        let mut builder = FunctionBuilder::new(&mut context.func, &mut builder_context);
+        let block = builder.create_block();
+        builder.switch_to_block(block);
+        if let Some(&function_0) = ids.first() {
+            let callee = module.declare_func_in_func(function_0, builder.func);
+            let caller = builder.ins().iconst(pointer, 0);
+            builder.ins().call(callee, &[caller]);
+        }
+        builder.ins().return_(&[]);
+        builder.seal_all_blocks();
+        builder.finalize(module.target_config());
+    }
+    module
+        .define_function(entry, &mut context)
+        .map_err(backend_error)?;
+
+    module.finish().emit().map_err(backend_error)
+}
+
+fn constant_table(bytecode: &Bytecode) -> Result<Vec<u8>, AotError> {
+    let constants = bytecode.constants();
+    let mut table = (constants.len() as u32).to_le_bytes().to_vec();
+    for constant in constants {
+        match constant {
+            Value::Number(n) => {
+                table.push(TAG_NUMBER);
+                table.extend_from_slice(&n.to_bits().to_le_bytes());
+            }
+            Value::Boolean(b) => {
+                table.push(TAG_BOOLEAN);
+                table.push(*b as u8);
+            }
+            Value::Char(c) => {
+                table.push(TAG_CHAR);
+                table.extend_from_slice(&(*c as u32).to_le_bytes());
+            }
+            Value::Str(s) => {
+                table.push(TAG_STR);
+                table.extend_from_slice(&(s.len() as u32).to_le_bytes());
+                table.extend_from_slice(s.as_bytes());
+            }
+            other => {
+                return error(format!(
+                    "Cannot compile a {} constant to native code",
+                    other.type_name()
+                ))
+            }
+        }
+    }
+    Ok(table)
+}
+
+struct Translator<'a, 'b> {
+    module: &'a mut ObjectModule,
+    builder: FunctionBuilder<'b>,
+    functions: &'a [FuncId],
+    imports: HashMap<String, FuncRef>,
+    blocks: HashMap<usize, Block>,
+    invalid: Block,
+    pointer: ir::Type,
+}
+
+impl Translator<'_, '_> {
+    // Calls a runtime entry point that takes `args`, all pointer-sized, and
+    // returns `returns` values, which are pointers or C ints.
+    fn call(
+        &mut self,
+        name: &str,
+        args: &[ir::Value],
+        returns: Option<ir::Type>,
+    ) -> Option<ir::Value> {
+        let callee = match self.imports.get(name) {
+            Some(&callee) => callee,
+            None => {
+                let mut signature = Signature::new(self.module.isa().default_call_conv());
+                for _ in args {
+                    signature.params.push(AbiParam::new(self.pointer));
+                }
+                if let Some(ty) = returns {
+                    signature.returns.push(AbiParam::new(ty));
+                }
+                let id = self
+                    .module
+                    .declare_function(name, Linkage::Import, &signature)
+                    .expect("Runtime entry point declared with two signatures.");
+                let callee = self.module.declare_func_in_func(id, self.builder.func);
+                self.imports.insert(name.to_string(), callee);
+                callee
+            }
+        };
+        let call = self.builder.ins().call(callee, args);
+        self.builder.inst_results(call).first().copied()
+    }
+
+    fn call_with_operand(&mut self, name: &str, frame: ir::Value, operand: usize) {
+        let operand = self.builder.ins().iconst(self.pointer, operand as i64);
+        self.call(name, &[frame, operand], None);
+    }
+
+    // The block starting at `target`, or the block that fails as the VM does
+    // when the target lies past the end of the function.
+    fn block(&self, target: usize) -> Block {
+        self.blocks.get(&target).copied().unwrap_or(self.invalid)
+    }
+
+    fn function(mut self, bytecode: &Bytecode, function: &Function) -> Result<(), AotError> {
+        let len = function.instructions.len();
+        let starts = block_starts(function);
+
+        let entry = self.builder.create_block();
+        self.builder.append_block_params_for_function_params(entry);
+        for &start in &starts {
+            let block = self.builder.create_block();
+            self.blocks.insert(start, block);
+        }
+        self.invalid = self.builder.create_block();
+
+        self.builder.switch_to_block(entry);
+        let caller = self.builder.block_params(entry)[0];
+        let num_args = self
+            .builder
+            .ins()
+            .iconst(self.pointer, function.num_args as i64);
+        let count = self
+            .builder
+            .ins()
+            .iconst(self.pointer, local_count(function) as i64);
+        let frame = self
+            .call(
+                "zr_aot_enter",
+                &[caller, num_args, count],
+                Some(self.pointer),
+            )
+            .unwrap();
+        let first = self.block(0);
+        self.builder.ins().jump(first, &[]);
+
+        for (n, &start) in starts.iter().enumerate() {
+            let end = starts.get(n + 1).copied().unwrap_or(len);
+            self.builder.switch_to_block(self.blocks[&start]);
+            for ip in start..end {
+                self.instruction(bytecode, function, ip, frame, caller)
+                    .map_err(|e| AotError {
+                        message: format!("instruction {}: {}", ip, e.message),
+                    })?;
+            }
+            let last = function.instructions[end - 1].opcode();
+            if !is_terminator(last) && !last.is_branch() {
+                let next = self.block(end);
+                self.builder.ins().jump(next, &[]);
+            }
+        }
+
+        self.builder.switch_to_block(self.invalid);
+        self.call("zr_aot_invalid_index", &[], None);
+        self.builder.ins().return_(&[]);
+
+        self.builder.seal_all_blocks();
+        let config = self.module.target_config();
+        self.builder.finalize(config);
+        Ok(())
+    }
+
+    fn instruction(
+        &mut self,
+        bytecode: &Bytecode,
+        function: &Function,
+        ip: usize,
+        frame: ir::Value,
+        caller: ir::Value,
+    ) -> Result<(), AotError> {
+        let instruction = &function.instructions[ip];
+        let operand = || usize::from(instruction.operand());
+        let simple = match instruction.opcode() {
+            Opcode::Add => Some("zr_aot_add"),
+            Opcode::Subtract => Some("zr_aot_subtract"),
+            Opcode::Multiply => Some("zr_aot_multiply"),
+            Opcode::Divide => Some("zr_aot_divide"),
+            Opcode::Modulo => Some("zr_aot_modulo"),
+            Opcode::And => Some("zr_aot_and"),
+            Opcode::Or => Some("zr_aot_or"),
+            Opcode::Equal => Some("zr_aot_equal"),
+            Opcode::Identical => Some("zr_aot_identical"),
+            Opcode::LessThan => Some("zr_aot_less"),
+            Opcode::GreaterThan => Some("zr_aot_greater"),
+            Opcode::NotEqual => Some("zr_aot_not_equal"),
+            Opcode::LessOrEqual => Some("zr_aot_less_or_equal"),
+            Opcode::GreaterOrEqual => Some("zr_aot_greater_or_equal"),
+            Opcode::Compare => Some("zr_aot_compare"),
+            Opcode::Negate => Some("zr_aot_negate"),
+            Opcode::Not => Some("zr_aot_not"),
+            Opcode::Print => Some("zr_aot_print"),
+            Opcode::PrintRaw => Some("zr_aot_print_raw"),
+            Opcode::EPrint => Some("zr_aot_eprint"),
+            Opcode::EPrintRaw => Some("zr_aot_eprint_raw"),
+            Opcode::MakeError => Some("zr_aot_make_error"),
+            Opcode::IsError => Some("zr_aot_is_error"),
+            _ => None,
+        };
+        if let Some(name) = simple {
+            self.call(name, &[frame], None);
+            return Ok(());
+        }
+
+        match instruction.opcode() {
+            Opcode::PushConst => self.call_with_operand("zr_aot_push_const", frame, operand()),
+            Opcode::Format => self.call_with_operand("zr_aot_format", frame, operand()),
+            Opcode::GetLocal => self.call_with_operand("zr_aot_get_local", frame, operand()),
+            Opcode::SetLocal => self.call_with_operand("zr_aot_set_local", frame, operand()),
+            Opcode::IncLocal => self.call_with_operand("zr_aot_inc_local", frame, operand()),
+            Opcode::DecLocal => self.call_with_operand("zr_aot_dec_local", frame, operand()),
+            Opcode::AddLocalConst => {
+                let (slot, index) = instruction.local_constant();
+                let slot = self.builder.ins().iconst(self.pointer, slot as i64);
+                let index = self.builder.ins().iconst(self.pointer, index as i64);
+                self.call("zr_aot_add_local_const", &[frame, slot, index], None);
+            }
+            Opcode::MakeTuple => self.call_with_operand("zr_aot_make_tuple", frame, operand()),
+            Opcode::TupleGet => self.call_with_operand("zr_aot_tuple_get", frame, operand()),
+            Opcode::TupleUnpack => self.call_with_operand("zr_aot_tuple_unpack", frame, operand()),
+            Opcode::Jump => {
+                let target = self.block(operand());
+                self.builder.ins().jump(target, &[]);
+            }
+            Opcode::JumpIfTrue
+            | Opcode::JumpIfFalse
+            | Opcode::JumpIfEqual
+            | Opcode::JumpIfNotEqual
+            | Opcode::JumpIfLess
+            | Opcode::JumpIfLessOrEqual
+            | Opcode::JumpIfGreater
+            | Opcode::JumpIfGreaterOrEqual => {
+                let test = match instruction.opcode() {
+                    Opcode::JumpIfTrue => "zr_aot_is_true".to_string(),
+                    Opcode::JumpIfFalse => "zr_aot_is_false".to_string(),
+                    opcode => format!("zr_aot_{}", opcode.name()),
+                };
+                let taken = self.call(&test, &[frame], Some(types::I32)).unwrap();
+                let (target, next) = (self.block(operand()), self.block(ip + 1));
+                self.builder.ins().brif(taken, target, &[], next, &[]);
+            }
+            Opcode::Call => {
+                let id = match self.functions.get(operand()) {
+                    Some(&id) => id,
+                    None => return error(format!("Invalid function index {}", operand())),
+                };
+                let callee = self.module.declare_func_in_func(id, self.builder.func);
+                self.builder.ins().call(callee, &[frame]);
+            }
+            Opcode::Return => {
+                self.call("zr_aot_return", &[frame, caller], None);
+                self.builder.ins().return_(&[]);
+            }
+            Opcode::Try => {
+                let returned = self
+                    .call("zr_aot_try", &[frame, caller], Some(types::I32))
+                    .unwrap();
+                let exit = self.builder.create_block();
+                let next = self.builder.create_block();
+                self.builder.ins().brif(returned, exit, &[], next, &[]);
+                self.builder.switch_to_block(exit);
+                self.builder.ins().return_(&[]);
+                self.builder.switch_to_block(next);
+            }
+            Opcode::CallNative => {
+                let name = match bytecode.get_constant(operand()) {
+                    Some(Value::Str(name)) => name,
+                    _ => {
+                        return error("Native function name must be a string constant".to_string())
+                    }
+                };
+                if !c::NATIVES.iter().any(|(native, _)| *native == &**name) {
+                    return error(format!(
+                        "Native function '{}' is not supported by native compilation",
+                        name
+                    ));
+                }
+                self.call(&format!("zr_aot_native_{}", name), &[frame], None);
+            }
+            Opcode::Import => {
+                return error(
+                    "The import instruction is not supported by native compilation".to_string(),
+                )
+            }
+            Opcode::Match => {
+                return error(
+                    "The match instruction is not supported by native compilation".to_string(),
+                )
+            }
+            Opcode::ArgCount => {
+                return error(
+                    "Default arguments are not supported by native compilation".to_string(),
+                )
+            }
+            Opcode::ArrayConcat | Opcode::ArraySlice | Opcode::ArrayPush | Opcode::ArrayPop => {
+                return error("Arrays are not supported by native compilation".to_string())
+            }
+            Opcode::SetAdd | Opcode::SetContains | Opcode::SetRemove => {
+                return error("Sets are not supported by native compilation".to_string())
+            }
+            Opcode::TotalCompare => {
+                return error(
+                    "The total_compare instruction is not supported by native compilation"
+                        .to_string(),
+                )
+            }
+            Opcode::Defer | Opcode::RunDefers => {
+                return error("Deferred calls are not supported by native compilation".to_string())
+            }
+            Opcode::Extension(extension) => {
+                return error(format!(
+                    "Extension instruction '{}' is not supported by native compilation",
+                    extension.name()
+                ))
+            }
+            Opcode::Halt => {
+                self.call("zr_aot_halt", &[], None);
+                self.builder.ins().return_(&[]);
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,259 @@
+/* Entry points for native code produced by `zircon aot`. This file is
+ * appended to the value runtime of the C transpiler, and each function
+ * performs one instruction against a frame. Generated functions call
+ * zr_aot_enter with their caller's frame, which moves the arguments off the
+ * caller's stack, and zr_aot_return pushes the result back onto it. */
+
+typedef struct zr_frame {
+    zr_stack stack;
+    zr_value *locals;
+    size_t count;
+} zr_frame;
+
+/* Defined by the generated object. The constant table starts with a u32
+ * count, followed by a tag byte and a little-endian payload per constant. */
+extern const unsigned char zircon_constants[];
+void zircon_main(void);
+
+static zr_value *zr_constants;
+static size_t zr_constant_count;
+
+static uint32_t zr_read_u32(const unsigned char *p) {
+    return (uint32_t)p[0] | (uint32_t)p[1] << 8 | (uint32_t)p[2] << 16 | (uint32_t)p[3] << 24;
+}
+
+static void zr_load_constants(void) {
+    const unsigned char *p = zircon_constants;
+    size_t i;
+    int shift;
+    zr_constant_count = zr_read_u32(p);
+    p += 4;
+    zr_constants = zr_alloc((zr_constant_count > 0 ? zr_constant_count : 1) * sizeof(zr_value));
+    for (i = 0; i < zr_constant_count; i++) {
+        int tag = *p++;
+        switch (tag) {
+        case ZR_NUMBER: {
+            uint64_t bits = 0;
+            double n;
+            for (shift = 0; shift < 64; shift += 8) {
+                bits |= (uint64_t)*p++ << shift;
+            }
+            memcpy(&n, &bits, sizeof(n));
+            zr_constants[i] = zr_number(n);
+            break;
+        }
+        case ZR_BOOLEAN:
+            zr_constants[i] = zr_boolean(*p++);
+            break;
+        case ZR_CHAR:
+            zr_constants[i] = zr_char(zr_read_u32(p));
+            p += 4;
+            break;
+        case ZR_STR: {
+            uint32_t len = zr_read_u32(p);
+            zr_constants[i] = zr_str((const char *)p + 4, len);
+            p += 4 + len;
+            break;
+        }
+        default:
+            zr_panic("Invalid constant table.");
+        }
+    }
+}
+
+zr_frame *zr_aot_enter(zr_frame *caller, size_t num_args, size_t count) {
+    zr_frame *frame = zr_alloc(sizeof(zr_frame));
+    size_t i;
+    frame->stack.data = NULL;
+    frame->stack.len = 0;
+    frame->stack.cap = 0;
+    frame->count = count;
+    frame->locals = zr_alloc((count > 0 ? count : 1) * sizeof(zr_value));
+    for (i = 0; i < count; i++) {
+        frame->locals[i] = zr_unset();
+    }
+    /* The entry point gets no arguments, so its parameters stay unset. */
+    if (caller != NULL) {
+        zr_pop_args(&caller->stack, frame->locals, num_args);
+    }
+    return frame;
+}
+
+void zr_aot_return(zr_frame *frame, zr_frame *caller) {
+    zr_value result = frame->stack.len > 0 ? zr_pop(&frame->stack) : zr_boolean(0);
+    zr_leave(&frame->stack, frame->locals, frame->count);
+    free(frame->locals);
+    free(frame);
+    if (caller != NULL) {
+        zr_push(&caller->stack, result);
+    } else {
+        zr_release(result);
+    }
+}
+
+/* Returns from the frame with the value on top of the stack if it is an
+ * error, in which case the generated code returns too. */
+int zr_aot_try(zr_frame *frame, zr_frame *caller) {
+    zr_value value = zr_pop(&frame->stack);
+    zr_push(&frame->stack, value);
+    if (value.tag == ZR_ERROR) {
+        zr_aot_return(frame, caller);
+        return 1;
+    }
+    return 0;
+}
+
+void zr_aot_push_const(zr_frame *frame, size_t index) {
+    if (index >= zr_constant_count) {
+        zr_panic("Constant index out of range.");
+    }
+    zr_push(&frame->stack, zr_retain(zr_constants[index]));
+}
+
+#define ZR_AOT_BINARY(name)                                                                        \
+    void zr_aot_##name(zr_frame *frame) {                                                          \
+        zr_value b = zr_pop(&frame->stack);                                                        \
+        zr_value a = zr_pop(&frame->stack);                                                        \
+        zr_push(&frame->stack, zr_##name(a, b));                                                   \
+    }
+
+#define ZR_AOT_UNARY(name)                                                                         \
+    void zr_aot_##name(zr_frame *frame) {                                                          \
+        zr_push(&frame->stack, zr_##name(zr_pop(&frame->stack)));                                  \
+    }
+
+ZR_AOT_BINARY(add)
+ZR_AOT_BINARY(subtract)
+ZR_AOT_BINARY(multiply)
+ZR_AOT_BINARY(divide)
+ZR_AOT_BINARY(modulo)
+ZR_AOT_BINARY(and)
+ZR_AOT_BINARY(or)
+ZR_AOT_BINARY(equal)
+ZR_AOT_BINARY(identical)
+ZR_AOT_BINARY(less)
+ZR_AOT_BINARY(greater)
+ZR_AOT_BINARY(not_equal)
+ZR_AOT_BINARY(less_or_equal)
+ZR_AOT_BINARY(greater_or_equal)
+ZR_AOT_BINARY(compare)
+ZR_AOT_UNARY(negate)
+ZR_AOT_UNARY(not)
+ZR_AOT_UNARY(make_error)
+ZR_AOT_UNARY(is_error)
+
+int zr_aot_is_true(zr_frame *frame) {
+    return zr_is_true(zr_pop(&frame->stack));
+}
+
+int zr_aot_is_false(zr_frame *frame) {
+    return zr_is_false(zr_pop(&frame->stack));
+}
+
+#define ZR_AOT_COMPARE(name)                                                                       \
+    int zr_aot_##name(zr_frame *frame) {                                                           \
+        zr_value b = zr_pop(&frame->stack);                                                        \
+        zr_value a = zr_pop(&frame->stack);                                                        \
+        return zr_##name(a, b);                                                                    \
+    }
+
+ZR_AOT_COMPARE(jump_if_equal)
+ZR_AOT_COMPARE(jump_if_not_equal)
+ZR_AOT_COMPARE(jump_if_less)
+ZR_AOT_COMPARE(jump_if_less_or_equal)
+ZR_AOT_COMPARE(jump_if_greater)
+ZR_AOT_COMPARE(jump_if_greater_or_equal)
+
+void zr_aot_print(zr_frame *frame) {
+    zr_print(zr_pop(&frame->stack));
+}
+
+void zr_aot_print_raw(zr_frame *frame) {
+    zr_print_raw(zr_pop(&frame->stack));
+}
+
+void zr_aot_eprint(zr_frame *frame) {
+    zr_eprint(zr_pop(&frame->stack));
+}
+
+void zr_aot_eprint_raw(zr_frame *frame) {
+    zr_eprint_raw(zr_pop(&frame->stack));
+}
+
+void zr_aot_format(zr_frame *frame, size_t count) {
+    zr_value *args = zr_alloc((count > 0 ? count : 1) * sizeof(zr_value));
+    zr_value template;
+    zr_pop_args(&frame->stack, args, count);
+    template = zr_pop(&frame->stack);
+    zr_push(&frame->stack, zr_format(template, args, count));
+    free(args);
+}
+
+void zr_aot_get_local(zr_frame *frame, size_t index) {
+    zr_push(&frame->stack, zr_get_local(frame->locals, index));
+}
+
+void zr_aot_set_local(zr_frame *frame, size_t index) {
+    zr_set_local(frame->locals, index, zr_pop(&frame->stack));
+}
+
+void zr_aot_inc_local(zr_frame *frame, size_t index) {
+    zr_value a = zr_get_local(frame->locals, index);
+    zr_set_local(frame->locals, index, zr_add(a, zr_number(1)));
+}
+
+void zr_aot_dec_local(zr_frame *frame, size_t index) {
+    zr_value a = zr_get_local(frame->locals, index);
+    zr_set_local(frame->locals, index, zr_subtract(a, zr_number(1)));
+}
+
+void zr_aot_add_local_const(zr_frame *frame, size_t index, size_t constant) {
+    zr_value a = zr_get_local(frame->locals, index);
+    if (constant >= zr_constant_count) {
+        zr_panic("Constant index out of range.");
+    }
+    zr_set_local(frame->locals, index, zr_add(a, zr_retain(zr_constants[constant])));
+}
+
+void zr_aot_make_tuple(zr_frame *frame, size_t count) {
+    zr_value tuple = zr_make_tuple(&frame->stack, count);
+    zr_push(&frame->stack, tuple);
+}
+
+void zr_aot_tuple_get(zr_frame *frame, size_t index) {
+    zr_push(&frame->stack, zr_tuple_get(zr_pop(&frame->stack), index));
+}
+
+void zr_aot_tuple_unpack(zr_frame *frame, size_t count) {
+    zr_tuple_unpack(&frame->stack, zr_pop(&frame->stack), count);
+}
+
+void zr_aot_halt(void) {
+    zr_halt();
+}
+
+void zr_aot_invalid_index(void) {
+    zr_panic("Invalid instruction index");
+}
+
+#define ZR_AOT_NATIVE(name, arity)                                                                 \
+    void zr_aot_native_##name(zr_frame *frame) {                                                   \
+        zr_value args[arity];                                                                      \
+        zr_pop_args(&frame->stack, args, arity);                                                   \
+        zr_push(&frame->stack, zr_native_##name(args));                                            \
+    }
+
+ZR_AOT_NATIVE(sqrt, 1)
+ZR_AOT_NATIVE(pow, 2)
+ZR_AOT_NATIVE(sin, 1)
+ZR_AOT_NATIVE(cos, 1)
+ZR_AOT_NATIVE(floor, 1)
+ZR_AOT_NATIVE(abs, 1)
+ZR_AOT_NATIVE(min, 2)
+ZR_AOT_NATIVE(max, 2)
+
+int main(void) {
+    zr_load_constants();
+    zircon_main();
+    return 0;
+}
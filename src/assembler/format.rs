@@ -0,0 +1,126 @@
+use super::{is_label, lex, AssembleError};
+
+const INDENT: &str = "    ";
+
+// A line of formatted output. Statements belong to the function or macro
+// body they are in, counted from 0, and the operands of a body's statements
+// are lined up.
+enum Entry<'s> {
+    Blank,
+    Verbatim(&'s str),
+    Comment(&'s str, bool),
+    Label(&'s str, Option<&'s str>),
+    Directive(Vec<&'s str>, Option<&'s str>),
+    Statement(Vec<&'s str>, Option<&'s str>, Option<usize>),
+}
+
+// Rewrites `.zasm` source in the canonical layout: directives at the start of
+// the line with one blank line after each function and macro, instructions
+// indented with their operands in a column, each label on a line of its own,
+// and comments kept where they were. Only the layout changes, so the source
+// assembles to the same program. String, character, and pattern literals are
+// kept as written.
+pub fn format_assembly(source: &str) -> Result<String, AssembleError> {
+    let mut entries = Vec::new();
+    let mut block = None;
+    let mut blocks = 0;
+    for (index, text) in source.lines().enumerate() {
+        if index == 0 && text.starts_with("#!") {
+            entries.push(Entry::Verbatim(text.trim_end()));
+            continue;
+        }
+        let Some((tokens, comment)) = lex(text) else {
+            return Err(AssembleError {
+                file: None,
+                line: index + 1,
+                message: "Unterminated literal".to_string(),
+            });
+        };
+        let comment = comment.map(|start| text[start..].trim_end());
+        if tokens.is_empty() {
+            entries.push(match comment {
+                Some(comment) => Entry::Comment(comment, block.is_some()),
+                None => Entry::Blank,
+            });
+            continue;
+        }
+        let labels = tokens.iter().take_while(|token| is_label(token)).count();
+        let statement = &tokens[labels..];
+        for (i, label) in tokens[..labels].iter().enumerate() {
+            let last = i + 1 == labels && statement.is_empty();
+            entries.push(Entry::Label(label, comment.filter(|_| last)));
+        }
+        match statement.first().copied() {
+            None => {}
+            Some(".func" | "%macro") => {
+                entries.push(Entry::Directive(statement.to_vec(), comment));
+                block = Some(blocks);
+                blocks += 1;
+            }
+            Some(".end" | "%end") => {
+                entries.push(Entry::Directive(statement.to_vec(), comment));
+                entries.push(Entry::Blank);
+                block = None;
+            }
+            Some(_) => entries.push(Entry::Statement(statement.to_vec(), comment, block)),
+        }
+    }
+
+    let mut widths = vec![0; blocks];
+    for entry in &entries {
+        if let Entry::Statement(tokens, _, Some(block)) = entry {
+            if tokens.len() > 1 {
+                widths[*block] = widths[*block].max(tokens[0].len());
+            }
+        }
+    }
+
+    let mut lines: Vec<String> = Vec::new();
+    for entry in entries {
+        let line = match entry {
+            Entry::Blank => {
+                if lines.last().is_none_or(String::is_empty) {
+                    continue;
+                }
+                String::new()
+            }
+            Entry::Verbatim(text) => text.to_string(),
+            Entry::Comment(comment, indented) => {
+                let indent = if indented { INDENT } else { "" };
+                format!("{}{}", indent, comment)
+            }
+            Entry::Label(label, comment) => with_comment(label.to_string(), comment),
+            Entry::Directive(tokens, comment) => with_comment(tokens.join(" "), comment),
+            Entry::Statement(tokens, comment, block) => {
+                let indent = if block.is_some() { INDENT } else { "" };
+                let mut line = format!("{}{}", indent, tokens[0]);
+                if tokens.len() > 1 {
+                    let width = block.map_or(0, |block| widths[block]);
+                    line = format!(
+                        "{:<w$} {}",
+                        line,
+                        tokens[1..].join(" "),
+                        w = indent.len() + width
+                    );
+                }
+                with_comment(line, comment)
+            }
+        };
+        lines.push(line);
+    }
+    while lines.last().is_some_and(String::is_empty) {
+        lines.pop();
+    }
+    let mut formatted = lines.join("\n");
+    if !formatted.is_empty() {
+        formatted.push('\n');
+    }
+    Ok(formatted)
+}
+
+fn with_comment(line: String, comment: Option<&str>) -> String {
+    match comment {
+        Some(comment) => format!("{} {}", line, comment),
+        None => line,
+    }
+}
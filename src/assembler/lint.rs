@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use super::{is_label, lex, literal_at, opcode_for, parse_number, AssembleError};
+use crate::bytecode::{Opcode, OperandKind};
+
+// A likely mistake in assembly source, at a line and column counted from 1.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AssemblyWarning {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssemblyWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+// A token with the line and column it starts at.
+#[derive(Clone, Copy)]
+struct Token<'s> {
+    text: &'s str,
+    line: usize,
+    column: usize,
+}
+
+impl Token<'_> {
+    fn warn(&self, message: String) -> AssemblyWarning {
+        AssemblyWarning {
+            line: self.line,
+            column: self.column,
+            message,
+        }
+    }
+}
+
+struct FunctionLines<'s> {
+    name: Token<'s>,
+    num_args: usize,
+    num_defaults: usize,
+    body: Vec<Vec<Token<'s>>>,
+}
+
+// Checks assembly source before it is assembled, and reports every problem
+// found rather than stopping at the first: labels that are defined twice or
+// never defined, instructions that can't be reached, `arg_count` passing a
+// number of arguments that the function called can't take, functions
+// defined twice or called without being defined, and integer literals that
+// are different numbers but round to the same constant. Macro bodies are
+// skipped, and an invocation is assumed to fall through. Includes are not
+// read, so calls are only checked in a file that includes nothing.
+pub fn lint_assembly(source: &str) -> Result<Vec<AssemblyWarning>, AssembleError> {
+    let lines = lines(source)?;
+    let mut warnings = Vec::new();
+    let mut functions: Vec<FunctionLines> = Vec::new();
+    let mut macros = Vec::new();
+    let mut current: Option<FunctionLines> = None;
+    let mut in_macro = false;
+    let mut includes = false;
+    for tokens in lines {
+        match tokens[0].text {
+            "%macro" => {
+                in_macro = true;
+                macros.extend(tokens.get(1).map(|name| name.text));
+            }
+            "%end" => in_macro = false,
+            "%include" => includes = true,
+            _ if in_macro => {}
+            ".func" => {
+                let Some(&name) = tokens.get(1) else { continue };
+                let num_args = tokens.get(2).and_then(|n| n.text.parse().ok());
+                current = Some(FunctionLines {
+                    name,
+                    num_args: num_args.unwrap_or(0),
+                    num_defaults: tokens.len().saturating_sub(3),
+                    body: Vec::new(),
+                });
+            }
+            ".end" => functions.extend(current.take()),
+            _ => {
+                if let Some(function) = &mut current {
+                    function.body.push(tokens);
+                }
+            }
+        }
+    }
+
+    let mut declared: HashMap<&str, &FunctionLines> = HashMap::new();
+    for function in &functions {
+        if declared.insert(function.name.text, function).is_some() {
+            warnings.push(function.name.warn(format!(
+                "Function '{}' is already defined",
+                function.name.text
+            )));
+        }
+    }
+    let mut integers: HashMap<u64, (Token, u128)> = HashMap::new();
+    for function in &functions {
+        check_labels(function, &mut warnings);
+        check_reachability(function, &macros, &mut warnings);
+        if !includes {
+            check_calls(function, &declared, &mut warnings);
+        }
+        for token in function.body.iter().flatten() {
+            check_integer(*token, &mut integers, &mut warnings);
+        }
+    }
+    warnings.sort_by_key(|warning| (warning.line, warning.column));
+    Ok(warnings)
+}
+
+// The tokens of each line that has any, with their positions.
+fn lines(source: &str) -> Result<Vec<Vec<Token<'_>>>, AssembleError> {
+    let mut lines = Vec::new();
+    for (index, text) in source.lines().enumerate() {
+        if index == 0 && text.starts_with("#!") {
+            continue;
+        }
+        let Some((tokens, _)) = lex(text) else {
+            return Err(AssembleError {
+                file: None,
+                line: index + 1,
+                message: "Unterminated literal".to_string(),
+            });
+        };
+        let tokens: Vec<Token> = tokens
+            .into_iter()
+            .map(|token| {
+                let offset = token.as_ptr() as usize - text.as_ptr() as usize;
+                Token {
+                    text: token,
+                    line: index + 1,
+                    column: text[..offset].chars().count() + 1,
+                }
+            })
+            .collect();
+        if !tokens.is_empty() {
+            lines.push(tokens);
+        }
+    }
+    Ok(lines)
+}
+
+// The labels a line defines, and the statement after them.
+fn split_labels<'t, 's>(tokens: &'t [Token<'s>]) -> (&'t [Token<'s>], &'t [Token<'s>]) {
+    let count = tokens
+        .iter()
+        .take_while(|token| is_label(token.text))
+        .count();
+    tokens.split_at(count)
+}
+
+fn check_labels(function: &FunctionLines, warnings: &mut Vec<AssemblyWarning>) {
+    let mut labels = HashMap::new();
+    for tokens in &function.body {
+        for label in split_labels(tokens).0 {
+            let name = &label.text[..label.text.len() - 1];
+            if labels.insert(name, label.line).is_some() {
+                warnings.push(label.warn(format!("Label '{}' is already defined", name)));
+            }
+        }
+    }
+    for tokens in &function.body {
+        let statement = split_labels(tokens).1;
+        let (Some(mnemonic), Some(target)) = (statement.first(), statement.get(1)) else {
+            continue;
+        };
+        let jumps = opcode_for(mnemonic.text)
+            .is_some_and(|opcode| opcode.operand_kind() == OperandKind::Target);
+        if jumps && !labels.contains_key(target.text) {
+            warnings.push(target.warn(format!("Undefined label '{}'", target.text)));
+        }
+    }
+}
+
+// Reports the first instruction of each run that follows an unconditional
+// jump, return, or halt with no label in between.
+fn check_reachability(
+    function: &FunctionLines,
+    macros: &[&str],
+    warnings: &mut Vec<AssemblyWarning>,
+) {
+    let mut after = None;
+    let mut warned = false;
+    for tokens in &function.body {
+        let (labels, statement) = split_labels(tokens);
+        if !labels.is_empty() {
+            after = None;
+            warned = false;
+        }
+        let Some(mnemonic) = statement.first() else {
+            continue;
+        };
+        if let Some(terminator) = after {
+            if !warned {
+                warnings.push(
+                    mnemonic.warn(format!("Instruction after '{}' is unreachable", terminator)),
+                );
+                warned = true;
+            }
+            continue;
+        }
+        if macros.contains(&mnemonic.text) {
+            continue;
+        }
+        if let Some(Opcode::Jump | Opcode::Return | Opcode::Halt) = opcode_for(mnemonic.text) {
+            after = Some(mnemonic.text);
+        }
+    }
+}
+
+fn check_calls(
+    function: &FunctionLines,
+    declared: &HashMap<&str, &FunctionLines>,
+    warnings: &mut Vec<AssemblyWarning>,
+) {
+    let mut passed = None;
+    for tokens in &function.body {
+        let statement = split_labels(tokens).1;
+        let (Some(mnemonic), operand) = (statement.first(), statement.get(1)) else {
+            continue;
+        };
+        let count = passed.take();
+        match (opcode_for(mnemonic.text), operand) {
+            (Some(Opcode::ArgCount), Some(operand)) => passed = operand.text.parse::<usize>().ok(),
+            (Some(Opcode::Call | Opcode::Defer), Some(callee)) => {
+                let Some(target) = declared.get(callee.text) else {
+                    warnings.push(callee.warn(format!("Undefined function '{}'", callee.text)));
+                    continue;
+                };
+                let required = target.num_args.saturating_sub(target.num_defaults);
+                match count {
+                    Some(count) if count < required || count > target.num_args => {
+                        let takes = if required == target.num_args {
+                            format!("{}", required)
+                        } else {
+                            format!("{} to {}", required, target.num_args)
+                        };
+                        warnings.push(callee.warn(format!(
+                            "'{}' takes {} arguments, but arg_count passes {}",
+                            callee.text, takes, count
+                        )));
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// Integer literals are numbers, so two that differ only past the precision
+// of a number become the same constant.
+fn check_integer<'s>(
+    token: Token<'s>,
+    integers: &mut HashMap<u64, (Token<'s>, u128)>,
+    warnings: &mut Vec<AssemblyWarning>,
+) {
+    let Some(exact) = exact_integer(token.text) else {
+        return;
+    };
+    let Some(n) = parse_number(token.text) else {
+        return;
+    };
+    match integers.get(&n.to_bits()) {
+        Some(&(first, first_exact)) if first_exact != exact => {
+            warnings.push(token.warn(format!(
+                "Literal {} is the same constant as {} on line {}, as both round to {}",
+                token.text, first.text, first.line, n
+            )));
+        }
+        Some(_) => {}
+        None => {
+            integers.insert(n.to_bits(), (token, exact));
+        }
+    }
+}
+
+// The value of an unsigned integer literal, exactly, if the token is one.
+fn exact_integer(token: &str) -> Option<u128> {
+    if literal_at(token, 0) {
+        return None;
+    }
+    let (radix, digits) = match token.get(..2) {
+        Some("0x" | "0X") => (16, &token[2..]),
+        Some("0o" | "0O") => (8, &token[2..]),
+        Some("0b" | "0B") => (2, &token[2..]),
+        _ => (10, token),
+    };
+    let digits = digits.replace('_', "");
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    u128::from_str_radix(&digits, radix).ok()
+}
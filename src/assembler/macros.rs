@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+
+use super::{error, opcode_for, AssembleError, Line};
+
+// Bounds nested expansion so a macro that invokes itself fails cleanly.
+const MAX_DEPTH: usize = 64;
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Line>,
+}
+
+// Collects `%macro name params... %end` definitions and replaces each
+// invocation with the macro body. Within the body, `%param` refers to an
+// argument. Expanded lines keep the line number of the invocation.
+pub(super) fn expand(lines: Vec<Line>) -> Result<Vec<Line>, AssembleError> {
+    let mut macros = HashMap::new();
+    let mut output = Vec::new();
+    let mut lines = lines.into_iter();
+    while let Some(line) = lines.next() {
+        match line.tokens[0].as_str() {
+            "%macro" => {
+                let (name, params) = match line.tokens[1..].split_first() {
+                    Some(split) => split,
+                    None => return error(&line, "Expected %macro <name> [params]".to_string()),
+                };
+                if opcode_for(name).is_some() {
+                    return error(
+                        &line,
+                        format!("Macro '{}' has the name of an instruction", name),
+                    );
+                }
+                let mut body = Vec::new();
+                loop {
+                    match lines.next() {
+                        Some(body_line) if body_line.tokens[0] == "%end" => break,
+                        Some(body_line) if body_line.tokens[0] == "%macro" => {
+                            return error(
+                                &body_line,
+                                "Macros cannot be defined inside a macro".to_string(),
+                            );
+                        }
+                        Some(body_line) => body.push(body_line),
+                        None => {
+                            return error(&line, format!("Macro '{}' is missing %end", name));
+                        }
+                    }
+                }
+                let definition = Macro {
+                    params: params.to_vec(),
+                    body,
+                };
+                if macros.insert(name.clone(), definition).is_some() {
+                    return error(&line, format!("Macro '{}' is already defined", name));
+                }
+            }
+            "%end" => return error(&line, "%end without %macro".to_string()),
+            directive if directive.starts_with('%') => {
+                return error(&line, format!("Unknown directive '{}'", directive));
+            }
+            _ => expand_line(&macros, line, 0, &mut output)?,
+        }
+    }
+    Ok(output)
+}
+
+fn expand_line(
+    macros: &HashMap<String, Macro>,
+    line: Line,
+    depth: usize,
+    output: &mut Vec<Line>,
+) -> Result<(), AssembleError> {
+    let (labels, statement) = line.split_labels();
+    let (definition, args) = match statement.split_first() {
+        Some((name, args)) => match macros.get(name) {
+            Some(definition) => (definition, args),
+            None => {
+                output.push(line);
+                return Ok(());
+            }
+        },
+        None => {
+            output.push(line);
+            return Ok(());
+        }
+    };
+    let name = &statement[0];
+    if depth == MAX_DEPTH {
+        return error(&line, format!("Macro '{}' expands too deeply", name));
+    }
+    if args.len() != definition.params.len() {
+        return error(
+            &line,
+            format!(
+                "Macro '{}' expects {} arguments but got {}",
+                name,
+                definition.params.len(),
+                args.len()
+            ),
+        );
+    }
+
+    // Labels in front of an invocation attach to the first expanded line.
+    if !labels.is_empty() {
+        output.push(Line {
+            file: line.file.clone(),
+            number: line.number,
+            tokens: labels.to_vec(),
+        });
+    }
+    for body_line in &definition.body {
+        let mut tokens = Vec::with_capacity(body_line.tokens.len());
+        for token in &body_line.tokens {
+            let substituted = match token.strip_prefix('%') {
+                Some(param) => match definition.params.iter().position(|p| p == param) {
+                    Some(i) => args[i].clone(),
+                    None => {
+                        return error(
+                            body_line,
+                            format!("Macro '{}' has no parameter '{}'", name, param),
+                        );
+                    }
+                },
+                None => token.clone(),
+            };
+            tokens.push(substituted);
+        }
+        let expanded = Line {
+            file: line.file.clone(),
+            number: line.number,
+            tokens,
+        };
+        expand_line(macros, expanded, depth + 1, output)?;
+    }
+    Ok(())
+}
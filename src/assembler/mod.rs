@@ -0,0 +1,828 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str::{CharIndices, Chars};
+
+use crate::builtins;
+use crate::bytecode::{Bytecode, Extension, Function, Instruction, Opcode, OperandKind, Value};
+
+mod format;
+mod lint;
+mod macros;
+
+pub use format::format_assembly;
+pub use lint::{lint_assembly, AssemblyWarning};
+
+// The file is the one the error occurred in, which may be an included file,
+// and is unknown for source assembled from memory. A line of 0 means the
+// error is not tied to a line, such as failing to open the file.
+#[derive(Debug)]
+pub struct AssembleError {
+    pub file: Option<PathBuf>,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}: ", file.display())?;
+        }
+        if self.line > 0 {
+            write!(f, "line {}: ", self.line)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+fn error<T>(line: &Line, message: String) -> Result<T, AssembleError> {
+    Err(AssembleError {
+        file: line.file.as_deref().map(Path::to_path_buf),
+        line: line.number,
+        message,
+    })
+}
+
+pub(crate) fn opcode_for(mnemonic: &str) -> Option<Opcode> {
+    Opcode::from_name(mnemonic)
+}
+
+// A source line split into tokens. String and character literals are kept
+// whole, quotes included, so they survive macro expansion unchanged.
+struct Line {
+    file: Option<Rc<Path>>,
+    number: usize,
+    tokens: Vec<String>,
+}
+
+impl Line {
+    // Leading `name:` tokens define labels; the rest is the statement.
+    fn split_labels(&self) -> (&[String], &[String]) {
+        let count = self
+            .tokens
+            .iter()
+            .take_while(|token| is_label(token))
+            .count();
+        self.tokens.split_at(count)
+    }
+}
+
+fn is_label(token: &str) -> bool {
+    token.len() > 1 && token.ends_with(':') && !token.starts_with(['"', '\''])
+}
+
+// A `#!` first line is skipped, so a file can be run as a script.
+fn tokenize(source: &str, file: Option<Rc<Path>>) -> Result<Vec<Line>, AssembleError> {
+    let mut lines = Vec::new();
+    for (index, text) in source.lines().enumerate() {
+        if index == 0 && text.starts_with("#!") {
+            continue;
+        }
+        let mut line = Line {
+            file: file.clone(),
+            number: index + 1,
+            tokens: Vec::new(),
+        };
+        match lex(text) {
+            Some((tokens, _)) => line.tokens = tokens.into_iter().map(str::to_string).collect(),
+            None => return error(&line, "Unterminated literal".to_string()),
+        }
+        if !line.tokens.is_empty() {
+            lines.push(line);
+        }
+    }
+    Ok(lines)
+}
+
+// Splits a line into tokens, returning them and where its comment starts, or
+// None if a literal is not terminated.
+fn lex(text: &str) -> Option<(Vec<&str>, Option<usize>)> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == ';' {
+            return Some((tokens, Some(start)));
+        } else if literal_at(text, start) {
+            let end = skip_literal(text, start, &mut chars)?;
+            tokens.push(&text[start..end]);
+        } else {
+            // Literals inside a token, as in a `match` pattern, may hold
+            // spaces and semicolons.
+            let mut end = text.len();
+            while let Some(&(i, d)) = chars.peek() {
+                if d.is_whitespace() || d == ';' {
+                    end = i;
+                    break;
+                }
+                if !literal_at(text, i) {
+                    chars.next();
+                } else {
+                    skip_literal(text, i, &mut chars)?;
+                }
+            }
+            tokens.push(&text[start..end]);
+        }
+    }
+    Some((tokens, None))
+}
+
+// Whether a string or character literal starts at byte `i` of `text`. A raw
+// string starts with `r` and any number of `#`s before its opening quote,
+// unless the `r` ends a word.
+pub(crate) fn literal_at(text: &str, i: usize) -> bool {
+    let rest = &text[i..];
+    rest.starts_with(['"', '\''])
+        || (raw_prefix(rest).is_some()
+            && !text[..i].ends_with(|c: char| c.is_alphanumeric() || c == '_'))
+}
+
+// The length of the `r#"` that opens a raw string at the start of `text`.
+fn raw_prefix(text: &str) -> Option<usize> {
+    let hashes = text
+        .strip_prefix('r')?
+        .bytes()
+        .take_while(|&b| b == b'#')
+        .count();
+    (text.as_bytes().get(1 + hashes) == Some(&b'"')).then_some(hashes + 2)
+}
+
+// The length of the literal at the start of `text`, or None if it is not
+// terminated. Escaped quotes do not end a quoted literal, and a raw string
+// ends at the first quote followed by as many `#`s as opened it.
+pub(crate) fn literal_len(text: &str) -> Option<usize> {
+    if let Some(prefix) = raw_prefix(text) {
+        let close = format!("\"{}", &text[1..prefix - 1]);
+        return text[prefix..]
+            .find(&close)
+            .map(|i| prefix + i + close.len());
+    }
+    let quote = text.chars().next()?;
+    let mut escaped = false;
+    for (i, d) in text.char_indices().skip(1) {
+        if escaped {
+            escaped = false;
+        } else if d == '\\' {
+            escaped = true;
+        } else if d == quote {
+            return Some(i + 1);
+        }
+    }
+    None
+}
+
+// Consumes the literal starting at byte `start` of `text` from `chars`,
+// returning the index just past its end.
+fn skip_literal(text: &str, start: usize, chars: &mut Peekable<CharIndices>) -> Option<usize> {
+    let end = start + literal_len(&text[start..])?;
+    while chars.next_if(|&(i, _)| i < end).is_some() {}
+    Some(end)
+}
+
+// Reads a file and the files it includes into `lines`. `stack` holds the
+// canonical paths of the files currently being read, to detect cycles.
+fn include(
+    path: &Path,
+    from: Option<&Line>,
+    stack: &mut Vec<PathBuf>,
+    lines: &mut Vec<Line>,
+) -> Result<(), AssembleError> {
+    let fail = |message: String| match from {
+        Some(line) => error(line, message),
+        None => Err(AssembleError {
+            file: None,
+            line: 0,
+            message,
+        }),
+    };
+    let canonical = match fs::canonicalize(path) {
+        Ok(canonical) => canonical,
+        Err(e) => return fail(format!("Failed to read '{}': {}", path.display(), e)),
+    };
+    if let Some(start) = stack.iter().position(|p| *p == canonical) {
+        let mut cycle: Vec<String> = stack[start..]
+            .iter()
+            .map(|p| p.display().to_string())
+            .collect();
+        cycle.push(canonical.display().to_string());
+        return fail(format!("Include cycle: {}", cycle.join(" -> ")));
+    }
+    let source = match fs::read_to_string(&canonical) {
+        Ok(source) => source,
+        Err(e) => return fail(format!("Failed to read '{}': {}", path.display(), e)),
+    };
+    stack.push(canonical);
+    preprocess(&source, Some(Rc::from(path)), stack, lines)?;
+    stack.pop();
+    Ok(())
+}
+
+// Tokenizes source and replaces each `%include "file"` line with the lines of
+// that file. Paths are relative to the including file, or to the current
+// directory for source assembled from memory.
+fn preprocess(
+    source: &str,
+    file: Option<Rc<Path>>,
+    stack: &mut Vec<PathBuf>,
+    lines: &mut Vec<Line>,
+) -> Result<(), AssembleError> {
+    for line in tokenize(source, file.clone())? {
+        if line.tokens[0] != "%include" {
+            lines.push(line);
+            continue;
+        }
+        let name = match &line.tokens[1..] {
+            [name] if literal_at(name, 0) && !name.starts_with('\'') => {
+                match parse_literal(name, &line)? {
+                    Value::Str(name) => name.to_string(),
+                    _ => unreachable!(),
+                }
+            }
+            _ => return error(&line, "Expected %include \"file\"".to_string()),
+        };
+        let dir = file
+            .as_deref()
+            .and_then(Path::parent)
+            .unwrap_or(Path::new(""));
+        include(&dir.join(name), Some(&line), stack, lines)?;
+    }
+    Ok(())
+}
+
+fn unescape(body: &str, line: &Line) -> Result<String, AssembleError> {
+    let mut result = String::with_capacity(body.len());
+    let mut chars = body.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('r') => result.push('\r'),
+            Some('0') => result.push('\0'),
+            Some(c @ ('"' | '\'' | '\\')) => result.push(c),
+            Some('u') => result.push(unicode_escape(&mut chars, line)?),
+            Some(c) => return error(line, format!("Unknown escape '\\{}'", c)),
+            None => return error(line, "Unterminated escape".to_string()),
+        }
+    }
+    Ok(result)
+}
+
+// Reads the `{...}` of a `\u{...}` escape: one to six hex digits naming a
+// Unicode scalar value.
+fn unicode_escape(chars: &mut Chars, line: &Line) -> Result<char, AssembleError> {
+    let rest = chars.as_str();
+    let digits = rest
+        .strip_prefix('{')
+        .and_then(|rest| rest.split_once('}'))
+        .map(|(digits, _)| digits);
+    let value = digits
+        .filter(|digits| (1..=6).contains(&digits.len()))
+        .filter(|digits| digits.bytes().all(|b| b.is_ascii_hexdigit()))
+        .and_then(|digits| u32::from_str_radix(digits, 16).ok());
+    let Some(value) = value else {
+        return error(
+            line,
+            "Expected \\u{...} with one to six hex digits".to_string(),
+        );
+    };
+    let Some(c) = char::from_u32(value) else {
+        return error(line, format!("Invalid Unicode escape '\\u{{{:x}}}'", value));
+    };
+    let close = rest.find('}').unwrap();
+    *chars = rest[close + 1..].chars();
+    Ok(c)
+}
+
+// Parses a number: decimal, or an integer with a `0x`, `0o`, or `0b` prefix,
+// with an optional sign and `_` between digits.
+fn parse_number(token: &str) -> Option<f64> {
+    let (negative, unsigned) = match token.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, token.strip_prefix('+').unwrap_or(token)),
+    };
+    let radix = match unsigned.get(..2) {
+        Some("0x" | "0X") => 16,
+        Some("0o" | "0O") => 8,
+        Some("0b" | "0B") => 2,
+        _ => 10,
+    };
+    let digits = if radix == 10 {
+        unsigned
+    } else {
+        &unsigned[2..]
+    };
+    // Underscores may only separate digits, so `_1`, `1_`, and `1_.5` are
+    // not numbers.
+    let digit = |c: Option<char>| c.is_some_and(|c| c.is_digit(radix));
+    for (i, _) in digits.match_indices('_') {
+        if !digit(digits[..i].chars().next_back()) || !digit(digits[i + 1..].chars().next()) {
+            return None;
+        }
+    }
+    let digits = digits.replace('_', "");
+    if !digits.starts_with(|c: char| c.is_digit(radix) || radix == 10 && c == '.') {
+        return None;
+    }
+    let n = if radix == 10 {
+        digits.parse::<f64>().ok()?
+    } else {
+        u64::from_str_radix(&digits, radix).ok()? as f64
+    };
+    Some(if negative { -n } else { n })
+}
+
+fn parse_literal(token: &str, line: &Line) -> Result<Value, AssembleError> {
+    if let Some(prefix) = raw_prefix(token) {
+        if literal_len(token) != Some(token.len()) {
+            return error(line, format!("Invalid constant '{}'", token));
+        }
+        let body = &token[prefix..token.len() - (prefix - 1)];
+        return Ok(Value::Str(body.into()));
+    }
+    if let Some(body) = token.strip_prefix('"') {
+        let body = &body[..body.len() - 1];
+        return Ok(Value::Str(unescape(body, line)?.into()));
+    }
+    if let Some(body) = token.strip_prefix('\'') {
+        let body = unescape(&body[..body.len() - 1], line)?;
+        let mut chars = body.chars();
+        return match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(Value::Char(c)),
+            _ => error(line, format!("Invalid character literal {}", token)),
+        };
+    }
+    match token {
+        "true" => return Ok(Value::Boolean(true)),
+        "false" => return Ok(Value::Boolean(false)),
+        _ => {}
+    }
+    match parse_number(token) {
+        Some(n) => Ok(Value::Number(n)),
+        None => error(line, format!("Invalid constant '{}'", token)),
+    }
+}
+
+// Parses a `push_const` operand on its own.
+pub(crate) fn literal(token: &str) -> Result<Value, AssembleError> {
+    let line = Line {
+        file: None,
+        number: 0,
+        tokens: Vec::new(),
+    };
+    parse_literal(token, &line)
+}
+
+// Constants are shared when they are equal, except that numbers must have the
+// same bits, so 0 and -0 stay distinct.
+pub(crate) fn is_same_constant(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.to_bits() == b.to_bits(),
+        (Value::Tuple(a), Value::Tuple(b)) => {
+            a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| is_same_constant(a, b))
+        }
+        (a, b) => a == b,
+    }
+}
+
+// Type names a `match` pattern can test for.
+const TYPE_NAMES: &[&str] = &[
+    "nil",
+    "number",
+    "bigint",
+    "decimal",
+    "boolean",
+    "char",
+    "string",
+    "bytes",
+    "array",
+    "tuple",
+    "error",
+    "timestamp",
+    "duration",
+    "string_builder",
+    "weak",
+];
+
+// Parses a `match` operand into the tuple constant the VM reads, as described
+// in `pattern.rs`. A pattern is `_`, a type name, a literal, a tuple
+// `(p, ...)`, an array `[p, ...]`, or `error(p)`.
+fn parse_pattern(text: &str, line: &Line) -> Result<Value, AssembleError> {
+    let (pattern, rest) = pattern_prefix(text.trim(), line)?;
+    if !rest.trim().is_empty() {
+        return error(line, format!("Unexpected '{}' after pattern", rest.trim()));
+    }
+    Ok(pattern)
+}
+
+fn pattern_value(kind: &str, fields: Vec<Value>) -> Value {
+    let mut elements = vec![Value::Str(kind.into())];
+    elements.extend(fields);
+    Value::Tuple(elements.into())
+}
+
+// Parses the pattern at the start of `text`, returning it and the text after.
+fn pattern_prefix<'t>(text: &'t str, line: &Line) -> Result<(Value, &'t str), AssembleError> {
+    let text = text.trim_start();
+    if let Some(rest) = text.strip_prefix('(') {
+        let (fields, rest) = pattern_list(rest, ')', line)?;
+        return Ok((pattern_value("tuple", fields), rest));
+    }
+    if let Some(rest) = text.strip_prefix('[') {
+        let (fields, rest) = pattern_list(rest, ']', line)?;
+        return Ok((pattern_value("array", fields), rest));
+    }
+    if let Some(rest) = text.strip_prefix("error(") {
+        let (fields, rest) = pattern_list(rest, ')', line)?;
+        return match <[Value; 1]>::try_from(fields) {
+            Ok(fields) => Ok((pattern_value("error", fields.into()), rest)),
+            Err(_) => error(line, "error(...) takes one pattern".to_string()),
+        };
+    }
+    // Otherwise the pattern is one word or literal, ending at a delimiter.
+    let mut end = text.len();
+    let mut chars = text.char_indices().peekable();
+    while let Some(&(i, c)) = chars.peek() {
+        if matches!(c, ',' | ')' | ']') || c.is_whitespace() {
+            end = i;
+            break;
+        }
+        if !literal_at(text, i) {
+            chars.next();
+        } else if skip_literal(text, i, &mut chars).is_none() {
+            return error(line, "Unterminated literal".to_string());
+        }
+    }
+    let (word, rest) = text.split_at(end);
+    let pattern = match word {
+        "" => return error(line, "Expected a pattern".to_string()),
+        "_" => pattern_value("any", Vec::new()),
+        _ if TYPE_NAMES.contains(&word) => pattern_value("type", vec![Value::Str(word.into())]),
+        _ => pattern_value("literal", vec![parse_literal(word, line)?]),
+    };
+    Ok((pattern, rest))
+}
+
+// Parses comma-separated patterns up to and including `close`.
+fn pattern_list<'t>(
+    mut text: &'t str,
+    close: char,
+    line: &Line,
+) -> Result<(Vec<Value>, &'t str), AssembleError> {
+    let mut patterns = Vec::new();
+    if let Some(rest) = text.trim_start().strip_prefix(close) {
+        return Ok((patterns, rest));
+    }
+    loop {
+        let (pattern, rest) = pattern_prefix(text, line)?;
+        patterns.push(pattern);
+        let rest = rest.trim_start();
+        if let Some(rest) = rest.strip_prefix(',') {
+            text = rest;
+        } else if let Some(rest) = rest.strip_prefix(close) {
+            return Ok((patterns, rest));
+        } else {
+            return error(line, format!("Expected ',' or '{}' in pattern", close));
+        }
+    }
+}
+
+fn parse_integer(token: &str, line: &Line) -> Result<u16, AssembleError> {
+    token
+        .parse()
+        .or_else(|_| error(line, format!("Invalid operand '{}'", token)))
+}
+
+struct FunctionSource<'l> {
+    name: String,
+    num_args: usize,
+    // Default values of the last parameters.
+    defaults: Vec<Value>,
+    header: &'l Line,
+    body: Vec<&'l Line>,
+}
+
+struct Assembler<'e> {
+    bytecode: Bytecode,
+    functions: HashMap<String, u16>,
+    extensions: &'e [&'static Extension],
+}
+
+// Assembles `.zasm` source. The function named `main` becomes function 0, the
+// entry point, and the rest are numbered in the order they are declared. In
+// a file without `main`, the first function declared is the entry point.
+pub fn assemble(source: &str) -> Result<Bytecode, AssembleError> {
+    assemble_with_extensions(source, &[])
+}
+
+// Assembles source that may use the given extension instructions, by their
+// names.
+pub fn assemble_with_extensions(
+    source: &str,
+    extensions: &[&'static Extension],
+) -> Result<Bytecode, AssembleError> {
+    let mut lines = Vec::new();
+    preprocess(source, None, &mut Vec::new(), &mut lines)?;
+    assemble_lines(lines, extensions)
+}
+
+// Assembles source held in memory as though it had been read from `path`, so
+// includes resolve relative to it. The language server uses this for
+// documents with unsaved changes.
+pub(crate) fn assemble_source(source: &str, path: &Path) -> Result<Bytecode, AssembleError> {
+    let mut stack: Vec<PathBuf> = fs::canonicalize(path).into_iter().collect();
+    let mut lines = Vec::new();
+    preprocess(source, Some(Rc::from(path)), &mut stack, &mut lines)?;
+    assemble_lines(lines, &[])
+}
+
+pub fn assemble_file<P: AsRef<Path>>(path: P) -> Result<Bytecode, AssembleError> {
+    let mut lines = Vec::new();
+    include(path.as_ref(), None, &mut Vec::new(), &mut lines)?;
+    assemble_lines(lines, &[])
+}
+
+// Assembles every `.zasm` file in a directory, in name order, into one
+// program, as though they were a single file. Functions can call functions
+// defined in any of the files by name, and `main` is the entry point
+// wherever it is defined. A file that another file in the directory includes
+// is only read through that include.
+pub fn assemble_dir<P: AsRef<Path>>(dir: P) -> Result<Bytecode, AssembleError> {
+    let dir = dir.as_ref();
+    let fail = |message: String| AssembleError {
+        file: None,
+        line: 0,
+        message,
+    };
+    let entries = fs::read_dir(dir)
+        .map_err(|e| fail(format!("Failed to read '{}': {}", dir.display(), e)))?;
+    let mut paths = Vec::new();
+    for entry in entries {
+        let path = entry
+            .map_err(|e| fail(format!("Failed to read '{}': {}", dir.display(), e)))?
+            .path();
+        if path.is_file() && path.extension().is_some_and(|e| e == "zasm") {
+            paths.push(path);
+        }
+    }
+    if paths.is_empty() {
+        return Err(fail(format!("No .zasm files in '{}'", dir.display())));
+    }
+    paths.sort();
+
+    let mut units = Vec::new();
+    for path in &paths {
+        let mut lines = Vec::new();
+        include(path, None, &mut Vec::new(), &mut lines)?;
+        units.push((fs::canonicalize(path).ok(), lines));
+    }
+    let mut included = HashSet::new();
+    for (root, lines) in &units {
+        let files: HashSet<&Path> = lines.iter().filter_map(|l| l.file.as_deref()).collect();
+        included.extend(
+            files
+                .into_iter()
+                .filter_map(|file| fs::canonicalize(file).ok())
+                .filter(|file| Some(file) != root.as_ref()),
+        );
+    }
+    let lines = units
+        .into_iter()
+        .filter(|(root, _)| !root.as_ref().is_some_and(|root| included.contains(root)))
+        .flat_map(|(_, lines)| lines)
+        .collect();
+    assemble_lines(lines, &[])
+}
+
+fn assemble_lines(
+    lines: Vec<Line>,
+    extensions: &[&'static Extension],
+) -> Result<Bytecode, AssembleError> {
+    let lines = macros::expand(lines)?;
+    let mut functions = split_functions(&lines)?;
+    if let Some(main) = functions.iter().position(|f| f.name == "main") {
+        let main = functions.remove(main);
+        functions.insert(0, main);
+    }
+
+    let mut assembler = Assembler {
+        bytecode: Bytecode::new(),
+        functions: HashMap::new(),
+        extensions,
+    };
+    for (i, function) in functions.iter().enumerate() {
+        let index = match u16::try_from(i) {
+            Ok(index) => index,
+            Err(_) => return error(function.header, "Too many functions".to_string()),
+        };
+        if assembler
+            .functions
+            .insert(function.name.clone(), index)
+            .is_some()
+        {
+            return error(
+                function.header,
+                format!("Function '{}' is already defined", function.name),
+            );
+        }
+    }
+    for function in &functions {
+        assembler.function(function)?;
+    }
+    Ok(assembler.bytecode)
+}
+
+fn split_functions(lines: &[Line]) -> Result<Vec<FunctionSource<'_>>, AssembleError> {
+    let mut functions = Vec::new();
+    let mut current: Option<FunctionSource> = None;
+    for line in lines {
+        match line.tokens[0].as_str() {
+            ".func" => {
+                if current.is_some() {
+                    return error(line, "Missing .end before .func".to_string());
+                }
+                let (name, num_args, defaults) = match &line.tokens[1..] {
+                    [name] => (name, 0, &[][..]),
+                    [name, num_args, defaults @ ..] => {
+                        (name, parse_integer(num_args, line)?.into(), defaults)
+                    }
+                    _ => return error(line, "Expected .func <name> [args [defaults]]".to_string()),
+                };
+                if defaults.len() > num_args {
+                    return error(line, "More default values than arguments".to_string());
+                }
+                let defaults = defaults
+                    .iter()
+                    .map(|token| parse_literal(token, line))
+                    .collect::<Result<_, _>>()?;
+                current = Some(FunctionSource {
+                    name: name.clone(),
+                    num_args,
+                    defaults,
+                    header: line,
+                    body: Vec::new(),
+                });
+            }
+            ".end" => match current.take() {
+                Some(function) => functions.push(function),
+                None => return error(line, ".end without .func".to_string()),
+            },
+            _ => match current.as_mut() {
+                Some(function) => function.body.push(line),
+                None => return error(line, "Instruction outside of a function".to_string()),
+            },
+        }
+    }
+    if let Some(function) = current {
+        return error(
+            function.header,
+            format!("Function '{}' is missing .end", function.name),
+        );
+    }
+    Ok(functions)
+}
+
+impl Assembler<'_> {
+    fn constant(&mut self, value: Value, line: &Line) -> Result<u16, AssembleError> {
+        let existing = self
+            .bytecode
+            .constants()
+            .iter()
+            .position(|c| is_same_constant(c, &value));
+        let index = match existing {
+            Some(index) => index,
+            None => self.bytecode.add_constant(value),
+        };
+        u16::try_from(index).or_else(|_| error(line, "Too many constants".to_string()))
+    }
+
+    fn function(&mut self, function: &FunctionSource) -> Result<(), AssembleError> {
+        let mut labels = HashMap::new();
+        let mut count = 0;
+        for line in &function.body {
+            let (line_labels, statement) = line.split_labels();
+            for label in line_labels {
+                let label = &label[..label.len() - 1];
+                if labels.insert(label, count).is_some() {
+                    return error(line, format!("Label '{}' is already defined", label));
+                }
+            }
+            if !statement.is_empty() {
+                count += 1;
+            }
+        }
+
+        let mut instructions = Vec::with_capacity(count);
+        for line in &function.body {
+            let (_, statement) = line.split_labels();
+            let (mnemonic, operands) = match statement.split_first() {
+                Some(split) => split,
+                None => continue,
+            };
+            let extension = self.extensions.iter().find(|e| e.name() == mnemonic);
+            let opcode = match opcode_for(mnemonic).or(extension.map(|&e| Opcode::Extension(e))) {
+                Some(opcode) => opcode,
+                None => return error(line, format!("Unknown instruction '{}'", mnemonic)),
+            };
+            let operand = match (opcode.has_operand(), operands) {
+                (false, []) => None,
+                (true, [slot, value]) if opcode == Opcode::AddLocalConst => {
+                    Some(self.local_constant(slot, value, line)?)
+                }
+                (true, _) if opcode == Opcode::AddLocalConst => {
+                    return error(line, format!("'{}' takes a local and a literal", mnemonic));
+                }
+                (true, [operand]) => Some(self.operand(opcode, operand, &labels, line)?),
+                // A pattern may be split into several tokens by spaces.
+                (true, [_, ..]) if opcode == Opcode::Match => {
+                    let pattern = parse_pattern(&operands.join(" "), line)?;
+                    Some(self.constant(pattern, line)?)
+                }
+                (false, _) => {
+                    return error(line, format!("'{}' takes no operand", mnemonic));
+                }
+                (true, _) => {
+                    return error(line, format!("'{}' takes one operand", mnemonic));
+                }
+            };
+            instructions.push(Instruction::new(opcode, operand));
+        }
+        let mut defaults = Vec::with_capacity(function.defaults.len());
+        for value in &function.defaults {
+            defaults.push(self.constant(value.clone(), function.header)?);
+        }
+        self.bytecode
+            .add_function(Function::new(instructions, function.num_args).with_defaults(defaults));
+        Ok(())
+    }
+
+    // Packs the operands of `add_local_const`, which must both fit in a byte.
+    fn local_constant(
+        &mut self,
+        slot: &str,
+        value: &str,
+        line: &Line,
+    ) -> Result<u16, AssembleError> {
+        let slot = parse_integer(slot, line)?;
+        if slot > 0xff {
+            return error(
+                line,
+                format!("Local {} is too high for add_local_const", slot),
+            );
+        }
+        let value = parse_literal(value, line)?;
+        let index = self.constant(value, line)?;
+        if index > 0xff {
+            return error(
+                line,
+                format!("Constant index {} is too high for add_local_const", index),
+            );
+        }
+        Ok(slot << 8 | index)
+    }
+
+    fn operand(
+        &mut self,
+        opcode: Opcode,
+        operand: &str,
+        labels: &HashMap<&str, usize>,
+        line: &Line,
+    ) -> Result<u16, AssembleError> {
+        match opcode {
+            Opcode::PushConst => {
+                let value = parse_literal(operand, line)?;
+                self.constant(value, line)
+            }
+            _ if opcode.operand_kind() == OperandKind::Target => match labels.get(operand) {
+                Some(&target) => u16::try_from(target)
+                    .or_else(|_| error(line, "Function is too long".to_string())),
+                None => error(line, format!("Undefined label '{}'", operand)),
+            },
+            Opcode::Call | Opcode::Defer => match self.functions.get(operand) {
+                Some(&index) => Ok(index),
+                None => error(line, format!("Undefined function '{}'", operand)),
+            },
+            Opcode::CallNative => {
+                if builtins::lookup(operand).is_none() {
+                    return error(line, format!("Unknown native function '{}'", operand));
+                }
+                self.constant(Value::Str(operand.into()), line)
+            }
+            Opcode::Import => self.constant(Value::Str(operand.into()), line),
+            Opcode::Match => {
+                let pattern = parse_pattern(operand, line)?;
+                self.constant(pattern, line)
+            }
+            _ => parse_integer(operand, line),
+        }
+    }
+}
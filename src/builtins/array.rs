@@ -0,0 +1,154 @@
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use crate::bytecode::Value;
+use crate::vm::VirtualMachine;
+
+use super::NativeFunction;
+
+pub(super) const FUNCTIONS: &[NativeFunction] = &[
+    NativeFunction {
+        name: "sort",
+        arity: 1,
+        function: sort,
+        capability: None,
+    },
+    NativeFunction {
+        name: "sort_by",
+        arity: 2,
+        function: sort_by,
+        capability: None,
+    },
+    NativeFunction {
+        name: "reverse",
+        arity: 1,
+        function: reverse,
+        capability: None,
+    },
+    NativeFunction {
+        name: "contains",
+        arity: 2,
+        function: contains,
+        capability: None,
+    },
+    NativeFunction {
+        name: "index_of",
+        arity: 2,
+        function: index_of,
+        capability: None,
+    },
+];
+
+fn array<'v>(value: &'v Value, name: &str) -> &'v [Value] {
+    match value {
+        Value::Array(elements) => elements,
+        _ => runtime_error!("Invalid operand type for {}.", name),
+    }
+}
+
+fn sort(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let mut elements = array(&args[0], "sort").to_vec();
+    elements.sort_by(|a, b| {
+        a.compare(b)
+            .unwrap_or_else(|| runtime_error!("Cannot compare values in sort."))
+    });
+    Value::Array(Rc::new(elements))
+}
+
+// The comparator is the index of a two-argument function returning a number
+// that is negative, zero, or positive.
+fn sort_by(vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let mut elements = array(&args[0], "sort_by").to_vec();
+    let comparator = match &args[1] {
+        Value::Number(n) if *n >= 0.0 && n.fract() == 0.0 => *n as usize,
+        _ => runtime_error!("Invalid operand type for sort_by."),
+    };
+    elements.sort_by(
+        |a, b| match vm.call_function(comparator, vec![a.clone(), b.clone()]) {
+            Value::Number(n) if n < 0.0 => Ordering::Less,
+            Value::Number(n) if n > 0.0 => Ordering::Greater,
+            Value::Number(_) => Ordering::Equal,
+            _ => runtime_error!("Comparator for sort_by must return a number."),
+        },
+    );
+    Value::Array(Rc::new(elements))
+}
+
+fn reverse(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let mut elements = array(&args[0], "reverse").to_vec();
+    elements.reverse();
+    Value::Array(Rc::new(elements))
+}
+
+fn contains(vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let numbers = vm.number_equality();
+    Value::Boolean(
+        array(&args[0], "contains")
+            .iter()
+            .any(|e| e.equals(&args[1], numbers)),
+    )
+}
+
+fn index_of(vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let numbers = vm.number_equality();
+    match array(&args[0], "index_of")
+        .iter()
+        .position(|e| e.equals(&args[1], numbers))
+    {
+        Some(index) => Value::Number(index as f64),
+        None => Value::Number(-1.0),
+    }
+}
+
+// The operations behind the array instructions, shared with the transpiled
+// runtime. Arrays are only copied when another value still refers to them.
+
+pub(crate) fn concat(a: Value, b: Value) -> Value {
+    match (a, b) {
+        (Value::Array(mut a), Value::Array(b)) => {
+            Rc::make_mut(&mut a).extend(b.iter().cloned());
+            Value::Array(a)
+        }
+        _ => runtime_error!("Invalid operand types for array concat."),
+    }
+}
+
+// Elements from start up to, but not including, end.
+pub(crate) fn slice(array: Value, start: Value, end: Value) -> Value {
+    let elements = match array {
+        Value::Array(elements) => elements,
+        _ => runtime_error!("Invalid operand type for array slice."),
+    };
+    let index = |value: Value| match value {
+        Value::Number(n) if n >= 0.0 && n.fract() == 0.0 => n as usize,
+        _ => runtime_error!("Invalid index for array slice."),
+    };
+    let (start, end) = (index(start), index(end));
+    if start > end || end > elements.len() {
+        runtime_error!("Array slice out of range.");
+    }
+    Value::Array(Rc::new(elements[start..end].to_vec()))
+}
+
+pub(crate) fn push(array: Value, value: Value) -> Value {
+    match array {
+        Value::Array(mut elements) => {
+            Rc::make_mut(&mut elements).push(value);
+            Value::Array(elements)
+        }
+        _ => runtime_error!("Invalid operand type for array push."),
+    }
+}
+
+// The array without its last element, and that element.
+pub(crate) fn pop(array: Value) -> (Value, Value) {
+    match array {
+        Value::Array(mut elements) => {
+            let last = Rc::make_mut(&mut elements)
+                .pop()
+                .unwrap_or_else(|| runtime_error!("Cannot pop an empty array."));
+            (Value::Array(elements), last)
+        }
+        _ => runtime_error!("Invalid operand type for array pop."),
+    }
+}
@@ -0,0 +1,128 @@
+use crate::bytecode::Value;
+use crate::vm::VirtualMachine;
+
+use super::NativeFunction;
+
+pub(super) const FUNCTIONS: &[NativeFunction] = &[
+    NativeFunction {
+        name: "hex_encode",
+        arity: 1,
+        function: hex_encode,
+        capability: None,
+    },
+    NativeFunction {
+        name: "hex_decode",
+        arity: 1,
+        function: hex_decode,
+        capability: None,
+    },
+    NativeFunction {
+        name: "base64_encode",
+        arity: 1,
+        function: base64_encode,
+        capability: None,
+    },
+    NativeFunction {
+        name: "base64_decode",
+        arity: 1,
+        function: base64_decode,
+        capability: None,
+    },
+];
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// Strings are encoded as their UTF-8 bytes.
+fn bytes<'v>(value: &'v Value, name: &str) -> &'v [u8] {
+    match value {
+        Value::Bytes(bytes) => bytes,
+        Value::Str(s) => s.as_bytes(),
+        _ => runtime_error!("Invalid operand type for {}.", name),
+    }
+}
+
+fn string<'v>(value: &'v Value, name: &str) -> &'v str {
+    match value {
+        Value::Str(s) => s,
+        _ => runtime_error!("Invalid operand type for {}.", name),
+    }
+}
+
+fn hex_encode(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let bytes = bytes(&args[0], "hex_encode");
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    Value::Str(hex.into())
+}
+
+fn hex_decode(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let s = string(&args[0], "hex_decode").as_bytes();
+    if !s.len().is_multiple_of(2) {
+        runtime_error!("Invalid hex string.");
+    }
+    let digit = |c: u8| {
+        (c as char)
+            .to_digit(16)
+            .unwrap_or_else(|| runtime_error!("Invalid hex string.")) as u8
+    };
+    Value::Bytes(
+        s.chunks(2)
+            .map(|pair| digit(pair[0]) << 4 | digit(pair[1]))
+            .collect::<Vec<u8>>()
+            .into(),
+    )
+}
+
+fn base64_encode(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let bytes = bytes(&args[0], "base64_encode");
+    let mut result = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let index = (n >> (18 - 6 * i)) & 0x3F;
+                result.push(BASE64_ALPHABET[index as usize] as char);
+            } else {
+                result.push('=');
+            }
+        }
+    }
+    Value::Str(result.into())
+}
+
+fn base64_decode(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let s = string(&args[0], "base64_decode").as_bytes();
+    if !s.len().is_multiple_of(4) {
+        runtime_error!("Invalid base64 string.");
+    }
+    let mut result = Vec::with_capacity(s.len() / 4 * 3);
+    for (i, chunk) in s.chunks(4).enumerate() {
+        let is_last = i == s.len() / 4 - 1;
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 || (padding > 0 && !is_last) {
+            runtime_error!("Invalid base64 string.");
+        }
+        let mut n = 0u32;
+        for &c in &chunk[..4 - padding] {
+            let digit = BASE64_ALPHABET
+                .iter()
+                .position(|&a| a == c)
+                .unwrap_or_else(|| runtime_error!("Invalid base64 string."));
+            n = n << 6 | digit as u32;
+        }
+        n <<= 6 * padding;
+        result.push((n >> 16) as u8);
+        if padding < 2 {
+            result.push((n >> 8) as u8);
+        }
+        if padding < 1 {
+            result.push(n as u8);
+        }
+    }
+    Value::Bytes(result.into())
+}
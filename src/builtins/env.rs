@@ -0,0 +1,65 @@
+use std::env;
+use std::rc::Rc;
+
+use crate::bytecode::Value;
+use crate::vm::VirtualMachine;
+
+use super::{Capability, NativeFunction};
+
+pub(super) const FUNCTIONS: &[NativeFunction] = &[
+    NativeFunction {
+        name: "env_get",
+        arity: 1,
+        function: env_get,
+        capability: Some(Capability::Env),
+    },
+    NativeFunction {
+        name: "env_vars",
+        arity: 0,
+        function: env_vars,
+        capability: Some(Capability::Env),
+    },
+    NativeFunction {
+        name: "cwd",
+        arity: 0,
+        function: cwd,
+        capability: Some(Capability::Env),
+    },
+];
+
+fn env_get(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let name = match &args[0] {
+        Value::Str(s) => s,
+        _ => runtime_error!("Invalid operand type for env_get."),
+    };
+    match env::var(&**name) {
+        Ok(value) => Value::Str(value.into()),
+        Err(_) => Value::Nil,
+    }
+}
+
+// Returns an array of [name, value] pairs sorted by name, skipping variables
+// that are not valid UTF-8.
+fn env_vars(_vm: &mut VirtualMachine, _args: &[Value]) -> Value {
+    let mut vars: Vec<(String, String)> = env::vars_os()
+        .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+        .collect();
+    vars.sort();
+    Value::Array(Rc::new(
+        vars.into_iter()
+            .map(|(name, value)| {
+                Value::Array(Rc::new(vec![
+                    Value::Str(name.into()),
+                    Value::Str(value.into()),
+                ]))
+            })
+            .collect(),
+    ))
+}
+
+fn cwd(_vm: &mut VirtualMachine, _args: &[Value]) -> Value {
+    match env::current_dir() {
+        Ok(path) => Value::Str(path.to_string_lossy().into()),
+        Err(_) => Value::Nil,
+    }
+}
@@ -0,0 +1,140 @@
+use crate::bytecode::Value;
+use crate::vm::VirtualMachine;
+
+use super::NativeFunction;
+
+pub(super) const FUNCTIONS: &[NativeFunction] = &[NativeFunction {
+    name: "hash",
+    arity: 1,
+    function: hash,
+    capability: None,
+}];
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn write_len(&mut self, len: usize) {
+        self.write(&(len as u64).to_le_bytes());
+    }
+}
+
+// Each value is fed to the hasher as a type tag followed by a canonical
+// little-endian encoding, so values that compare equal hash equally.
+fn feed(hasher: &mut Fnv1a, value: &Value) {
+    match value {
+        Value::Nil => hasher.write(&[0x00]),
+        Value::Number(n) => {
+            let n = if *n == 0.0 {
+                0.0
+            } else if n.is_nan() {
+                f64::NAN
+            } else {
+                *n
+            };
+            hasher.write(&[0x01]);
+            hasher.write(&n.to_bits().to_le_bytes());
+        }
+        Value::Boolean(b) => hasher.write(&[0x02, *b as u8]),
+        Value::Str(s) => {
+            hasher.write(&[0x03]);
+            hasher.write_len(s.len());
+            hasher.write(s.as_bytes());
+        }
+        #[cfg(feature = "bigint")]
+        Value::BigInt(n) => {
+            let bytes = n.to_signed_bytes_le();
+            hasher.write(&[0x04]);
+            hasher.write_len(bytes.len());
+            hasher.write(&bytes);
+        }
+        #[cfg(feature = "decimal")]
+        Value::Decimal(n) => {
+            hasher.write(&[0x05]);
+            hasher.write(&n.normalize().serialize());
+        }
+        Value::Char(c) => {
+            hasher.write(&[0x06]);
+            hasher.write(&(*c as u32).to_le_bytes());
+        }
+        Value::Bytes(bytes) => {
+            hasher.write(&[0x07]);
+            hasher.write_len(bytes.len());
+            hasher.write(bytes);
+        }
+        Value::Array(elements) => {
+            hasher.write(&[0x08]);
+            hasher.write_len(elements.len());
+            for element in elements.iter() {
+                feed(hasher, element);
+            }
+        }
+        Value::Tuple(elements) => {
+            hasher.write(&[0x09]);
+            hasher.write_len(elements.len());
+            for element in elements.iter() {
+                feed(hasher, element);
+            }
+        }
+        Value::Error(payload) => {
+            hasher.write(&[0x0A]);
+            feed(hasher, payload);
+        }
+        Value::Timestamp(t) => {
+            hasher.write(&[0x0B]);
+            hasher.write(&t.to_le_bytes());
+        }
+        Value::Duration(d) => {
+            hasher.write(&[0x0C]);
+            hasher.write(&d.to_le_bytes());
+        }
+        Value::StringBuilder(builder) => {
+            let s = builder.borrow();
+            hasher.write(&[0x0D]);
+            hasher.write_len(s.len());
+            hasher.write(s.as_bytes());
+        }
+        Value::Weak(_) => hasher.write(&[0x0E]),
+        Value::Socket(_) => hasher.write(&[0x0F]),
+        #[cfg(feature = "sqlite")]
+        Value::Database(_) => hasher.write(&[0x10]),
+        Value::Set(elements) => {
+            hasher.write(&[0x11]);
+            hasher.write_len(elements.len());
+            for element in elements.iter() {
+                feed(hasher, element);
+            }
+        }
+    }
+}
+
+// The 64-bit FNV-1a digest of raw bytes.
+pub(crate) fn digest(bytes: &[u8]) -> u64 {
+    digest_parts(&[bytes])
+}
+
+// The digest of several byte strings written one after another.
+pub(crate) fn digest_parts(parts: &[&[u8]]) -> u64 {
+    let mut hasher = Fnv1a(FNV_OFFSET_BASIS);
+    for part in parts {
+        hasher.write(part);
+    }
+    hasher.0
+}
+
+// The 64-bit FNV-1a digest is truncated to 53 bits so that it is exactly
+// representable as a number.
+fn hash(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let mut hasher = Fnv1a(FNV_OFFSET_BASIS);
+    feed(&mut hasher, &args[0]);
+    Value::Number((hasher.0 & ((1 << 53) - 1)) as f64)
+}
@@ -0,0 +1,199 @@
+use std::io::{self, Read, Write};
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::bytecode::Value;
+use crate::vm::VirtualMachine;
+
+use super::{net, Capability, NativeFunction};
+
+pub(super) const FUNCTIONS: &[NativeFunction] = &[
+    NativeFunction {
+        name: "http_get",
+        arity: 1,
+        function: http_get,
+        capability: Some(Capability::Net),
+    },
+    NativeFunction {
+        name: "http_post",
+        arity: 3,
+        function: http_post,
+        capability: Some(Capability::Net),
+    },
+];
+
+// How long a request waits to send or receive anything once connected.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+// The largest response a request reads, headers included.
+const MAX_RESPONSE: u64 = 16 * 1024 * 1024;
+
+fn io_failure(e: io::Error) -> String {
+    match e.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => "Timed out".to_string(),
+        _ => e.to_string(),
+    }
+}
+
+fn failure(message: String) -> Value {
+    Value::Error(Rc::new(Value::Str(message.into())))
+}
+
+fn http_get(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let url = match &args[0] {
+        Value::Str(s) => s,
+        _ => runtime_error!("Invalid operand type for http_get."),
+    };
+    request("GET", url, None).unwrap_or_else(failure)
+}
+
+// Sends the body with the given content type.
+fn http_post(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let (url, content_type) = match (&args[0], &args[1]) {
+        (Value::Str(url), Value::Str(content_type)) => (url, content_type),
+        _ => runtime_error!("Invalid operand type for http_post."),
+    };
+    let body: &[u8] = match &args[2] {
+        Value::Str(s) => s.as_bytes(),
+        Value::Bytes(bytes) => bytes,
+        _ => runtime_error!("Invalid operand type for http_post."),
+    };
+    request("POST", url, Some((content_type, body))).unwrap_or_else(failure)
+}
+
+// Makes a plain HTTP/1.1 request and returns a `(status, headers, body)`
+// tuple. Headers are `[name, value]` pairs in the order received, with names
+// lowercased. The body is a string if it is valid UTF-8 and bytes otherwise.
+fn request(method: &str, url: &str, body: Option<(&str, &[u8])>) -> Result<Value, String> {
+    let (host, path) = parse_url(url)?;
+    let mut stream = net::connect(host).map_err(io_failure)?;
+    stream
+        .set_read_timeout(Some(TIMEOUT))
+        .and_then(|()| stream.set_write_timeout(Some(TIMEOUT)))
+        .map_err(io_failure)?;
+    let mut request = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nUser-Agent: zircon\r\n",
+        method, path, host
+    )
+    .into_bytes();
+    if let Some((content_type, body)) = body {
+        request.extend(
+            format!(
+                "Content-Type: {}\r\nContent-Length: {}\r\n",
+                content_type,
+                body.len()
+            )
+            .bytes(),
+        );
+        request.extend(b"\r\n");
+        request.extend(body);
+    } else {
+        request.extend(b"\r\n");
+    }
+    stream.write_all(&request).map_err(io_failure)?;
+    let mut response = Vec::new();
+    stream
+        .take(MAX_RESPONSE + 1)
+        .read_to_end(&mut response)
+        .map_err(io_failure)?;
+    if response.len() as u64 > MAX_RESPONSE {
+        return Err("HTTP response is too large".to_string());
+    }
+    parse_response(&response).ok_or_else(|| "Malformed HTTP response".to_string())
+}
+
+// Splits `http://host[:port]/path` into an address to connect to and the
+// path to request.
+fn parse_url(url: &str) -> Result<(&str, &str), String> {
+    let rest = match url.strip_prefix("http://") {
+        Some(rest) => rest,
+        None if url.starts_with("https://") => return Err("HTTPS is not supported".to_string()),
+        None => return Err(format!("Invalid HTTP URL '{}'", url)),
+    };
+    let (host, path) = match rest.find('/') {
+        Some(slash) => rest.split_at(slash),
+        None => (rest, "/"),
+    };
+    if host.is_empty() {
+        return Err(format!("Invalid HTTP URL '{}'", url));
+    }
+    Ok((host, path))
+}
+
+fn parse_response(response: &[u8]) -> Option<Value> {
+    let end = response.windows(4).position(|w| w == b"\r\n\r\n")?;
+    let head = std::str::from_utf8(&response[..end]).ok()?;
+    let body = &response[end + 4..];
+    let mut lines = head.split("\r\n");
+    let status: u16 = lines.next()?.split(' ').nth(1)?.parse().ok()?;
+
+    let mut fields = Vec::new();
+    for line in lines {
+        let (name, value) = line.split_once(':')?;
+        fields.push((name.trim().to_ascii_lowercase(), value.trim()));
+    }
+
+    // The body is chunked if that is the last transfer coding, and then any
+    // Content-Length is ignored, as RFC 9112 requires. Otherwise every
+    // Content-Length must agree.
+    let last_coding = fields
+        .iter()
+        .filter(|(name, _)| name == "transfer-encoding")
+        .flat_map(|(_, value)| value.split(','))
+        .map(str::trim)
+        .rfind(|coding| !coding.is_empty());
+    let body = if last_coding.is_some_and(|coding| coding.eq_ignore_ascii_case("chunked")) {
+        dechunk(body)?
+    } else {
+        let mut lengths = fields
+            .iter()
+            .filter(|(name, _)| name == "content-length")
+            .flat_map(|(_, value)| value.split(','))
+            .map(|length| length.trim().parse::<usize>());
+        match lengths.next() {
+            Some(first) => {
+                let length = first.ok()?;
+                if !lengths.all(|other| other == Ok(length)) {
+                    return None;
+                }
+                body.get(..length)?.to_vec()
+            }
+            None => body.to_vec(),
+        }
+    };
+
+    let headers = fields
+        .into_iter()
+        .map(|(name, value)| {
+            Value::Array(Rc::new(vec![
+                Value::Str(name.into()),
+                Value::Str(value.into()),
+            ]))
+        })
+        .collect();
+    let body = match String::from_utf8(body) {
+        Ok(s) => Value::Str(s.into()),
+        Err(e) => Value::Bytes(e.into_bytes().into()),
+    };
+    Some(Value::Tuple(Rc::from([
+        Value::Number(status.into()),
+        Value::Array(Rc::new(headers)),
+        body,
+    ])))
+}
+
+fn dechunk(mut body: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    loop {
+        let end = body.windows(2).position(|w| w == b"\r\n")?;
+        let size = std::str::from_utf8(&body[..end]).ok()?;
+        let size = size.split(';').next()?.trim();
+        let size = usize::from_str_radix(size, 16).ok()?;
+        body = &body[end + 2..];
+        if size == 0 {
+            return Some(out);
+        }
+        out.extend(body.get(..size)?);
+        body = body.get(size + 2..)?;
+    }
+}
@@ -0,0 +1,134 @@
+use crate::bytecode::Value;
+use crate::vm::VirtualMachine;
+
+use super::NativeFunction;
+
+pub(super) const FUNCTIONS: &[NativeFunction] = &[
+    NativeFunction {
+        name: "sqrt",
+        arity: 1,
+        function: sqrt,
+        capability: None,
+    },
+    NativeFunction {
+        name: "pow",
+        arity: 2,
+        function: pow,
+        capability: None,
+    },
+    NativeFunction {
+        name: "sin",
+        arity: 1,
+        function: sin,
+        capability: None,
+    },
+    NativeFunction {
+        name: "cos",
+        arity: 1,
+        function: cos,
+        capability: None,
+    },
+    NativeFunction {
+        name: "floor",
+        arity: 1,
+        function: floor,
+        capability: None,
+    },
+    NativeFunction {
+        name: "abs",
+        arity: 1,
+        function: abs,
+        capability: None,
+    },
+    NativeFunction {
+        name: "min",
+        arity: 2,
+        function: min,
+        capability: None,
+    },
+    NativeFunction {
+        name: "max",
+        arity: 2,
+        function: max,
+        capability: None,
+    },
+];
+
+// Natives whose results can differ between platforms, unless the
+// deterministic-float feature provides portable versions.
+pub(super) const PLATFORM_DEPENDENT: &[&str] = &["pow", "sin", "cos", "min", "max"];
+
+fn number(value: &Value, name: &str) -> f64 {
+    match value {
+        Value::Number(n) => *n,
+        _ => runtime_error!("Invalid operand type for {}.", name),
+    }
+}
+
+fn sqrt(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    Value::Number(number(&args[0], "sqrt").sqrt())
+}
+
+// The platform's `pow`, `sin`, and `cos` may round differently, so
+// deterministic float mode uses the portable implementations from `libm`.
+// Operations that IEEE 754 requires to be exact, like `sqrt` and `floor`,
+// give the same result everywhere already.
+fn pow(vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let (x, y) = (number(&args[0], "pow"), number(&args[1], "pow"));
+    #[cfg(feature = "deterministic-float")]
+    if vm.is_float_deterministic() {
+        return Value::Number(libm::pow(x, y));
+    }
+    let _ = vm;
+    Value::Number(x.powf(y))
+}
+
+fn sin(vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let x = number(&args[0], "sin");
+    #[cfg(feature = "deterministic-float")]
+    if vm.is_float_deterministic() {
+        return Value::Number(libm::sin(x));
+    }
+    let _ = vm;
+    Value::Number(x.sin())
+}
+
+fn cos(vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let x = number(&args[0], "cos");
+    #[cfg(feature = "deterministic-float")]
+    if vm.is_float_deterministic() {
+        return Value::Number(libm::cos(x));
+    }
+    let _ = vm;
+    Value::Number(x.cos())
+}
+
+fn floor(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    Value::Number(number(&args[0], "floor").floor())
+}
+
+fn abs(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    Value::Number(number(&args[0], "abs").abs())
+}
+
+// Which zero `f64::min` and `f64::max` return for `min(-0, 0)` depends on
+// the platform, so deterministic float mode uses `libm` for these too.
+fn min(vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let (x, y) = (number(&args[0], "min"), number(&args[1], "min"));
+    #[cfg(feature = "deterministic-float")]
+    if vm.is_float_deterministic() {
+        return Value::Number(libm::fmin(x, y));
+    }
+    let _ = vm;
+    Value::Number(x.min(y))
+}
+
+fn max(vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let (x, y) = (number(&args[0], "max"), number(&args[1], "max"));
+    #[cfg(feature = "deterministic-float")]
+    if vm.is_float_deterministic() {
+        return Value::Number(libm::fmax(x, y));
+    }
+    let _ = vm;
+    Value::Number(x.max(y))
+}
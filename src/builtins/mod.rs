@@ -0,0 +1,131 @@
+use crate::bytecode::Value;
+use crate::vm::VirtualMachine;
+
+pub(crate) mod array;
+mod encoding;
+mod env;
+pub(crate) mod hash;
+#[cfg(feature = "http")]
+mod http;
+mod math;
+mod net;
+mod process;
+mod reflect;
+#[cfg(feature = "regex")]
+mod regex;
+pub(crate) mod set;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod storage;
+mod string;
+mod time;
+mod weak;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Capability {
+    Env,
+    Exec,
+    Net,
+    Sleep,
+    Sqlite,
+    Time,
+}
+
+impl Capability {
+    pub fn from_name(name: &str) -> Option<Capability> {
+        match name {
+            "env" => Some(Capability::Env),
+            "exec" => Some(Capability::Exec),
+            "net" => Some(Capability::Net),
+            "sleep" => Some(Capability::Sleep),
+            "sqlite" => Some(Capability::Sqlite),
+            "time" => Some(Capability::Time),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Capability::Env => "env",
+            Capability::Exec => "exec",
+            Capability::Net => "net",
+            Capability::Sleep => "sleep",
+            Capability::Sqlite => "sqlite",
+            Capability::Time => "time",
+        }
+    }
+}
+
+pub(crate) struct NativeFunction {
+    pub(crate) name: &'static str,
+    pub(crate) arity: usize,
+    pub(crate) function: fn(&mut VirtualMachine, &[Value]) -> Value,
+    pub(crate) capability: Option<Capability>,
+}
+
+impl NativeFunction {
+    pub(crate) fn call(&self, vm: &mut VirtualMachine, args: &[Value]) -> Value {
+        (self.function)(vm, args)
+    }
+
+    // Whether the native gives the same result for the same arguments on any
+    // host. `now` and `sleep` count as deterministic because deterministic
+    // mode replaces the clock. The `kv_*` natives don't, because the store is
+    // the host's and outlives a run.
+    pub(crate) fn is_deterministic(&self) -> bool {
+        if storage::FUNCTIONS
+            .iter()
+            .any(|native| native.name == self.name)
+        {
+            return false;
+        }
+        match self.capability {
+            Some(Capability::Env | Capability::Exec | Capability::Net | Capability::Sqlite) => {
+                false
+            }
+            _ => {
+                cfg!(feature = "deterministic-float")
+                    || !math::PLATFORM_DEPENDENT.contains(&self.name)
+            }
+        }
+    }
+}
+
+// Natives that take or return function indexes, which a program can only
+// keep working with if its functions keep their numbers.
+pub(crate) const FUNCTION_INDEX_NATIVES: &[&str] = &[
+    "sort_by",
+    "function_index",
+    "function_count",
+    "function_arity",
+    "backtrace",
+];
+
+const LIBRARIES: &[&[NativeFunction]] = &[
+    math::FUNCTIONS,
+    string::FUNCTIONS,
+    array::FUNCTIONS,
+    set::FUNCTIONS,
+    env::FUNCTIONS,
+    process::FUNCTIONS,
+    net::FUNCTIONS,
+    storage::FUNCTIONS,
+    #[cfg(feature = "sqlite")]
+    sqlite::FUNCTIONS,
+    #[cfg(feature = "http")]
+    http::FUNCTIONS,
+    encoding::FUNCTIONS,
+    time::FUNCTIONS,
+    hash::FUNCTIONS,
+    weak::FUNCTIONS,
+    reflect::FUNCTIONS,
+    #[cfg(feature = "regex")]
+    regex::FUNCTIONS,
+];
+
+pub(crate) fn lookup(name: &str) -> Option<&'static NativeFunction> {
+    LIBRARIES
+        .iter()
+        .flat_map(|library| library.iter())
+        .find(|native| native.name == name)
+}
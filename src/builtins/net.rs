@@ -0,0 +1,265 @@
+use std::cell::Cell;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::bytecode::{Socket, Value};
+use crate::vm::VirtualMachine;
+
+use super::{Capability, NativeFunction};
+
+pub(super) const FUNCTIONS: &[NativeFunction] = &[
+    NativeFunction {
+        name: "tcp_connect",
+        arity: 1,
+        function: tcp_connect,
+        capability: Some(Capability::Net),
+    },
+    NativeFunction {
+        name: "tcp_listen",
+        arity: 1,
+        function: tcp_listen,
+        capability: Some(Capability::Net),
+    },
+    NativeFunction {
+        name: "tcp_accept",
+        arity: 1,
+        function: tcp_accept,
+        capability: Some(Capability::Net),
+    },
+    NativeFunction {
+        name: "tcp_read",
+        arity: 2,
+        function: tcp_read,
+        capability: Some(Capability::Net),
+    },
+    NativeFunction {
+        name: "tcp_write",
+        arity: 2,
+        function: tcp_write,
+        capability: Some(Capability::Net),
+    },
+    NativeFunction {
+        name: "tcp_close",
+        arity: 1,
+        function: tcp_close,
+        capability: Some(Capability::Net),
+    },
+    NativeFunction {
+        name: "tcp_local_address",
+        arity: 1,
+        function: tcp_local_address,
+        capability: Some(Capability::Net),
+    },
+    NativeFunction {
+        name: "tcp_set_timeout",
+        arity: 2,
+        function: tcp_set_timeout,
+        capability: Some(Capability::Net),
+    },
+];
+
+// How long `tcp_connect` waits for a connection, how long `tcp_accept` waits
+// on a listener, and how long `tcp_read` and `tcp_write` wait on a
+// connection, until `tcp_set_timeout` says otherwise. Waiting executes no
+// instructions, so the step limit can't end it, and these bound every wait.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const ACCEPT_TIMEOUT: Duration = Duration::from_secs(30);
+const STREAM_TIMEOUT: Duration = Duration::from_secs(30);
+
+// How often a waiting `tcp_accept` checks for a connection. The standard
+// library has no accept with a timeout, so the listener is nonblocking and
+// polled until its deadline instead.
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(5);
+
+// The most `tcp_read` reads at once, whatever the program asks for, so a
+// large count cannot make it allocate a large buffer.
+const MAX_READ: usize = 64 * 1024;
+
+// Failures the guest can expect, such as a refused connection or a timeout,
+// are returned as error values rather than halting.
+fn io_error(e: io::Error) -> Value {
+    let message = match e.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => "Timed out".to_string(),
+        _ => e.to_string(),
+    };
+    Value::Error(Rc::new(Value::Str(message.into())))
+}
+
+fn address<'a>(args: &'a [Value], name: &str) -> &'a str {
+    match &args[0] {
+        Value::Str(s) => s,
+        _ => runtime_error!("Invalid operand type for {}.", name),
+    }
+}
+
+fn socket<'a>(args: &'a [Value], name: &str) -> &'a Socket {
+    match &args[0] {
+        Value::Socket(socket) => socket,
+        _ => runtime_error!("Invalid operand type for {}.", name),
+    }
+}
+
+fn stream<'a>(args: &'a [Value], name: &str) -> &'a TcpStream {
+    match socket(args, name) {
+        Socket::Stream(stream) => stream,
+        Socket::Listener(..) => runtime_error!("Invalid operand type for {}.", name),
+    }
+}
+
+// Tries each address the name resolves to in turn, waiting at most
+// `CONNECT_TIMEOUT` for each.
+pub(super) fn connect(address: &str) -> io::Result<TcpStream> {
+    let mut last = None;
+    for address in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, CONNECT_TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last = Some(e),
+        }
+    }
+    Err(last
+        .unwrap_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "address did not resolve")))
+}
+
+// A connection from `tcp_connect` or `tcp_accept`, blocking and with the
+// default timeouts.
+fn stream_value(stream: TcpStream) -> Value {
+    let configured = stream
+        .set_nonblocking(false)
+        .and_then(|()| stream.set_read_timeout(Some(STREAM_TIMEOUT)))
+        .and_then(|()| stream.set_write_timeout(Some(STREAM_TIMEOUT)));
+    match configured {
+        Ok(()) => Value::Socket(Rc::new(Socket::Stream(stream))),
+        Err(e) => io_error(e),
+    }
+}
+
+fn tcp_connect(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    match connect(address(args, "tcp_connect")) {
+        Ok(stream) => stream_value(stream),
+        Err(e) => io_error(e),
+    }
+}
+
+// Listeners are nonblocking so that `tcp_accept` can give up after its
+// timeout.
+fn tcp_listen(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let listener = TcpListener::bind(address(args, "tcp_listen"))
+        .and_then(|listener| listener.set_nonblocking(true).map(|()| listener));
+    match listener {
+        Ok(listener) => {
+            let timeout = Cell::new(Some(ACCEPT_TIMEOUT));
+            Value::Socket(Rc::new(Socket::Listener(listener, timeout)))
+        }
+        Err(e) => io_error(e),
+    }
+}
+
+fn tcp_accept(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let (listener, timeout) = match socket(args, "tcp_accept") {
+        Socket::Listener(listener, timeout) => (listener, timeout.get()),
+        Socket::Stream(_) => runtime_error!("Invalid operand type for tcp_accept."),
+    };
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    loop {
+        match listener.accept() {
+            // Some platforms pass the listener's nonblocking mode on to the
+            // connections it accepts, which `stream_value` undoes.
+            Ok((stream, _)) => return stream_value(stream),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    return io_error(e);
+                }
+                thread::sleep(ACCEPT_INTERVAL);
+            }
+            Err(e) => return io_error(e),
+        }
+    }
+}
+
+// Reads whatever has arrived, up to the given number of bytes or `MAX_READ`,
+// waiting for at least one. Empty bytes mean the peer closed the connection.
+fn tcp_read(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let mut stream = stream(args, "tcp_read");
+    let max = match &args[1] {
+        Value::Number(n) if *n >= 0.0 && n.fract() == 0.0 => *n as usize,
+        _ => runtime_error!("Invalid operand type for tcp_read."),
+    };
+    let mut buffer = vec![0; max.min(MAX_READ)];
+    match stream.read(&mut buffer) {
+        Ok(count) => {
+            buffer.truncate(count);
+            Value::Bytes(buffer.into())
+        }
+        Err(e) => io_error(e),
+    }
+}
+
+// Writes all of a string or byte string, returning nil.
+fn tcp_write(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let mut stream = stream(args, "tcp_write");
+    let result = match &args[1] {
+        Value::Str(s) => stream.write_all(s.as_bytes()),
+        Value::Bytes(bytes) => stream.write_all(bytes),
+        _ => runtime_error!("Invalid operand type for tcp_write."),
+    };
+    match result {
+        Ok(()) => Value::Nil,
+        Err(e) => io_error(e),
+    }
+}
+
+// Shuts a connection down in both directions, so the peer reads the end of
+// the stream. Listeners close when they are dropped.
+fn tcp_close(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    match stream(args, "tcp_close").shutdown(std::net::Shutdown::Both) {
+        Ok(()) => Value::Nil,
+        Err(e) if e.kind() == io::ErrorKind::NotConnected => Value::Nil,
+        Err(e) => io_error(e),
+    }
+}
+
+// The address a socket is bound to, which tells a program listening on port
+// 0 the port it was given.
+fn tcp_local_address(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let address = match socket(args, "tcp_local_address") {
+        Socket::Listener(listener, _) => listener.local_addr(),
+        Socket::Stream(stream) => stream.local_addr(),
+    };
+    match address {
+        Ok(address) => Value::Str(address.to_string().into()),
+        Err(e) => io_error(e),
+    }
+}
+
+// Limits how long `tcp_read` and `tcp_write` wait on a connection, or
+// `tcp_accept` on a listener, before returning a timeout error. Nil waits
+// forever.
+fn tcp_set_timeout(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let timeout = match &args[1] {
+        Value::Nil => None,
+        Value::Duration(nanos) if *nanos > 0 => Some(Duration::from_nanos(*nanos as u64)),
+        Value::Number(seconds) if *seconds > 0.0 => Some(
+            Duration::try_from_secs_f64(*seconds)
+                .unwrap_or_else(|_| runtime_error!("Timeout is too long.")),
+        ),
+        Value::Duration(_) | Value::Number(_) => runtime_error!("Timeout must be positive."),
+        _ => runtime_error!("Invalid operand type for tcp_set_timeout."),
+    };
+    let stream = match socket(args, "tcp_set_timeout") {
+        Socket::Listener(_, listener_timeout) => {
+            listener_timeout.set(timeout);
+            return Value::Nil;
+        }
+        Socket::Stream(stream) => stream,
+    };
+    match stream
+        .set_read_timeout(timeout)
+        .and_then(|()| stream.set_write_timeout(timeout))
+    {
+        Ok(()) => Value::Nil,
+        Err(e) => io_error(e),
+    }
+}
@@ -0,0 +1,54 @@
+use std::process::Command;
+use std::rc::Rc;
+
+use crate::bytecode::Value;
+use crate::vm::VirtualMachine;
+
+use super::{Capability, NativeFunction};
+
+pub(super) const FUNCTIONS: &[NativeFunction] = &[NativeFunction {
+    name: "exec",
+    arity: 2,
+    function: exec,
+    capability: Some(Capability::Exec),
+}];
+
+// Runs an allowed executable to completion and returns a
+// `(status, stdout, stderr)` tuple. The status is nil if the process was
+// killed by a signal. Returns an error value rather than halting when the
+// process cannot be started.
+fn exec(vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let program = match &args[0] {
+        Value::Str(s) => s,
+        _ => runtime_error!("Invalid operand type for exec."),
+    };
+    let arguments = match &args[1] {
+        Value::Array(elements) => elements
+            .iter()
+            .map(|element| match element {
+                Value::Str(s) => s.to_string(),
+                _ => runtime_error!("Invalid operand type for exec."),
+            })
+            .collect::<Vec<_>>(),
+        _ => runtime_error!("Invalid operand type for exec."),
+    };
+    if !vm.is_executable_allowed(program) {
+        runtime_error!("Executable '{}' is not allowed.", program);
+    }
+    let output = match Command::new(&**program).args(&arguments).output() {
+        Ok(output) => output,
+        Err(e) => {
+            let message = format!("Cannot run '{}': {}", program, e);
+            return Value::Error(Rc::new(Value::Str(message.into())));
+        }
+    };
+    let status = match output.status.code() {
+        Some(code) => Value::Number(code.into()),
+        None => Value::Nil,
+    };
+    Value::Tuple(Rc::from([
+        status,
+        Value::Str(String::from_utf8_lossy(&output.stdout).into_owned().into()),
+        Value::Str(String::from_utf8_lossy(&output.stderr).into_owned().into()),
+    ]))
+}
@@ -0,0 +1,80 @@
+use std::rc::Rc;
+
+use crate::bytecode::Value;
+use crate::vm::VirtualMachine;
+
+use super::NativeFunction;
+
+pub(super) const FUNCTIONS: &[NativeFunction] = &[
+    NativeFunction {
+        name: "function_index",
+        arity: 0,
+        function: function_index,
+        capability: None,
+    },
+    NativeFunction {
+        name: "call_depth",
+        arity: 0,
+        function: call_depth,
+        capability: None,
+    },
+    NativeFunction {
+        name: "function_count",
+        arity: 0,
+        function: function_count,
+        capability: None,
+    },
+    NativeFunction {
+        name: "function_arity",
+        arity: 1,
+        function: function_arity,
+        capability: None,
+    },
+    NativeFunction {
+        name: "backtrace",
+        arity: 0,
+        function: backtrace,
+        capability: None,
+    },
+];
+
+fn function_index(vm: &mut VirtualMachine, _args: &[Value]) -> Value {
+    Value::Number(vm.function_index() as f64)
+}
+
+fn call_depth(vm: &mut VirtualMachine, _args: &[Value]) -> Value {
+    Value::Number(vm.call_depth() as f64)
+}
+
+fn function_count(vm: &mut VirtualMachine, _args: &[Value]) -> Value {
+    Value::Number(vm.current_bytecode().functions().len() as f64)
+}
+
+fn function_arity(vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let index = match args[0] {
+        Value::Number(n) if n >= 0.0 && n.fract() == 0.0 => n as usize,
+        _ => runtime_error!("Invalid operand type for function_arity."),
+    };
+    match vm.current_bytecode().functions().get(index) {
+        Some(function) => Value::Number(function.num_args as f64),
+        None => runtime_error!("Invalid function index {} for function_arity.", index),
+    }
+}
+
+// Each frame is a tuple of the function index, the instruction pointer, and
+// the module name, or nil for the program. Bytecode has no function names or
+// source lines to report.
+fn backtrace(vm: &mut VirtualMachine, _args: &[Value]) -> Value {
+    let frames = vm
+        .backtrace()
+        .into_iter()
+        .map(|(function, ip, module)| {
+            Value::Tuple(Rc::new([
+                Value::Number(function as f64),
+                Value::Number(ip as f64),
+                module.map_or(Value::Nil, Value::Str),
+            ]))
+        })
+        .collect();
+    Value::Array(Rc::new(frames))
+}
@@ -0,0 +1,81 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use regex::Regex;
+
+use crate::bytecode::Value;
+use crate::vm::VirtualMachine;
+
+use super::NativeFunction;
+
+pub(super) const FUNCTIONS: &[NativeFunction] = &[
+    NativeFunction {
+        name: "regex_match",
+        arity: 2,
+        function: regex_match,
+        capability: None,
+    },
+    NativeFunction {
+        name: "regex_find_all",
+        arity: 2,
+        function: regex_find_all,
+        capability: None,
+    },
+    NativeFunction {
+        name: "regex_replace",
+        arity: 3,
+        function: regex_replace,
+        capability: None,
+    },
+];
+
+thread_local! {
+    static CACHE: RefCell<HashMap<String, Regex>> = RefCell::new(HashMap::new());
+}
+
+fn string<'v>(value: &'v Value, name: &str) -> &'v str {
+    match value {
+        Value::Str(s) => s,
+        _ => runtime_error!("Invalid operand type for {}.", name),
+    }
+}
+
+fn with_regex<T>(pattern: &str, f: impl FnOnce(&Regex) -> T) -> T {
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if !cache.contains_key(pattern) {
+            let regex = Regex::new(pattern)
+                .unwrap_or_else(|e| runtime_error!("Invalid regular expression: {}", e));
+            cache.insert(pattern.to_string(), regex);
+        }
+        f(&cache[pattern])
+    })
+}
+
+fn regex_match(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let s = string(&args[0], "regex_match");
+    let pattern = string(&args[1], "regex_match");
+    Value::Boolean(with_regex(pattern, |regex| regex.is_match(s)))
+}
+
+fn regex_find_all(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let s = string(&args[0], "regex_find_all");
+    let pattern = string(&args[1], "regex_find_all");
+    let matches = with_regex(pattern, |regex| {
+        regex
+            .find_iter(s)
+            .map(|m| Value::Str(m.as_str().into()))
+            .collect()
+    });
+    Value::Array(Rc::new(matches))
+}
+
+fn regex_replace(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let s = string(&args[0], "regex_replace");
+    let pattern = string(&args[1], "regex_replace");
+    let replacement = string(&args[2], "regex_replace");
+    Value::Str(with_regex(pattern, |regex| {
+        regex.replace_all(s, replacement).into()
+    }))
+}
@@ -0,0 +1,171 @@
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use crate::bytecode::Value;
+use crate::vm::VirtualMachine;
+
+use super::NativeFunction;
+
+pub(super) const FUNCTIONS: &[NativeFunction] = &[
+    NativeFunction {
+        name: "set",
+        arity: 1,
+        function: from_array,
+        capability: None,
+    },
+    NativeFunction {
+        name: "set_elements",
+        arity: 1,
+        function: elements,
+        capability: None,
+    },
+    NativeFunction {
+        name: "set_union",
+        arity: 2,
+        function: union,
+        capability: None,
+    },
+    NativeFunction {
+        name: "set_intersection",
+        arity: 2,
+        function: intersection,
+        capability: None,
+    },
+];
+
+fn set<'v>(value: &'v Value, name: &str) -> &'v [Value] {
+    match value {
+        Value::Set(elements) => elements,
+        _ => runtime_error!("Invalid operand type for {}.", name),
+    }
+}
+
+// Where a kind of value sorts among the others in a set. Kinds without a
+// rank, and NaN, can't be set elements.
+fn rank(value: &Value) -> Option<u8> {
+    match value {
+        Value::Nil => Some(0),
+        Value::Boolean(_) => Some(1),
+        Value::Number(_) => Some(2),
+        #[cfg(feature = "bigint")]
+        Value::BigInt(_) => Some(3),
+        #[cfg(feature = "decimal")]
+        Value::Decimal(_) => Some(4),
+        Value::Char(_) => Some(5),
+        Value::Str(_) => Some(6),
+        Value::Bytes(_) => Some(7),
+        Value::Array(_) => Some(8),
+        Value::Tuple(_) => Some(9),
+        Value::Timestamp(_) => Some(10),
+        Value::Duration(_) => Some(11),
+        _ => None,
+    }
+}
+
+// Sets keep their elements in this order: by kind, then by value.
+fn order(a: &Value, b: &Value) -> Ordering {
+    match (rank(a), rank(b)) {
+        (Some(x), Some(y)) if x != y => x.cmp(&y),
+        (Some(0), Some(0)) => Ordering::Equal,
+        (Some(_), Some(_)) => a
+            .compare(b)
+            .unwrap_or_else(|| runtime_error!("Invalid set element.")),
+        _ => runtime_error!("Invalid set element."),
+    }
+}
+
+fn from_array(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let mut elements = match &args[0] {
+        Value::Array(elements) => elements.to_vec(),
+        _ => runtime_error!("Invalid operand type for set."),
+    };
+    elements.sort_by(order);
+    elements.dedup_by(|a, b| order(a, b) == Ordering::Equal);
+    Value::Set(Rc::new(elements))
+}
+
+fn elements(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    Value::Array(Rc::new(set(&args[0], "set_elements").to_vec()))
+}
+
+fn union(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let (a, b) = (set(&args[0], "set_union"), set(&args[1], "set_union"));
+    let mut elements = Vec::with_capacity(a.len() + b.len());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        match order(&a[i], &b[j]) {
+            Ordering::Less => {
+                elements.push(a[i].clone());
+                i += 1;
+            }
+            Ordering::Greater => {
+                elements.push(b[j].clone());
+                j += 1;
+            }
+            Ordering::Equal => {
+                elements.push(a[i].clone());
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    elements.extend_from_slice(&a[i..]);
+    elements.extend_from_slice(&b[j..]);
+    Value::Set(Rc::new(elements))
+}
+
+fn intersection(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let (a, b) = (
+        set(&args[0], "set_intersection"),
+        set(&args[1], "set_intersection"),
+    );
+    let mut elements = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        match order(&a[i], &b[j]) {
+            Ordering::Less => i += 1,
+            Ordering::Greater => j += 1,
+            Ordering::Equal => {
+                elements.push(a[i].clone());
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    Value::Set(Rc::new(elements))
+}
+
+// The operations behind the set instructions, shared with the transpiled
+// runtime. Like arrays, a set is only copied when another value still refers
+// to it.
+
+pub(crate) fn add(set: Value, value: Value) -> Value {
+    match set {
+        Value::Set(mut elements) => {
+            if let Err(index) = elements.binary_search_by(|e| order(e, &value)) {
+                Rc::make_mut(&mut elements).insert(index, value);
+            }
+            Value::Set(elements)
+        }
+        _ => runtime_error!("Invalid operand type for set add."),
+    }
+}
+
+pub(crate) fn contains(set: &Value, value: &Value) -> bool {
+    match set {
+        Value::Set(elements) => elements.binary_search_by(|e| order(e, value)).is_ok(),
+        _ => runtime_error!("Invalid operand type for set contains."),
+    }
+}
+
+pub(crate) fn remove(set: Value, value: Value) -> Value {
+    match set {
+        Value::Set(mut elements) => {
+            if let Ok(index) = elements.binary_search_by(|e| order(e, &value)) {
+                Rc::make_mut(&mut elements).remove(index);
+            }
+            Value::Set(elements)
+        }
+        _ => runtime_error!("Invalid operand type for set remove."),
+    }
+}
@@ -0,0 +1,141 @@
+use std::rc::Rc;
+
+use rusqlite::types::{Value as SqlValue, ValueRef};
+use rusqlite::{params_from_iter, Connection};
+
+use crate::bytecode::Value;
+use crate::text::Text;
+use crate::vm::VirtualMachine;
+
+use super::{Capability, NativeFunction};
+
+pub(super) const FUNCTIONS: &[NativeFunction] = &[
+    NativeFunction {
+        name: "db_open",
+        arity: 1,
+        function: db_open,
+        capability: Some(Capability::Sqlite),
+    },
+    NativeFunction {
+        name: "db_execute",
+        arity: 3,
+        function: db_execute,
+        capability: Some(Capability::Sqlite),
+    },
+    NativeFunction {
+        name: "db_query",
+        arity: 3,
+        function: db_query,
+        capability: Some(Capability::Sqlite),
+    },
+];
+
+// Failures in the database, such as a syntax error or a violated constraint,
+// are returned as error values rather than halting.
+fn failure(e: rusqlite::Error) -> Value {
+    Value::Error(Rc::new(Value::Str(e.to_string().into())))
+}
+
+fn db_open(vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let path = match &args[0] {
+        Value::Str(s) => s,
+        _ => runtime_error!("Invalid operand type for db_open."),
+    };
+    if !vm.is_database_allowed(path) {
+        runtime_error!("Database '{}' is not allowed.", path);
+    }
+    match Connection::open(&**path) {
+        Ok(connection) => Value::Database(Rc::new(connection)),
+        Err(e) => failure(e),
+    }
+}
+
+fn connection<'a>(args: &'a [Value], name: &str) -> &'a Connection {
+    match &args[0] {
+        Value::Database(connection) => connection,
+        _ => runtime_error!("Invalid operand type for {}.", name),
+    }
+}
+
+// Binds an array or tuple of values to the statement's `?` parameters. Integral
+// numbers are bound as integers and booleans as 0 or 1.
+fn parameters(args: &[Value], name: &str) -> Vec<SqlValue> {
+    let values: &[Value] = match &args[2] {
+        Value::Array(values) => values,
+        Value::Tuple(values) => values,
+        _ => runtime_error!("Invalid operand type for {}.", name),
+    };
+    values
+        .iter()
+        .map(|value| match value {
+            Value::Nil => SqlValue::Null,
+            Value::Number(n) if n.fract() == 0.0 && n.abs() < 2f64.powi(63) => {
+                SqlValue::Integer(*n as i64)
+            }
+            Value::Number(n) => SqlValue::Real(*n),
+            Value::Boolean(b) => SqlValue::Integer(*b as i64),
+            Value::Str(s) => SqlValue::Text(s.to_string()),
+            Value::Bytes(bytes) => SqlValue::Blob(bytes.to_vec()),
+            _ => runtime_error!("Invalid operand type for {}.", name),
+        })
+        .collect()
+}
+
+fn sql<'a>(args: &'a [Value], name: &str) -> &'a str {
+    match &args[1] {
+        Value::Str(s) => s,
+        _ => runtime_error!("Invalid operand type for {}.", name),
+    }
+}
+
+// Runs a statement that returns no rows, returning how many rows it changed.
+fn db_execute(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let connection = connection(args, "db_execute");
+    let params = parameters(args, "db_execute");
+    match connection.execute(sql(args, "db_execute"), params_from_iter(params)) {
+        Ok(changed) => Value::Number(changed as f64),
+        Err(e) => failure(e),
+    }
+}
+
+// Returns an array with one entry per row, each an array of
+// `[column, value]` pairs in column order.
+fn db_query(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let connection = connection(args, "db_query");
+    let params = parameters(args, "db_query");
+    let result = connection
+        .prepare(sql(args, "db_query"))
+        .and_then(|mut statement| {
+            let columns: Vec<Text> = statement
+                .column_names()
+                .into_iter()
+                .map(Text::from)
+                .collect();
+            let mut rows = statement.query(params_from_iter(params))?;
+            let mut out = Vec::new();
+            while let Some(row) = rows.next()? {
+                let mut fields = Vec::with_capacity(columns.len());
+                for (index, column) in columns.iter().enumerate() {
+                    let value = match row.get_ref(index)? {
+                        ValueRef::Null => Value::Nil,
+                        ValueRef::Integer(n) => Value::Number(n as f64),
+                        ValueRef::Real(n) => Value::Number(n),
+                        ValueRef::Text(text) => {
+                            Value::Str(String::from_utf8_lossy(text).into_owned().into())
+                        }
+                        ValueRef::Blob(bytes) => Value::Bytes(bytes.into()),
+                    };
+                    fields.push(Value::Array(Rc::new(vec![
+                        Value::Str(column.clone()),
+                        value,
+                    ])));
+                }
+                out.push(Value::Array(Rc::new(fields)));
+            }
+            Ok(out)
+        });
+    match result {
+        Ok(rows) => Value::Array(Rc::new(rows)),
+        Err(e) => failure(e),
+    }
+}
@@ -0,0 +1,65 @@
+use std::rc::Rc;
+
+use crate::bytecode::Value;
+use crate::vm::VirtualMachine;
+
+use super::NativeFunction;
+
+pub(super) const FUNCTIONS: &[NativeFunction] = &[
+    NativeFunction {
+        name: "kv_get",
+        arity: 1,
+        function: kv_get,
+        capability: None,
+    },
+    NativeFunction {
+        name: "kv_set",
+        arity: 2,
+        function: kv_set,
+        capability: None,
+    },
+    NativeFunction {
+        name: "kv_delete",
+        arity: 1,
+        function: kv_delete,
+        capability: None,
+    },
+    NativeFunction {
+        name: "kv_keys",
+        arity: 0,
+        function: kv_keys,
+        capability: None,
+    },
+];
+
+fn key<'a>(args: &'a [Value], name: &str) -> &'a str {
+    match &args[0] {
+        Value::Str(s) => s,
+        _ => runtime_error!("Invalid operand type for {}.", name),
+    }
+}
+
+fn kv_get(vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let key = key(args, "kv_get");
+    vm.storage().get(key).unwrap_or(Value::Nil)
+}
+
+fn kv_set(vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let key = key(args, "kv_set");
+    vm.storage().set(key, args[1].clone());
+    Value::Nil
+}
+
+fn kv_delete(vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let key = key(args, "kv_delete");
+    Value::Boolean(vm.storage().delete(key))
+}
+
+// Keys are sorted so the order does not depend on the store.
+fn kv_keys(vm: &mut VirtualMachine, _args: &[Value]) -> Value {
+    let mut keys = vm.storage().keys();
+    keys.sort();
+    Value::Array(Rc::new(
+        keys.into_iter().map(|key| Value::Str(key.into())).collect(),
+    ))
+}
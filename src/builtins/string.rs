@@ -0,0 +1,218 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::bytecode::Value;
+use crate::vm::VirtualMachine;
+
+use super::NativeFunction;
+
+pub(super) const FUNCTIONS: &[NativeFunction] = &[
+    NativeFunction {
+        name: "length",
+        arity: 1,
+        function: length,
+        capability: None,
+    },
+    NativeFunction {
+        name: "upper",
+        arity: 1,
+        function: upper,
+        capability: None,
+    },
+    NativeFunction {
+        name: "lower",
+        arity: 1,
+        function: lower,
+        capability: None,
+    },
+    NativeFunction {
+        name: "trim",
+        arity: 1,
+        function: trim,
+        capability: None,
+    },
+    NativeFunction {
+        name: "split",
+        arity: 2,
+        function: split,
+        capability: None,
+    },
+    NativeFunction {
+        name: "find",
+        arity: 2,
+        function: find,
+        capability: None,
+    },
+    NativeFunction {
+        name: "replace",
+        arity: 3,
+        function: replace,
+        capability: None,
+    },
+    NativeFunction {
+        name: "builder_new",
+        arity: 0,
+        function: builder_new,
+        capability: None,
+    },
+    NativeFunction {
+        name: "builder_append",
+        arity: 2,
+        function: builder_append,
+        capability: None,
+    },
+    NativeFunction {
+        name: "builder_finish",
+        arity: 1,
+        function: builder_finish,
+        capability: None,
+    },
+    NativeFunction {
+        name: "chars",
+        arity: 1,
+        function: chars,
+        capability: None,
+    },
+    NativeFunction {
+        name: "char_at",
+        arity: 2,
+        function: char_at,
+        capability: None,
+    },
+    NativeFunction {
+        name: "char_code",
+        arity: 1,
+        function: char_code,
+        capability: None,
+    },
+    NativeFunction {
+        name: "char_from_code",
+        arity: 1,
+        function: char_from_code,
+        capability: None,
+    },
+];
+
+fn string<'v>(value: &'v Value, name: &str) -> &'v str {
+    match value {
+        Value::Str(s) => s,
+        _ => runtime_error!("Invalid operand type for {}.", name),
+    }
+}
+
+fn length(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    match &args[0] {
+        Value::Str(s) => Value::Number(s.chars().count() as f64),
+        Value::Bytes(bytes) => Value::Number(bytes.len() as f64),
+        Value::Array(elements) => Value::Number(elements.len() as f64),
+        Value::Tuple(elements) => Value::Number(elements.len() as f64),
+        Value::Set(elements) => Value::Number(elements.len() as f64),
+        _ => runtime_error!("Invalid operand type for length."),
+    }
+}
+
+fn upper(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    Value::Str(string(&args[0], "upper").to_uppercase().into())
+}
+
+fn lower(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    Value::Str(string(&args[0], "lower").to_lowercase().into())
+}
+
+fn trim(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    Value::Str(string(&args[0], "trim").trim().into())
+}
+
+fn split(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let s = string(&args[0], "split");
+    let separator = string(&args[1], "split");
+    let parts = if separator.is_empty() {
+        s.chars()
+            .map(|c| Value::Str(c.to_string().into()))
+            .collect()
+    } else {
+        s.split(separator)
+            .map(|part| Value::Str(part.into()))
+            .collect()
+    };
+    Value::Array(Rc::new(parts))
+}
+
+// Returns the character index of the first occurrence, or -1 if there is none.
+fn find(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let s = string(&args[0], "find");
+    let needle = string(&args[1], "find");
+    match s.find(needle) {
+        Some(byte_index) => Value::Number(s[..byte_index].chars().count() as f64),
+        None => Value::Number(-1.0),
+    }
+}
+
+fn replace(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let s = string(&args[0], "replace");
+    let from = string(&args[1], "replace");
+    let to = string(&args[2], "replace");
+    Value::Str(s.replace(from, to).into())
+}
+
+fn builder_new(_vm: &mut VirtualMachine, _args: &[Value]) -> Value {
+    Value::StringBuilder(Rc::new(RefCell::new(String::new())))
+}
+
+// Appends in place and returns the same builder so calls can be chained.
+fn builder_append(vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    match &args[0] {
+        Value::StringBuilder(builder) => {
+            let mut builder_ref = builder.borrow_mut();
+            match &args[1] {
+                Value::Str(s) => builder_ref.push_str(s),
+                value => builder_ref.push_str(&value.display(vm.number_format()).to_string()),
+            }
+        }
+        _ => runtime_error!("Invalid operand type for builder_append."),
+    }
+    args[0].clone()
+}
+
+fn builder_finish(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    match &args[0] {
+        Value::StringBuilder(builder) => Value::Str(builder.borrow().as_str().into()),
+        _ => runtime_error!("Invalid operand type for builder_finish."),
+    }
+}
+
+fn chars(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let s = string(&args[0], "chars");
+    Value::Array(Rc::new(s.chars().map(Value::Char).collect()))
+}
+
+// Returns nil when the index is out of range or not a whole number.
+fn char_at(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let s = string(&args[0], "char_at");
+    let index = match &args[1] {
+        Value::Number(n) => *n,
+        _ => runtime_error!("Invalid operand type for char_at."),
+    };
+    if index < 0.0 || index.fract() != 0.0 {
+        return Value::Nil;
+    }
+    match s.chars().nth(index as usize) {
+        Some(c) => Value::Char(c),
+        None => Value::Nil,
+    }
+}
+
+fn char_code(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    match &args[0] {
+        Value::Char(c) => Value::Number(*c as u32 as f64),
+        _ => runtime_error!("Invalid operand type for char_code."),
+    }
+}
+
+fn char_from_code(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let code = match &args[0] {
+        Value::Number(n) if *n >= 0.0 && n.fract() == 0.0 && *n <= u32::MAX as f64 => *n as u32,
+        _ => runtime_error!("Invalid operand type for char_from_code."),
+    };
+    Value::Char(char::from_u32(code).unwrap_or_else(|| runtime_error!("Invalid character code.")))
+}
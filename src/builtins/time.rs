@@ -0,0 +1,114 @@
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::bytecode::Value;
+use crate::datetime;
+use crate::vm::VirtualMachine;
+
+use super::{Capability, NativeFunction};
+
+pub(super) const FUNCTIONS: &[NativeFunction] = &[
+    NativeFunction {
+        name: "now",
+        arity: 0,
+        function: now,
+        capability: Some(Capability::Time),
+    },
+    NativeFunction {
+        name: "sleep",
+        arity: 1,
+        function: sleep,
+        capability: Some(Capability::Sleep),
+    },
+    NativeFunction {
+        name: "time_format",
+        arity: 1,
+        function: time_format,
+        capability: None,
+    },
+    NativeFunction {
+        name: "time_parse",
+        arity: 1,
+        function: time_parse,
+        capability: None,
+    },
+    NativeFunction {
+        name: "duration",
+        arity: 1,
+        function: duration,
+        capability: None,
+    },
+    NativeFunction {
+        name: "duration_seconds",
+        arity: 1,
+        function: duration_seconds,
+        capability: None,
+    },
+];
+
+fn now(vm: &mut VirtualMachine, _args: &[Value]) -> Value {
+    if vm.is_deterministic() {
+        return Value::Timestamp(vm.virtual_clock());
+    }
+    let nanos = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(elapsed) => elapsed.as_nanos() as i64,
+        Err(e) => -(e.duration().as_nanos() as i64),
+    };
+    Value::Timestamp(nanos)
+}
+
+// Accepts seconds as a number or a duration. In deterministic mode nothing
+// waits, and the virtual clock moves forward instead.
+fn sleep(vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let nanos = match &args[0] {
+        Value::Number(seconds) if *seconds >= 0.0 => (seconds * 1e9) as i64,
+        Value::Duration(nanos) if *nanos >= 0 => *nanos,
+        Value::Number(_) | Value::Duration(_) => {
+            runtime_error!("Cannot sleep for a negative time.")
+        }
+        _ => runtime_error!("Invalid operand type for sleep."),
+    };
+    if vm.is_deterministic() {
+        vm.advance_clock(nanos);
+    } else {
+        thread::sleep(Duration::from_nanos(nanos as u64));
+    }
+    Value::Nil
+}
+
+fn time_format(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    match &args[0] {
+        Value::Timestamp(t) => Value::Str(datetime::format_rfc3339(*t).into()),
+        _ => runtime_error!("Invalid operand type for time_format."),
+    }
+}
+
+// Returns an error value rather than halting when the string is malformed.
+fn time_parse(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    let s = match &args[0] {
+        Value::Str(s) => s,
+        _ => runtime_error!("Invalid operand type for time_parse."),
+    };
+    match datetime::parse_rfc3339(s) {
+        Some(t) => Value::Timestamp(t),
+        None => {
+            let message = format!("Invalid RFC 3339 timestamp '{}'", s);
+            Value::Error(Rc::new(Value::Str(message.into())))
+        }
+    }
+}
+
+fn duration(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    match &args[0] {
+        Value::Number(seconds) => Value::Duration((seconds * 1e9) as i64),
+        _ => runtime_error!("Invalid operand type for duration."),
+    }
+}
+
+fn duration_seconds(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    match &args[0] {
+        Value::Duration(d) => Value::Number(*d as f64 / 1e9),
+        _ => runtime_error!("Invalid operand type for duration_seconds."),
+    }
+}
@@ -0,0 +1,33 @@
+use crate::bytecode::Value;
+use crate::vm::VirtualMachine;
+
+use super::NativeFunction;
+
+pub(super) const FUNCTIONS: &[NativeFunction] = &[
+    NativeFunction {
+        name: "weak_new",
+        arity: 1,
+        function: weak_new,
+        capability: None,
+    },
+    NativeFunction {
+        name: "weak_get",
+        arity: 1,
+        function: weak_get,
+        capability: None,
+    },
+];
+
+fn weak_new(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    match args[0].downgrade() {
+        Some(weak) => Value::Weak(weak),
+        None => runtime_error!("Invalid operand type for weak_new."),
+    }
+}
+
+fn weak_get(_vm: &mut VirtualMachine, args: &[Value]) -> Value {
+    match &args[0] {
+        Value::Weak(weak) => weak.upgrade().unwrap_or(Value::Nil),
+        _ => runtime_error!("Invalid operand type for weak_get."),
+    }
+}
@@ -0,0 +1,61 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::bytecode::{self, Bytecode};
+
+// Marks the end of an executable with a program appended. It follows the
+// program and its length, as an 8-byte little-endian integer.
+const TRAILER_MAGIC: &[u8; 8] = b"ZRCBUNDL";
+
+// Writes a copy of `runner` with the program file appended, which runs the
+// program when started. The program is stored as it is in the file, so an
+// encrypted program stays encrypted.
+pub fn bundle<P: AsRef<Path>, Q: AsRef<Path>, R: AsRef<Path>>(
+    runner: P,
+    program: Q,
+    output: R,
+) -> io::Result<()> {
+    let program = fs::read(program)?;
+    // Loads the program once, so a file that isn't one fails now rather than
+    // when the bundle is run.
+    Bytecode::from_bytes(&bytecode::decode_program(program.clone())?)?;
+    let output = output.as_ref();
+    fs::copy(runner, output)?;
+    let mut file = OpenOptions::new().append(true).open(output)?;
+    file.write_all(&program)?;
+    file.write_all(&(program.len() as u64).to_le_bytes())?;
+    file.write_all(TRAILER_MAGIC)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(output, fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
+
+// The program appended to an executable by `bundle`, or None if it has none.
+pub fn bundled_program<P: AsRef<Path>>(executable: P) -> io::Result<Option<Bytecode>> {
+    let mut file = File::open(executable)?;
+    let size = file.metadata()?.len();
+    if size < 16 {
+        return Ok(None);
+    }
+    let mut end = [0; 16];
+    file.seek(SeekFrom::End(-16))?;
+    file.read_exact(&mut end)?;
+    if &end[8..] != TRAILER_MAGIC {
+        return Ok(None);
+    }
+    let length = u64::from_le_bytes(end[..8].try_into().unwrap());
+    if length > size - 16 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Bundled program is truncated",
+        ));
+    }
+    let mut program = vec![0; length as usize];
+    file.seek(SeekFrom::Start(size - 16 - length))?;
+    file.read_exact(&mut program)?;
+    Bytecode::from_bytes(&bytecode::decode_program(program)?).map(Some)
+}
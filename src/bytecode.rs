@@ -1,37 +1,162 @@
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
 use std::fmt;
-use std::fs::File;
-use std::io::{self, BufReader, Read};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::ops::{Range, RangeInclusive};
 use std::path::Path;
+use std::rc::{Rc, Weak};
+use std::time::Duration;
 use std::vec::Vec;
 
-use byteorder::{LittleEndian, ReadBytesExt};
-
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub(crate) enum Opcode {
-    PushConst = 0x01,
-    Add = 0x10,
-    Subtract = 0x11,
-    Multiply = 0x12,
-    Divide = 0x13,
-    Modulo = 0x14,
-    Negate = 0x15,
-    And = 0x20,
-    Or = 0x21,
-    Not = 0x22,
-    Equal = 0x30,
-    Jump = 0x40,
-    JumpIfTrue = 0x41,
-    JumpIfFalse = 0x42,
-    Print = 0x60,
-    GetLocal = 0x70,
-    SetLocal = 0x71,
-    Call = 0x80,
-    Return = 0x81,
-    Halt = 0xFF,
+use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::builtins::{self, NativeFunction};
+use crate::datetime;
+use crate::text::{Pool, Text, WeakText};
+use crate::verifier;
+#[cfg(feature = "bigint")]
+use num_bigint::BigInt;
+#[cfg(feature = "sqlite")]
+use rusqlite::Connection;
+#[cfg(feature = "decimal")]
+use rust_decimal::Decimal;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Opcode {
+    PushConst,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Negate,
+    And,
+    Or,
+    Not,
+    Equal,
+    Identical,
+    Match,
+    TotalCompare,
+    LessThan,
+    GreaterThan,
+    NotEqual,
+    LessOrEqual,
+    GreaterOrEqual,
+    Compare,
+    Jump,
+    JumpIfTrue,
+    JumpIfFalse,
+    JumpIfEqual,
+    JumpIfNotEqual,
+    JumpIfLess,
+    JumpIfLessOrEqual,
+    JumpIfGreater,
+    JumpIfGreaterOrEqual,
+    Print,
+    Format,
+    PrintRaw,
+    EPrint,
+    EPrintRaw,
+    GetLocal,
+    SetLocal,
+    IncLocal,
+    DecLocal,
+    AddLocalConst,
+    Call,
+    Return,
+    CallNative,
+    Import,
+    Defer,
+    RunDefers,
+    ArgCount,
+    MakeTuple,
+    TupleGet,
+    TupleUnpack,
+    MakeError,
+    IsError,
+    Try,
+    ArrayConcat,
+    ArraySlice,
+    ArrayPush,
+    ArrayPop,
+    SetAdd,
+    SetContains,
+    SetRemove,
+    Halt,
+    // An instruction added by the embedder, in the reserved byte range.
+    Extension(&'static Extension),
 }
 
 impl Opcode {
-    fn from_u8(value: u8) -> io::Result<Opcode> {
+    pub fn to_u8(self) -> u8 {
+        match self {
+            Opcode::PushConst => 0x01,
+            Opcode::Add => 0x10,
+            Opcode::Subtract => 0x11,
+            Opcode::Multiply => 0x12,
+            Opcode::Divide => 0x13,
+            Opcode::Modulo => 0x14,
+            Opcode::Negate => 0x15,
+            Opcode::And => 0x20,
+            Opcode::Or => 0x21,
+            Opcode::Not => 0x22,
+            Opcode::Equal => 0x30,
+            Opcode::Identical => 0x31,
+            Opcode::Match => 0x32,
+            Opcode::TotalCompare => 0x33,
+            Opcode::LessThan => 0x34,
+            Opcode::GreaterThan => 0x35,
+            Opcode::NotEqual => 0x36,
+            Opcode::LessOrEqual => 0x37,
+            Opcode::GreaterOrEqual => 0x38,
+            Opcode::Compare => 0x39,
+            Opcode::Jump => 0x40,
+            Opcode::JumpIfTrue => 0x41,
+            Opcode::JumpIfFalse => 0x42,
+            Opcode::JumpIfEqual => 0x43,
+            Opcode::JumpIfNotEqual => 0x44,
+            Opcode::JumpIfLess => 0x45,
+            Opcode::JumpIfLessOrEqual => 0x46,
+            Opcode::JumpIfGreater => 0x47,
+            Opcode::JumpIfGreaterOrEqual => 0x48,
+            Opcode::Print => 0x60,
+            Opcode::Format => 0x61,
+            Opcode::PrintRaw => 0x62,
+            Opcode::EPrint => 0x63,
+            Opcode::EPrintRaw => 0x64,
+            Opcode::GetLocal => 0x70,
+            Opcode::SetLocal => 0x71,
+            Opcode::IncLocal => 0x72,
+            Opcode::DecLocal => 0x73,
+            Opcode::AddLocalConst => 0x74,
+            Opcode::Call => 0x80,
+            Opcode::Return => 0x81,
+            Opcode::CallNative => 0x82,
+            Opcode::Import => 0x83,
+            Opcode::Defer => 0x84,
+            Opcode::RunDefers => 0x85,
+            Opcode::ArgCount => 0x86,
+            Opcode::MakeTuple => 0x90,
+            Opcode::TupleGet => 0x91,
+            Opcode::TupleUnpack => 0x92,
+            Opcode::MakeError => 0xA0,
+            Opcode::IsError => 0xA1,
+            Opcode::Try => 0xA2,
+            Opcode::ArrayConcat => 0xB0,
+            Opcode::ArraySlice => 0xB1,
+            Opcode::ArrayPush => 0xB2,
+            Opcode::ArrayPop => 0xB3,
+            Opcode::SetAdd => 0xC0,
+            Opcode::SetContains => 0xC1,
+            Opcode::SetRemove => 0xC2,
+            Opcode::Halt => 0xFF,
+            Opcode::Extension(extension) => extension.byte,
+        }
+    }
+
+    pub fn from_u8(value: u8) -> io::Result<Opcode> {
         match value {
             0x01 => Ok(Opcode::PushConst),
             0x10 => Ok(Opcode::Add),
@@ -44,56 +169,463 @@ impl Opcode {
             0x21 => Ok(Opcode::Or),
             0x22 => Ok(Opcode::Not),
             0x30 => Ok(Opcode::Equal),
+            0x31 => Ok(Opcode::Identical),
+            0x32 => Ok(Opcode::Match),
+            0x33 => Ok(Opcode::TotalCompare),
+            0x34 => Ok(Opcode::LessThan),
+            0x35 => Ok(Opcode::GreaterThan),
+            0x36 => Ok(Opcode::NotEqual),
+            0x37 => Ok(Opcode::LessOrEqual),
+            0x38 => Ok(Opcode::GreaterOrEqual),
+            0x39 => Ok(Opcode::Compare),
             0x40 => Ok(Opcode::Jump),
             0x41 => Ok(Opcode::JumpIfTrue),
             0x42 => Ok(Opcode::JumpIfFalse),
+            0x43 => Ok(Opcode::JumpIfEqual),
+            0x44 => Ok(Opcode::JumpIfNotEqual),
+            0x45 => Ok(Opcode::JumpIfLess),
+            0x46 => Ok(Opcode::JumpIfLessOrEqual),
+            0x47 => Ok(Opcode::JumpIfGreater),
+            0x48 => Ok(Opcode::JumpIfGreaterOrEqual),
             0x60 => Ok(Opcode::Print),
+            0x61 => Ok(Opcode::Format),
+            0x62 => Ok(Opcode::PrintRaw),
+            0x63 => Ok(Opcode::EPrint),
+            0x64 => Ok(Opcode::EPrintRaw),
             0x70 => Ok(Opcode::GetLocal),
             0x71 => Ok(Opcode::SetLocal),
+            0x72 => Ok(Opcode::IncLocal),
+            0x73 => Ok(Opcode::DecLocal),
+            0x74 => Ok(Opcode::AddLocalConst),
             0x80 => Ok(Opcode::Call),
             0x81 => Ok(Opcode::Return),
+            0x82 => Ok(Opcode::CallNative),
+            0x83 => Ok(Opcode::Import),
+            0x84 => Ok(Opcode::Defer),
+            0x85 => Ok(Opcode::RunDefers),
+            0x86 => Ok(Opcode::ArgCount),
+            0x90 => Ok(Opcode::MakeTuple),
+            0x91 => Ok(Opcode::TupleGet),
+            0x92 => Ok(Opcode::TupleUnpack),
+            0xA0 => Ok(Opcode::MakeError),
+            0xA1 => Ok(Opcode::IsError),
+            0xA2 => Ok(Opcode::Try),
+            0xB0 => Ok(Opcode::ArrayConcat),
+            0xB1 => Ok(Opcode::ArraySlice),
+            0xB2 => Ok(Opcode::ArrayPush),
+            0xB3 => Ok(Opcode::ArrayPop),
+            0xC0 => Ok(Opcode::SetAdd),
+            0xC1 => Ok(Opcode::SetContains),
+            0xC2 => Ok(Opcode::SetRemove),
             0xFF => Ok(Opcode::Halt),
             _ => Err(io::Error::new(io::ErrorKind::InvalidData, "Unknown opcode")),
         }
     }
 
-    fn has_operand(self) -> bool {
+    // Like `from_u8`, also accepting the bytes of the given extensions.
+    pub fn from_u8_with(value: u8, extensions: &[&'static Extension]) -> io::Result<Opcode> {
+        match extensions.iter().find(|extension| extension.byte == value) {
+            Some(extension) => Ok(Opcode::Extension(extension)),
+            None => Opcode::from_u8(value),
+        }
+    }
+
+    // The bytes the core instruction set leaves for extensions.
+    pub const EXTENSION_RANGE: RangeInclusive<u8> = 0xE0..=0xFE;
+
+    // Every core opcode, in byte order.
+    pub const ALL: &'static [Opcode] = &[
+        Opcode::PushConst,
+        Opcode::Add,
+        Opcode::Subtract,
+        Opcode::Multiply,
+        Opcode::Divide,
+        Opcode::Modulo,
+        Opcode::Negate,
+        Opcode::And,
+        Opcode::Or,
+        Opcode::Not,
+        Opcode::Equal,
+        Opcode::Identical,
+        Opcode::Match,
+        Opcode::TotalCompare,
+        Opcode::LessThan,
+        Opcode::GreaterThan,
+        Opcode::NotEqual,
+        Opcode::LessOrEqual,
+        Opcode::GreaterOrEqual,
+        Opcode::Compare,
+        Opcode::Jump,
+        Opcode::JumpIfTrue,
+        Opcode::JumpIfFalse,
+        Opcode::JumpIfEqual,
+        Opcode::JumpIfNotEqual,
+        Opcode::JumpIfLess,
+        Opcode::JumpIfLessOrEqual,
+        Opcode::JumpIfGreater,
+        Opcode::JumpIfGreaterOrEqual,
+        Opcode::Print,
+        Opcode::Format,
+        Opcode::PrintRaw,
+        Opcode::EPrint,
+        Opcode::EPrintRaw,
+        Opcode::GetLocal,
+        Opcode::SetLocal,
+        Opcode::IncLocal,
+        Opcode::DecLocal,
+        Opcode::AddLocalConst,
+        Opcode::Call,
+        Opcode::Return,
+        Opcode::CallNative,
+        Opcode::Import,
+        Opcode::Defer,
+        Opcode::RunDefers,
+        Opcode::ArgCount,
+        Opcode::MakeTuple,
+        Opcode::TupleGet,
+        Opcode::TupleUnpack,
+        Opcode::MakeError,
+        Opcode::IsError,
+        Opcode::Try,
+        Opcode::ArrayConcat,
+        Opcode::ArraySlice,
+        Opcode::ArrayPush,
+        Opcode::ArrayPop,
+        Opcode::SetAdd,
+        Opcode::SetContains,
+        Opcode::SetRemove,
+        Opcode::Halt,
+    ];
+
+    // The assembly mnemonic.
+    pub fn name(self) -> &'static str {
+        match self {
+            Opcode::PushConst => "push_const",
+            Opcode::Add => "add",
+            Opcode::Subtract => "subtract",
+            Opcode::Multiply => "multiply",
+            Opcode::Divide => "divide",
+            Opcode::Modulo => "modulo",
+            Opcode::Negate => "negate",
+            Opcode::And => "and",
+            Opcode::Or => "or",
+            Opcode::Not => "not",
+            Opcode::Equal => "equal",
+            Opcode::Identical => "identical",
+            Opcode::Match => "match",
+            Opcode::TotalCompare => "total_compare",
+            Opcode::LessThan => "less",
+            Opcode::GreaterThan => "greater",
+            Opcode::NotEqual => "not_equal",
+            Opcode::LessOrEqual => "less_or_equal",
+            Opcode::GreaterOrEqual => "greater_or_equal",
+            Opcode::Compare => "compare",
+            Opcode::Jump => "jump",
+            Opcode::JumpIfTrue => "jump_if_true",
+            Opcode::JumpIfFalse => "jump_if_false",
+            Opcode::JumpIfEqual => "jump_if_equal",
+            Opcode::JumpIfNotEqual => "jump_if_not_equal",
+            Opcode::JumpIfLess => "jump_if_less",
+            Opcode::JumpIfLessOrEqual => "jump_if_less_or_equal",
+            Opcode::JumpIfGreater => "jump_if_greater",
+            Opcode::JumpIfGreaterOrEqual => "jump_if_greater_or_equal",
+            Opcode::Print => "print",
+            Opcode::Format => "format",
+            Opcode::PrintRaw => "print_raw",
+            Opcode::EPrint => "eprint",
+            Opcode::EPrintRaw => "eprint_raw",
+            Opcode::GetLocal => "get_local",
+            Opcode::SetLocal => "set_local",
+            Opcode::IncLocal => "inc_local",
+            Opcode::DecLocal => "dec_local",
+            Opcode::AddLocalConst => "add_local_const",
+            Opcode::Call => "call",
+            Opcode::Return => "return",
+            Opcode::CallNative => "call_native",
+            Opcode::Import => "import",
+            Opcode::Defer => "defer",
+            Opcode::RunDefers => "run_defers",
+            Opcode::ArgCount => "arg_count",
+            Opcode::MakeTuple => "make_tuple",
+            Opcode::TupleGet => "tuple_get",
+            Opcode::TupleUnpack => "tuple_unpack",
+            Opcode::MakeError => "make_error",
+            Opcode::IsError => "is_error",
+            Opcode::Try => "try",
+            Opcode::ArrayConcat => "array_concat",
+            Opcode::ArraySlice => "array_slice",
+            Opcode::ArrayPush => "array_push",
+            Opcode::ArrayPop => "array_pop",
+            Opcode::SetAdd => "set_add",
+            Opcode::SetContains => "set_contains",
+            Opcode::SetRemove => "set_remove",
+            Opcode::Halt => "halt",
+            Opcode::Extension(extension) => extension.name,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Opcode> {
+        Opcode::ALL
+            .iter()
+            .copied()
+            .find(|opcode| opcode.name() == name)
+    }
+
+    pub fn operand_kind(self) -> OperandKind {
+        match self {
+            Opcode::PushConst | Opcode::Match | Opcode::CallNative | Opcode::Import => {
+                OperandKind::Constant
+            }
+            Opcode::Jump
+            | Opcode::JumpIfTrue
+            | Opcode::JumpIfFalse
+            | Opcode::JumpIfEqual
+            | Opcode::JumpIfNotEqual
+            | Opcode::JumpIfLess
+            | Opcode::JumpIfLessOrEqual
+            | Opcode::JumpIfGreater
+            | Opcode::JumpIfGreaterOrEqual => OperandKind::Target,
+            Opcode::GetLocal | Opcode::SetLocal | Opcode::IncLocal | Opcode::DecLocal => {
+                OperandKind::Local
+            }
+            Opcode::AddLocalConst => OperandKind::LocalConstant,
+            Opcode::Call | Opcode::Defer => OperandKind::Function,
+            Opcode::Format | Opcode::ArgCount | Opcode::MakeTuple | Opcode::TupleUnpack => {
+                OperandKind::Count
+            }
+            Opcode::TupleGet => OperandKind::Element,
+            Opcode::Add
+            | Opcode::Subtract
+            | Opcode::Multiply
+            | Opcode::Divide
+            | Opcode::Modulo
+            | Opcode::Negate
+            | Opcode::And
+            | Opcode::Or
+            | Opcode::Not
+            | Opcode::Equal
+            | Opcode::Identical
+            | Opcode::TotalCompare
+            | Opcode::LessThan
+            | Opcode::GreaterThan
+            | Opcode::NotEqual
+            | Opcode::LessOrEqual
+            | Opcode::GreaterOrEqual
+            | Opcode::Compare
+            | Opcode::Print
+            | Opcode::PrintRaw
+            | Opcode::EPrint
+            | Opcode::EPrintRaw
+            | Opcode::Return
+            | Opcode::RunDefers
+            | Opcode::ArrayConcat
+            | Opcode::ArraySlice
+            | Opcode::ArrayPush
+            | Opcode::ArrayPop
+            | Opcode::SetAdd
+            | Opcode::SetContains
+            | Opcode::SetRemove
+            | Opcode::MakeError
+            | Opcode::IsError
+            | Opcode::Try
+            | Opcode::Halt => OperandKind::None,
+            Opcode::Extension(extension) => extension.operand,
+        }
+    }
+
+    pub fn has_operand(self) -> bool {
+        self.operand_kind() != OperandKind::None
+    }
+
+    // Whether the instruction jumps to its target only on some condition,
+    // continuing with the next instruction otherwise.
+    pub fn is_branch(self) -> bool {
+        self.operand_kind() == OperandKind::Target && self != Opcode::Jump
+    }
+
+    pub fn stack_effect(self) -> StackEffect {
+        let effect = |pops, pushes| StackEffect { pops, pushes };
         match self {
-            Opcode::PushConst => true,
-            Opcode::Add => false,
-            Opcode::Subtract => false,
-            Opcode::Multiply => false,
-            Opcode::Divide => false,
-            Opcode::Modulo => false,
-            Opcode::Negate => false,
-            Opcode::And => false,
-            Opcode::Or => false,
-            Opcode::Not => false,
-            Opcode::Equal => false,
-            Opcode::Jump => true,
-            Opcode::JumpIfTrue => true,
-            Opcode::JumpIfFalse => true,
-            Opcode::Print => false,
-            Opcode::GetLocal => true,
-            Opcode::SetLocal => true,
-            Opcode::Call => true,
-            Opcode::Return => false,
-            Opcode::Halt => false,
-        }
-    }
-}
-
-pub(crate) struct Instruction {
+            Opcode::PushConst | Opcode::GetLocal | Opcode::Import => {
+                effect(Count::Fixed(0), Count::Fixed(1))
+            }
+            Opcode::Add
+            | Opcode::Subtract
+            | Opcode::Multiply
+            | Opcode::Divide
+            | Opcode::Modulo
+            | Opcode::And
+            | Opcode::Or
+            | Opcode::Equal
+            | Opcode::Identical
+            | Opcode::TotalCompare
+            | Opcode::LessThan
+            | Opcode::GreaterThan
+            | Opcode::NotEqual
+            | Opcode::LessOrEqual
+            | Opcode::GreaterOrEqual
+            | Opcode::Compare
+            | Opcode::ArrayConcat
+            | Opcode::ArrayPush
+            | Opcode::SetAdd
+            | Opcode::SetContains
+            | Opcode::SetRemove => effect(Count::Fixed(2), Count::Fixed(1)),
+            Opcode::JumpIfEqual
+            | Opcode::JumpIfNotEqual
+            | Opcode::JumpIfLess
+            | Opcode::JumpIfLessOrEqual
+            | Opcode::JumpIfGreater
+            | Opcode::JumpIfGreaterOrEqual => effect(Count::Fixed(2), Count::Fixed(0)),
+            Opcode::Negate
+            | Opcode::Not
+            | Opcode::TupleGet
+            | Opcode::MakeError
+            | Opcode::IsError
+            | Opcode::Try => effect(Count::Fixed(1), Count::Fixed(1)),
+            // Leaves the value in place and pushes the result above it.
+            Opcode::Match => effect(Count::Fixed(1), Count::Fixed(2)),
+            Opcode::ArraySlice => effect(Count::Fixed(3), Count::Fixed(1)),
+            // Pushes the shorter array, then the element removed from it.
+            Opcode::ArrayPop => effect(Count::Fixed(1), Count::Fixed(2)),
+            Opcode::JumpIfTrue
+            | Opcode::JumpIfFalse
+            | Opcode::Print
+            | Opcode::PrintRaw
+            | Opcode::EPrint
+            | Opcode::EPrintRaw
+            | Opcode::SetLocal => effect(Count::Fixed(1), Count::Fixed(0)),
+            Opcode::Jump
+            | Opcode::IncLocal
+            | Opcode::DecLocal
+            | Opcode::AddLocalConst
+            | Opcode::RunDefers
+            | Opcode::ArgCount
+            | Opcode::Halt => effect(Count::Fixed(0), Count::Fixed(0)),
+            // The template sits below the arguments.
+            Opcode::Format => effect(Count::Operand(1), Count::Fixed(1)),
+            Opcode::Call => effect(Count::Arguments, Count::Fixed(1)),
+            Opcode::Defer => effect(Count::Arguments, Count::Fixed(0)),
+            Opcode::Return => effect(Count::Rest, Count::Fixed(0)),
+            Opcode::CallNative => effect(Count::Native, Count::Fixed(1)),
+            Opcode::MakeTuple => effect(Count::Operand(0), Count::Fixed(1)),
+            Opcode::TupleUnpack => effect(Count::Fixed(1), Count::Operand(0)),
+            Opcode::Extension(extension) => {
+                effect(Count::Fixed(extension.pops), Count::Fixed(extension.pushes))
+            }
+        }
+    }
+}
+
+// An instruction defined by an embedder: its byte in
+// `Opcode::EXTENSION_RANGE`, its assembly mnemonic, whether it takes an
+// operand, and how many values it pops and pushes. Programs that use it are
+// loaded with `LoadOptions::extension` or assembled with
+// `assemble_with_extensions`, and run by a VM given a handler with
+// `VirtualMachine::set_extension_handler`.
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct Extension {
+    byte: u8,
+    name: &'static str,
+    operand: OperandKind,
+    pops: usize,
+    pushes: usize,
+}
+
+impl Extension {
+    pub const fn new(
+        byte: u8,
+        name: &'static str,
+        operand: OperandKind,
+        pops: usize,
+        pushes: usize,
+    ) -> Self {
+        if byte < *Opcode::EXTENSION_RANGE.start() || byte > *Opcode::EXTENSION_RANGE.end() {
+            panic!("Extension opcodes must be between 0xE0 and 0xFE.");
+        }
+        // The handler sees the operand as a number, so it can't be one the VM
+        // resolves, such as a constant or a jump target.
+        if !matches!(
+            operand,
+            OperandKind::None | OperandKind::Count | OperandKind::Element
+        ) {
+            panic!("Extension operands must be counts or element indexes.");
+        }
+        Extension {
+            byte,
+            name,
+            operand,
+            pops,
+            pushes,
+        }
+    }
+
+    pub fn byte(&self) -> u8 {
+        self.byte
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    // How many values the instruction pops, then pushes.
+    pub fn stack_effect(&self) -> (usize, usize) {
+        (self.pops, self.pushes)
+    }
+}
+
+// What an instruction's operand refers to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OperandKind {
+    None,
+    // An index into the constant pool.
+    Constant,
+    // An instruction index in the same function.
+    Target,
+    // A local variable slot.
+    Local,
+    // A local variable slot in the high byte and a constant index in the low
+    // byte.
+    LocalConstant,
+    // A function index.
+    Function,
+    // A number of values or arguments.
+    Count,
+    // A tuple element index.
+    Element,
+}
+
+// How many values an instruction pops from the operand stack and then pushes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StackEffect {
+    pub pops: Count,
+    pub pushes: Count,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Count {
+    Fixed(usize),
+    // The operand plus the given number.
+    Operand(usize),
+    // The callee's argument count, or the count set by a pending `arg_count`.
+    Arguments,
+    // The arity of the native named by the operand.
+    Native,
+    // Everything left on the stack.
+    Rest,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Instruction {
     opcode: Opcode,
     operand: Option<u16>,
 }
 
 impl Instruction {
-    fn new(opcode: Opcode, operand: Option<u16>) -> Self {
+    pub fn new(opcode: Opcode, operand: Option<u16>) -> Self {
         Instruction { opcode, operand }
     }
 
-    pub(crate) fn opcode(&self) -> Opcode {
+    pub fn opcode(&self) -> Opcode {
         self.opcode
     }
 
@@ -104,96 +636,684 @@ impl Instruction {
     pub(crate) fn operand(&self) -> u16 {
         self.operand.expect("Instruction has no operand")
     }
+
+    pub fn optional_operand(&self) -> Option<u16> {
+        self.operand
+    }
+
+    // The local slot the instruction reads or writes, if it uses one.
+    pub(crate) fn local(&self) -> Option<usize> {
+        match self.opcode.operand_kind() {
+            OperandKind::Local => Some(self.operand().into()),
+            OperandKind::LocalConstant => Some(self.local_constant().0),
+            _ => None,
+        }
+    }
+
+    // The constant the instruction reads, if it uses one.
+    pub(crate) fn constant(&self) -> Option<usize> {
+        match self.opcode.operand_kind() {
+            OperandKind::Constant => Some(self.operand().into()),
+            OperandKind::LocalConstant => Some(self.local_constant().1),
+            _ => None,
+        }
+    }
+
+    // The local slot and constant index packed into an `add_local_const`
+    // operand.
+    pub(crate) fn local_constant(&self) -> (usize, usize) {
+        let operand = self.operand();
+        ((operand >> 8).into(), (operand & 0xff).into())
+    }
+
+    // Writes the opcode byte, then the operand as a little-endian u16 if the
+    // opcode takes one.
+    pub fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        if self.operand.is_some() != self.opcode.has_operand() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Wrong operand for {}", self.opcode.name()),
+            ));
+        }
+        writer.write_u8(self.opcode.to_u8())?;
+        if let Some(operand) = self.operand {
+            writer.write_u16::<LittleEndian>(operand)?;
+        }
+        Ok(())
+    }
+
+    pub fn decode<R: Read>(reader: &mut R) -> io::Result<Instruction> {
+        Instruction::decode_with::<LittleEndian, _>(reader, &[])
+    }
+
+    pub(crate) fn decode_with<B: ByteOrder, R: Read>(
+        reader: &mut R,
+        extensions: &[&'static Extension],
+    ) -> io::Result<Instruction> {
+        let opcode = Opcode::from_u8_with(reader.read_u8()?, extensions)?;
+        let operand = if opcode.has_operand() {
+            Some(reader.read_u16::<B>()?)
+        } else {
+            None
+        };
+        Ok(Instruction::new(opcode, operand))
+    }
 }
 
 #[derive(Clone, Debug)]
-pub(crate) enum Value {
+pub enum Value {
+    Nil,
     Number(f64),
+    #[cfg(feature = "bigint")]
+    BigInt(BigInt),
+    #[cfg(feature = "decimal")]
+    Decimal(Decimal),
     Boolean(bool),
-    Str(String),
+    Char(char),
+    Str(Text),
+    Bytes(Rc<[u8]>),
+    Array(Rc<Vec<Value>>),
+    Tuple(Rc<[Value]>),
+    // Elements in ascending order, each once.
+    Set(Rc<Vec<Value>>),
+    Error(Rc<Value>),
+    Timestamp(i64),
+    Duration(i64),
+    StringBuilder(Rc<RefCell<String>>),
+    Socket(Rc<Socket>),
+    #[cfg(feature = "sqlite")]
+    Database(Rc<Connection>),
+    Weak(WeakRef),
+}
+
+// A TCP listener or connection opened by the `tcp_*` natives. It is closed
+// when the last copy of the value is dropped. A listener keeps how long
+// `tcp_accept` waits on it, since the operating system has no such limit.
+#[derive(Debug)]
+pub enum Socket {
+    Listener(TcpListener, Cell<Option<Duration>>),
+    Stream(TcpStream),
+}
+
+#[derive(Clone, Debug)]
+pub enum WeakRef {
+    Str(WeakText),
+    Bytes(Weak<[u8]>),
+    Array(Weak<Vec<Value>>),
+    Tuple(Weak<[Value]>),
+    Set(Weak<Vec<Value>>),
+    Error(Weak<Value>),
+    StringBuilder(Weak<RefCell<String>>),
+}
+
+impl WeakRef {
+    pub fn upgrade(&self) -> Option<Value> {
+        match self {
+            WeakRef::Str(w) => w.upgrade().map(Value::Str),
+            WeakRef::Bytes(w) => w.upgrade().map(Value::Bytes),
+            WeakRef::Array(w) => w.upgrade().map(Value::Array),
+            WeakRef::Tuple(w) => w.upgrade().map(Value::Tuple),
+            WeakRef::Set(w) => w.upgrade().map(Value::Set),
+            WeakRef::Error(w) => w.upgrade().map(Value::Error),
+            WeakRef::StringBuilder(w) => w.upgrade().map(Value::StringBuilder),
+        }
+    }
+
+    fn ptr_eq(&self, other: &WeakRef) -> bool {
+        match (self, other) {
+            (WeakRef::Str(a), WeakRef::Str(b)) => a.ptr_eq(b),
+            (WeakRef::Bytes(a), WeakRef::Bytes(b)) => a.ptr_eq(b),
+            (WeakRef::Array(a), WeakRef::Array(b)) => a.ptr_eq(b),
+            (WeakRef::Tuple(a), WeakRef::Tuple(b)) => a.ptr_eq(b),
+            (WeakRef::Set(a), WeakRef::Set(b)) => a.ptr_eq(b),
+            (WeakRef::Error(a), WeakRef::Error(b)) => a.ptr_eq(b),
+            (WeakRef::StringBuilder(a), WeakRef::StringBuilder(b)) => a.ptr_eq(b),
+            _ => false,
+        }
+    }
 }
 
 impl Value {
     pub(crate) fn add(&self, other: &Value) -> Value {
         match (self, other) {
             (Value::Number(a), Value::Number(b)) => Value::Number(a + b),
-            _ => panic!("Invalid operand types for add."),
+            #[cfg(feature = "bigint")]
+            (Value::BigInt(a), Value::BigInt(b)) => Value::BigInt(a + b),
+            #[cfg(feature = "decimal")]
+            (Value::Decimal(a), Value::Decimal(b)) => Value::Decimal(
+                a.checked_add(*b)
+                    .unwrap_or_else(|| runtime_error!("Decimal overflow.")),
+            ),
+            (Value::Timestamp(a), Value::Duration(b))
+            | (Value::Duration(b), Value::Timestamp(a)) => Value::Timestamp(
+                a.checked_add(*b)
+                    .unwrap_or_else(|| runtime_error!("Timestamp overflow.")),
+            ),
+            (Value::Duration(a), Value::Duration(b)) => Value::Duration(
+                a.checked_add(*b)
+                    .unwrap_or_else(|| runtime_error!("Duration overflow.")),
+            ),
+            _ => runtime_error!("Invalid operand types for add."),
         }
     }
 
     pub(crate) fn subtract(&self, other: &Value) -> Value {
         match (self, other) {
             (Value::Number(a), Value::Number(b)) => Value::Number(a - b),
-            _ => panic!("Invalid operand types for subtract."),
+            #[cfg(feature = "bigint")]
+            (Value::BigInt(a), Value::BigInt(b)) => Value::BigInt(a - b),
+            #[cfg(feature = "decimal")]
+            (Value::Decimal(a), Value::Decimal(b)) => Value::Decimal(
+                a.checked_sub(*b)
+                    .unwrap_or_else(|| runtime_error!("Decimal overflow.")),
+            ),
+            (Value::Timestamp(a), Value::Timestamp(b))
+            | (Value::Duration(a), Value::Duration(b)) => Value::Duration(
+                a.checked_sub(*b)
+                    .unwrap_or_else(|| runtime_error!("Duration overflow.")),
+            ),
+            (Value::Timestamp(a), Value::Duration(b)) => Value::Timestamp(
+                a.checked_sub(*b)
+                    .unwrap_or_else(|| runtime_error!("Timestamp overflow.")),
+            ),
+            _ => runtime_error!("Invalid operand types for subtract."),
         }
     }
 
     pub(crate) fn multiply(&self, other: &Value) -> Value {
         match (self, other) {
             (Value::Number(a), Value::Number(b)) => Value::Number(a * b),
-            _ => panic!("Invalid operand types for multiply."),
+            #[cfg(feature = "bigint")]
+            (Value::BigInt(a), Value::BigInt(b)) => Value::BigInt(a * b),
+            #[cfg(feature = "decimal")]
+            (Value::Decimal(a), Value::Decimal(b)) => Value::Decimal(
+                a.checked_mul(*b)
+                    .unwrap_or_else(|| runtime_error!("Decimal overflow.")),
+            ),
+            _ => runtime_error!("Invalid operand types for multiply."),
         }
     }
 
     pub(crate) fn divide(&self, other: &Value) -> Value {
         match (self, other) {
             (Value::Number(a), Value::Number(b)) => Value::Number(a / b),
-            _ => panic!("Invalid operand types for divide."),
+            #[cfg(feature = "bigint")]
+            (Value::BigInt(a), Value::BigInt(b)) => {
+                if b.sign() == num_bigint::Sign::NoSign {
+                    runtime_error!("Division by zero.");
+                }
+                Value::BigInt(a / b)
+            }
+            #[cfg(feature = "decimal")]
+            (Value::Decimal(a), Value::Decimal(b)) => {
+                if b.is_zero() {
+                    runtime_error!("Division by zero.");
+                }
+                Value::Decimal(
+                    a.checked_div(*b)
+                        .unwrap_or_else(|| runtime_error!("Decimal overflow.")),
+                )
+            }
+            _ => runtime_error!("Invalid operand types for divide."),
         }
     }
 
     pub(crate) fn modulo(&self, other: &Value) -> Value {
         match (self, other) {
             (Value::Number(a), Value::Number(b)) => Value::Number(a % b),
-            _ => panic!("Invalid operand types for modulo."),
+            #[cfg(feature = "bigint")]
+            (Value::BigInt(a), Value::BigInt(b)) => {
+                if b.sign() == num_bigint::Sign::NoSign {
+                    runtime_error!("Division by zero.");
+                }
+                Value::BigInt(a % b)
+            }
+            #[cfg(feature = "decimal")]
+            (Value::Decimal(a), Value::Decimal(b)) => {
+                if b.is_zero() {
+                    runtime_error!("Division by zero.");
+                }
+                Value::Decimal(
+                    a.checked_rem(*b)
+                        .unwrap_or_else(|| runtime_error!("Decimal overflow.")),
+                )
+            }
+            _ => runtime_error!("Invalid operand types for modulo."),
         }
     }
 
     pub(crate) fn negate(&self) -> Value {
         match self {
             Value::Number(a) => Value::Number(-a),
-            _ => panic!("Invalid operand type for negate."),
+            #[cfg(feature = "bigint")]
+            Value::BigInt(a) => Value::BigInt(-a),
+            #[cfg(feature = "decimal")]
+            Value::Decimal(a) => Value::Decimal(-a),
+            Value::Duration(a) => Value::Duration(
+                a.checked_neg()
+                    .unwrap_or_else(|| runtime_error!("Duration overflow.")),
+            ),
+            _ => runtime_error!("Invalid operand type for negate."),
         }
     }
 
     pub(crate) fn logical_and(&self, other: &Value) -> Value {
         match (self, other) {
             (Value::Boolean(a), Value::Boolean(b)) => Value::Boolean(*a && *b),
-            _ => panic!("Invalid operand types for logical and."),
+            _ => runtime_error!("Invalid operand types for logical and."),
         }
     }
 
     pub(crate) fn logical_or(&self, other: &Value) -> Value {
         match (self, other) {
             (Value::Boolean(a), Value::Boolean(b)) => Value::Boolean(*a || *b),
-            _ => panic!("Invalid operand types for logical or."),
+            _ => runtime_error!("Invalid operand types for logical or."),
+        }
+    }
+
+    // Orders values of the same kind; returns None for mismatched kinds and
+    // for NaN.
+    pub(crate) fn compare(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Number(a), Value::Number(b)) => a.partial_cmp(b),
+            #[cfg(feature = "bigint")]
+            (Value::BigInt(a), Value::BigInt(b)) => Some(a.cmp(b)),
+            #[cfg(feature = "decimal")]
+            (Value::Decimal(a), Value::Decimal(b)) => Some(a.cmp(b)),
+            (Value::Boolean(a), Value::Boolean(b)) => Some(a.cmp(b)),
+            (Value::Char(a), Value::Char(b)) => Some(a.cmp(b)),
+            (Value::Str(a), Value::Str(b)) => Some(a.cmp(b)),
+            (Value::Bytes(a), Value::Bytes(b)) => Some(a.cmp(b)),
+            (Value::Array(a), Value::Array(b)) => compare_elements(a, b),
+            (Value::Tuple(a), Value::Tuple(b)) => compare_elements(a, b),
+            (Value::Timestamp(a), Value::Timestamp(b)) => Some(a.cmp(b)),
+            (Value::Duration(a), Value::Duration(b)) => Some(a.cmp(b)),
+            _ => None,
+        }
+    }
+
+    // Orders values of the same kind like `compare`, but with numbers in IEEE
+    // 754 total order, so that every number, NaN included, has a place:
+    // negative NaNs first, then -0.0 before 0.0, then positive NaNs last.
+    pub(crate) fn total_order(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Number(a), Value::Number(b)) => Some(a.total_cmp(b)),
+            (Value::Array(a), Value::Array(b)) => total_order_elements(a, b),
+            (Value::Tuple(a), Value::Tuple(b)) => total_order_elements(a, b),
+            _ => self.compare(other),
+        }
+    }
+
+    // Whether two values are equal, with numbers compared under the given
+    // policy. `==` is this with `NumberEquality::Ieee`.
+    pub(crate) fn equals(&self, other: &Value, numbers: NumberEquality) -> bool {
+        match (self, other) {
+            (Value::Number(a), Value::Number(b)) => match numbers {
+                NumberEquality::Ieee => a == b,
+                NumberEquality::Total => a.total_cmp(b) == Ordering::Equal,
+            },
+            (Value::Array(a), Value::Array(b)) => equal_elements(a, b, numbers),
+            (Value::Tuple(a), Value::Tuple(b)) => equal_elements(a, b, numbers),
+            (Value::Set(a), Value::Set(b)) => equal_elements(a, b, numbers),
+            (Value::Error(a), Value::Error(b)) => a.equals(b, numbers),
+            _ => self == other,
+        }
+    }
+
+    // Orders two values for the comparison instruction named `name`. NaN is
+    // unordered, so every comparison with it is false, and so is one between
+    // two arrays or two tuples whose first differing elements are unordered,
+    // such as a NaN and a number. Other values that cannot be ordered against
+    // each other are an error.
+    pub(crate) fn order(&self, other: &Value, name: &str) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Number(a), Value::Number(b)) => a.partial_cmp(b),
+            (Value::Array(_), Value::Array(_)) | (Value::Tuple(_), Value::Tuple(_)) => {
+                self.compare(other)
+            }
+            _ => match self.compare(other) {
+                Some(ordering) => Some(ordering),
+                None => runtime_error!("Invalid operand types for {}.", name),
+            },
+        }
+    }
+
+    // The results of the ordered comparisons that push a boolean, which are
+    // all false for NaN.
+    pub(crate) fn less_than(&self, other: &Value) -> Value {
+        Value::Boolean(self.order(other, "less") == Some(Ordering::Less))
+    }
+
+    pub(crate) fn greater_than(&self, other: &Value) -> Value {
+        Value::Boolean(self.order(other, "greater") == Some(Ordering::Greater))
+    }
+
+    pub(crate) fn less_or_equal(&self, other: &Value) -> Value {
+        let order = self.order(other, "less_or_equal");
+        Value::Boolean(matches!(order, Some(Ordering::Less | Ordering::Equal)))
+    }
+
+    pub(crate) fn greater_or_equal(&self, other: &Value) -> Value {
+        let order = self.order(other, "greater_or_equal");
+        Value::Boolean(matches!(order, Some(Ordering::Greater | Ordering::Equal)))
+    }
+
+    // The result of `compare`: -1, 0, or 1 as `self` orders before, the same
+    // as, or after `other`. Unlike the comparisons that push a boolean, there
+    // is no answer for values that are unordered, which are NaN and arrays or
+    // tuples whose first differing elements are unordered, so those are an
+    // error.
+    pub(crate) fn three_way(&self, other: &Value) -> Value {
+        match self.order(other, "compare") {
+            Some(ordering) => Value::Number(ordering as i8 as f64),
+            None => runtime_error!("Cannot compare unordered values."),
+        }
+    }
+
+    // Whether a compare-and-branch instruction jumps, with `self` below
+    // `other` on the stack.
+    pub(crate) fn branches(&self, other: &Value, opcode: Opcode, numbers: NumberEquality) -> bool {
+        let order = || self.order(other, opcode.name());
+        match opcode {
+            Opcode::JumpIfEqual => self.equals(other, numbers),
+            Opcode::JumpIfNotEqual => !self.equals(other, numbers),
+            Opcode::JumpIfLess => order() == Some(Ordering::Less),
+            Opcode::JumpIfLessOrEqual => matches!(order(), Some(Ordering::Less | Ordering::Equal)),
+            Opcode::JumpIfGreater => order() == Some(Ordering::Greater),
+            Opcode::JumpIfGreaterOrEqual => {
+                matches!(order(), Some(Ordering::Greater | Ordering::Equal))
+            }
+            _ => panic!("Invalid opcode for comparison."),
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Nil => "nil",
+            Value::Number(_) => "number",
+            #[cfg(feature = "bigint")]
+            Value::BigInt(_) => "bigint",
+            #[cfg(feature = "decimal")]
+            Value::Decimal(_) => "decimal",
+            Value::Boolean(_) => "boolean",
+            Value::Char(_) => "char",
+            Value::Str(_) => "string",
+            Value::Bytes(_) => "bytes",
+            Value::Array(_) => "array",
+            Value::Tuple(_) => "tuple",
+            Value::Set(_) => "set",
+            Value::Error(_) => "error",
+            Value::Timestamp(_) => "timestamp",
+            Value::Duration(_) => "duration",
+            Value::StringBuilder(_) => "string_builder",
+            Value::Socket(_) => "socket",
+            #[cfg(feature = "sqlite")]
+            Value::Database(_) => "database",
+            Value::Weak(_) => "weak",
+        }
+    }
+
+    // Returns None for values that are not heap-allocated.
+    pub(crate) fn downgrade(&self) -> Option<WeakRef> {
+        match self {
+            Value::Str(s) => Some(WeakRef::Str(Text::downgrade(s))),
+            Value::Bytes(bytes) => Some(WeakRef::Bytes(Rc::downgrade(bytes))),
+            Value::Array(elements) => Some(WeakRef::Array(Rc::downgrade(elements))),
+            Value::Tuple(elements) => Some(WeakRef::Tuple(Rc::downgrade(elements))),
+            Value::Set(elements) => Some(WeakRef::Set(Rc::downgrade(elements))),
+            Value::Error(payload) => Some(WeakRef::Error(Rc::downgrade(payload))),
+            Value::StringBuilder(builder) => Some(WeakRef::StringBuilder(Rc::downgrade(builder))),
+            _ => None,
+        }
+    }
+
+    // Heap values are identical only if they are the same allocation. Scalars
+    // are identical to anything they are equal to.
+    pub(crate) fn is_identical(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Str(a), Value::Str(b)) => Text::ptr_eq(a, b),
+            (Value::Bytes(a), Value::Bytes(b)) => Rc::ptr_eq(a, b),
+            (Value::Array(a), Value::Array(b)) => Rc::ptr_eq(a, b),
+            (Value::Tuple(a), Value::Tuple(b)) => Rc::ptr_eq(a, b),
+            (Value::Set(a), Value::Set(b)) => Rc::ptr_eq(a, b),
+            (Value::Error(a), Value::Error(b)) => Rc::ptr_eq(a, b),
+            (Value::StringBuilder(a), Value::StringBuilder(b)) => Rc::ptr_eq(a, b),
+            (Value::Socket(a), Value::Socket(b)) => Rc::ptr_eq(a, b),
+            #[cfg(feature = "sqlite")]
+            (Value::Database(a), Value::Database(b)) => Rc::ptr_eq(a, b),
+            _ => self == other,
         }
     }
 
     pub(crate) fn logical_not(&self) -> Value {
         match self {
             Value::Boolean(a) => Value::Boolean(!a),
-            _ => panic!("Invalid operand type for logical not."),
+            _ => runtime_error!("Invalid operand type for logical not."),
+        }
+    }
+}
+
+fn total_order_elements(a: &[Value], b: &[Value]) -> Option<Ordering> {
+    for (x, y) in a.iter().zip(b) {
+        match x.total_order(y)? {
+            Ordering::Equal => continue,
+            ordering => return Some(ordering),
+        }
+    }
+    Some(a.len().cmp(&b.len()))
+}
+
+fn equal_elements(a: &[Value], b: &[Value], numbers: NumberEquality) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(x, y)| x.equals(y, numbers))
+}
+
+fn compare_elements(a: &[Value], b: &[Value]) -> Option<Ordering> {
+    for (x, y) in a.iter().zip(b) {
+        match x.compare(y)? {
+            Ordering::Equal => continue,
+            ordering => return Some(ordering),
+        }
+    }
+    Some(a.len().cmp(&b.len()))
+}
+
+// How numbers are written when values are printed or formatted.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum NumberFormat {
+    // The shortest digits that read back as the same number, without a
+    // fraction for integral values: `1`, `0.30000000000000004`.
+    #[default]
+    IntegerIfIntegral,
+    // The shortest round-trip digits, keeping `.0` on integral values: `1.0`.
+    Shortest,
+    // A fixed number of digits after the decimal point: `0.30` for two.
+    Fixed(usize),
+}
+
+// How `OP_EQUAL`, the equality branches, `match` literals, and the natives
+// that search for a value compare numbers.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum NumberEquality {
+    // IEEE 754 equality: NaN is equal to nothing, itself included, and 0.0
+    // is equal to -0.0.
+    #[default]
+    Ieee,
+    // Equal when neither sorts before the other in IEEE 754 total order: a
+    // NaN is equal to a NaN with the same bits, and 0.0 and -0.0 differ.
+    Total,
+}
+
+// A value written with a given number format, which applies to numbers
+// inside composite values too.
+pub(crate) struct Formatted<'v> {
+    value: &'v Value,
+    numbers: NumberFormat,
+}
+
+impl Value {
+    pub(crate) fn display(&self, numbers: NumberFormat) -> Formatted<'_> {
+        Formatted {
+            value: self,
+            numbers,
         }
     }
 }
 
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Value::Number(n) => write!(f, "{}", n),
+        self.display(NumberFormat::default()).fmt(f)
+    }
+}
+
+impl fmt::Display for Formatted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.value {
+            Value::Nil => write!(f, "nil"),
+            Value::Number(n) => match self.numbers {
+                NumberFormat::IntegerIfIntegral => write!(f, "{}", n),
+                NumberFormat::Shortest if n.is_finite() && n.fract() == 0.0 => {
+                    write!(f, "{}.0", n)
+                }
+                NumberFormat::Shortest => write!(f, "{}", n),
+                NumberFormat::Fixed(digits) => write!(f, "{:.*}", digits, n),
+            },
+            #[cfg(feature = "bigint")]
+            Value::BigInt(n) => write!(f, "{}", n),
+            #[cfg(feature = "decimal")]
+            Value::Decimal(n) => write!(f, "{}", n),
             Value::Boolean(b) => write!(f, "{}", b),
+            Value::Char(c) => write!(f, "{}", c),
             Value::Str(s) => write!(f, "{}", s),
+            Value::Bytes(bytes) => {
+                write!(f, "0x")?;
+                for byte in bytes.iter() {
+                    write!(f, "{:02x}", byte)?;
+                }
+                Ok(())
+            }
+            Value::Array(elements) => {
+                write!(f, "[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", element.display(self.numbers))?;
+                }
+                write!(f, "]")
+            }
+            Value::Tuple(elements) => {
+                write!(f, "(")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", element.display(self.numbers))?;
+                }
+                if elements.len() == 1 {
+                    write!(f, ",")?;
+                }
+                write!(f, ")")
+            }
+            Value::Set(elements) => {
+                write!(f, "{{")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", element.display(self.numbers))?;
+                }
+                write!(f, "}}")
+            }
+            Value::Error(payload) => write!(f, "error({})", payload.display(self.numbers)),
+            Value::Timestamp(t) => write!(f, "{}", datetime::format_rfc3339(*t)),
+            Value::Duration(d) => write!(f, "{}", datetime::format_duration(*d)),
+            Value::StringBuilder(builder) => write!(f, "{}", builder.borrow()),
+            Value::Socket(_) => write!(f, "<socket>"),
+            #[cfg(feature = "sqlite")]
+            Value::Database(_) => write!(f, "<database>"),
+            Value::Weak(_) => write!(f, "<weak>"),
         }
     }
 }
 
+// The widest a composite value is written on one line by `Value::pretty`.
+const PRETTY_WIDTH: usize = 72;
+
+impl Value {
+    // The value written for reading: composite values that don't fit on a
+    // line are written an element to a line, indented by their depth, and
+    // those nested deeper than `depth_limit` are cut short as `[...]`. Heap
+    // values can't contain themselves, and a weak reference is written
+    // without following it, so the output is always finite.
+    pub fn pretty(&self, depth_limit: usize) -> String {
+        self.pretty_at(depth_limit, 0)
+    }
+
+    fn pretty_at(&self, depth_limit: usize, indent: usize) -> String {
+        let (open, close, elements) = match self {
+            Value::Array(elements) => ("[", "]", &elements[..]),
+            Value::Tuple(elements) => ("(", ")", &elements[..]),
+            Value::Set(elements) => ("{", "}", &elements[..]),
+            Value::Error(payload) => {
+                return format!("error({})", payload.pretty_at(depth_limit, indent));
+            }
+            _ => return self.to_string(),
+        };
+        if elements.is_empty() {
+            return format!("{}{}", open, close);
+        }
+        if depth_limit == 0 {
+            return format!("{}...{}", open, close);
+        }
+        let written: Vec<String> = elements
+            .iter()
+            .map(|element| element.pretty_at(depth_limit - 1, indent + 1))
+            .collect();
+        let trailer = if matches!(self, Value::Tuple(_)) && elements.len() == 1 {
+            ","
+        } else {
+            ""
+        };
+        let line = format!("{}{}{}{}", open, written.join(", "), trailer, close);
+        if !line.contains('\n') && indent * 2 + line.len() <= PRETTY_WIDTH {
+            return line;
+        }
+        let mut lines = String::from(open);
+        for element in written {
+            lines.push_str(&format!("\n{}{},", "  ".repeat(indent + 1), element));
+        }
+        lines.push_str(&format!("\n{}{}", "  ".repeat(indent), close));
+        lines
+    }
+}
+
+// Composite values compare element-wise. Heap values are immutable once
+// built, so a value can never contain itself and the comparison always
+// terminates.
 impl PartialEq for Value {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
+            (Value::Nil, Value::Nil) => true,
             (Value::Number(a), Value::Number(b)) => a == b,
+            #[cfg(feature = "bigint")]
+            (Value::BigInt(a), Value::BigInt(b)) => a == b,
+            #[cfg(feature = "decimal")]
+            (Value::Decimal(a), Value::Decimal(b)) => a == b,
             (Value::Boolean(a), Value::Boolean(b)) => a == b,
+            (Value::Char(a), Value::Char(b)) => a == b,
             (Value::Str(a), Value::Str(b)) => a == b,
+            (Value::Bytes(a), Value::Bytes(b)) => a == b,
+            (Value::Array(a), Value::Array(b)) => a == b,
+            (Value::Tuple(a), Value::Tuple(b)) => a == b,
+            (Value::Set(a), Value::Set(b)) => a == b,
+            (Value::Error(a), Value::Error(b)) => a == b,
+            (Value::Timestamp(a), Value::Timestamp(b)) => a == b,
+            (Value::Duration(a), Value::Duration(b)) => a == b,
+            (Value::StringBuilder(a), Value::StringBuilder(b)) => Rc::ptr_eq(a, b),
+            (Value::Socket(a), Value::Socket(b)) => Rc::ptr_eq(a, b),
+            #[cfg(feature = "sqlite")]
+            (Value::Database(a), Value::Database(b)) => Rc::ptr_eq(a, b),
+            (Value::Weak(a), Value::Weak(b)) => a.ptr_eq(b),
             _ => false,
         }
     }
@@ -202,108 +1322,814 @@ impl PartialEq for Value {
 pub(crate) struct Function {
     pub(crate) instructions: Vec<Instruction>,
     pub(crate) num_args: usize,
+    // Constant indexes of the default values of the last parameters.
+    pub(crate) defaults: Vec<u16>,
+    // Local variable slots, including the arguments.
+    pub(crate) num_locals: usize,
+    // The deepest the operand stack can get, found by the verifier when the
+    // program is loaded, and used to size each frame's stack.
+    pub(crate) max_stack: Option<usize>,
 }
 
 impl Function {
-    fn new(instructions: Vec<Instruction>, num_args: usize) -> Self {
+    // The local count covers the arguments and every slot the instructions
+    // use.
+    pub(crate) fn new(instructions: Vec<Instruction>, num_args: usize) -> Self {
+        let num_locals = instructions
+            .iter()
+            .filter_map(|i| i.local())
+            .map(|slot| slot + 1)
+            .fold(num_args, usize::max);
         Function {
             instructions,
             num_args,
+            defaults: Vec::new(),
+            num_locals,
+            max_stack: None,
         }
     }
 
+    pub(crate) fn with_defaults(mut self, defaults: Vec<u16>) -> Self {
+        self.defaults = defaults;
+        self
+    }
+
+    // The fewest arguments a call may pass.
+    pub(crate) fn required_args(&self) -> usize {
+        self.num_args - self.defaults.len()
+    }
+
     pub(crate) fn get_instruction(&self, index: usize) -> &Instruction {
-        return self
-            .instructions
+        self.instructions
             .get(index)
-            .expect("Invalid instruction index");
+            .expect("Invalid instruction index")
+    }
+}
+
+// The version of the bytecode format written by `write`.
+const VERSION: u8 = 6;
+
+// The magic number of an encrypted program, in place of `ZRCN`.
+pub(crate) const ENCRYPTED_MAGIC: &[u8; 4] = b"ZRCE";
+
+// The magic number of a verified program written by `write_verified`.
+const VERIFIED_MAGIC: &[u8; 4] = b"ZRCV";
+
+// Loads the program in a `.zrcv` file written by `write_verified`, if there
+// is one and it was written for `source`, without following its control flow
+// again. The digest covers the source and everything after it in the file,
+// and the local slots and stack depths are checked, so a missing, stale, or
+// damaged file is ignored and the source is verified in full instead. A
+// depth can only exceed the instruction count through instructions that push
+// several values, so such a rare program is verified in full as well.
+fn read_verified(path: &Path, source: &[u8], options: &LoadOptions) -> Option<Bytecode> {
+    let file = fs::read(path).ok()?;
+    let mut reader = file.strip_prefix(VERIFIED_MAGIC)?;
+    let digest = reader.read_u64::<LittleEndian>().ok()?;
+    if digest != builtins::hash::digest_parts(&[source, reader]) {
+        return None;
+    }
+    let count = reader.read_u32::<LittleEndian>().ok()? as usize;
+    let mut depths = Vec::with_capacity(count.min(reader.len() / 4));
+    for _ in 0..count {
+        depths.push(reader.read_u32::<LittleEndian>().ok()? as usize);
+    }
+    let (constants, functions, unknown_sections) = parse_program(reader, options).ok()?;
+    if functions.len() != depths.len()
+        || functions
+            .iter()
+            .zip(&depths)
+            .any(|(function, &depth)| depth > function.instructions.len())
+    {
+        return None;
+    }
+    let mut bytecode = Bytecode {
+        functions,
+        constants,
+        unknown_sections,
+        natives: Vec::new(),
+    };
+    verifier::check_slots(&bytecode).ok()?;
+    bytecode.finish_loading(depths);
+    Some(bytecode)
+}
+
+// Decodes the sections of a program in memory.
+fn parse_program(bytes: &[u8], options: &LoadOptions) -> io::Result<Sections> {
+    let mut reader = bytes;
+    let (version, big_endian) = read_header(&mut reader)?;
+    if big_endian {
+        parse_body::<BigEndian>(bytes, &mut reader, options, version)
+    } else {
+        parse_body::<LittleEndian>(bytes, &mut reader, options, version)
+    }
+}
+
+// Reads the bytes of a program file, decrypting it if it is encrypted.
+#[cfg(feature = "signing")]
+pub(crate) fn read_program_file(path: &Path) -> io::Result<Vec<u8>> {
+    decode_program(fs::read(path)?)
+}
+
+// Decrypts the bytes of a program file if it is encrypted.
+pub(crate) fn decode_program(bytes: Vec<u8>) -> io::Result<Vec<u8>> {
+    if !bytes.starts_with(ENCRYPTED_MAGIC) {
+        return Ok(bytes);
+    }
+    #[cfg(feature = "encryption")]
+    return crate::encryption::decrypt_with_environment_key(&bytes);
+    #[cfg(not(feature = "encryption"))]
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "Encrypted bytecode requires the encryption feature",
+    ))
+}
+
+// What to do with a string constant that isn't valid UTF-8, such as one
+// generated from legacy data.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum InvalidUtf8 {
+    // Fail to load the program.
+    #[default]
+    Error,
+    // Replace each invalid sequence with U+FFFD.
+    Replace,
+    // Load the constant as bytes instead of a string.
+    Bytes,
+}
+
+// Settings for `Bytecode::from_file_with_options` and `read_with_options`.
+#[derive(Clone, Debug, Default)]
+pub struct LoadOptions {
+    invalid_utf8: InvalidUtf8,
+    extensions: Vec<&'static Extension>,
+}
+
+impl LoadOptions {
+    pub fn new() -> Self {
+        LoadOptions::default()
+    }
+
+    pub fn invalid_utf8(mut self, policy: InvalidUtf8) -> Self {
+        self.invalid_utf8 = policy;
+        self
+    }
+
+    // Accepts an extension instruction, which fails to load otherwise.
+    pub fn extension(mut self, extension: &'static Extension) -> Self {
+        self.extensions.push(extension);
+        self
     }
 }
 
-pub(crate) struct Bytecode {
+pub struct Bytecode {
     functions: Vec<Function>,
     constants: Vec<Value>,
+    // Sections this version doesn't know, by ID, kept so that writing the
+    // program back out doesn't lose them.
+    unknown_sections: Vec<(u8, Vec<u8>)>,
+    // The native function each constant names, by constant index, looked up
+    // once when the program is loaded instead of every time it is called.
+    natives: Vec<Option<&'static NativeFunction>>,
 }
 
 impl Bytecode {
-    // fn new() -> Self {
-    //     Bytecode {
-    //         functions: Vec::new(),
-    //         constants: Vec::new(),
-    //     }
-    // }
+    pub(crate) fn new() -> Self {
+        Bytecode {
+            functions: Vec::new(),
+            constants: Vec::new(),
+            unknown_sections: Vec::new(),
+            natives: Vec::new(),
+        }
+    }
+
+    // Encrypted programs are decrypted with the key in `ZIRCON_BYTECODE_KEY`
+    // when the encryption feature is enabled. A `.zrcv` file written for the
+    // program by `write_verified` is loaded in its place.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Bytecode::from_file_with_options(path, &LoadOptions::default())
+    }
+
+    pub fn from_file_with_options<P: AsRef<Path>>(
+        path: P,
+        options: &LoadOptions,
+    ) -> io::Result<Self> {
+        let path = path.as_ref();
+        let bytes = fs::read(path)?;
+        if let Some(bytecode) = read_verified(&path.with_extension("zrcv"), &bytes, options) {
+            return Ok(bytecode);
+        }
+        Bytecode::from_bytes_with_options(&decode_program(bytes)?, options)
+    }
+
+    // Loads a program that is already in memory, such as a mapped file. The
+    // sections are decoded where they are instead of being copied out first.
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        Bytecode::from_bytes_with_options(bytes, &LoadOptions::default())
+    }
+
+    pub fn from_bytes_with_options(bytes: &[u8], options: &LoadOptions) -> io::Result<Self> {
+        Bytecode::from_sections(parse_program(bytes, options)?)
+    }
+
+    // Reads a program, ignoring anything after the last section, such as a
+    // signature.
+    pub fn read<R: Read>(file: &mut R) -> io::Result<Self> {
+        Bytecode::read_with_options(file, &LoadOptions::default())
+    }
 
-    pub(crate) fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
-        let mut file = BufReader::new(File::open(path)?);
-        let mut magic = [0u8; 4];
-        file.read_exact(&mut magic)?;
+    pub fn read_with_options<R: Read>(file: &mut R, options: &LoadOptions) -> io::Result<Self> {
+        let (version, big_endian) = read_header(file)?;
+        let sections = if big_endian {
+            read_body::<BigEndian, _>(file, options, version)?
+        } else {
+            read_body::<LittleEndian, _>(file, options, version)?
+        };
+        Bytecode::from_sections(sections)
+    }
+
+    fn from_sections((constants, functions, unknown_sections): Sections) -> io::Result<Self> {
+        let mut bytecode = Bytecode {
+            functions,
+            constants,
+            unknown_sections,
+            natives: Vec::new(),
+        };
+        let depths = verifier::verify(&bytecode)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        bytecode.finish_loading(depths);
+        Ok(bytecode)
+    }
+
+    // Records the deepest operand stack of each function, as found by the
+    // verifier, and looks up the natives the constants name.
+    fn finish_loading(&mut self, depths: Vec<usize>) {
+        for (function, depth) in self.functions.iter_mut().zip(depths) {
+            function.max_stack = Some(depth);
+        }
+        self.natives = self
+            .constants
+            .iter()
+            .map(|constant| match constant {
+                Value::Str(name) => builtins::lookup(name),
+                _ => None,
+            })
+            .collect();
+    }
+
+    // The IDs and contents of sections that were loaded but not understood.
+    pub fn unknown_sections(&self) -> &[(u8, Vec<u8>)] {
+        &self.unknown_sections
+    }
+
+    pub(crate) fn functions(&self) -> &[Function] {
+        &self.functions
+    }
+
+    pub(crate) fn functions_mut(&mut self) -> &mut [Function] {
+        &mut self.functions
+    }
+
+    pub(crate) fn get_function(&self, index: usize) -> &Function {
+        self.functions.get(index).expect("Invalid function index")
+    }
 
-        // Check magic number
-        if magic != [b'Z', b'R', b'C', b'N'] {
+    pub(crate) fn get_constant(&self, index: usize) -> Option<&Value> {
+        self.constants.get(index)
+    }
+
+    // The native function named by a string constant.
+    pub(crate) fn get_native(&self, index: usize) -> Option<&'static NativeFunction> {
+        if let Some(&native) = self.natives.get(index) {
+            return native;
+        }
+        match self.constants.get(index) {
+            Some(Value::Str(name)) => builtins::lookup(name),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn constants(&self) -> &[Value] {
+        &self.constants
+    }
+
+    // The functions and constants, for passes that remove or renumber them.
+    // Natives are looked up by name again afterwards.
+    pub(crate) fn parts_mut(&mut self) -> (&mut Vec<Function>, &mut Vec<Value>) {
+        self.natives.clear();
+        (&mut self.functions, &mut self.constants)
+    }
+
+    pub(crate) fn add_function(&mut self, function: Function) {
+        self.functions.push(function);
+    }
+
+    pub(crate) fn add_constant(&mut self, constant: Value) -> usize {
+        self.constants.push(constant);
+        self.constants.len() - 1
+    }
+
+    pub fn to_file<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        self.write(&mut file)?;
+        file.flush()
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.write_version(writer, VERSION)
+    }
+
+    // Writes the program, once it passes the verifier, as a `.zrcv` file that
+    // holds what the verifier found and a hash of `source`, the bytecode file
+    // the program was loaded from. `from_file` loads the `.zrcv` file next to
+    // that bytecode file in its place, without verifying it again, until the
+    // bytecode file changes. The `.zrcv` file is not encrypted, so encrypted
+    // programs are refused.
+    pub fn write_verified<W: Write>(&self, writer: &mut W, source: &[u8]) -> io::Result<()> {
+        if source.starts_with(ENCRYPTED_MAGIC) {
             return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Invalid magic number",
+                io::ErrorKind::InvalidInput,
+                "Encrypted bytecode cannot be cached",
             ));
         }
+        let depths =
+            verifier::verify(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let mut body = Vec::new();
+        body.write_u32::<LittleEndian>(depths.len() as u32)?;
+        for depth in depths {
+            body.write_u32::<LittleEndian>(depth as u32)?;
+        }
+        self.write(&mut body)?;
+        writer.write_all(VERIFIED_MAGIC)?;
+        writer.write_u64::<LittleEndian>(builtins::hash::digest_parts(&[source, &body]))?;
+        writer.write_all(&body)
+    }
+
+    // Writes the program in another version of the format, such as an older
+    // one for tools that only read that version. Fails if the program uses something the
+    // version can't hold: default arguments before version 2, or extra
+    // sections before version 4.
+    pub fn write_version<W: Write>(&self, writer: &mut W, version: u8) -> io::Result<()> {
+        let unsupported = |message| Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        if !(1..=VERSION).contains(&version) {
+            return unsupported("Unsupported version".to_string());
+        }
+        if version < 2 && self.functions.iter().any(|f| !f.defaults.is_empty()) {
+            return unsupported(format!("Version {} has no default arguments", version));
+        }
+        if version < 4 && !self.unknown_sections.is_empty() {
+            return unsupported(format!("Version {} has no sections", version));
+        }
+
+        let mut constants = Vec::new();
+        constants.write_u32::<LittleEndian>(self.constants.len() as u32)?;
+        for constant in &self.constants {
+            write_constant(&mut constants, constant)?;
+        }
+
+        let mut functions = Vec::new();
+        functions.write_u32::<LittleEndian>(self.functions.len() as u32)?;
+        for function in &self.functions {
+            write_function(&mut functions, function, version)?;
+        }
+
+        writer.write_all(b"ZRCN")?;
+        writer.write_u8(version)?;
+        if version < 5 {
+            writer.write_all(&constants)?;
+            writer.write_all(&functions)?;
+            if version == 4 {
+                write_positional_sections(writer, &self.unknown_sections)?;
+            }
+            return Ok(());
+        }
+
+        let mut sections = vec![
+            (SECTION_CONSTANTS, constants.as_slice()),
+            (SECTION_FUNCTIONS, functions.as_slice()),
+        ];
+        for (id, contents) in &self.unknown_sections {
+            sections.push((*id, contents));
+        }
+        let count = u16::try_from(sections.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Too many sections"))?;
+
+        if version >= 6 {
+            writer.write_u8(LITTLE_ENDIAN)?;
+        }
+        writer.write_u16::<LittleEndian>(count)?;
+        let too_long = || io::Error::new(io::ErrorKind::InvalidInput, "Program is too long");
+        let mut offsets = Vec::with_capacity(sections.len());
+        let mut offset = u64::from(contents_offset(version, sections.len()));
+        for (id, contents) in &sections {
+            offset = offset.next_multiple_of(SECTION_ALIGNMENT);
+            offsets.push(offset);
+            let len = u32::try_from(contents.len()).map_err(|_| too_long())?;
+            writer.write_u8(*id)?;
+            writer.write_u32::<LittleEndian>(u32::try_from(offset).map_err(|_| too_long())?)?;
+            writer.write_u32::<LittleEndian>(len)?;
+            offset += u64::from(len);
+        }
+        let mut position = u64::from(contents_offset(version, sections.len()));
+        for ((_, contents), offset) in sections.iter().zip(offsets) {
+            let padding = [0; SECTION_ALIGNMENT as usize];
+            writer.write_all(&padding[..(offset - position) as usize])?;
+            writer.write_all(contents)?;
+            position = offset + contents.len() as u64;
+        }
+        Ok(())
+    }
+}
+
+// Checks the magic number, and returns the version and whether the file is
+// big-endian.
+fn read_header<R: Read>(reader: &mut R) -> io::Result<(u8, bool)> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+
+    // Check magic number
+    if magic != [b'Z', b'R', b'C', b'N'] {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Invalid magic number",
+        ));
+    }
+
+    // Version 2 added default arguments to the function headers, version 3
+    // the local count, and version 4 the sections after the functions.
+    // Version 5 puts everything in sections, and version 6 records the byte
+    // order.
+    let version = reader.read_u8()?;
+    if !(1..=VERSION).contains(&version) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Unsupported version",
+        ));
+    }
+    if version < 6 {
+        return Ok((version, false));
+    }
+    match reader.read_u8()? {
+        LITTLE_ENDIAN => Ok((version, false)),
+        BIG_ENDIAN => Ok((version, true)),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Unknown byte order",
+        )),
+    }
+}
+
+// The byte order marker after the version. Programs are written
+// little-endian, but a big-endian file from another toolchain loads too.
+const LITTLE_ENDIAN: u8 = 0x01;
+const BIG_ENDIAN: u8 = 0x02;
+
+#[cfg(any(feature = "bigint", feature = "decimal"))]
+fn is_big_endian<B: ByteOrder>() -> bool {
+    B::read_u16(&[0, 1]) == 1
+}
+
+fn read_body<B: ByteOrder, R: Read>(
+    reader: &mut R,
+    options: &LoadOptions,
+    version: u8,
+) -> io::Result<Sections> {
+    if version >= 5 {
+        read_sectioned::<B, _>(reader, options, version)
+    } else {
+        read_positional::<B, _>(reader, options, version)
+    }
+}
+
+// Like `read_body`, where `reader` is the rest of `bytes` after the header.
+fn parse_body<B: ByteOrder>(
+    bytes: &[u8],
+    reader: &mut &[u8],
+    options: &LoadOptions,
+    version: u8,
+) -> io::Result<Sections> {
+    if version >= 5 {
+        let table = read_section_table::<B, _>(reader, version)?;
+        parse_sections::<B>(bytes, 0, &table, options)
+    } else {
+        read_positional::<B, _>(reader, options, version)
+    }
+}
+
+type Sections = (Vec<Value>, Vec<Function>, Vec<(u8, Vec<u8>)>);
+
+// Section IDs. IDs 0x03 to 0x05 are reserved for function names, debug
+// information and metadata.
+const SECTION_CONSTANTS: u8 = 0x01;
+const SECTION_FUNCTIONS: u8 = 0x02;
+
+// Every section written starts at a multiple of this offset in the file, so
+// that a program mapped or read into an aligned buffer has aligned sections.
+// Readers accept any offset.
+const SECTION_ALIGNMENT: u64 = 8;
+
+// Where section contents start: after the magic number, the version, from
+// version 6 the byte order, the section count, and a table entry per section.
+fn contents_offset(version: u8, sections: usize) -> u32 {
+    let byte_order = usize::from(version >= 6);
+    (4 + 1 + byte_order + 2 + 9 * sections) as u32
+}
+
+// Versions 1 to 4: the constants, then the functions, then from version 4 a
+// list of sections.
+fn read_positional<B: ByteOrder, R: Read>(
+    reader: &mut R,
+    options: &LoadOptions,
+    version: u8,
+) -> io::Result<Sections> {
+    let constants = read_constants::<B, _>(reader, options)?;
+    let functions = read_functions::<B, _>(reader, version, &options.extensions)?;
+    let unknown_sections = if version >= 4 {
+        read_sections::<B, _>(reader)?
+    } else {
+        Vec::new()
+    };
+    Ok((constants, functions, unknown_sections))
+}
+
+// Version 5: a table of sections, each an ID, an offset from the start of
+// the file, and a length, followed by their contents. A reader steps over
+// sections it doesn't know, and a missing section is empty.
+fn read_sectioned<B: ByteOrder, R: Read>(
+    reader: &mut R,
+    options: &LoadOptions,
+    version: u8,
+) -> io::Result<Sections> {
+    let table = read_section_table::<B, _>(reader, version)?;
+    let start = u64::from(contents_offset(version, table.len()));
+    let end = table
+        .iter()
+        .map(|&(_, offset, len)| offset + len)
+        .fold(start, u64::max);
+    let mut contents = Vec::new();
+    reader
+        .by_ref()
+        .take(end - start)
+        .read_to_end(&mut contents)?;
+    if contents.len() as u64 != end - start {
+        return Err(truncated_section());
+    }
+    parse_sections::<B>(&contents, start, &table, options)
+}
+
+type SectionTable = Vec<(u8, u64, u64)>;
 
-        let version = file.read_u8()?;
-        if version != 1 {
+fn read_section_table<B: ByteOrder, R: Read>(
+    reader: &mut R,
+    version: u8,
+) -> io::Result<SectionTable> {
+    let count = usize::from(reader.read_u16::<B>()?);
+    let start = u64::from(contents_offset(version, count));
+    let mut table = Vec::with_capacity(count);
+    for _ in 0..count {
+        let id = reader.read_u8()?;
+        let offset = u64::from(reader.read_u32::<B>()?);
+        let len = u64::from(reader.read_u32::<B>()?);
+        if offset < start {
             return Err(io::Error::new(
                 io::ErrorKind::InvalidData,
-                "Unsupported version",
+                "Section overlaps the section table",
             ));
         }
+        table.push((id, offset, len));
+    }
+    Ok(table)
+}
 
-        let num_constants = file.read_u32::<LittleEndian>()?;
+fn truncated_section() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "Truncated section")
+}
 
-        let mut constants = Vec::with_capacity(num_constants as usize);
-        for _ in 0..num_constants {
-            constants.push(read_constant(&mut file)?);
+// Decodes the sections in `buffer`, which holds the file from offset `base`
+// on.
+fn parse_sections<B: ByteOrder>(
+    buffer: &[u8],
+    base: u64,
+    table: &SectionTable,
+    options: &LoadOptions,
+) -> io::Result<Sections> {
+    let invalid = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+    let mut constants = None;
+    let mut functions = None;
+    let mut unknown_sections = Vec::new();
+    for &(id, offset, len) in table {
+        let start = (offset - base) as usize;
+        let mut section = buffer
+            .get(start..start + len as usize)
+            .ok_or_else(truncated_section)?;
+        match id {
+            SECTION_CONSTANTS if constants.is_none() => {
+                constants = Some(read_constants::<B, _>(&mut section, options)?);
+            }
+            SECTION_FUNCTIONS if functions.is_none() => {
+                functions = Some(read_functions::<B, _>(
+                    &mut section,
+                    VERSION,
+                    &options.extensions,
+                )?);
+            }
+            SECTION_CONSTANTS | SECTION_FUNCTIONS => return Err(invalid("Duplicate section")),
+            _ => {
+                unknown_sections.push((id, section.to_vec()));
+                continue;
+            }
         }
+        if !section.is_empty() {
+            return Err(invalid("Section has trailing bytes"));
+        }
+    }
+    Ok((
+        constants.unwrap_or_default(),
+        functions.unwrap_or_default(),
+        unknown_sections,
+    ))
+}
 
-        let num_functions = file.read_u32::<LittleEndian>()?;
+fn read_constants<B: ByteOrder, R: Read>(
+    reader: &mut R,
+    options: &LoadOptions,
+) -> io::Result<Vec<Value>> {
+    let num_constants = reader.read_u32::<B>()?;
+    let mut loaded = Vec::with_capacity(num_constants as usize);
+    let mut strings = Vec::new();
+    for _ in 0..num_constants {
+        loaded.push(read_constant::<B, _>(reader, options, &mut strings)?);
+    }
+    // Only whole strings that were valid UTF-8 were kept.
+    let strings = String::from_utf8(strings).expect("String constants are valid UTF-8.");
+    if u32::try_from(strings.len()).is_err() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "String constants are too long",
+        ));
+    }
+    let pool = Pool::new(strings);
+    Ok(loaded
+        .into_iter()
+        .map(|constant| constant.into_value(&pool))
+        .collect())
+}
 
-        let mut functions = Vec::with_capacity(num_functions as usize);
-        for _ in 0..num_functions {
-            functions.push(read_function(&mut file)?);
-        }
+// A constant as it is read. The strings are ranges of the program's string
+// pool, which is only complete once every constant has been read.
+enum Loaded {
+    Value(Value),
+    Str(Range<usize>),
+    Tuple(Vec<Loaded>),
+}
 
-        Ok(Bytecode {
-            functions,
-            constants,
-        })
+impl Loaded {
+    fn into_value(self, pool: &Rc<Pool>) -> Value {
+        match self {
+            Loaded::Value(value) => value,
+            Loaded::Str(range) => Value::Str(Text::pooled(pool, range)),
+            Loaded::Tuple(elements) => Value::Tuple(
+                elements
+                    .into_iter()
+                    .map(|element| element.into_value(pool))
+                    .collect(),
+            ),
+        }
     }
+}
 
-    pub(crate) fn get_function(&self, index: usize) -> &Function {
-        self.functions.get(index).expect("Invalid function index")
+fn read_functions<B: ByteOrder, R: Read>(
+    reader: &mut R,
+    version: u8,
+    extensions: &[&'static Extension],
+) -> io::Result<Vec<Function>> {
+    let num_functions = reader.read_u32::<B>()?;
+    let mut functions = Vec::with_capacity(num_functions as usize);
+    for _ in 0..num_functions {
+        functions.push(read_function::<B, _>(reader, version, extensions)?);
     }
+    Ok(functions)
+}
 
-    pub(crate) fn get_constant(&self, index: usize) -> Option<&Value> {
-        self.constants.get(index)
+// In version 4, each section is an ID byte and a length, so a reader can
+// step over one it doesn't know.
+fn read_sections<B: ByteOrder, R: Read>(reader: &mut R) -> io::Result<Vec<(u8, Vec<u8>)>> {
+    let count = reader.read_u16::<B>()?;
+    let mut unknown = Vec::new();
+    for _ in 0..count {
+        let id = reader.read_u8()?;
+        let len = reader.read_u32::<B>()? as usize;
+        let mut contents = Vec::new();
+        reader
+            .by_ref()
+            .take(len as u64)
+            .read_to_end(&mut contents)?;
+        if contents.len() != len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Truncated section",
+            ));
+        }
+        unknown.push((id, contents));
     }
+    Ok(unknown)
+}
 
-    // fn add_function(&mut self, function: Function) {
-    //     self.functions.push(function);
-    // }
-    //
-    // fn add_constant(&mut self, constant: Value) -> usize {
-    //     self.constants.push(constant);
-    //     self.constants.len() - 1
-    // }
+// The version 4 form of `read_sections`.
+fn write_positional_sections<W: Write>(
+    writer: &mut W,
+    sections: &[(u8, Vec<u8>)],
+) -> io::Result<()> {
+    let count = u16::try_from(sections.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Too many sections"))?;
+    writer.write_u16::<LittleEndian>(count)?;
+    for (id, contents) in sections {
+        let len = u32::try_from(contents.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Program is too long"))?;
+        writer.write_u8(*id)?;
+        writer.write_u32::<LittleEndian>(len)?;
+        writer.write_all(contents)?;
+    }
+    Ok(())
 }
 
-fn read_constant<R: Read>(reader: &mut R) -> io::Result<Value> {
+fn read_constant<B: ByteOrder, R: Read>(
+    reader: &mut R,
+    options: &LoadOptions,
+    strings: &mut Vec<u8>,
+) -> io::Result<Loaded> {
     let type_id = reader.read_u8()?;
     match type_id {
-        0x01 => Ok(Value::Number(reader.read_f64::<LittleEndian>()?)),
-        0x02 => Ok(Value::Boolean(reader.read_u8()? != 0)),
+        0x01 => Ok(Loaded::Value(Value::Number(reader.read_f64::<B>()?))),
+        0x02 => Ok(Loaded::Value(Value::Boolean(reader.read_u8()? != 0))),
         0x03 => {
-            let len = reader.read_u16::<LittleEndian>()? as usize;
+            // Read straight into the pool, and taken back out if it isn't
+            // UTF-8.
+            let len = reader.read_u16::<B>()? as usize;
+            let start = strings.len();
+            strings.resize(start + len, 0);
+            reader.read_exact(&mut strings[start..])?;
+            if let Err(e) = std::str::from_utf8(&strings[start..]) {
+                let bytes = strings.split_off(start);
+                return match options.invalid_utf8 {
+                    InvalidUtf8::Error => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+                    InvalidUtf8::Replace => Ok(Loaded::Value(Value::Str(
+                        String::from_utf8_lossy(&bytes).into_owned().into(),
+                    ))),
+                    InvalidUtf8::Bytes => Ok(Loaded::Value(Value::Bytes(bytes.into()))),
+                };
+            }
+            Ok(Loaded::Str(start..strings.len()))
+        }
+        #[cfg(feature = "bigint")]
+        0x04 => {
+            let len = reader.read_u16::<B>()? as usize;
             let mut buffer = vec![0; len];
             reader.read_exact(&mut buffer)?;
-            let string = String::from_utf8(buffer)
-                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-            Ok(Value::Str(string))
+            // Stored in the file's byte order.
+            if is_big_endian::<B>() {
+                buffer.reverse();
+            }
+            Ok(Loaded::Value(Value::BigInt(BigInt::from_signed_bytes_le(
+                &buffer,
+            ))))
+        }
+        #[cfg(not(feature = "bigint"))]
+        0x04 => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "BigInt constants require the bigint feature",
+        )),
+        #[cfg(feature = "decimal")]
+        0x05 => {
+            let mut buffer = [0; 16];
+            reader.read_exact(&mut buffer)?;
+            // Each of the four words is in the file's byte order.
+            if is_big_endian::<B>() {
+                for word in buffer.chunks_mut(4) {
+                    word.reverse();
+                }
+            }
+            Ok(Loaded::Value(Value::Decimal(Decimal::deserialize(buffer))))
+        }
+        #[cfg(not(feature = "decimal"))]
+        0x05 => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Decimal constants require the decimal feature",
+        )),
+        0x06 => {
+            let code = reader.read_u32::<B>()?;
+            let c = char::from_u32(code).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "Invalid character constant")
+            })?;
+            Ok(Loaded::Value(Value::Char(c)))
+        }
+        0x07 => {
+            let len = reader.read_u16::<B>()? as usize;
+            let mut elements = Vec::with_capacity(len);
+            for _ in 0..len {
+                elements.push(read_constant::<B, _>(reader, options, strings)?);
+            }
+            Ok(Loaded::Tuple(elements))
         }
         _ => Err(io::Error::new(
             io::ErrorKind::InvalidData,
@@ -312,21 +2138,124 @@ fn read_constant<R: Read>(reader: &mut R) -> io::Result<Value> {
     }
 }
 
-fn read_function<R: Read>(reader: &mut R) -> io::Result<Function> {
-    let num_instructions = reader.read_u32::<LittleEndian>()?;
-    let num_args = reader.read_u32::<LittleEndian>()? as usize;
+fn write_length<W: Write>(writer: &mut W, len: usize) -> io::Result<()> {
+    let len = u16::try_from(len)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Constant is too long"))?;
+    writer.write_u16::<LittleEndian>(len)
+}
+
+fn write_constant<W: Write>(writer: &mut W, constant: &Value) -> io::Result<()> {
+    match constant {
+        Value::Number(n) => {
+            writer.write_u8(0x01)?;
+            writer.write_f64::<LittleEndian>(*n)
+        }
+        Value::Boolean(b) => {
+            writer.write_u8(0x02)?;
+            writer.write_u8(*b as u8)
+        }
+        Value::Str(s) => {
+            writer.write_u8(0x03)?;
+            write_length(writer, s.len())?;
+            writer.write_all(s.as_bytes())
+        }
+        #[cfg(feature = "bigint")]
+        Value::BigInt(n) => {
+            let bytes = n.to_signed_bytes_le();
+            writer.write_u8(0x04)?;
+            write_length(writer, bytes.len())?;
+            writer.write_all(&bytes)
+        }
+        #[cfg(feature = "decimal")]
+        Value::Decimal(n) => {
+            writer.write_u8(0x05)?;
+            writer.write_all(&n.serialize())
+        }
+        Value::Char(c) => {
+            writer.write_u8(0x06)?;
+            writer.write_u32::<LittleEndian>(*c as u32)
+        }
+        Value::Tuple(elements) => {
+            writer.write_u8(0x07)?;
+            write_length(writer, elements.len())?;
+            for element in elements.iter() {
+                write_constant(writer, element)?;
+            }
+            Ok(())
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Value cannot be stored as a constant",
+        )),
+    }
+}
+
+fn write_function<W: Write>(writer: &mut W, function: &Function, version: u8) -> io::Result<()> {
+    writer.write_u32::<LittleEndian>(function.instructions.len() as u32)?;
+    writer.write_u32::<LittleEndian>(function.num_args as u32)?;
+    if version >= 3 {
+        writer.write_u32::<LittleEndian>(function.num_locals as u32)?;
+    }
+    if version >= 2 {
+        let len = u16::try_from(function.defaults.len()).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Too many default arguments")
+        })?;
+        writer.write_u16::<LittleEndian>(len)?;
+        for &index in &function.defaults {
+            writer.write_u16::<LittleEndian>(index)?;
+        }
+    }
+    for instruction in &function.instructions {
+        instruction.encode(writer)?;
+    }
+    Ok(())
+}
+
+// Local slots are 16-bit operands, so no function can use more locals than
+// this. A larger count in a file would only make every call allocate them.
+const MAX_LOCALS: usize = u16::MAX as usize + 1;
+
+fn read_function<B: ByteOrder, R: Read>(
+    reader: &mut R,
+    version: u8,
+    extensions: &[&'static Extension],
+) -> io::Result<Function> {
+    let num_instructions = reader.read_u32::<B>()?;
+    let num_args = reader.read_u32::<B>()? as usize;
+    let num_locals = if version >= 3 {
+        Some(reader.read_u32::<B>()? as usize)
+    } else {
+        None
+    };
+    if num_args > MAX_LOCALS || num_locals.is_some_and(|n| n > MAX_LOCALS) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Functions have at most {} locals", MAX_LOCALS),
+        ));
+    }
+    let mut defaults = Vec::new();
+    if version >= 2 {
+        let len = reader.read_u16::<B>()? as usize;
+        if len > num_args {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "More default arguments than parameters",
+            ));
+        }
+        for _ in 0..len {
+            defaults.push(reader.read_u16::<B>()?);
+        }
+    }
     let mut instructions = Vec::with_capacity(num_instructions as usize);
 
     for _ in 0..num_instructions {
-        let opcode = Opcode::from_u8(reader.read_u8()?)?;
-        let has_operand = opcode.has_operand();
-        let operand = if has_operand {
-            Some(reader.read_u16::<LittleEndian>()?)
-        } else {
-            None
-        };
-        instructions.push(Instruction::new(opcode, operand));
+        instructions.push(Instruction::decode_with::<B, _>(reader, extensions)?);
     }
 
-    Ok(Function::new(instructions, num_args))
+    let mut function = Function::new(instructions, num_args).with_defaults(defaults);
+    // Older files leave the count to be worked out from the instructions.
+    if let Some(num_locals) = num_locals {
+        function.num_locals = num_locals;
+    }
+    Ok(function)
 }
@@ -0,0 +1,367 @@
+use std::collections::HashMap;
+
+use super::parser::{BinaryOp, Expr, FunctionDecl, Program, Stmt, UnaryOp};
+use super::CompileError;
+use crate::builtins;
+use crate::bytecode::{Bytecode, Function, Instruction, Opcode, Value};
+
+#[derive(Hash, PartialEq, Eq)]
+enum ConstantKey {
+    Number(u64),
+    Boolean(bool),
+    Str(String),
+}
+
+struct Generator {
+    bytecode: Bytecode,
+    constants: HashMap<ConstantKey, u16>,
+    // Maps each declared function to its index and number of parameters.
+    functions: HashMap<String, (u16, usize)>,
+}
+
+struct FunctionState {
+    instructions: Vec<Instruction>,
+    scopes: Vec<Vec<(String, u16)>>,
+    next_slot: usize,
+    // Slot used to discard the values of expression statements.
+    scratch: Option<u16>,
+    is_main: bool,
+    line: usize,
+}
+
+impl FunctionState {
+    fn new(is_main: bool, line: usize) -> Self {
+        FunctionState {
+            instructions: Vec::new(),
+            scopes: vec![Vec::new()],
+            next_slot: 0,
+            scratch: None,
+            is_main,
+            line,
+        }
+    }
+
+    fn error(&self, message: String) -> CompileError {
+        CompileError {
+            line: self.line,
+            message,
+        }
+    }
+
+    fn emit(&mut self, opcode: Opcode, operand: Option<u16>) -> usize {
+        self.instructions.push(Instruction::new(opcode, operand));
+        self.instructions.len() - 1
+    }
+
+    fn here(&self) -> Result<u16, CompileError> {
+        u16::try_from(self.instructions.len())
+            .map_err(|_| self.error("Function is too long".to_string()))
+    }
+
+    fn patch_jump(&mut self, index: usize, target: u16) {
+        let opcode = self.instructions[index].opcode();
+        self.instructions[index] = Instruction::new(opcode, Some(target));
+    }
+
+    fn allocate_slot(&mut self) -> Result<u16, CompileError> {
+        let slot = u16::try_from(self.next_slot)
+            .map_err(|_| self.error("Too many local variables".to_string()))?;
+        self.next_slot += 1;
+        Ok(slot)
+    }
+
+    fn declare(&mut self, name: &str) -> Result<u16, CompileError> {
+        let slot = self.allocate_slot()?;
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .push((name.to_string(), slot));
+        Ok(slot)
+    }
+
+    fn resolve(&self, name: &str) -> Result<u16, CompileError> {
+        self.scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev())
+            .find(|(local, _)| local == name)
+            .map(|&(_, slot)| slot)
+            .ok_or_else(|| self.error(format!("Undefined variable '{}'", name)))
+    }
+
+    fn scratch(&mut self) -> Result<u16, CompileError> {
+        match self.scratch {
+            Some(slot) => Ok(slot),
+            None => {
+                let slot = self.allocate_slot()?;
+                self.scratch = Some(slot);
+                Ok(slot)
+            }
+        }
+    }
+}
+
+// Top-level statements become function 0, which ends with a halt. Declared
+// functions follow in source order, so calls can refer to functions declared
+// later in the file.
+pub(super) fn generate(program: &Program) -> Result<Bytecode, CompileError> {
+    let mut generator = Generator {
+        bytecode: Bytecode::new(),
+        constants: HashMap::new(),
+        functions: HashMap::new(),
+    };
+
+    for (i, decl) in program.functions.iter().enumerate() {
+        let index = u16::try_from(i + 1).map_err(|_| CompileError {
+            line: decl.line,
+            message: "Too many functions".to_string(),
+        })?;
+        if generator
+            .functions
+            .insert(decl.name.clone(), (index, decl.params.len()))
+            .is_some()
+        {
+            return Err(CompileError {
+                line: decl.line,
+                message: format!("Function '{}' is already defined", decl.name),
+            });
+        }
+    }
+
+    let mut main = FunctionState::new(true, 1);
+    generator.block(&mut main, &program.main)?;
+    main.emit(Opcode::Halt, None);
+    generator
+        .bytecode
+        .add_function(Function::new(main.instructions, 0));
+
+    for decl in &program.functions {
+        generator.function(decl)?;
+    }
+
+    Ok(generator.bytecode)
+}
+
+// An expression compiles to function 0, taking one argument per parameter and
+// returning the value of the expression.
+pub(super) fn generate_expr(expr: &Expr, params: &[&str]) -> Result<Bytecode, CompileError> {
+    let mut generator = Generator {
+        bytecode: Bytecode::new(),
+        constants: HashMap::new(),
+        functions: HashMap::new(),
+    };
+    let mut state = FunctionState::new(false, 1);
+    for param in params {
+        state.declare(param)?;
+    }
+    generator.expression(&mut state, expr)?;
+    state.emit(Opcode::Return, None);
+    generator
+        .bytecode
+        .add_function(Function::new(state.instructions, params.len()));
+    Ok(generator.bytecode)
+}
+
+impl Generator {
+    fn function(&mut self, decl: &FunctionDecl) -> Result<(), CompileError> {
+        let mut state = FunctionState::new(false, decl.line);
+        for param in &decl.params {
+            state.declare(param)?;
+        }
+        self.block(&mut state, &decl.body)?;
+        if !matches!(decl.body.last(), Some(Stmt::Return(_))) {
+            state.emit(Opcode::Return, None);
+        }
+        self.bytecode
+            .add_function(Function::new(state.instructions, decl.params.len()));
+        Ok(())
+    }
+
+    fn constant(&mut self, f: &FunctionState, value: Value) -> Result<u16, CompileError> {
+        let key = match &value {
+            Value::Number(n) => ConstantKey::Number(n.to_bits()),
+            Value::Boolean(b) => ConstantKey::Boolean(*b),
+            Value::Str(s) => ConstantKey::Str(s.to_string()),
+            _ => unreachable!(),
+        };
+        if let Some(&index) = self.constants.get(&key) {
+            return Ok(index);
+        }
+        let index = u16::try_from(self.bytecode.constants().len())
+            .map_err(|_| f.error("Too many constants".to_string()))?;
+        self.bytecode.add_constant(value);
+        self.constants.insert(key, index);
+        Ok(index)
+    }
+
+    fn block(&mut self, f: &mut FunctionState, statements: &[Stmt]) -> Result<(), CompileError> {
+        for statement in statements {
+            self.statement(f, statement)?;
+        }
+        Ok(())
+    }
+
+    fn scoped_block(
+        &mut self,
+        f: &mut FunctionState,
+        statements: &[Stmt],
+    ) -> Result<(), CompileError> {
+        f.scopes.push(Vec::new());
+        let result = self.block(f, statements);
+        f.scopes.pop();
+        result
+    }
+
+    fn statement(&mut self, f: &mut FunctionState, statement: &Stmt) -> Result<(), CompileError> {
+        match statement {
+            Stmt::Let { name, value } => {
+                self.expression(f, value)?;
+                let slot = f.declare(name)?;
+                f.emit(Opcode::SetLocal, Some(slot));
+            }
+            Stmt::Assign { name, value, line } => {
+                f.line = *line;
+                let slot = f.resolve(name)?;
+                self.expression(f, value)?;
+                f.emit(Opcode::SetLocal, Some(slot));
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expression(f, condition)?;
+                let jump_to_else = f.emit(Opcode::JumpIfFalse, Some(0));
+                self.scoped_block(f, then_branch)?;
+                match else_branch {
+                    Some(else_branch) => {
+                        let jump_to_end = f.emit(Opcode::Jump, Some(0));
+                        let else_start = f.here()?;
+                        f.patch_jump(jump_to_else, else_start);
+                        self.scoped_block(f, else_branch)?;
+                        let end = f.here()?;
+                        f.patch_jump(jump_to_end, end);
+                    }
+                    None => {
+                        let end = f.here()?;
+                        f.patch_jump(jump_to_else, end);
+                    }
+                }
+            }
+            Stmt::While { condition, body } => {
+                let start = f.here()?;
+                self.expression(f, condition)?;
+                let jump_to_end = f.emit(Opcode::JumpIfFalse, Some(0));
+                self.scoped_block(f, body)?;
+                f.emit(Opcode::Jump, Some(start));
+                let end = f.here()?;
+                f.patch_jump(jump_to_end, end);
+            }
+            Stmt::Print(value) => {
+                self.expression(f, value)?;
+                f.emit(Opcode::Print, None);
+            }
+            Stmt::Return(value) => {
+                if f.is_main {
+                    return Err(f.error("Cannot return from top-level code".to_string()));
+                }
+                if let Some(value) = value {
+                    self.expression(f, value)?;
+                }
+                f.emit(Opcode::Return, None);
+            }
+            Stmt::Expr(value) => {
+                self.expression(f, value)?;
+                let slot = f.scratch()?;
+                f.emit(Opcode::SetLocal, Some(slot));
+            }
+        }
+        Ok(())
+    }
+
+    fn expression(&mut self, f: &mut FunctionState, expr: &Expr) -> Result<(), CompileError> {
+        match expr {
+            Expr::Number(n) => {
+                let index = self.constant(f, Value::Number(*n))?;
+                f.emit(Opcode::PushConst, Some(index));
+            }
+            Expr::Str(s) => {
+                let index = self.constant(f, Value::Str(s.as_str().into()))?;
+                f.emit(Opcode::PushConst, Some(index));
+            }
+            Expr::Boolean(b) => {
+                let index = self.constant(f, Value::Boolean(*b))?;
+                f.emit(Opcode::PushConst, Some(index));
+            }
+            Expr::Variable { name, line } => {
+                f.line = *line;
+                let slot = f.resolve(name)?;
+                f.emit(Opcode::GetLocal, Some(slot));
+            }
+            Expr::Unary { op, operand } => {
+                self.expression(f, operand)?;
+                let opcode = match op {
+                    UnaryOp::Negate => Opcode::Negate,
+                    UnaryOp::Not => Opcode::Not,
+                };
+                f.emit(opcode, None);
+            }
+            Expr::Binary { op, left, right } => {
+                self.expression(f, left)?;
+                self.expression(f, right)?;
+                let opcode = match op {
+                    BinaryOp::Add => Opcode::Add,
+                    BinaryOp::Subtract => Opcode::Subtract,
+                    BinaryOp::Multiply => Opcode::Multiply,
+                    BinaryOp::Divide => Opcode::Divide,
+                    BinaryOp::Modulo => Opcode::Modulo,
+                    BinaryOp::Equal => Opcode::Equal,
+                    BinaryOp::NotEqual => Opcode::NotEqual,
+                    BinaryOp::Less => Opcode::LessThan,
+                    BinaryOp::Greater => Opcode::GreaterThan,
+                    BinaryOp::LessOrEqual => Opcode::LessOrEqual,
+                    BinaryOp::GreaterOrEqual => Opcode::GreaterOrEqual,
+                    BinaryOp::And => Opcode::And,
+                    BinaryOp::Or => Opcode::Or,
+                };
+                f.emit(opcode, None);
+            }
+            Expr::Call { name, args, line } => {
+                f.line = *line;
+                self.call(f, name, args)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Calls resolve to a declared function first and fall back to a native
+    // function of the same name.
+    fn call(
+        &mut self,
+        f: &mut FunctionState,
+        name: &str,
+        args: &[Expr],
+    ) -> Result<(), CompileError> {
+        let (opcode, operand, arity) = if let Some(&(index, arity)) = self.functions.get(name) {
+            (Opcode::Call, index, arity)
+        } else if let Some(native) = builtins::lookup(name) {
+            let index = self.constant(f, Value::Str(name.into()))?;
+            (Opcode::CallNative, index, native.arity)
+        } else {
+            return Err(f.error(format!("Unknown function '{}'", name)));
+        };
+        if args.len() != arity {
+            return Err(f.error(format!(
+                "Function '{}' expects {} arguments but got {}",
+                name,
+                arity,
+                args.len()
+            )));
+        }
+        for arg in args {
+            self.expression(f, arg)?;
+        }
+        f.emit(opcode, Some(operand));
+        Ok(())
+    }
+}
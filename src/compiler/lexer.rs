@@ -0,0 +1,254 @@
+use super::CompileError;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Token {
+    Number(f64),
+    Str(String),
+    Identifier(String),
+    Fn,
+    Let,
+    If,
+    Else,
+    While,
+    Return,
+    Print,
+    True,
+    False,
+    LeftParen,
+    RightParen,
+    LeftBrace,
+    RightBrace,
+    Comma,
+    Semicolon,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    Bang,
+    Assign,
+    EqualEqual,
+    BangEqual,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    AndAnd,
+    OrOr,
+    Eof,
+}
+
+impl Token {
+    pub(crate) fn describe(&self) -> String {
+        match self {
+            Token::Number(n) => format!("number {}", n),
+            Token::Str(_) => "string".to_string(),
+            Token::Identifier(name) => format!("'{}'", name),
+            Token::Eof => "end of input".to_string(),
+            token => format!("'{}'", token.symbol()),
+        }
+    }
+
+    fn symbol(&self) -> &'static str {
+        match self {
+            Token::Fn => "fn",
+            Token::Let => "let",
+            Token::If => "if",
+            Token::Else => "else",
+            Token::While => "while",
+            Token::Return => "return",
+            Token::Print => "print",
+            Token::True => "true",
+            Token::False => "false",
+            Token::LeftParen => "(",
+            Token::RightParen => ")",
+            Token::LeftBrace => "{",
+            Token::RightBrace => "}",
+            Token::Comma => ",",
+            Token::Semicolon => ";",
+            Token::Plus => "+",
+            Token::Minus => "-",
+            Token::Star => "*",
+            Token::Slash => "/",
+            Token::Percent => "%",
+            Token::Bang => "!",
+            Token::Assign => "=",
+            Token::EqualEqual => "==",
+            Token::BangEqual => "!=",
+            Token::Less => "<",
+            Token::Greater => ">",
+            Token::LessEqual => "<=",
+            Token::GreaterEqual => ">=",
+            Token::AndAnd => "&&",
+            Token::OrOr => "||",
+            Token::Number(_) | Token::Str(_) | Token::Identifier(_) | Token::Eof => "",
+        }
+    }
+}
+
+pub(crate) struct Lexer<'s> {
+    chars: std::iter::Peekable<std::str::Chars<'s>>,
+    line: usize,
+}
+
+impl<'s> Lexer<'s> {
+    pub(crate) fn new(source: &'s str) -> Self {
+        Lexer {
+            chars: source.chars().peekable(),
+            line: 1,
+        }
+    }
+
+    // Returns every token paired with the line it starts on, ending with Eof.
+    pub(crate) fn tokenize(mut self) -> Result<Vec<(Token, usize)>, CompileError> {
+        let mut tokens = Vec::new();
+        loop {
+            self.skip_whitespace_and_comments();
+            let line = self.line;
+            let token = self.next_token()?;
+            let is_eof = token == Token::Eof;
+            tokens.push((token, line));
+            if is_eof {
+                return Ok(tokens);
+            }
+        }
+    }
+
+    fn error(&self, message: String) -> CompileError {
+        CompileError {
+            line: self.line,
+            message,
+        }
+    }
+
+    fn skip_whitespace_and_comments(&mut self) {
+        while let Some(&c) = self.chars.peek() {
+            if c == '\n' {
+                self.line += 1;
+                self.chars.next();
+            } else if c.is_whitespace() {
+                self.chars.next();
+            } else if c == '/' && self.chars.clone().nth(1) == Some('/') {
+                while self.chars.peek().is_some_and(|&c| c != '\n') {
+                    self.chars.next();
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn next_if(&mut self, expected: char) -> bool {
+        if self.chars.peek() == Some(&expected) {
+            self.chars.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Token, CompileError> {
+        let c = match self.chars.next() {
+            Some(c) => c,
+            None => return Ok(Token::Eof),
+        };
+        let token = match c {
+            '(' => Token::LeftParen,
+            ')' => Token::RightParen,
+            '{' => Token::LeftBrace,
+            '}' => Token::RightBrace,
+            ',' => Token::Comma,
+            ';' => Token::Semicolon,
+            '+' => Token::Plus,
+            '-' => Token::Minus,
+            '*' => Token::Star,
+            '/' => Token::Slash,
+            '%' => Token::Percent,
+            '!' if self.next_if('=') => Token::BangEqual,
+            '!' => Token::Bang,
+            '=' if self.next_if('=') => Token::EqualEqual,
+            '=' => Token::Assign,
+            '<' if self.next_if('=') => Token::LessEqual,
+            '<' => Token::Less,
+            '>' if self.next_if('=') => Token::GreaterEqual,
+            '>' => Token::Greater,
+            '&' if self.next_if('&') => Token::AndAnd,
+            '|' if self.next_if('|') => Token::OrOr,
+            '"' => self.string()?,
+            c if c.is_ascii_digit() => self.number(c)?,
+            c if c.is_alphabetic() || c == '_' => self.identifier(c),
+            c => return Err(self.error(format!("Unexpected character '{}'", c))),
+        };
+        Ok(token)
+    }
+
+    fn string(&mut self) -> Result<Token, CompileError> {
+        let mut s = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(Token::Str(s)),
+                Some('\\') => match self.chars.next() {
+                    Some('n') => s.push('\n'),
+                    Some('t') => s.push('\t'),
+                    Some('"') => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    Some(c) => return Err(self.error(format!("Unknown escape '\\{}'", c))),
+                    None => return Err(self.error("Unterminated string".to_string())),
+                },
+                Some(c) => {
+                    if c == '\n' {
+                        self.line += 1;
+                    }
+                    s.push(c);
+                }
+                None => return Err(self.error("Unterminated string".to_string())),
+            }
+        }
+    }
+
+    fn number(&mut self, first: char) -> Result<Token, CompileError> {
+        let mut text = first.to_string();
+        while let Some(&c) = self.chars.peek() {
+            let is_fraction = c == '.'
+                && !text.contains('.')
+                && self
+                    .chars
+                    .clone()
+                    .nth(1)
+                    .is_some_and(|c| c.is_ascii_digit());
+            if c.is_ascii_digit() || is_fraction {
+                text.push(c);
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+        text.parse()
+            .map(Token::Number)
+            .map_err(|_| self.error(format!("Invalid number '{}'", text)))
+    }
+
+    fn identifier(&mut self, first: char) -> Token {
+        let mut name = first.to_string();
+        while let Some(&c) = self.chars.peek() {
+            if c.is_alphanumeric() || c == '_' {
+                name.push(c);
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+        match name.as_str() {
+            "fn" => Token::Fn,
+            "let" => Token::Let,
+            "if" => Token::If,
+            "else" => Token::Else,
+            "while" => Token::While,
+            "return" => Token::Return,
+            "print" => Token::Print,
+            "true" => Token::True,
+            "false" => Token::False,
+            _ => Token::Identifier(name),
+        }
+    }
+}
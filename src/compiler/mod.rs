@@ -0,0 +1,53 @@
+use std::fmt;
+
+use crate::bytecode::{Bytecode, Value};
+use crate::optimizer;
+use crate::vm::VirtualMachine;
+use lexer::Lexer;
+use parser::Parser;
+
+mod codegen;
+mod lexer;
+mod parser;
+
+#[derive(Debug)]
+pub struct CompileError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+pub fn compile(source: &str) -> Result<Bytecode, CompileError> {
+    let tokens = Lexer::new(source).tokenize()?;
+    let program = Parser::new(tokens).parse_program()?;
+    let mut bytecode = codegen::generate(&program)?;
+    optimizer::optimize(&mut bytecode);
+    Ok(bytecode)
+}
+
+// Compiles a single expression into a function that takes the named
+// parameters as arguments, in order. The expression can call native
+// functions, but not ones that need a capability unless the host grants it.
+pub fn compile_expr(source: &str, params: &[&str]) -> Result<Bytecode, CompileError> {
+    let tokens = Lexer::new(source).tokenize()?;
+    let expr = Parser::new(tokens).parse_expression()?;
+    let mut bytecode = codegen::generate_expr(&expr, params)?;
+    optimizer::optimize(&mut bytecode);
+    Ok(bytecode)
+}
+
+// Compiles and evaluates an expression with the given variable bindings.
+// Runtime errors panic, as they do for any other program.
+pub fn eval_expr(source: &str, bindings: &[(&str, Value)]) -> Result<Value, CompileError> {
+    let params: Vec<&str> = bindings.iter().map(|(name, _)| *name).collect();
+    let bytecode = compile_expr(source, &params)?;
+    let args = bindings.iter().map(|(_, value)| value.clone()).collect();
+    Ok(VirtualMachine::new(&bytecode).call_function(0, args))
+}
@@ -0,0 +1,380 @@
+use super::lexer::Token;
+use super::CompileError;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum UnaryOp {
+    Negate,
+    Not,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    LessOrEqual,
+    GreaterOrEqual,
+    And,
+    Or,
+}
+
+#[derive(Debug)]
+pub(crate) enum Expr {
+    Number(f64),
+    Str(String),
+    Boolean(bool),
+    Variable {
+        name: String,
+        line: usize,
+    },
+    Unary {
+        op: UnaryOp,
+        operand: Box<Expr>,
+    },
+    Binary {
+        op: BinaryOp,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    Call {
+        name: String,
+        args: Vec<Expr>,
+        line: usize,
+    },
+}
+
+#[derive(Debug)]
+pub(crate) enum Stmt {
+    Let {
+        name: String,
+        value: Expr,
+    },
+    Assign {
+        name: String,
+        value: Expr,
+        line: usize,
+    },
+    If {
+        condition: Expr,
+        then_branch: Vec<Stmt>,
+        else_branch: Option<Vec<Stmt>>,
+    },
+    While {
+        condition: Expr,
+        body: Vec<Stmt>,
+    },
+    Print(Expr),
+    Return(Option<Expr>),
+    Expr(Expr),
+}
+
+#[derive(Debug)]
+pub(crate) struct FunctionDecl {
+    pub(crate) name: String,
+    pub(crate) params: Vec<String>,
+    pub(crate) body: Vec<Stmt>,
+    pub(crate) line: usize,
+}
+
+// Top-level statements outside any function make up the entry function.
+#[derive(Debug)]
+pub(crate) struct Program {
+    pub(crate) functions: Vec<FunctionDecl>,
+    pub(crate) main: Vec<Stmt>,
+}
+
+pub(crate) struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+}
+
+impl Parser {
+    pub(crate) fn new(tokens: Vec<(Token, usize)>) -> Self {
+        Parser {
+            tokens,
+            position: 0,
+        }
+    }
+
+    pub(crate) fn parse_program(&mut self) -> Result<Program, CompileError> {
+        let mut functions = Vec::new();
+        let mut main = Vec::new();
+        while *self.peek() != Token::Eof {
+            if *self.peek() == Token::Fn {
+                functions.push(self.function()?);
+            } else {
+                main.push(self.statement()?);
+            }
+        }
+        Ok(Program { functions, main })
+    }
+
+    pub(crate) fn parse_expression(&mut self) -> Result<Expr, CompileError> {
+        let expr = self.expression()?;
+        self.expect(Token::Eof)?;
+        Ok(expr)
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.position].0
+    }
+
+    fn line(&self) -> usize {
+        self.tokens[self.position].1
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.position].0.clone();
+        if token != Token::Eof {
+            self.position += 1;
+        }
+        token
+    }
+
+    fn next_if(&mut self, expected: Token) -> bool {
+        if *self.peek() == expected {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn error(&self, message: String) -> CompileError {
+        CompileError {
+            line: self.line(),
+            message,
+        }
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), CompileError> {
+        if self.next_if(expected.clone()) {
+            Ok(())
+        } else {
+            Err(self.error(format!(
+                "Expected {} but found {}",
+                expected.describe(),
+                self.peek().describe()
+            )))
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String, CompileError> {
+        match self.peek().clone() {
+            Token::Identifier(name) => {
+                self.advance();
+                Ok(name)
+            }
+            token => Err(self.error(format!(
+                "Expected identifier but found {}",
+                token.describe()
+            ))),
+        }
+    }
+
+    fn function(&mut self) -> Result<FunctionDecl, CompileError> {
+        let line = self.line();
+        self.expect(Token::Fn)?;
+        let name = self.identifier()?;
+        self.expect(Token::LeftParen)?;
+        let mut params = Vec::new();
+        if *self.peek() != Token::RightParen {
+            loop {
+                params.push(self.identifier()?);
+                if !self.next_if(Token::Comma) {
+                    break;
+                }
+            }
+        }
+        self.expect(Token::RightParen)?;
+        let body = self.block()?;
+        Ok(FunctionDecl {
+            name,
+            params,
+            body,
+            line,
+        })
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, CompileError> {
+        self.expect(Token::LeftBrace)?;
+        let mut statements = Vec::new();
+        while *self.peek() != Token::RightBrace {
+            if *self.peek() == Token::Eof {
+                return Err(self.error("Expected '}' but found end of input".to_string()));
+            }
+            statements.push(self.statement()?);
+        }
+        self.expect(Token::RightBrace)?;
+        Ok(statements)
+    }
+
+    fn statement(&mut self) -> Result<Stmt, CompileError> {
+        let line = self.line();
+        match self.peek().clone() {
+            Token::Let => {
+                self.advance();
+                let name = self.identifier()?;
+                self.expect(Token::Assign)?;
+                let value = self.expression()?;
+                self.expect(Token::Semicolon)?;
+                Ok(Stmt::Let { name, value })
+            }
+            Token::If => self.if_statement(),
+            Token::While => {
+                self.advance();
+                let condition = self.expression()?;
+                let body = self.block()?;
+                Ok(Stmt::While { condition, body })
+            }
+            Token::Print => {
+                self.advance();
+                let value = self.expression()?;
+                self.expect(Token::Semicolon)?;
+                Ok(Stmt::Print(value))
+            }
+            Token::Return => {
+                self.advance();
+                let value = if *self.peek() == Token::Semicolon {
+                    None
+                } else {
+                    Some(self.expression()?)
+                };
+                self.expect(Token::Semicolon)?;
+                Ok(Stmt::Return(value))
+            }
+            Token::Identifier(name) if self.tokens[self.position + 1].0 == Token::Assign => {
+                self.advance();
+                self.advance();
+                let value = self.expression()?;
+                self.expect(Token::Semicolon)?;
+                Ok(Stmt::Assign { name, value, line })
+            }
+            _ => {
+                let expr = self.expression()?;
+                self.expect(Token::Semicolon)?;
+                Ok(Stmt::Expr(expr))
+            }
+        }
+    }
+
+    fn if_statement(&mut self) -> Result<Stmt, CompileError> {
+        self.expect(Token::If)?;
+        let condition = self.expression()?;
+        let then_branch = self.block()?;
+        let else_branch = if self.next_if(Token::Else) {
+            if *self.peek() == Token::If {
+                Some(vec![self.if_statement()?])
+            } else {
+                Some(self.block()?)
+            }
+        } else {
+            None
+        };
+        Ok(Stmt::If {
+            condition,
+            then_branch,
+            else_branch,
+        })
+    }
+
+    fn expression(&mut self) -> Result<Expr, CompileError> {
+        self.binary(0)
+    }
+
+    // Parses binary operators by precedence level, lowest first.
+    fn binary(&mut self, level: usize) -> Result<Expr, CompileError> {
+        const LEVELS: &[&[(Token, BinaryOp)]] = &[
+            &[(Token::OrOr, BinaryOp::Or)],
+            &[(Token::AndAnd, BinaryOp::And)],
+            &[
+                (Token::EqualEqual, BinaryOp::Equal),
+                (Token::BangEqual, BinaryOp::NotEqual),
+            ],
+            &[
+                (Token::Less, BinaryOp::Less),
+                (Token::Greater, BinaryOp::Greater),
+                (Token::LessEqual, BinaryOp::LessOrEqual),
+                (Token::GreaterEqual, BinaryOp::GreaterOrEqual),
+            ],
+            &[
+                (Token::Plus, BinaryOp::Add),
+                (Token::Minus, BinaryOp::Subtract),
+            ],
+            &[
+                (Token::Star, BinaryOp::Multiply),
+                (Token::Slash, BinaryOp::Divide),
+                (Token::Percent, BinaryOp::Modulo),
+            ],
+        ];
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        while let Some((_, op)) = LEVELS[level].iter().find(|(t, _)| t == self.peek()) {
+            self.advance();
+            let right = self.binary(level + 1)?;
+            left = Expr::Binary {
+                op: *op,
+                left: Box::new(left),
+                right: Box::new(right),
+            };
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, CompileError> {
+        let op = match self.peek() {
+            Token::Minus => UnaryOp::Negate,
+            Token::Bang => UnaryOp::Not,
+            _ => return self.primary(),
+        };
+        self.advance();
+        let operand = self.unary()?;
+        Ok(Expr::Unary {
+            op,
+            operand: Box::new(operand),
+        })
+    }
+
+    fn primary(&mut self) -> Result<Expr, CompileError> {
+        let line = self.line();
+        match self.advance() {
+            Token::Number(n) => Ok(Expr::Number(n)),
+            Token::Str(s) => Ok(Expr::Str(s)),
+            Token::True => Ok(Expr::Boolean(true)),
+            Token::False => Ok(Expr::Boolean(false)),
+            Token::Identifier(name) => {
+                if !self.next_if(Token::LeftParen) {
+                    return Ok(Expr::Variable { name, line });
+                }
+                let mut args = Vec::new();
+                if *self.peek() != Token::RightParen {
+                    loop {
+                        args.push(self.expression()?);
+                        if !self.next_if(Token::Comma) {
+                            break;
+                        }
+                    }
+                }
+                self.expect(Token::RightParen)?;
+                Ok(Expr::Call { name, args, line })
+            }
+            Token::LeftParen => {
+                let expr = self.expression()?;
+                self.expect(Token::RightParen)?;
+                Ok(expr)
+            }
+            token => Err(CompileError {
+                line,
+                message: format!("Expected expression but found {}", token.describe()),
+            }),
+        }
+    }
+}
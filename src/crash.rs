@@ -0,0 +1,206 @@
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+use crate::builtins::hash;
+use crate::bytecode::{Bytecode, Value};
+use crate::json::{self, Json};
+
+// A value as a crash dump records it: its type name and how it prints.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DumpedValue {
+    pub kind: String,
+    pub text: String,
+}
+
+// A frame of the call stack when the program failed. The instruction is the
+// one the frame was running, and the module is None for the program itself.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CrashFrame {
+    pub function: usize,
+    pub instruction: usize,
+    pub module: Option<String>,
+    // Slots that had not been set are None.
+    pub locals: Vec<Option<DumpedValue>>,
+    pub stack: Vec<DumpedValue>,
+}
+
+// The state of a VM whose program failed with an unrecovered error, with the
+// innermost frame first. The program hash identifies the bytecode that ran,
+// so the dump can be matched with it later.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CrashDump {
+    pub message: String,
+    pub program_hash: u64,
+    pub frames: Vec<CrashFrame>,
+}
+
+// The FNV-1a digest of the program as it would be written out.
+pub fn program_hash(bytecode: &Bytecode) -> u64 {
+    let mut bytes = Vec::new();
+    bytecode
+        .write(&mut bytes)
+        .expect("Writing to memory cannot fail.");
+    hash::digest(&bytes)
+}
+
+impl DumpedValue {
+    pub(crate) fn new(value: &Value) -> DumpedValue {
+        DumpedValue {
+            kind: value.type_name().to_string(),
+            text: value.to_string(),
+        }
+    }
+
+    pub(crate) fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("type", self.kind.as_str().into()),
+            ("value", self.text.as_str().into()),
+        ])
+    }
+
+    fn from_json(json: &Json) -> Result<DumpedValue, String> {
+        Ok(DumpedValue {
+            kind: string(json, "type")?.to_string(),
+            text: string(json, "value")?.to_string(),
+        })
+    }
+}
+
+impl CrashDump {
+    // Writes the dump as a JSON document.
+    pub fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let frames = self.frames.iter().map(|frame| {
+            let locals = frame.locals.iter().map(|local| match local {
+                Some(value) => value.to_json(),
+                None => Json::Null,
+            });
+            Json::object(vec![
+                ("function", frame.function.into()),
+                ("instruction", frame.instruction.into()),
+                (
+                    "module",
+                    frame.module.as_deref().map_or(Json::Null, Json::from),
+                ),
+                ("locals", Json::Array(locals.collect())),
+                (
+                    "stack",
+                    Json::Array(frame.stack.iter().map(DumpedValue::to_json).collect()),
+                ),
+            ])
+        });
+        let dump = Json::object(vec![
+            ("message", self.message.as_str().into()),
+            ("program_hash", format!("{:016x}", self.program_hash).into()),
+            ("frames", Json::Array(frames.collect())),
+        ]);
+        writeln!(out, "{}", dump)
+    }
+
+    pub fn parse(text: &str) -> Result<CrashDump, String> {
+        let dump = json::parse(text)?;
+        let program_hash = u64::from_str_radix(string(&dump, "program_hash")?, 16)
+            .map_err(|_| "Invalid program hash".to_string())?;
+        let frames = array(&dump, "frames")?
+            .iter()
+            .map(|frame| {
+                let locals = array(frame, "locals")?
+                    .iter()
+                    .map(|local| match local {
+                        Json::Null => Ok(None),
+                        value => DumpedValue::from_json(value).map(Some),
+                    })
+                    .collect::<Result<_, String>>()?;
+                let stack = array(frame, "stack")?
+                    .iter()
+                    .map(DumpedValue::from_json)
+                    .collect::<Result<_, String>>()?;
+                let module = match frame.get("module") {
+                    Some(Json::Null) | None => None,
+                    Some(module) => Some(module.as_str().ok_or("Invalid module")?.to_string()),
+                };
+                Ok(CrashFrame {
+                    function: index(frame, "function")?,
+                    instruction: index(frame, "instruction")?,
+                    module,
+                    locals,
+                    stack,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(CrashDump {
+            message: string(&dump, "message")?.to_string(),
+            program_hash,
+            frames,
+        })
+    }
+
+    // A readable listing of the dump. Given the program that failed, each
+    // frame of it also shows the instruction it was running. A program that
+    // doesn't match the hash is only reported, as its instructions could be
+    // anything.
+    pub fn inspect(&self, bytecode: Option<&Bytecode>) -> String {
+        let mut out = String::new();
+        writeln!(out, "Error: {}", self.message).unwrap();
+        writeln!(out, "Program: {:016x}", self.program_hash).unwrap();
+        let bytecode = match bytecode {
+            Some(bytecode) if program_hash(bytecode) != self.program_hash => {
+                writeln!(out, "The bytecode does not match the program hash.").unwrap();
+                None
+            }
+            bytecode => bytecode,
+        };
+        for (depth, frame) in self.frames.iter().enumerate() {
+            write!(
+                out,
+                "\n#{} function {}, instruction {}",
+                depth, frame.function, frame.instruction
+            )
+            .unwrap();
+            if let Some(module) = &frame.module {
+                write!(out, " in module '{}'", module).unwrap();
+            }
+            let instruction = bytecode
+                .filter(|_| frame.module.is_none())
+                .and_then(|bytecode| bytecode.functions().get(frame.function))
+                .and_then(|function| function.instructions.get(frame.instruction));
+            if let Some(instruction) = instruction {
+                write!(out, ": {}", instruction.opcode().name()).unwrap();
+                if let Some(operand) = instruction.optional_operand() {
+                    write!(out, " {}", operand).unwrap();
+                }
+            }
+            out.push('\n');
+            for (slot, local) in frame.locals.iter().enumerate() {
+                match local {
+                    Some(value) => {
+                        writeln!(out, "  local {}: {} {}", slot, value.kind, value.text).unwrap()
+                    }
+                    None => writeln!(out, "  local {}: unset", slot).unwrap(),
+                }
+            }
+            for (slot, value) in frame.stack.iter().enumerate() {
+                writeln!(out, "  stack {}: {} {}", slot, value.kind, value.text).unwrap();
+            }
+        }
+        out
+    }
+}
+
+fn string<'j>(json: &'j Json, key: &str) -> Result<&'j str, String> {
+    json.get(key)
+        .and_then(Json::as_str)
+        .ok_or_else(|| format!("Missing or invalid '{}'", key))
+}
+
+fn index(json: &Json, key: &str) -> Result<usize, String> {
+    json.get(key)
+        .and_then(Json::as_usize)
+        .ok_or_else(|| format!("Missing or invalid '{}'", key))
+}
+
+fn array<'j>(json: &'j Json, key: &str) -> Result<&'j [Json], String> {
+    match json.get(key) {
+        Some(Json::Array(elements)) => Ok(elements),
+        _ => Err(format!("Missing or invalid '{}'", key)),
+    }
+}
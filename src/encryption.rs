@@ -0,0 +1,126 @@
+use std::env;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+
+use crate::bytecode::{Bytecode, ENCRYPTED_MAGIC};
+use crate::keys;
+
+// An encrypted program is a header of `ZRCE` and a version byte, a 12-byte
+// nonce, and then the ChaCha20-Poly1305 encryption of the bytecode file,
+// with the header as associated data.
+const VERSION: u8 = 1;
+const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = ENCRYPTED_MAGIC.len() + 1;
+
+// Holds the key used to decrypt programs loaded with `Bytecode::from_file`.
+const KEY_VARIABLE: &str = "ZIRCON_BYTECODE_KEY";
+
+#[derive(Debug)]
+pub struct EncryptionError {
+    pub message: String,
+}
+
+impl fmt::Display for EncryptionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for EncryptionError {}
+
+fn error<T>(message: String) -> Result<T, EncryptionError> {
+    Err(EncryptionError { message })
+}
+
+pub fn generate_encryption_key() -> Result<[u8; 32], EncryptionError> {
+    keys::random_key().or_else(|e| error(e.to_string()))
+}
+
+pub fn encrypt_bytecode(bytes: &[u8], key: &[u8; 32]) -> Result<Vec<u8>, EncryptionError> {
+    let mut nonce = [0; NONCE_LEN];
+    if let Err(e) = keys::random_bytes(&mut nonce) {
+        return error(e.to_string());
+    }
+    let nonce = Nonce::from_slice(&nonce);
+    let mut encrypted = Vec::with_capacity(HEADER_LEN + NONCE_LEN + bytes.len() + 16);
+    encrypted.extend_from_slice(ENCRYPTED_MAGIC);
+    encrypted.push(VERSION);
+    let payload = Payload {
+        msg: bytes,
+        aad: &encrypted,
+    };
+    let ciphertext = match ChaCha20Poly1305::new(Key::from_slice(key)).encrypt(nonce, payload) {
+        Ok(ciphertext) => ciphertext,
+        Err(_) => return error("Failed to encrypt bytecode".to_string()),
+    };
+    encrypted.extend_from_slice(nonce);
+    encrypted.extend_from_slice(&ciphertext);
+    Ok(encrypted)
+}
+
+pub fn decrypt_bytecode(bytes: &[u8], key: &[u8; 32]) -> Result<Vec<u8>, EncryptionError> {
+    if !bytes.starts_with(ENCRYPTED_MAGIC) {
+        return error("Bytecode is not encrypted".to_string());
+    }
+    if bytes.len() < HEADER_LEN + NONCE_LEN {
+        return error("Encrypted bytecode is truncated".to_string());
+    }
+    let (header, rest) = bytes.split_at(HEADER_LEN);
+    if header[ENCRYPTED_MAGIC.len()] != VERSION {
+        return error("Unsupported encrypted bytecode version".to_string());
+    }
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let payload = Payload {
+        msg: ciphertext,
+        aad: header,
+    };
+    ChaCha20Poly1305::new(Key::from_slice(key))
+        .decrypt(Nonce::from_slice(nonce), payload)
+        .or_else(|_| error("Failed to decrypt bytecode: wrong key or corrupted file".to_string()))
+}
+
+// Decrypts a program with the key in `ZIRCON_BYTECODE_KEY`.
+pub(crate) fn decrypt_with_environment_key(bytes: &[u8]) -> io::Result<Vec<u8>> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let key = match env::var(KEY_VARIABLE) {
+        Ok(text) => keys::parse_key(&text)
+            .ok_or_else(|| invalid(format!("{} is not a valid key", KEY_VARIABLE)))?,
+        Err(_) => {
+            return Err(invalid(format!(
+                "Bytecode is encrypted and {} is not set",
+                KEY_VARIABLE
+            )))
+        }
+    };
+    decrypt_bytecode(bytes, &key).map_err(|e| invalid(e.message))
+}
+
+impl Bytecode {
+    // Loads a program encrypted with `key`.
+    pub fn from_file_encrypted<P: AsRef<Path>>(
+        path: P,
+        key: &[u8; 32],
+    ) -> Result<Self, EncryptionError> {
+        let path = path.as_ref();
+        let load_error = |e: &dyn fmt::Display| {
+            error(format!(
+                "Failed to load bytecode from '{}': {}",
+                path.display(),
+                e
+            ))
+        };
+        let bytes = match std::fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) => return load_error(&e),
+        };
+        let decrypted = match decrypt_bytecode(&bytes, key) {
+            Ok(decrypted) => decrypted,
+            Err(e) => return load_error(&e),
+        };
+        Bytecode::read(&mut decrypted.as_slice()).or_else(|e| load_error(&e))
+    }
+}
@@ -0,0 +1,27 @@
+use std::fmt;
+
+use crate::vm::VirtualMachine;
+
+// Runs a VM's instructions. The interpreter is the default, and another
+// backend, such as a register interpreter or a JIT, implements the same
+// trait so it can be chosen with `VmOptions::backend` and compared against
+// the interpreter on the same programs. A backend works on the VM's own call
+// stack and values, and can hand any instruction it doesn't handle to
+// `VirtualMachine::step`.
+pub trait Executor: fmt::Debug {
+    // Runs until the call stack is down to `base_depth` frames or the VM has
+    // halted.
+    fn execute(&self, vm: &mut VirtualMachine, base_depth: usize);
+}
+
+// Runs one instruction at a time.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Interpreter;
+
+impl Executor for Interpreter {
+    fn execute(&self, vm: &mut VirtualMachine, base_depth: usize) {
+        while vm.call_depth() > base_depth && vm.is_running() {
+            vm.step();
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+use std::mem::size_of;
+use std::rc::Rc;
+
+use crate::bytecode::{Socket, Value, WeakRef};
+use crate::json;
+
+pub(crate) enum Root {
+    Constant(usize),
+    Stack { frame: usize, slot: usize },
+    Local { frame: usize, slot: usize },
+    Handle(usize),
+}
+
+struct Object {
+    type_name: &'static str,
+    size: usize,
+    refs: Vec<usize>,
+    weak_refs: Vec<usize>,
+}
+
+#[derive(Default)]
+struct Snapshot {
+    ids: HashMap<usize, usize>,
+    objects: Vec<Object>,
+}
+
+// Identifies a heap value by its allocation, or returns None for scalars.
+fn address(value: &Value) -> Option<usize> {
+    match value {
+        Value::Str(s) => Some(s.address()),
+        Value::Bytes(bytes) => Some(Rc::as_ptr(bytes) as *const u8 as usize),
+        Value::Array(elements) => Some(Rc::as_ptr(elements) as usize),
+        Value::Tuple(elements) => Some(Rc::as_ptr(elements) as *const u8 as usize),
+        Value::Set(elements) => Some(Rc::as_ptr(elements) as usize),
+        Value::Error(payload) => Some(Rc::as_ptr(payload) as usize),
+        Value::StringBuilder(builder) => Some(Rc::as_ptr(builder) as usize),
+        Value::Socket(socket) => Some(Rc::as_ptr(socket) as usize),
+        #[cfg(feature = "sqlite")]
+        Value::Database(connection) => Some(Rc::as_ptr(connection) as usize),
+        _ => None,
+    }
+}
+
+impl Snapshot {
+    // Records the value and everything reachable from it, returning its id.
+    // Objects are walked with a worklist rather than recursion, so deeply
+    // nested values don't overflow the stack.
+    fn visit(&mut self, value: &Value) -> Option<usize> {
+        let mut pending = Vec::new();
+        let id = self.discover(value, &mut pending)?;
+        while let Some((id, value)) = pending.pop() {
+            let (size, children): (usize, &[Value]) = match &value {
+                Value::Str(s) => (s.len(), &[]),
+                Value::Bytes(bytes) => (bytes.len(), &[]),
+                Value::Array(elements) => (elements.len() * size_of::<Value>(), elements),
+                Value::Tuple(elements) => (elements.len() * size_of::<Value>(), elements),
+                Value::Set(elements) => (elements.len() * size_of::<Value>(), elements),
+                Value::Error(payload) => (size_of::<Value>(), std::slice::from_ref(&**payload)),
+                Value::StringBuilder(builder) => (builder.borrow().capacity(), &[]),
+                Value::Socket(_) => (size_of::<Socket>(), &[]),
+                #[cfg(feature = "sqlite")]
+                Value::Database(_) => (size_of::<rusqlite::Connection>(), &[]),
+                _ => unreachable!(),
+            };
+            let mut refs = Vec::new();
+            let mut weak_refs = Vec::new();
+            for child in children {
+                match child {
+                    Value::Weak(weak) => {
+                        if let Some(target) = weak.upgrade() {
+                            weak_refs.extend(self.discover(&target, &mut pending));
+                        }
+                    }
+                    _ => refs.extend(self.discover(child, &mut pending)),
+                }
+            }
+            let object = &mut self.objects[id];
+            object.size = size;
+            object.refs = refs;
+            object.weak_refs = weak_refs;
+        }
+        Some(id)
+    }
+
+    // The id of a heap value, adding it to `pending` if it hasn't been seen.
+    fn discover(&mut self, value: &Value, pending: &mut Vec<(usize, Value)>) -> Option<usize> {
+        let address = address(value)?;
+        if let Some(id) = self.ids.get(&address) {
+            return Some(*id);
+        }
+        let id = self.objects.len();
+        self.ids.insert(address, id);
+        self.objects.push(Object {
+            type_name: value.type_name(),
+            size: 0,
+            refs: Vec::new(),
+            weak_refs: Vec::new(),
+        });
+        pending.push((id, value.clone()));
+        Some(id)
+    }
+
+    fn visit_weak(&mut self, weak: &WeakRef) -> Option<usize> {
+        self.visit(&weak.upgrade()?)
+    }
+}
+
+// The number and total size of the heap values reachable from the roots, by
+// type name, in name order.
+pub(crate) fn census<'v>(
+    roots: impl Iterator<Item = (Root, &'v Value)>,
+) -> Vec<(&'static str, usize, usize)> {
+    let mut snapshot = Snapshot::default();
+    for (_, value) in roots {
+        match value {
+            Value::Weak(weak) => snapshot.visit_weak(weak),
+            _ => snapshot.visit(value),
+        };
+    }
+    let mut kinds = BTreeMap::new();
+    for object in &snapshot.objects {
+        let (count, bytes) = kinds.entry(object.type_name).or_insert((0, 0));
+        *count += 1;
+        *bytes += object.size;
+    }
+    kinds
+        .into_iter()
+        .map(|(kind, (count, bytes))| (kind, count, bytes))
+        .collect()
+}
+
+// Writes a JSON snapshot of every heap value reachable from the roots, with
+// strong and weak reference edges between them.
+pub(crate) fn dump<'v, W: Write>(
+    out: &mut W,
+    roots: impl Iterator<Item = (Root, &'v Value)>,
+) -> io::Result<()> {
+    let mut snapshot = Snapshot::default();
+    let mut root_entries = Vec::new();
+    for (root, value) in roots {
+        let (id, weak) = match value {
+            Value::Weak(weak) => (snapshot.visit_weak(weak), true),
+            _ => (snapshot.visit(value), false),
+        };
+        if let Some(id) = id {
+            root_entries.push((root, id, weak));
+        }
+    }
+
+    writeln!(out, "{{")?;
+    writeln!(out, "  \"roots\": [")?;
+    for (i, (root, id, weak)) in root_entries.iter().enumerate() {
+        let location = match root {
+            Root::Constant(index) => format!("\"kind\": \"constant\", \"index\": {}", index),
+            Root::Stack { frame, slot } => {
+                format!(
+                    "\"kind\": \"stack\", \"frame\": {}, \"slot\": {}",
+                    frame, slot
+                )
+            }
+            Root::Local { frame, slot } => {
+                format!(
+                    "\"kind\": \"local\", \"frame\": {}, \"slot\": {}",
+                    frame, slot
+                )
+            }
+            Root::Handle(index) => format!("\"kind\": \"handle\", \"index\": {}", index),
+        };
+        let separator = if i + 1 < root_entries.len() { "," } else { "" };
+        writeln!(
+            out,
+            "    {{{}, \"object\": {}, \"weak\": {}}}{}",
+            location, id, weak, separator
+        )?;
+    }
+    writeln!(out, "  ],")?;
+    writeln!(out, "  \"objects\": [")?;
+    for (id, object) in snapshot.objects.iter().enumerate() {
+        let separator = if id + 1 < snapshot.objects.len() {
+            ","
+        } else {
+            ""
+        };
+        writeln!(
+            out,
+            "    {{\"id\": {}, \"type\": {}, \"size\": {}, \"refs\": {:?}, \"weak_refs\": {:?}}}{}",
+            id,
+            json::escape(object.type_name),
+            object.size,
+            object.refs,
+            object.weak_refs,
+            separator
+        )?;
+    }
+    writeln!(out, "  ]")?;
+    writeln!(out, "}}")
+}
@@ -0,0 +1,55 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Keys for signing and encryption are 32 bytes, written as 64 hexadecimal
+// digits.
+pub(crate) fn parse_key(text: &str) -> Option<[u8; 32]> {
+    let text = text.trim();
+    if text.len() != 64 || !text.is_ascii() {
+        return None;
+    }
+    let mut key = [0; 32];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&text[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(key)
+}
+
+pub fn read_key_file<P: AsRef<Path>>(path: P) -> io::Result<[u8; 32]> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Failed to read key from '{}': {}", path.display(), e),
+        )
+    })?;
+    parse_key(&text).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid key in '{}'", path.display()),
+        )
+    })
+}
+
+pub fn write_key_file<P: AsRef<Path>>(path: P, key: &[u8; 32]) -> io::Result<()> {
+    let path = path.as_ref();
+    let text: String = key.iter().map(|byte| format!("{:02x}", byte)).collect();
+    fs::write(path, text + "\n").map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Failed to write key to '{}': {}", path.display(), e),
+        )
+    })
+}
+
+pub(crate) fn random_bytes(buffer: &mut [u8]) -> io::Result<()> {
+    getrandom::getrandom(buffer)
+        .map_err(|e| io::Error::other(format!("Failed to generate random bytes: {}", e)))
+}
+
+pub(crate) fn random_key() -> io::Result<[u8; 32]> {
+    let mut key = [0; 32];
+    random_bytes(&mut key)?;
+    Ok(key)
+}
@@ -0,0 +1,85 @@
+#[cfg(feature = "aot")]
+pub use aot::{compile_native, AotError};
+pub use assembler::{
+    assemble, assemble_dir, assemble_file, assemble_with_extensions, format_assembly,
+    lint_assembly, AssembleError, AssemblyWarning,
+};
+pub use builtins::Capability;
+pub use bundle::{bundle, bundled_program};
+pub use bytecode::{
+    Bytecode, Count, Extension, Instruction, InvalidUtf8, LoadOptions, NumberEquality,
+    NumberFormat, Opcode, OperandKind, Socket, StackEffect, Value, WeakRef,
+};
+pub use compiler::{compile, compile_expr, eval_expr, CompileError};
+pub use crash::{program_hash, CrashDump, CrashFrame, DumpedValue};
+#[cfg(feature = "encryption")]
+pub use encryption::{
+    decrypt_bytecode, encrypt_bytecode, generate_encryption_key, EncryptionError,
+};
+pub use executor::{Executor, Interpreter};
+#[cfg(any(feature = "signing", feature = "encryption"))]
+pub use keys::{read_key_file, write_key_file};
+pub use lint::{lint, LintWarning};
+pub use lsp::language_server;
+pub use memory::{KindUsage, MemoryProfile, MemorySample};
+pub use metrics::Metrics;
+pub use pool::{Job, JobError, JobOutput, ProgramId, VmPool};
+pub use prune::{prune, PruneReport};
+pub use quicken::Quickening;
+pub use sampler::SamplingProfile;
+#[cfg(feature = "signing")]
+pub use signing::{generate_signing_key, sign_bytecode, verify_bytecode, SigningError};
+pub use state::{FrameState, VmState, STACK_SUMMARY};
+pub use stats::{FunctionStats, Stats};
+pub use storage::{MemoryStorage, Storage};
+pub use text::{Text, WeakText};
+pub use transpile::{transpile, Target, TranspileError};
+pub use vm::{
+    panic_message, report_runtime_errors, ExtensionHandler, Handle, RecoveredError, RuntimeError,
+    VirtualMachine, VmOptions,
+};
+
+// Halts the running program with a `RuntimeError`, formatted like `panic!`.
+macro_rules! runtime_error {
+    ($($arg:tt)*) => {
+        std::panic::panic_any($crate::vm::RuntimeError(format!($($arg)*)))
+    };
+}
+
+#[cfg(feature = "aot")]
+mod aot;
+mod assembler;
+mod builtins;
+mod bundle;
+mod bytecode;
+mod compiler;
+mod crash;
+mod datetime;
+#[cfg(feature = "encryption")]
+mod encryption;
+mod executor;
+mod heap;
+mod json;
+#[cfg(any(feature = "signing", feature = "encryption"))]
+mod keys;
+mod lint;
+mod lsp;
+mod memory;
+mod metrics;
+mod optimizer;
+mod pattern;
+mod pool;
+mod prune;
+mod quicken;
+pub mod runtime;
+mod sampler;
+#[cfg(feature = "signing")]
+mod signing;
+mod state;
+mod stats;
+mod storage;
+mod text;
+mod trace;
+mod transpile;
+mod verifier;
+mod vm;
@@ -0,0 +1,158 @@
+use std::fmt;
+
+use crate::bytecode::{Bytecode, Function, Opcode, OperandKind};
+
+// A problem that doesn't stop a program from running, but usually points to
+// a mistake in the code that generated it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LintWarning {
+    // The function and instruction the warning is about, if any.
+    pub function: Option<usize>,
+    pub instruction: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for LintWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.function, self.instruction) {
+            (Some(function), Some(ip)) => write!(f, "function {}, instruction {}: ", function, ip)?,
+            (Some(function), None) => write!(f, "function {}: ", function)?,
+            _ => {}
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+fn warning(function: Option<usize>, instruction: Option<usize>, message: String) -> LintWarning {
+    LintWarning {
+        function,
+        instruction,
+        message,
+    }
+}
+
+// Looks for unreachable instructions, unused constants and functions, jumps
+// to the next instruction, and locals that are written but never read.
+pub fn lint(bytecode: &Bytecode) -> Vec<LintWarning> {
+    let mut warnings = Vec::new();
+    let functions = bytecode.functions();
+    let mut used_constants = vec![false; bytecode.constants().len()];
+    let mut called = vec![false; functions.len()];
+    for function in functions {
+        for &constant in &function.defaults {
+            mark(&mut used_constants, constant.into());
+        }
+        for instruction in &function.instructions {
+            if let Some(constant) = instruction.constant() {
+                mark(&mut used_constants, constant);
+            }
+            if instruction.opcode().operand_kind() == OperandKind::Function {
+                mark(&mut called, instruction.operand().into());
+            }
+        }
+    }
+
+    for (index, used) in used_constants.iter().enumerate() {
+        if !used {
+            warnings.push(warning(
+                None,
+                None,
+                format!("constant {} is never used", index),
+            ));
+        }
+    }
+    // Function 0 is the entry point.
+    for (index, called) in called.iter().enumerate().skip(1) {
+        if !called {
+            warnings.push(warning(Some(index), None, "never called".to_string()));
+        }
+    }
+    for (index, function) in functions.iter().enumerate() {
+        lint_function(index, function, &mut warnings);
+    }
+    warnings
+}
+
+fn mark(used: &mut [bool], index: usize) {
+    if let Some(used) = used.get_mut(index) {
+        *used = true;
+    }
+}
+
+fn lint_function(index: usize, function: &Function, warnings: &mut Vec<LintWarning>) {
+    let instructions = &function.instructions;
+
+    // Unreachable instructions are reported once per run.
+    let reachable = reachable(function);
+    let mut ip = 0;
+    while ip < instructions.len() {
+        if reachable[ip] {
+            ip += 1;
+            continue;
+        }
+        let start = ip;
+        while ip < instructions.len() && !reachable[ip] {
+            ip += 1;
+        }
+        let message = if ip - start == 1 {
+            "unreachable instruction".to_string()
+        } else {
+            format!("instructions {} to {} are unreachable", start, ip - 1)
+        };
+        warnings.push(warning(Some(index), Some(start), message));
+    }
+
+    for (ip, instruction) in instructions.iter().enumerate() {
+        if instruction.opcode().operand_kind() == OperandKind::Target
+            && usize::from(instruction.operand()) == ip + 1
+        {
+            let message = "jumps to the next instruction".to_string();
+            warnings.push(warning(Some(index), Some(ip), message));
+        }
+    }
+
+    let mut written = vec![None; function.num_locals];
+    let mut read = vec![false; function.num_locals];
+    for (ip, instruction) in instructions.iter().enumerate() {
+        let Some(slot) = instruction.local() else {
+            continue;
+        };
+        match instruction.opcode() {
+            Opcode::SetLocal if slot < written.len() => {
+                written[slot].get_or_insert(ip);
+            }
+            // The other local instructions all read the local.
+            _ if slot < read.len() => read[slot] = true,
+            _ => {}
+        }
+    }
+    for (slot, first_write) in written.iter().enumerate() {
+        if let (Some(ip), false) = (first_write, read[slot]) {
+            let message = format!("local {} is written but never read", slot);
+            warnings.push(warning(Some(index), Some(*ip), message));
+        }
+    }
+}
+
+fn reachable(function: &Function) -> Vec<bool> {
+    let instructions = &function.instructions;
+    let mut reachable = vec![false; instructions.len()];
+    let mut pending = vec![0];
+    while let Some(ip) = pending.pop() {
+        match reachable.get_mut(ip) {
+            Some(seen) if !*seen => *seen = true,
+            _ => continue,
+        }
+        let instruction = &instructions[ip];
+        match instruction.opcode() {
+            Opcode::Jump => pending.push(instruction.operand().into()),
+            opcode if opcode.is_branch() => {
+                pending.push(instruction.operand().into());
+                pending.push(ip + 1);
+            }
+            Opcode::Return | Opcode::Halt => {}
+            _ => pending.push(ip + 1),
+        }
+    }
+    reachable
+}
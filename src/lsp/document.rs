@@ -0,0 +1,340 @@
+use std::path::{Path, PathBuf};
+
+use crate::assembler;
+use crate::builtins;
+use crate::bytecode::{Bytecode, Opcode, OperandKind, Value};
+
+// Shown when hovering over an instruction: its operand and what it does.
+const DOCS: &[(&str, &str, &str)] = &[
+    ("push_const", "<literal>", "Pushes a specified constant onto the stack."),
+    ("add", "", "Adds the top two values on the stack, pushing the result."),
+    ("subtract", "", "Subtracts the top stack value from the second top value, pushing the result."),
+    ("multiply", "", "Multiplies the top two stack values, pushing the result."),
+    ("divide", "", "Divides the second top stack value by the top, pushing the result."),
+    ("modulo", "", "Calculates the modulus of the second top value by the top, pushing the result."),
+    ("negate", "", "Negates the top value on the stack, pushing the result."),
+    ("and", "", "Performs a logical AND on the top two stack values, pushing the result."),
+    ("or", "", "Performs a logical OR on the top two stack values, pushing the result."),
+    ("not", "", "Performs a logical NOT on the top stack value, pushing the result."),
+    ("equal", "", "Checks if the top two stack values are equal, pushing the boolean result."),
+    ("identical", "", "Checks if the top two stack values are the same object, pushing the boolean result."),
+    ("match", "<pattern>", "Tests the top stack value against a pattern, leaving it and pushing the boolean result."),
+    ("total_compare", "", "Pops two values and pushes -1, 0, or 1 as the first is before, equal to, or after the second."),
+    ("less", "", "Checks if the second value from the top is less than the top value, pushing the boolean result."),
+    ("greater", "", "Checks if the second value from the top is greater than the top value, pushing the boolean result."),
+    ("not_equal", "", "Checks if the top two stack values are not equal, pushing the boolean result."),
+    ("less_or_equal", "", "Checks if the second value from the top is less than or equal to the top value, pushing the boolean result."),
+    ("greater_or_equal", "", "Checks if the second value from the top is greater than or equal to the top value, pushing the boolean result."),
+    ("compare", "", "Pops two values and pushes -1, 0, or 1 as the first is less than, equal to, or greater than the second."),
+    ("jump", "<label>", "Unconditionally jumps to the label."),
+    ("jump_if_true", "<label>", "Jumps to the label if the top stack value is true, popping the value."),
+    ("jump_if_false", "<label>", "Jumps to the label if the top stack value is false, popping the value."),
+    ("jump_if_equal", "<label>", "Pops two values and jumps to the label if they are equal."),
+    ("jump_if_not_equal", "<label>", "Pops two values and jumps to the label if they are not equal."),
+    ("jump_if_less", "<label>", "Pops two values and jumps to the label if the second top value is less than the top."),
+    ("jump_if_less_or_equal", "<label>", "Pops two values and jumps to the label if the second top value is less than or equal to the top."),
+    ("jump_if_greater", "<label>", "Pops two values and jumps to the label if the second top value is greater than the top."),
+    ("jump_if_greater_or_equal", "<label>", "Pops two values and jumps to the label if the second top value is greater than or equal to the top."),
+    ("print", "", "Prints the top value of the stack and pops it."),
+    ("format", "<count>", "Pops N arguments and a template string, pushing the template with each `{}` replaced in order."),
+    ("print_raw", "", "Prints the top value of the stack without a newline and pops it."),
+    ("eprint", "", "Prints the top value of the stack to standard error and pops it."),
+    ("eprint_raw", "", "Prints the top value of the stack to standard error without a newline and pops it."),
+    ("get_local", "<index>", "Pushes the value of a local variable onto the stack."),
+    ("set_local", "<index>", "Sets a local variable to the top value on the stack, popping the value."),
+    ("inc_local", "<index>", "Adds 1 to a local variable in place."),
+    ("dec_local", "<index>", "Subtracts 1 from a local variable in place."),
+    ("add_local_const", "<index> <literal>", "Adds a constant to a local variable in place. Both the index and the constant's index must be below 256."),
+    ("call", "<function>", "Calls the named function, setting up a new call frame."),
+    ("return", "", "Returns from the current function, possibly pushing a return value onto the stack of the caller."),
+    ("call_native", "<native>", "Calls the named native function, pushing its result."),
+    ("import", "<module>", "Loads the named module on first use, running its main function, and pushes the value it returned."),
+    ("defer", "<function>", "Pops the named function's arguments and calls it when the current function returns."),
+    ("run_defers", "", "Runs the current function's deferred calls now, most recent first."),
+    ("arg_count", "<count>", "Sets the number of arguments the next call or defer passes, leaving the rest to their defaults."),
+    ("make_tuple", "<count>", "Pops N values and pushes an immutable tuple containing them in push order."),
+    ("tuple_get", "<index>", "Pops a tuple and pushes the element at the specified index."),
+    ("tuple_unpack", "<count>", "Pops a tuple of exactly N elements and pushes each element in order."),
+    ("make_error", "", "Pops a value and pushes an error value wrapping it."),
+    ("is_error", "", "Pops a value, pushing true if it is an error value and false otherwise."),
+    ("try", "", "If the top stack value is an error, returns it from the current function; otherwise leaves it."),
+    ("array_concat", "", "Pops two arrays and pushes the first followed by the elements of the second."),
+    ("array_slice", "", "Pops an end index, a start index, and an array, and pushes the elements from start up to end."),
+    ("array_push", "", "Pops a value and an array, and pushes the array with the value appended."),
+    ("array_pop", "", "Pops an array, and pushes it without its last element, followed by that element."),
+    ("set_add", "", "Pops a value and a set, and pushes the set with the value added."),
+    ("set_contains", "", "Pops a value and a set, pushing true if the value is in the set and false otherwise."),
+    ("set_remove", "", "Pops a value and a set, and pushes the set without the value."),
+    ("halt", "", "Halts the VM execution."),
+];
+
+// A token with its position. Columns count UTF-16 code units, as LSP
+// positions do.
+pub(super) struct Token {
+    pub(super) text: String,
+    pub(super) line: usize,
+    pub(super) start: usize,
+    pub(super) end: usize,
+}
+
+pub(super) enum Definition<'d> {
+    Token(&'d Token),
+    File(PathBuf),
+}
+
+pub(super) struct Document {
+    lines: Vec<Vec<Token>>,
+}
+
+fn is_jump(mnemonic: &str) -> bool {
+    Opcode::from_name(mnemonic).is_some_and(|opcode| opcode.operand_kind() == OperandKind::Target)
+}
+
+fn is_label(token: &str) -> bool {
+    token.len() > 1 && token.ends_with(':') && !token.starts_with(['"', '\''])
+}
+
+// Splits text into tokens the way the assembler does: `;` starts a comment
+// and quoted literals are kept whole.
+fn scan(text: &str) -> Vec<Vec<Token>> {
+    let mut lines = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let mut tokens = Vec::new();
+        let mut current: Option<(usize, usize)> = None;
+        let mut column = 0;
+        let mut chars = line.char_indices().peekable();
+        let push = |tokens: &mut Vec<Token>, (from, start): (usize, usize), to, end| {
+            tokens.push(Token {
+                text: line[from..to].to_string(),
+                line: number,
+                start,
+                end,
+            })
+        };
+        while let Some(&(i, c)) = chars.peek() {
+            if assembler::literal_at(line, i) {
+                // An unterminated literal runs to the end of the line.
+                let end = assembler::literal_len(&line[i..]).map_or(line.len(), |n| i + n);
+                let start = column;
+                column += line[i..end].encode_utf16().count();
+                while chars.next_if(|&(j, _)| j < end).is_some() {}
+                // A literal inside a token, as in a `match` pattern, does not
+                // end it.
+                if current.is_none() {
+                    push(&mut tokens, (i, start), end, column);
+                }
+                continue;
+            }
+            chars.next();
+            if c.is_whitespace() || c == ';' {
+                if let Some(token) = current.take() {
+                    push(&mut tokens, token, i, column);
+                }
+                if c == ';' {
+                    break;
+                }
+            } else if current.is_none() {
+                current = Some((i, column));
+            }
+            column += c.len_utf16();
+        }
+        if let Some(token) = current {
+            push(&mut tokens, token, line.len(), column);
+        }
+        lines.push(tokens);
+    }
+    lines
+}
+
+impl Document {
+    pub(super) fn new(text: &str) -> Document {
+        Document { lines: scan(text) }
+    }
+
+    // The statement on a line, after any labels.
+    fn statement(&self, line: usize) -> &[Token] {
+        let tokens = &self.lines[line];
+        let labels = tokens.iter().take_while(|t| is_label(&t.text)).count();
+        &tokens[labels..]
+    }
+
+    fn token_at(&self, line: usize, character: usize) -> Option<&Token> {
+        self.lines
+            .get(line)?
+            .iter()
+            .find(|t| t.start <= character && character <= t.end)
+    }
+
+    // The lines of the function containing `line`, from `.func` to `.end`.
+    fn function_span(&self, line: usize) -> Option<(usize, usize)> {
+        let start = (0..=line)
+            .rev()
+            .find(|&l| matches!(self.statement(l).first(), Some(t) if t.text == ".func"))?;
+        let end = (start..self.lines.len())
+            .find(|&l| matches!(self.statement(l).first(), Some(t) if t.text == ".end"))
+            .unwrap_or(self.lines.len() - 1);
+        if line > end {
+            return None;
+        }
+        Some((start, end))
+    }
+
+    fn find_label(&self, line: usize, name: &str) -> Option<&Token> {
+        let (start, end) = self.function_span(line)?;
+        self.lines[start..=end]
+            .iter()
+            .flatten()
+            .find(|t| is_label(&t.text) && t.text[..t.text.len() - 1] == *name)
+    }
+
+    // The name token of a `.func` or `%macro` line that declares `name`.
+    fn find_declaration(&self, keyword: &str, name: &str) -> Option<&Token> {
+        (0..self.lines.len()).find_map(|l| match self.statement(l) {
+            [first, declared, ..] if first.text == keyword && declared.text == name => {
+                Some(declared)
+            }
+            _ => None,
+        })
+    }
+
+    // The position of a token within the statement on its line, with the
+    // statement itself.
+    fn locate(&self, line: usize, character: usize) -> Option<(&Token, usize, &[Token])> {
+        let token = self.token_at(line, character)?;
+        let statement = self.statement(line);
+        let position = statement
+            .iter()
+            .position(|t| std::ptr::eq(t, token))
+            .unwrap_or(usize::MAX);
+        Some((token, position, statement))
+    }
+
+    pub(super) fn definition(
+        &self,
+        line: usize,
+        character: usize,
+        path: Option<&Path>,
+    ) -> Option<Definition<'_>> {
+        let (token, position, statement) = self.locate(line, character)?;
+        if is_label(&token.text) {
+            return Some(Definition::Token(token));
+        }
+        if position == 0 {
+            return self
+                .find_declaration("%macro", &token.text)
+                .map(Definition::Token);
+        }
+        match statement[0].text.as_str() {
+            mnemonic if is_jump(mnemonic) => {
+                self.find_label(line, &token.text).map(Definition::Token)
+            }
+            "call" | "defer" => self
+                .find_declaration(".func", &token.text)
+                .map(Definition::Token),
+            ".func" | "%macro" if position == 1 => Some(Definition::Token(token)),
+            "%include" => match assembler::literal(&token.text) {
+                Ok(Value::Str(name)) => {
+                    let dir = path.and_then(Path::parent).unwrap_or(Path::new(""));
+                    Some(Definition::File(dir.join(&*name)))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    // Markdown describing the token under the cursor, and the token itself.
+    // `bytecode` is the assembled document, used to resolve constants.
+    pub(super) fn hover(
+        &self,
+        line: usize,
+        character: usize,
+        bytecode: Option<&Bytecode>,
+    ) -> Option<(String, &Token)> {
+        let (token, position, statement) = self.locate(line, character)?;
+        if is_label(&token.text) {
+            return Some((
+                format!("Label `{}`", &token.text[..token.text.len() - 1]),
+                token,
+            ));
+        }
+        if position == 0 {
+            if let Some((mnemonic, operand, description)) =
+                DOCS.iter().find(|(name, _, _)| *name == token.text)
+            {
+                let signature = format!("{} {}", mnemonic, operand);
+                return Some((
+                    format!("```\n{}\n```\n{}", signature.trim_end(), description),
+                    token,
+                ));
+            }
+            let declaration = self.find_declaration("%macro", &token.text)?;
+            let params: Vec<&str> = self.statement(declaration.line)[2..]
+                .iter()
+                .map(|t| t.text.as_str())
+                .collect();
+            return Some((
+                format!(
+                    "Macro `{}`\n\nParameters: {}, defined on line {}",
+                    token.text,
+                    if params.is_empty() {
+                        "none".to_string()
+                    } else {
+                        params.join(", ")
+                    },
+                    declaration.line + 1
+                ),
+                token,
+            ));
+        }
+        let text = match (statement[0].text.as_str(), position) {
+            ("push_const", 1) | ("add_local_const", 2) => {
+                let value = assembler::literal(&token.text).ok()?;
+                let mut text = format!("Constant `{}` ({})", value, value.type_name());
+                let index = bytecode.and_then(|bytecode| {
+                    bytecode
+                        .constants()
+                        .iter()
+                        .position(|c| assembler::is_same_constant(c, &value))
+                });
+                if let Some(index) = index {
+                    text.push_str(&format!("\n\nConstant index {}", index));
+                }
+                text
+            }
+            (mnemonic, 1) if is_jump(mnemonic) => {
+                let label = self.find_label(line, &token.text)?;
+                format!("Label `{}`, defined on line {}", token.text, label.line + 1)
+            }
+            ("call" | "defer" | ".func", 1) => {
+                let declaration = self.find_declaration(".func", &token.text)?;
+                let num_args = self
+                    .statement(declaration.line)
+                    .get(2)
+                    .map_or("0", |t| t.text.as_str());
+                format!(
+                    "Function `{}`\n\nArguments: {}, defined on line {}",
+                    token.text,
+                    num_args,
+                    declaration.line + 1
+                )
+            }
+            ("call_native", 1) => {
+                let native = builtins::lookup(&token.text)?;
+                let mut text = format!(
+                    "Native function `{}`\n\nArguments: {}",
+                    native.name, native.arity
+                );
+                if let Some(capability) = native.capability {
+                    text.push_str(&format!(
+                        "\n\nRequires the `{}` capability",
+                        capability.name()
+                    ));
+                }
+                text
+            }
+            _ => return None,
+        };
+        Some((text, token))
+    }
+}
@@ -0,0 +1,353 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::assembler::{self, AssembleError};
+use crate::bytecode::Bytecode;
+use crate::json::{self, Json};
+
+use document::{Definition, Document, Token};
+
+mod document;
+
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+
+struct OpenDocument {
+    text: String,
+    path: Option<PathBuf>,
+    document: Document,
+    assembled: Result<Bytecode, AssembleError>,
+}
+
+struct Server<W> {
+    output: W,
+    documents: HashMap<String, OpenDocument>,
+}
+
+// Runs a language server for `.zasm` files over the given streams until the
+// client sends `exit`. It publishes the assembler's errors as diagnostics and
+// answers definition and hover requests.
+pub fn language_server<R: BufRead, W: Write>(mut input: R, output: W) -> io::Result<()> {
+    let mut server = Server {
+        output,
+        documents: HashMap::new(),
+    };
+    while let Some(body) = read_message(&mut input)? {
+        let message = match json::parse(&body) {
+            Ok(message) => message,
+            Err(e) => {
+                server.respond_error(Json::Null, PARSE_ERROR, e)?;
+                continue;
+            }
+        };
+        if message.get("method").and_then(Json::as_str) == Some("exit") {
+            break;
+        }
+        server.handle(&message)?;
+    }
+    Ok(())
+}
+
+// Reads one message framed by a `Content-Length` header, or returns None at
+// the end of the input.
+fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let length = length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "Missing Content-Length header")
+    })?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn position(line: usize, character: usize) -> Json {
+    Json::object(vec![("line", line.into()), ("character", character.into())])
+}
+
+fn range(start: (usize, usize), end: (usize, usize)) -> Json {
+    Json::object(vec![
+        ("start", position(start.0, start.1)),
+        ("end", position(end.0, end.1)),
+    ])
+}
+
+fn token_range(token: &Token) -> Json {
+    range((token.line, token.start), (token.line, token.end))
+}
+
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?;
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut chars = encoded.bytes();
+    while let Some(b) = chars.next() {
+        if b == b'%' {
+            let hex = [chars.next()?, chars.next()?];
+            let hex = std::str::from_utf8(&hex).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+        } else {
+            bytes.push(b);
+        }
+    }
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for b in path.to_string_lossy().bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                uri.push(b as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    uri
+}
+
+impl OpenDocument {
+    fn new(uri: &str, text: String) -> OpenDocument {
+        let path = uri_to_path(uri);
+        let assembled = match &path {
+            Some(path) => assembler::assemble_source(&text, path),
+            None => assembler::assemble(&text),
+        };
+        OpenDocument {
+            document: Document::new(&text),
+            text,
+            path,
+            assembled,
+        }
+    }
+
+    // Errors in this document cover the line they occur on. Errors in an
+    // included file are reported at the start of the document, since the
+    // client may not have that file open.
+    fn diagnostics(&self) -> Json {
+        let e = match &self.assembled {
+            Ok(_) => return Json::Array(Vec::new()),
+            Err(e) => e,
+        };
+        let here = e.file.is_none() || e.file.as_deref() == self.path.as_deref();
+        let (diagnostic_range, message) = if here && e.line > 0 {
+            let text = self.text.lines().nth(e.line - 1).unwrap_or("");
+            let width = text.encode_utf16().count();
+            (
+                range((e.line - 1, 0), (e.line - 1, width)),
+                e.message.clone(),
+            )
+        } else {
+            (range((0, 0), (0, 0)), e.to_string())
+        };
+        Json::Array(vec![Json::object(vec![
+            ("range", diagnostic_range),
+            ("severity", 1usize.into()),
+            ("source", "zircon".into()),
+            ("message", message.into()),
+        ])])
+    }
+}
+
+impl<W: Write> Server<W> {
+    fn send(&mut self, message: Json) -> io::Result<()> {
+        let body = message.to_string();
+        write!(
+            self.output,
+            "Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )?;
+        self.output.flush()
+    }
+
+    fn respond(&mut self, id: Json, result: Json) -> io::Result<()> {
+        self.send(Json::object(vec![
+            ("jsonrpc", "2.0".into()),
+            ("id", id),
+            ("result", result),
+        ]))
+    }
+
+    fn respond_error(&mut self, id: Json, code: i32, message: String) -> io::Result<()> {
+        let error = Json::object(vec![
+            ("code", Json::Number(code.into())),
+            ("message", message.into()),
+        ]);
+        self.send(Json::object(vec![
+            ("jsonrpc", "2.0".into()),
+            ("id", id),
+            ("error", error),
+        ]))
+    }
+
+    fn handle(&mut self, message: &Json) -> io::Result<()> {
+        let method = message.get("method").and_then(Json::as_str);
+        let params = message.get("params").unwrap_or(&Json::Null);
+        let id = match message.get("id") {
+            Some(id) => id.clone(),
+            // A notification, which gets no response.
+            None => {
+                match method {
+                    Some("textDocument/didOpen") => {
+                        let document = params.get("textDocument").unwrap_or(&Json::Null);
+                        let text = document.get("text").and_then(Json::as_str);
+                        self.update(document, text)?;
+                    }
+                    Some("textDocument/didChange") => {
+                        // Changes replace the whole text, as requested in
+                        // the server capabilities.
+                        let text = match params.get("contentChanges") {
+                            Some(Json::Array(changes)) => changes
+                                .last()
+                                .and_then(|c| c.get("text"))
+                                .and_then(Json::as_str),
+                            _ => None,
+                        };
+                        self.update(params.get("textDocument").unwrap_or(&Json::Null), text)?;
+                    }
+                    Some("textDocument/didClose") => {
+                        let uri = params
+                            .get("textDocument")
+                            .and_then(|d| d.get("uri"))
+                            .and_then(Json::as_str);
+                        if let Some(uri) = uri {
+                            self.documents.remove(uri);
+                            self.publish(uri, Json::Array(Vec::new()))?;
+                        }
+                    }
+                    _ => {}
+                }
+                return Ok(());
+            }
+        };
+        let method = match method {
+            Some(method) => method,
+            // A response to a request from the server, which sends none.
+            None if message.get("result").is_some() || message.get("error").is_some() => {
+                return Ok(());
+            }
+            None => {
+                return self.respond_error(id, INVALID_REQUEST, "Missing method".to_string());
+            }
+        };
+        let result = match method {
+            "initialize" => Json::object(vec![
+                (
+                    "capabilities",
+                    Json::object(vec![
+                        ("textDocumentSync", 1usize.into()),
+                        ("definitionProvider", Json::Bool(true)),
+                        ("hoverProvider", Json::Bool(true)),
+                    ]),
+                ),
+                (
+                    "serverInfo",
+                    Json::object(vec![
+                        ("name", "zircon".into()),
+                        ("version", env!("CARGO_PKG_VERSION").into()),
+                    ]),
+                ),
+            ]),
+            "shutdown" => Json::Null,
+            "textDocument/definition" => self.definition(params),
+            "textDocument/hover" => self.hover(params),
+            _ => {
+                return self.respond_error(
+                    id,
+                    METHOD_NOT_FOUND,
+                    format!("Unsupported method '{}'", method),
+                );
+            }
+        };
+        self.respond(id, result)
+    }
+
+    fn update(&mut self, document: &Json, text: Option<&str>) -> io::Result<()> {
+        let (uri, text) = match (document.get("uri").and_then(Json::as_str), text) {
+            (Some(uri), Some(text)) => (uri.to_string(), text.to_string()),
+            _ => return Ok(()),
+        };
+        let open = OpenDocument::new(&uri, text);
+        let diagnostics = open.diagnostics();
+        self.documents.insert(uri.clone(), open);
+        self.publish(&uri, diagnostics)
+    }
+
+    fn publish(&mut self, uri: &str, diagnostics: Json) -> io::Result<()> {
+        self.send(Json::object(vec![
+            ("jsonrpc", "2.0".into()),
+            ("method", "textDocument/publishDiagnostics".into()),
+            (
+                "params",
+                Json::object(vec![("uri", uri.into()), ("diagnostics", diagnostics)]),
+            ),
+        ]))
+    }
+
+    // The open document and the position a request refers to.
+    fn target<'a>(&'a self, params: &'a Json) -> Option<(&'a str, &'a OpenDocument, usize, usize)> {
+        let uri = params.get("textDocument")?.get("uri")?.as_str()?;
+        let position = params.get("position")?;
+        let line = position.get("line")?.as_usize()?;
+        let character = position.get("character")?.as_usize()?;
+        Some((uri, self.documents.get(uri)?, line, character))
+    }
+
+    fn definition(&self, params: &Json) -> Json {
+        let (uri, open, line, character) = match self.target(params) {
+            Some(target) => target,
+            None => return Json::Null,
+        };
+        match open
+            .document
+            .definition(line, character, open.path.as_deref())
+        {
+            Some(Definition::Token(token)) => {
+                Json::object(vec![("uri", uri.into()), ("range", token_range(token))])
+            }
+            Some(Definition::File(path)) => Json::object(vec![
+                ("uri", path_to_uri(&path).into()),
+                ("range", range((0, 0), (0, 0))),
+            ]),
+            None => Json::Null,
+        }
+    }
+
+    fn hover(&self, params: &Json) -> Json {
+        let (_, open, line, character) = match self.target(params) {
+            Some(target) => target,
+            None => return Json::Null,
+        };
+        match open
+            .document
+            .hover(line, character, open.assembled.as_ref().ok())
+        {
+            Some((text, token)) => Json::object(vec![
+                (
+                    "contents",
+                    Json::object(vec![("kind", "markdown".into()), ("value", text.into())]),
+                ),
+                ("range", token_range(token)),
+            ]),
+            None => Json::Null,
+        }
+    }
+}
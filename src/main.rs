@@ -1,26 +1,927 @@
-use bytecode::Bytecode;
-use vm::VirtualMachine;
 use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Duration;
+use zircon::{
+    Bytecode, Capability, CrashDump, NumberEquality, NumberFormat, Target, VirtualMachine,
+    VmOptions,
+};
 
-mod bytecode;
-mod vm;
+// How many instructions `--memory-profile` runs between samples.
+const MEMORY_PROFILE_INTERVAL: u64 = 10_000;
+
+// How often `--sample-profile` samples the call stack.
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(1);
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        eprintln!("Usage: {} <bytecode_file>", args[0]);
-        return;
+    zircon::report_runtime_errors();
+
+    #[cfg(feature = "tracing")]
+    init_tracing(&args);
+    // An executable made by `bundle` runs its own program, and only takes the
+    // options for running it.
+    let executable = env::current_exe().ok();
+    let bundled = match executable.as_deref().map(zircon::bundled_program) {
+        Some(Ok(bundled)) => bundled,
+        Some(Err(e)) => {
+            eprintln!("Failed to load the bundled program: {}", e);
+            return;
+        }
+        None => None,
+    };
+    match args
+        .get(1)
+        .map(String::as_str)
+        .filter(|_| bundled.is_none())
+    {
+        Some("compile") => return translate(&args, "<source_file>", compile_file),
+        Some("assemble") => {
+            return translate(&args, "<source_file>", |filename| {
+                zircon::assemble_file(filename).map_err(|e| e.to_string())
+            })
+        }
+        Some("build") => {
+            return translate(&args, "<directory>", |dirname| {
+                zircon::assemble_dir(dirname).map_err(|e| e.to_string())
+            })
+        }
+        Some("bundle") => return bundle(&args, executable.as_deref()),
+        Some("transpile") => return transpile(&args),
+        Some("lint") => return lint(&args),
+        Some("fmt") => return fmt(&args),
+        Some("prune") => return prune(&args),
+        Some("convert") => return convert(&args),
+        Some("cache") => return cache(&args),
+        Some("inspect-dump") => return inspect_dump(&args),
+        Some("lsp") => {
+            if let Err(e) = zircon::language_server(io::stdin().lock(), io::stdout().lock()) {
+                eprintln!("Language server failed: {}", e);
+            }
+            return;
+        }
+        #[cfg(feature = "aot")]
+        Some("aot") => return aot(&args),
+        #[cfg(any(feature = "signing", feature = "encryption"))]
+        Some("keygen") => return keygen(&args),
+        #[cfg(feature = "signing")]
+        Some("sign") => return sign(&args),
+        #[cfg(feature = "encryption")]
+        Some("encrypt") => return encrypt(&args),
+        _ => {}
     }
 
-    let bytecode_filename = &args[1];
-    let bytecode_result = Bytecode::from_file(bytecode_filename);
-    match bytecode_result {
+    let options = "[--allow-<capability>...] [--allow-exec=<program>...] [--allow-sqlite=<path>...] [--heap-dump-on-error=<file>] [--crash-dump=<file>] [--metrics] [--stats] [--memory-profile] [--sample-profile=<file>] [--error-tolerant] [--quicken] [--trace-format=text|jsonl] [--trusted-key=<public_key_file>] [--deterministic-float] [--deterministic] [--number-format=<format>] [--number-equality=ieee|total] [--entry=<function_index>] [--step-limit=<count>]";
+    let usage = if bundled.is_some() {
+        format!("Usage: {} {}", args[0], options)
+    } else {
+        format!(
+            "Usage: {0} [run] {1} <bytecode_file>|<assembly_file>\n       {0} compile|assemble <source_file> [-o <bytecode_file>]\n       {0} build <directory> [-o <bytecode_file>]\n       {0} bundle <bytecode_file> [-o <output_file>]\n       {0} transpile --target <language> <bytecode_file> [-o <output_file>]\n       {0} fmt <assembly_file>...\n       {0} lint <bytecode_file>|<assembly_file>\n       {0} prune [--keep=<function_index>...] <bytecode_file> [-o <output_file>]\n       {0} convert --to <version> <bytecode_file> [-o <output_file>]\n       {0} cache <bytecode_file>\n       {0} inspect-dump <dump_file> [<bytecode_file>]\n       {0} lsp",
+            args[0], options
+        )
+    };
+
+    let mut capabilities = Vec::new();
+    let mut executables = Vec::new();
+    let mut databases = Vec::new();
+    let mut heap_dump_filename = None;
+    let mut crash_dump_filename = None;
+    let mut sample_profile_filename = None;
+    let mut metrics = false;
+    let mut stats = false;
+    let mut memory_profile = false;
+    let mut error_tolerant = false;
+    let mut quicken = false;
+    let mut trusted_key = None;
+    let mut deterministic_float = false;
+    let mut deterministic = false;
+    let mut number_format = NumberFormat::default();
+    let mut number_equality = NumberEquality::default();
+    let mut entry = 0;
+    let mut step_limit = None;
+    let mut bytecode_filename = None;
+    // `run` is optional, for scripts that read better with it.
+    let start = if bundled.is_none() && args.get(1).map(String::as_str) == Some("run") {
+        2
+    } else {
+        1
+    };
+    for arg in &args[start..] {
+        if arg == "--metrics" {
+            metrics = true;
+        } else if arg == "--stats" {
+            stats = true;
+        } else if arg == "--memory-profile" {
+            memory_profile = true;
+        } else if arg == "--error-tolerant" {
+            error_tolerant = true;
+        } else if arg == "--quicken" {
+            quicken = true;
+        } else if let Some(name) = arg.strip_prefix("--number-format=") {
+            number_format = match parse_number_format(name) {
+                Some(format) => format,
+                None => {
+                    eprintln!("Unknown number format '{}'", name);
+                    return;
+                }
+            };
+        } else if let Some(name) = arg.strip_prefix("--number-equality=") {
+            number_equality = match name {
+                "ieee" => NumberEquality::Ieee,
+                "total" => NumberEquality::Total,
+                _ => {
+                    eprintln!("Unknown number equality '{}'", name);
+                    return;
+                }
+            };
+        } else if let Some(index) = arg.strip_prefix("--entry=") {
+            entry = match index.parse() {
+                Ok(index) => index,
+                Err(_) => {
+                    eprintln!("Invalid entry function index '{}'", index);
+                    return;
+                }
+            };
+        } else if let Some(count) = arg.strip_prefix("--step-limit=") {
+            step_limit = match count.parse() {
+                Ok(count) => Some(count),
+                Err(_) => {
+                    eprintln!("Invalid step limit '{}'", count);
+                    return;
+                }
+            };
+        } else if arg == "--deterministic" {
+            deterministic = true;
+        } else if arg == "--deterministic-float" {
+            if cfg!(not(feature = "deterministic-float")) {
+                eprintln!("--deterministic-float requires the deterministic-float feature");
+                return;
+            }
+            deterministic_float = true;
+        } else if let Some(format) = arg.strip_prefix("--trace-format=") {
+            // The format is applied by `init_tracing`.
+            if cfg!(not(feature = "tracing")) {
+                eprintln!("--trace-format requires the tracing feature");
+                return;
+            }
+            if format != "text" && format != "jsonl" {
+                eprintln!("Unknown trace format '{}'", format);
+                return;
+            }
+        } else if let Some(filename) = arg.strip_prefix("--heap-dump-on-error=") {
+            heap_dump_filename = Some(filename);
+        } else if let Some(filename) = arg.strip_prefix("--crash-dump=") {
+            crash_dump_filename = Some(filename);
+        } else if let Some(filename) = arg.strip_prefix("--sample-profile=") {
+            sample_profile_filename = Some(filename);
+        } else if let Some(filename) = arg.strip_prefix("--trusted-key=") {
+            if cfg!(not(feature = "signing")) {
+                eprintln!("--trusted-key requires the signing feature");
+                return;
+            }
+            trusted_key = Some(filename);
+        } else if let Some(name) = arg.strip_prefix("--allow-exec=") {
+            executables.push(name);
+        } else if let Some(path) = arg.strip_prefix("--allow-sqlite=") {
+            databases.push(path);
+        } else if let Some(name) = arg.strip_prefix("--allow-") {
+            match Capability::from_name(name) {
+                Some(capability) => capabilities.push(capability),
+                None => {
+                    eprintln!("Unknown capability '{}'", name);
+                    return;
+                }
+            }
+        } else if bytecode_filename.is_none() && bundled.is_none() {
+            bytecode_filename = Some(arg);
+        } else {
+            eprintln!("{}", usage);
+            return;
+        }
+    }
+
+    // Modules sit next to the program and must be signed like it.
+    let (loaded, program_path) = match (bundled, bytecode_filename) {
+        (Some(_), _) if trusted_key.is_some() => {
+            eprintln!("A bundled program has no signature to check");
+            return;
+        }
+        (Some(bytecode), _) => (Ok(bytecode), executable.unwrap_or_default()),
+        (None, Some(filename)) => (load_bytecode(filename, trusted_key), filename.into()),
+        (None, None) => {
+            eprintln!("{}", usage);
+            return;
+        }
+    };
+
+    match loaded {
         Ok(bytecode) => {
-            let mut vm = VirtualMachine::new(&bytecode);
-            vm.run();
+            let mut options = if deterministic {
+                VmOptions::deterministic()
+            } else {
+                VmOptions::new()
+            };
+            for capability in capabilities {
+                options = options.grant(capability);
+            }
+            for name in executables {
+                options = options.allow_executable(name);
+            }
+            for path in databases {
+                options = options.allow_database(path);
+            }
+            options = options.number_format(number_format);
+            options = options.number_equality(number_equality);
+            if let Some(limit) = step_limit {
+                options = options.step_limit(limit);
+            }
+            if quicken {
+                options = options.quickening();
+            }
+            if error_tolerant {
+                options = options.error_tolerant();
+                // Recovered errors are reported together at the end, so they
+                // don't print as they happen. Other panics still do.
+                let hook = panic::take_hook();
+                panic::set_hook(Box::new(move |info| {
+                    if !info.payload().is::<zircon::RuntimeError>() {
+                        hook(info);
+                    }
+                }));
+            }
+            let mut vm = VirtualMachine::with_options(&bytecode, options);
+            if metrics {
+                vm.enable_metrics();
+            }
+            if stats {
+                vm.enable_stats();
+            }
+            if memory_profile {
+                vm.enable_memory_profile(MEMORY_PROFILE_INTERVAL);
+            }
+            if sample_profile_filename.is_some() {
+                vm.enable_sampling_profile(SAMPLE_INTERVAL);
+            }
+            #[cfg(feature = "deterministic-float")]
+            if deterministic_float {
+                vm.enable_deterministic_float();
+            }
+            #[cfg(not(feature = "deterministic-float"))]
+            let _ = deterministic_float;
+            let directory = program_path.parent().unwrap_or(Path::new("")).to_path_buf();
+            let module_key = trusted_key.map(str::to_string);
+            vm.set_module_resolver(move |name| {
+                let path = directory.join(name).with_extension("zrc");
+                load_bytecode(&path.to_string_lossy(), module_key.as_deref())
+            });
+            let result = panic::catch_unwind(AssertUnwindSafe(|| vm.run_from(entry)));
+            if let Some(metrics) = vm.metrics() {
+                eprint!("{}", metrics.to_prometheus());
+            }
+            if let Some(stats) = vm.stats() {
+                eprint!("{}", stats);
+            }
+            if let Some(profile) = vm.memory_profile() {
+                eprint!("{}", profile);
+            }
+            if let (Some(filename), Some(profile)) =
+                (sample_profile_filename, vm.sampling_profile())
+            {
+                if let Err(e) = fs::write(filename, profile.to_string()) {
+                    eprintln!("Failed to write sample profile to '{}': {}", filename, e);
+                }
+            }
+            if let Err(payload) = result {
+                if let Some(filename) = heap_dump_filename {
+                    let written = File::create(filename)
+                        .and_then(|file| vm.dump_heap(&mut BufWriter::new(file)));
+                    if let Err(e) = written {
+                        eprintln!("Failed to write heap dump to '{}': {}", filename, e);
+                    }
+                }
+                if let Some(filename) = crash_dump_filename {
+                    let dump = vm.crash_dump(&zircon::panic_message(&*payload));
+                    let written = File::create(filename)
+                        .and_then(|file| dump.write(&mut BufWriter::new(file)));
+                    if let Err(e) = written {
+                        eprintln!("Failed to write crash dump to '{}': {}", filename, e);
+                    }
+                }
+                if error_tolerant {
+                    eprintln!("{}", zircon::panic_message(&*payload));
+                }
+                panic::resume_unwind(payload);
+            }
+            let recovered = vm.recovered_errors();
+            if !recovered.is_empty() {
+                eprintln!("{} runtime errors recovered:", recovered.len());
+                for error in recovered {
+                    eprint!(
+                        "  function {}, instruction {}",
+                        error.function, error.instruction
+                    );
+                    if let Some(module) = &error.module {
+                        eprint!(" in module '{}'", module);
+                    }
+                    eprintln!(": {}", error.message);
+                }
+                process::exit(1);
+            }
+        }
+        Err(e) => eprintln!("{}", e),
+    }
+}
+
+// Loads the program to run. A file that is not bytecode is assembled in
+// memory, so assembly files can be run directly as scripts, unless there is a
+// trusted key, in which case only bytecode signed with the matching secret
+// key is accepted.
+fn load_bytecode(filename: &str, trusted_key: Option<&str>) -> Result<Bytecode, String> {
+    if !is_bytecode(filename) {
+        if trusted_key.is_some() {
+            return Err(format!("'{}' is not signed bytecode", filename));
+        }
+        return zircon::assemble_file(filename).map_err(|e| e.to_string());
+    }
+    #[cfg(feature = "signing")]
+    if let Some(key_filename) = trusted_key {
+        let key = zircon::read_key_file(key_filename).map_err(|e| e.to_string())?;
+        return Bytecode::from_file_signed(filename, &key).map_err(|e| e.to_string());
+    }
+    #[cfg(not(feature = "signing"))]
+    let _ = trusted_key;
+    Bytecode::from_file(filename)
+        .map_err(|e| format!("Failed to load bytecode from '{}': {}", filename, e))
+}
+
+// Whether the file starts like plain or encrypted bytecode. A file that cannot
+// be opened is treated as bytecode, so the error names the bytecode file.
+fn is_bytecode(filename: &str) -> bool {
+    let mut magic = [0; 3];
+    match File::open(filename) {
+        Ok(mut file) => file.read_exact(&mut magic).is_ok() && &magic == b"ZRC",
+        Err(_) => true,
+    }
+}
+
+// `integer-if-integral`, `shortest`, or `fixed:<digits>`.
+fn parse_number_format(name: &str) -> Option<NumberFormat> {
+    match name {
+        "integer-if-integral" => Some(NumberFormat::IntegerIfIntegral),
+        "shortest" => Some(NumberFormat::Shortest),
+        _ => name
+            .strip_prefix("fixed:")
+            .and_then(|digits| digits.parse().ok())
+            .map(NumberFormat::Fixed),
+    }
+}
+
+// Writes a copy of the running executable with a bytecode file appended.
+fn bundle(args: &[String], executable: Option<&Path>) {
+    let usage = format!(
+        "Usage: {} bundle <bytecode_file> [-o <output_file>]",
+        args[0]
+    );
+    let (bytecode_filename, output_filename) = match &args[2..] {
+        [bytecode] => (bytecode, Path::new(bytecode).with_extension("")),
+        [bytecode, flag, output] if flag == "-o" => (bytecode, PathBuf::from(output)),
+        _ => {
+            eprintln!("{}", usage);
+            return;
+        }
+    };
+    let executable = match executable {
+        Some(executable) => executable,
+        None => {
+            eprintln!("Cannot find the zircon executable to bundle");
+            return;
+        }
+    };
+    if let Err(e) = zircon::bundle(executable, bytecode_filename, &output_filename) {
+        eprintln!("Failed to bundle '{}': {}", bytecode_filename, e);
+    }
+}
+
+// Runs a subcommand that turns text source, named by `source`, into a
+// bytecode file.
+fn translate(args: &[String], source: &str, translator: fn(&str) -> Result<Bytecode, String>) {
+    let usage = format!(
+        "Usage: {} {} {} [-o <bytecode_file>]",
+        args[0], args[1], source
+    );
+    let (source_filename, output_filename) = match &args[2..] {
+        [source] => (source, Path::new(source).with_extension("zrc")),
+        [source, flag, output] if flag == "-o" => (source, Path::new(output).to_path_buf()),
+        _ => {
+            eprintln!("{}", usage);
+            return;
+        }
+    };
+
+    let bytecode = match translator(source_filename) {
+        Ok(bytecode) => bytecode,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    if let Err(e) = bytecode.to_file(&output_filename) {
+        eprintln!(
+            "Failed to write bytecode to '{}': {}",
+            output_filename.display(),
+            e
+        );
+    }
+}
+
+fn compile_file(filename: &str) -> Result<Bytecode, String> {
+    let source = fs::read_to_string(filename)
+        .map_err(|e| format!("Failed to read source from '{}': {}", filename, e))?;
+    zircon::compile(&source).map_err(|e| format!("{}: {}", filename, e))
+}
+
+// Verifies a bytecode file and writes the result next to it as a `.zrcv`
+// file, which later runs load instead while the bytecode file is unchanged.
+fn cache(args: &[String]) {
+    let bytecode_filename = match &args[2..] {
+        [filename] => filename,
+        _ => {
+            eprintln!("Usage: {} cache <bytecode_file>", args[0]);
+            return;
+        }
+    };
+    let source = match fs::read(bytecode_filename) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("Failed to read '{}': {}", bytecode_filename, e);
+            return;
+        }
+    };
+    let bytecode = match Bytecode::from_bytes(&source) {
+        Ok(bytecode) => bytecode,
+        Err(e) => {
+            eprintln!(
+                "Failed to load bytecode from '{}': {}",
+                bytecode_filename, e
+            );
+            return;
+        }
+    };
+    let output_filename = Path::new(bytecode_filename).with_extension("zrcv");
+    let written = File::create(&output_filename).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        bytecode.write_verified(&mut writer, &source)?;
+        writer.flush()
+    });
+    if let Err(e) = written {
+        eprintln!("Failed to write '{}': {}", output_filename.display(), e);
+    }
+}
+
+// Prints warnings about a bytecode or assembly file, one per line.
+fn lint(args: &[String]) {
+    let bytecode_filename = match &args[2..] {
+        [filename] => filename,
+        _ => {
+            eprintln!("Usage: {} lint <bytecode_file>|<assembly_file>", args[0]);
+            return;
+        }
+    };
+    if !is_bytecode(bytecode_filename) {
+        let warnings = fs::read_to_string(bytecode_filename)
+            .map_err(|e| e.to_string())
+            .and_then(|source| zircon::lint_assembly(&source).map_err(|e| e.to_string()));
+        match warnings {
+            Ok(warnings) => {
+                for warning in warnings {
+                    println!("{}:{}", bytecode_filename, warning);
+                }
+            }
+            Err(e) => eprintln!("Failed to lint '{}': {}", bytecode_filename, e),
+        }
+        return;
+    }
+    let bytecode = match Bytecode::from_file(bytecode_filename) {
+        Ok(bytecode) => bytecode,
+        Err(e) => {
+            eprintln!(
+                "Failed to load bytecode from '{}': {}",
+                bytecode_filename, e
+            );
+            return;
+        }
+    };
+    for warning in zircon::lint(&bytecode) {
+        println!("{}: {}", bytecode_filename, warning);
+    }
+}
+
+// Prints a dump written by `--crash-dump`. With the program that failed, each
+// of its frames also shows the instruction it was running.
+fn inspect_dump(args: &[String]) {
+    let (dump_filename, bytecode_filename) = match &args[2..] {
+        [dump] => (dump, None),
+        [dump, bytecode] => (dump, Some(bytecode)),
+        _ => {
+            eprintln!(
+                "Usage: {} inspect-dump <dump_file> [<bytecode_file>]",
+                args[0]
+            );
+            return;
+        }
+    };
+    let dump = match fs::read_to_string(dump_filename)
+        .map_err(|e| e.to_string())
+        .and_then(|text| CrashDump::parse(&text))
+    {
+        Ok(dump) => dump,
+        Err(e) => {
+            eprintln!("Failed to read crash dump from '{}': {}", dump_filename, e);
+            return;
+        }
+    };
+    let bytecode = match bytecode_filename.map(Bytecode::from_file).transpose() {
+        Ok(bytecode) => bytecode,
+        Err(e) => {
+            eprintln!(
+                "Failed to load bytecode from '{}': {}",
+                bytecode_filename.unwrap(),
+                e
+            );
+            return;
+        }
+    };
+    print!("{}", dump.inspect(bytecode.as_ref()));
+}
+
+// Rewrites assembly files in place in the canonical layout. A file that
+// can't be formatted is reported and left as it was.
+fn fmt(args: &[String]) {
+    if args.len() < 3 {
+        eprintln!("Usage: {} fmt <assembly_file>...", args[0]);
+        return;
+    }
+    for filename in &args[2..] {
+        let formatted = fs::read_to_string(filename)
+            .map_err(|e| e.to_string())
+            .and_then(|source| {
+                let formatted = zircon::format_assembly(&source).map_err(|e| e.to_string())?;
+                Ok((formatted != source).then_some(formatted))
+            });
+        match formatted {
+            Ok(Some(formatted)) => {
+                if let Err(e) = fs::write(filename, formatted) {
+                    eprintln!("Failed to write '{}': {}", filename, e);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!("Failed to format '{}': {}", filename, e),
+        }
+    }
+}
+
+// Removes the functions a program can't call and the constants it no longer
+// uses, and prints what went.
+fn prune(args: &[String]) {
+    let usage = format!(
+        "Usage: {} prune [--keep=<function_index>...] <bytecode_file> [-o <output_file>]",
+        args[0]
+    );
+    let mut exports = Vec::new();
+    let mut bytecode_filename = None;
+    let mut output_filename = None;
+    let mut rest = args[2..].iter();
+    while let Some(arg) = rest.next() {
+        if let Some(index) = arg.strip_prefix("--keep=") {
+            match index.parse() {
+                Ok(index) => exports.push(index),
+                Err(_) => {
+                    eprintln!("Invalid function index '{}'", index);
+                    return;
+                }
+            }
+        } else if arg == "-o" {
+            match rest.next() {
+                Some(output) => output_filename = Some(output),
+                None => {
+                    eprintln!("{}", usage);
+                    return;
+                }
+            }
+        } else if bytecode_filename.is_none() {
+            bytecode_filename = Some(arg);
+        } else {
+            eprintln!("{}", usage);
+            return;
+        }
+    }
+    let Some(bytecode_filename) = bytecode_filename else {
+        eprintln!("{}", usage);
+        return;
+    };
+    let mut bytecode = match Bytecode::from_file(bytecode_filename) {
+        Ok(bytecode) => bytecode,
+        Err(e) => {
+            eprintln!(
+                "Failed to load bytecode from '{}': {}",
+                bytecode_filename, e
+            );
+            return;
+        }
+    };
+    let report = zircon::prune(&mut bytecode, &exports);
+    let output_filename = output_filename.unwrap_or(bytecode_filename);
+    if let Err(e) = bytecode.to_file(output_filename) {
+        eprintln!("Failed to write bytecode to '{}': {}", output_filename, e);
+        return;
+    }
+    print!("{}", report);
+}
+
+// Rewrites a bytecode file in another version of the format.
+fn convert(args: &[String]) {
+    let usage = format!(
+        "Usage: {} convert --to <version> <bytecode_file> [-o <output_file>]",
+        args[0]
+    );
+    let (version, bytecode_filename, output_filename) = match &args[2..] {
+        [flag, version, input] if flag == "--to" => (version, input, input),
+        [flag, version, input, o, output] if flag == "--to" && o == "-o" => {
+            (version, input, output)
+        }
+        _ => {
+            eprintln!("{}", usage);
+            return;
+        }
+    };
+    let version = match version.strip_prefix('v').unwrap_or(version).parse() {
+        Ok(version) => version,
+        Err(_) => {
+            eprintln!("Invalid version '{}'", version);
+            return;
         }
+    };
+    let bytecode = match Bytecode::from_file(bytecode_filename) {
+        Ok(bytecode) => bytecode,
         Err(e) => {
-            eprintln!("Failed to load bytecode from '{}': {}", bytecode_filename, e);
+            eprintln!(
+                "Failed to load bytecode from '{}': {}",
+                bytecode_filename, e
+            );
+            return;
+        }
+    };
+    let mut bytes = Vec::new();
+    if let Err(e) = bytecode.write_version(&mut bytes, version) {
+        eprintln!("Failed to convert '{}': {}", bytecode_filename, e);
+        return;
+    }
+    if let Err(e) = fs::write(output_filename, bytes) {
+        eprintln!("Failed to write bytecode to '{}': {}", output_filename, e);
+    }
+}
+
+fn transpile(args: &[String]) {
+    let usage = format!(
+        "Usage: {} transpile --target <language> <bytecode_file> [-o <output_file>]",
+        args[0]
+    );
+    let (target_name, bytecode_filename, output_filename) = match &args[2..] {
+        [flag, target, input] if flag == "--target" => (target, input, None),
+        [flag, target, input, o, output] if flag == "--target" && o == "-o" => {
+            (target, input, Some(output))
+        }
+        _ => {
+            eprintln!("{}", usage);
+            return;
+        }
+    };
+    let target = match Target::from_name(target_name) {
+        Some(target) => target,
+        None => {
+            eprintln!("Unknown target '{}'", target_name);
+            return;
+        }
+    };
+
+    let bytecode = match Bytecode::from_file(bytecode_filename) {
+        Ok(bytecode) => bytecode,
+        Err(e) => {
+            eprintln!(
+                "Failed to load bytecode from '{}': {}",
+                bytecode_filename, e
+            );
+            return;
+        }
+    };
+    let source = match zircon::transpile(&bytecode, target) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    match output_filename {
+        Some(filename) => {
+            if let Err(e) = fs::write(filename, source) {
+                eprintln!("Failed to write output to '{}': {}", filename, e);
+            }
+        }
+        None => print!("{}", source),
+    }
+}
+
+#[cfg(feature = "aot")]
+fn aot(args: &[String]) {
+    let usage = format!(
+        "Usage: {} aot <bytecode_file> [-o <executable_or_object_file>]",
+        args[0]
+    );
+    let (bytecode_filename, output_filename) = match &args[2..] {
+        [input] => (input, Path::new(input).with_extension("")),
+        [input, flag, output] if flag == "-o" => (input, Path::new(output).to_path_buf()),
+        _ => {
+            eprintln!("{}", usage);
+            return;
+        }
+    };
+
+    let bytecode = match Bytecode::from_file(bytecode_filename) {
+        Ok(bytecode) => bytecode,
+        Err(e) => {
+            eprintln!(
+                "Failed to load bytecode from '{}': {}",
+                bytecode_filename, e
+            );
+            return;
+        }
+    };
+    if let Err(e) = zircon::compile_native(&bytecode, &output_filename) {
+        eprintln!("{}", e);
+    }
+}
+
+// Writes a new secret signing key to the given file and its public key to
+// the same path with `.pub` appended, or with `--encryption`, a new
+// encryption key.
+#[cfg(any(feature = "signing", feature = "encryption"))]
+fn keygen(args: &[String]) {
+    let result = match &args[2..] {
+        #[cfg(feature = "signing")]
+        [filename] => zircon::generate_signing_key()
+            .map_err(|e| e.to_string())
+            .and_then(|(secret, public)| {
+                zircon::write_key_file(filename, &secret)
+                    .and_then(|_| zircon::write_key_file(format!("{}.pub", filename), &public))
+                    .map_err(|e| e.to_string())
+            }),
+        #[cfg(feature = "encryption")]
+        [flag, filename] if flag == "--encryption" => zircon::generate_encryption_key()
+            .map_err(|e| e.to_string())
+            .and_then(|key| zircon::write_key_file(filename, &key).map_err(|e| e.to_string())),
+        _ => Err(format!(
+            "Usage: {0} keygen <secret_key_file>\n       {0} keygen --encryption <key_file>",
+            args[0]
+        )),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+    }
+}
+
+#[cfg(feature = "signing")]
+fn sign(args: &[String]) {
+    let usage = format!(
+        "Usage: {} sign --key <secret_key_file> <bytecode_file> [-o <output_file>]",
+        args[0]
+    );
+    let (key_filename, bytecode_filename, output_filename) = match &args[2..] {
+        [flag, key, input] if flag == "--key" => (key, input, input),
+        [flag, key, input, o, output] if flag == "--key" && o == "-o" => (key, input, output),
+        _ => {
+            eprintln!("{}", usage);
+            return;
+        }
+    };
+    let key = match zircon::read_key_file(key_filename) {
+        Ok(key) => key,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    let bytes = match fs::read(bytecode_filename) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!(
+                "Failed to load bytecode from '{}': {}",
+                bytecode_filename, e
+            );
+            return;
+        }
+    };
+    // Only sign files that load, so a typo can't produce a signed non-program.
+    if let Err(e) = Bytecode::read(&mut bytes.as_slice()) {
+        eprintln!(
+            "Failed to load bytecode from '{}': {}",
+            bytecode_filename, e
+        );
+        return;
+    }
+    if let Err(e) = fs::write(output_filename, zircon::sign_bytecode(&bytes, &key)) {
+        eprintln!("Failed to write bytecode to '{}': {}", output_filename, e);
+    }
+}
+
+// Encrypts a bytecode file, which `zircon` runs when `ZIRCON_BYTECODE_KEY`
+// holds the key.
+#[cfg(feature = "encryption")]
+fn encrypt(args: &[String]) {
+    let usage = format!(
+        "Usage: {} encrypt --key <key_file> <bytecode_file> [-o <output_file>]",
+        args[0]
+    );
+    let (key_filename, bytecode_filename, output_filename) = match &args[2..] {
+        [flag, key, input] if flag == "--key" => (key, input, input),
+        [flag, key, input, o, output] if flag == "--key" && o == "-o" => (key, input, output),
+        _ => {
+            eprintln!("{}", usage);
+            return;
+        }
+    };
+    let key = match zircon::read_key_file(key_filename) {
+        Ok(key) => key,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    let bytes = match fs::read(bytecode_filename) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!(
+                "Failed to load bytecode from '{}': {}",
+                bytecode_filename, e
+            );
+            return;
+        }
+    };
+    if let Err(e) = Bytecode::read(&mut bytes.as_slice()) {
+        eprintln!(
+            "Failed to load bytecode from '{}': {}",
+            bytecode_filename, e
+        );
+        return;
+    }
+    let encrypted = match zircon::encrypt_bytecode(&bytes, &key) {
+        Ok(encrypted) => encrypted,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    if let Err(e) = fs::write(output_filename, encrypted) {
+        eprintln!("Failed to write bytecode to '{}': {}", output_filename, e);
+    }
+}
+
+// Logs VM events to standard error at the level named by `ZIRCON_LOG`, such
+// as `debug` for calls or `trace` for every instruction. With
+// `--trace-format=jsonl`, each event is written as a JSON object on its own
+// line, with the fields of the enclosing call span under `span`.
+#[cfg(feature = "tracing")]
+fn init_tracing(args: &[String]) {
+    let level = match env::var("ZIRCON_LOG") {
+        Ok(level) => level,
+        Err(_) => return,
+    };
+    let level = match level.parse::<tracing::Level>() {
+        Ok(level) => level,
+        Err(_) => {
+            eprintln!("Unknown log level '{}'", level);
+            return;
         }
+    };
+    let jsonl = args
+        .iter()
+        .skip(1)
+        .filter_map(|arg| arg.strip_prefix("--trace-format="))
+        .next_back()
+        == Some("jsonl");
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(io::stderr);
+    if jsonl {
+        subscriber
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .init();
+    } else {
+        subscriber.init();
     }
 }
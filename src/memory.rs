@@ -0,0 +1,93 @@
+use std::fmt;
+
+// How many live values of one kind there were, and roughly how many bytes
+// they held. Sizes count contents, such as string bytes and array elements,
+// and not allocator overhead.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KindUsage {
+    pub kind: &'static str,
+    pub count: usize,
+    pub bytes: usize,
+}
+
+// The live values after a number of instructions, by kind, in name order.
+// Kinds with no live values are left out.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemorySample {
+    pub steps: u64,
+    pub kinds: Vec<KindUsage>,
+}
+
+// Samples of the live values collected by a VM with memory profiling
+// enabled, taken every `interval` instructions.
+#[derive(Clone, Debug)]
+pub struct MemoryProfile {
+    pub(crate) interval: u64,
+    pub(crate) samples: Vec<MemorySample>,
+}
+
+impl MemoryProfile {
+    pub(crate) fn new(interval: u64) -> MemoryProfile {
+        MemoryProfile {
+            interval: interval.max(1),
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    pub fn samples(&self) -> &[MemorySample] {
+        &self.samples
+    }
+
+    // The most values of each kind seen in any one sample, with the most
+    // bytes, which may come from another sample.
+    pub fn peaks(&self) -> Vec<KindUsage> {
+        let mut peaks: Vec<KindUsage> = Vec::new();
+        for usage in self.samples.iter().flat_map(|sample| &sample.kinds) {
+            match peaks.iter_mut().find(|peak| peak.kind == usage.kind) {
+                Some(peak) => {
+                    peak.count = peak.count.max(usage.count);
+                    peak.bytes = peak.bytes.max(usage.bytes);
+                }
+                None => peaks.push(*usage),
+            }
+        }
+        peaks.sort_by_key(|peak| peak.kind);
+        peaks
+    }
+}
+
+// A table with a row for each kind: its peak, then its usage in the last
+// sample.
+impl fmt::Display for MemoryProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} samples, every {} instructions",
+            self.samples.len(),
+            self.interval
+        )?;
+        writeln!(
+            f,
+            "{:<14} {:>10} {:>12} {:>10} {:>12}",
+            "kind", "peak_count", "peak_bytes", "last_count", "last_bytes"
+        )?;
+        let last = self.samples.last().map_or(&[][..], |s| &s.kinds);
+        for peak in self.peaks() {
+            let current = last.iter().find(|usage| usage.kind == peak.kind);
+            writeln!(
+                f,
+                "{:<14} {:>10} {:>12} {:>10} {:>12}",
+                peak.kind,
+                peak.count,
+                peak.bytes,
+                current.map_or(0, |usage| usage.count),
+                current.map_or(0, |usage| usage.bytes)
+            )?;
+        }
+        Ok(())
+    }
+}
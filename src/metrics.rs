@@ -0,0 +1,110 @@
+use std::fmt::Write;
+
+use crate::assembler;
+use crate::bytecode::Opcode;
+
+// Counters collected by a VM with metrics enabled. There is no garbage
+// collector, since values are reference counted, so there are no GC metrics.
+#[derive(Clone)]
+pub struct Metrics {
+    pub(crate) instructions: u64,
+    pub(crate) calls: u64,
+    pub(crate) native_calls: u64,
+    pub(crate) host_calls: u64,
+    pub(crate) errors: u64,
+    pub(crate) error_returns: u64,
+    // Indexed by opcode byte.
+    pub(crate) opcodes: [u64; 256],
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics {
+            instructions: 0,
+            calls: 0,
+            native_calls: 0,
+            host_calls: 0,
+            errors: 0,
+            error_returns: 0,
+            opcodes: [0; 256],
+        }
+    }
+}
+
+impl Metrics {
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    // Calls to guest functions, including the entry point and calls from
+    // the host.
+    pub fn calls(&self) -> u64 {
+        self.calls
+    }
+
+    pub fn native_calls(&self) -> u64 {
+        self.native_calls
+    }
+
+    pub fn host_calls(&self) -> u64 {
+        self.host_calls
+    }
+
+    // Error values created with `make_error`.
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    // Error values returned early by `try`.
+    pub fn error_returns(&self) -> u64 {
+        self.error_returns
+    }
+
+    // How many times the instruction with the given assembly mnemonic ran.
+    pub fn opcode(&self, mnemonic: &str) -> Option<u64> {
+        assembler::opcode_for(mnemonic).map(|opcode| self.opcodes[usize::from(opcode.to_u8())])
+    }
+
+    pub(crate) fn record(&mut self, opcode: Opcode) {
+        self.instructions += 1;
+        self.opcodes[usize::from(opcode.to_u8())] += 1;
+    }
+
+    // Renders the counters in the Prometheus text exposition format.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        let counters = [
+            ("instructions", "Instructions executed.", self.instructions),
+            ("calls", "Guest function calls.", self.calls),
+            ("native_calls", "Native function calls.", self.native_calls),
+            (
+                "host_calls",
+                "Guest functions called by the host.",
+                self.host_calls,
+            ),
+            ("errors", "Error values created.", self.errors),
+            (
+                "error_returns",
+                "Errors returned by try.",
+                self.error_returns,
+            ),
+        ];
+        for (name, help, value) in counters {
+            writeln!(out, "# HELP zircon_{}_total {}", name, help).unwrap();
+            writeln!(out, "# TYPE zircon_{}_total counter", name).unwrap();
+            writeln!(out, "zircon_{}_total {}", name, value).unwrap();
+        }
+        out.push_str("# HELP zircon_opcode_total Instructions executed by opcode.\n");
+        out.push_str("# TYPE zircon_opcode_total counter\n");
+        for &opcode in Opcode::ALL {
+            writeln!(
+                out,
+                "zircon_opcode_total{{opcode=\"{}\"}} {}",
+                opcode.name(),
+                self.opcodes[usize::from(opcode.to_u8())]
+            )
+            .unwrap();
+        }
+        out
+    }
+}
@@ -0,0 +1,109 @@
+use crate::bytecode::{Bytecode, Instruction, Opcode, OperandKind, Value};
+
+// Rewrites common instruction sequences into shorter ones that do the same
+// thing. A sequence is only rewritten when no jump lands inside it, and jump
+// targets are moved to match the instructions that remain.
+pub(crate) fn optimize(bytecode: &mut Bytecode) {
+    for index in 0..bytecode.functions().len() {
+        let instructions = optimize_function(bytecode, &bytecode.functions()[index].instructions);
+        bytecode.functions_mut()[index].instructions = instructions;
+    }
+}
+
+fn optimize_function(bytecode: &Bytecode, instructions: &[Instruction]) -> Vec<Instruction> {
+    let len = instructions.len();
+    let mut targets = vec![false; len];
+    for instruction in instructions {
+        if let Some(target) = target(instruction) {
+            if target < len {
+                targets[target] = true;
+            }
+        }
+    }
+
+    let mut optimized = Vec::with_capacity(len);
+    // Where each instruction, and the end of the function, ends up.
+    let mut moved = vec![0; len + 1];
+    let mut ip = 0;
+    while ip < len {
+        let (instruction, count) = fuse(bytecode, &instructions[ip..])
+            .filter(|&(_, count)| !targets[ip + 1..ip + count].contains(&true))
+            .unwrap_or((instructions[ip], 1));
+        moved[ip..ip + count].fill(optimized.len());
+        optimized.push(instruction);
+        ip += count;
+    }
+    moved[len] = optimized.len();
+
+    for instruction in &mut optimized {
+        // A target past the end stays past the end, and fails as it would
+        // have.
+        if let Some(&target) = target(instruction).and_then(|target| moved.get(target)) {
+            *instruction = Instruction::new(instruction.opcode(), Some(target as u16));
+        }
+    }
+    optimized
+}
+
+fn target(instruction: &Instruction) -> Option<usize> {
+    match instruction.opcode().operand_kind() {
+        OperandKind::Target => Some(instruction.operand().into()),
+        _ => None,
+    }
+}
+
+// The instruction that can replace the sequence at the start of
+// `instructions`, and how many instructions it replaces.
+fn fuse(bytecode: &Bytecode, instructions: &[Instruction]) -> Option<(Instruction, usize)> {
+    update_local(bytecode, instructions).or_else(|| compare_and_branch(instructions))
+}
+
+// An `equal`, possibly negated by `not`, or a `not_equal` that only decides
+// a conditional jump, or an ordered comparison that decides a jump when it
+// is true. A `less` that is false doesn't mean the values are greater or
+// equal, as ordered comparisons with NaN are all false, so those aren't
+// fused.
+fn compare_and_branch(instructions: &[Instruction]) -> Option<(Instruction, usize)> {
+    let opcodes: Vec<Opcode> = instructions.iter().take(3).map(|i| i.opcode()).collect();
+    let (opcode, count) = match opcodes[..] {
+        [Opcode::Equal, Opcode::JumpIfTrue, ..] => (Opcode::JumpIfEqual, 2),
+        [Opcode::Equal, Opcode::JumpIfFalse, ..] => (Opcode::JumpIfNotEqual, 2),
+        [Opcode::Equal, Opcode::Not, Opcode::JumpIfTrue] => (Opcode::JumpIfNotEqual, 3),
+        [Opcode::Equal, Opcode::Not, Opcode::JumpIfFalse] => (Opcode::JumpIfEqual, 3),
+        [Opcode::NotEqual, Opcode::JumpIfTrue, ..] => (Opcode::JumpIfNotEqual, 2),
+        [Opcode::NotEqual, Opcode::JumpIfFalse, ..] => (Opcode::JumpIfEqual, 2),
+        [Opcode::LessThan, Opcode::JumpIfTrue, ..] => (Opcode::JumpIfLess, 2),
+        [Opcode::GreaterThan, Opcode::JumpIfTrue, ..] => (Opcode::JumpIfGreater, 2),
+        [Opcode::LessOrEqual, Opcode::JumpIfTrue, ..] => (Opcode::JumpIfLessOrEqual, 2),
+        [Opcode::GreaterOrEqual, Opcode::JumpIfTrue, ..] => (Opcode::JumpIfGreaterOrEqual, 2),
+        _ => return None,
+    };
+    let target = instructions[count - 1].operand();
+    Some((Instruction::new(opcode, Some(target)), count))
+}
+
+// A local updated with a constant: `get_local`, `push_const`, then `add` or
+// `subtract`, and `set_local` back to the same slot.
+fn update_local(bytecode: &Bytecode, instructions: &[Instruction]) -> Option<(Instruction, usize)> {
+    let [get, push, operation, set, ..] = instructions else {
+        return None;
+    };
+    if get.opcode() != Opcode::GetLocal
+        || push.opcode() != Opcode::PushConst
+        || set.opcode() != Opcode::SetLocal
+        || get.operand() != set.operand()
+    {
+        return None;
+    }
+    let (slot, index) = (get.operand(), push.operand());
+    let one = matches!(bytecode.get_constant(index.into()), Some(Value::Number(n)) if *n == 1.0);
+    let instruction = match operation.opcode() {
+        Opcode::Add if one => Instruction::new(Opcode::IncLocal, Some(slot)),
+        Opcode::Subtract if one => Instruction::new(Opcode::DecLocal, Some(slot)),
+        Opcode::Add if slot <= 0xff && index <= 0xff => {
+            Instruction::new(Opcode::AddLocalConst, Some(slot << 8 | index))
+        }
+        _ => return None,
+    };
+    Some((instruction, 4))
+}
@@ -0,0 +1,48 @@
+use crate::bytecode::{NumberEquality, Value};
+
+// Patterns for `match` are stored as tuple constants whose first element is a
+// string naming the kind of pattern:
+//
+//   ("any")                matches every value
+//   ("type", name)         matches values whose type is `name`, such as "number"
+//   ("literal", value)     matches values equal to `value`, with numbers
+//                          compared under the VM's number equality
+//   ("tuple", p1, ...)     matches tuples with one element per pattern
+//   ("array", p1, ...)     matches arrays with one element per pattern
+//   ("error", p)           matches errors whose payload matches `p`
+pub(crate) fn matches(pattern: &Value, value: &Value, numbers: NumberEquality) -> bool {
+    let fields = match pattern {
+        Value::Tuple(fields) => fields,
+        _ => runtime_error!("Invalid match pattern."),
+    };
+    let (kind, rest) = match fields.split_first() {
+        Some((Value::Str(kind), rest)) => (&**kind, rest),
+        _ => runtime_error!("Invalid match pattern."),
+    };
+    match (kind, rest) {
+        ("any", []) => true,
+        ("type", [Value::Str(name)]) => value.type_name() == &**name,
+        ("literal", [literal]) => value.equals(literal, numbers),
+        ("tuple", patterns) => match value {
+            Value::Tuple(elements) => all_match(patterns, elements, numbers),
+            _ => false,
+        },
+        ("array", patterns) => match value {
+            Value::Array(elements) => all_match(patterns, elements, numbers),
+            _ => false,
+        },
+        ("error", [payload]) => match value {
+            Value::Error(inner) => matches(payload, inner, numbers),
+            _ => false,
+        },
+        _ => runtime_error!("Invalid match pattern."),
+    }
+}
+
+fn all_match(patterns: &[Value], elements: &[Value], numbers: NumberEquality) -> bool {
+    patterns.len() == elements.len()
+        && patterns
+            .iter()
+            .zip(elements)
+            .all(|(pattern, element)| matches(pattern, element, numbers))
+}
@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::builtins::Capability;
+use crate::bytecode::Bytecode;
+use crate::crash::DumpedValue;
+use crate::executor::Executor;
+use crate::vm::{self, VirtualMachine, VmOptions};
+
+// How many instructions a job with a timeout runs between looks at the clock.
+const DEADLINE_INTERVAL: u32 = 1024;
+
+const TIMED_OUT: &str = "Job timed out.";
+
+// A program added to a pool.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ProgramId(usize);
+
+// A call to one of a program's functions, with the limits it runs under. By
+// default it calls function 0 with no capabilities and no limits.
+#[derive(Clone, Debug)]
+pub struct Job {
+    program: ProgramId,
+    entry: usize,
+    capabilities: Vec<Capability>,
+    step_limit: Option<u64>,
+    timeout: Option<Duration>,
+}
+
+impl Job {
+    pub fn new(program: ProgramId) -> Self {
+        Job {
+            program,
+            entry: 0,
+            capabilities: Vec::new(),
+            step_limit: None,
+            timeout: None,
+        }
+    }
+
+    // The function to call, which must take no arguments.
+    pub fn entry(mut self, index: usize) -> Self {
+        self.entry = index;
+        self
+    }
+
+    pub fn grant(mut self, capability: Capability) -> Self {
+        if !self.capabilities.contains(&capability) {
+            self.capabilities.push(capability);
+        }
+        self
+    }
+
+    pub fn step_limit(mut self, limit: u64) -> Self {
+        self.step_limit = Some(limit);
+        self
+    }
+
+    // Stops the job with an error once it has run for this long.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+// What a job's function returned, as a type and printed form since values
+// can't leave the thread that made them, and the instructions it took. A
+// program that halts returns nil.
+#[derive(Clone, Debug, PartialEq)]
+pub struct JobOutput {
+    pub value: DumpedValue,
+    pub steps: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum JobError {
+    // The job stopped with a runtime error, including going over its step
+    // limit.
+    Failed(String),
+    TimedOut,
+}
+
+impl fmt::Display for JobError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JobError::Failed(message) => write!(f, "{}", message),
+            JobError::TimedOut => write!(f, "{}", TIMED_OUT),
+        }
+    }
+}
+
+impl std::error::Error for JobError {}
+
+struct Task {
+    job: Job,
+    program: Arc<[u8]>,
+    reply: Sender<Result<JobOutput, JobError>>,
+}
+
+// Runs jobs on a fixed set of worker threads, taking them in the order they
+// were submitted. Programs are added once, as bytecode, and each worker
+// loads a program the first time it runs a job for it and keeps it. Every
+// job gets a VM of its own, so nothing one job does is seen by the next.
+// Runtime errors still reach the panic hook, so a host that wants quiet
+// workers installs its own.
+pub struct VmPool {
+    programs: Vec<Arc<[u8]>>,
+    tasks: Option<Sender<Task>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl VmPool {
+    pub fn new(workers: usize) -> Self {
+        let (tasks, queue) = mpsc::channel::<Task>();
+        let queue = Arc::new(Mutex::new(queue));
+        let workers = (0..workers.max(1))
+            .map(|_| {
+                let queue = Arc::clone(&queue);
+                thread::spawn(move || work(&queue))
+            })
+            .collect();
+        VmPool {
+            programs: Vec::new(),
+            tasks: Some(tasks),
+            workers,
+        }
+    }
+
+    // Adds a program in the bytecode file format, which is loaded and
+    // verified here so that a bad program is refused before any job runs.
+    pub fn add_program(&mut self, bytes: &[u8]) -> io::Result<ProgramId> {
+        Bytecode::read(&mut &bytes[..])?;
+        self.programs.push(Arc::from(bytes));
+        Ok(ProgramId(self.programs.len() - 1))
+    }
+
+    // Queues a job, returning the channel its result arrives on.
+    pub fn submit(&self, job: Job) -> Receiver<Result<JobOutput, JobError>> {
+        let program = match self.programs.get(job.program.0) {
+            Some(program) => Arc::clone(program),
+            None => panic!("Invalid program."),
+        };
+        let (reply, result) = mpsc::channel();
+        let task = Task {
+            job,
+            program,
+            reply,
+        };
+        self.tasks
+            .as_ref()
+            .expect("Pool is shut down.")
+            .send(task)
+            .expect("Pool workers have stopped.");
+        result
+    }
+}
+
+// Jobs already queued are finished before the workers stop.
+impl Drop for VmPool {
+    fn drop(&mut self) {
+        self.tasks = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn work(queue: &Mutex<Receiver<Task>>) {
+    let mut loaded: HashMap<usize, Bytecode> = HashMap::new();
+    loop {
+        let task = match queue.lock() {
+            Ok(queue) => queue.recv(),
+            Err(_) => return,
+        };
+        let Ok(task) = task else {
+            return;
+        };
+        let bytecode = loaded.entry(task.job.program.0).or_insert_with(|| {
+            Bytecode::read(&mut &task.program[..]).expect("Programs are verified when added.")
+        });
+        let _ = task.reply.send(run(bytecode, &task.job));
+    }
+}
+
+fn run(bytecode: &Bytecode, job: &Job) -> Result<JobOutput, JobError> {
+    let mut options = VmOptions::new();
+    for &capability in &job.capabilities {
+        options = options.grant(capability);
+    }
+    if let Some(limit) = job.step_limit {
+        options = options.step_limit(limit);
+    }
+    if let Some(timeout) = job.timeout {
+        options = options.backend(Deadline(Instant::now() + timeout));
+    }
+    let mut vm = VirtualMachine::with_options(bytecode, options);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| vm.call_function(job.entry, Vec::new())));
+    match result {
+        Ok(value) => Ok(JobOutput {
+            value: DumpedValue::new(&value),
+            steps: vm.state().steps,
+        }),
+        Err(payload) => match vm::panic_message(&*payload) {
+            message if message == TIMED_OUT => Err(JobError::TimedOut),
+            message => Err(JobError::Failed(message)),
+        },
+    }
+}
+
+// The interpreter, stopping with an error once the clock passes a deadline.
+#[derive(Debug)]
+struct Deadline(Instant);
+
+impl Executor for Deadline {
+    fn execute(&self, vm: &mut VirtualMachine, base_depth: usize) {
+        let mut steps: u32 = 0;
+        while vm.call_depth() > base_depth && vm.is_running() {
+            vm.step();
+            steps = steps.wrapping_add(1);
+            if steps.is_multiple_of(DEADLINE_INTERVAL) && Instant::now() >= self.0 {
+                runtime_error!("{}", TIMED_OUT);
+            }
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use std::fmt;
+
+use crate::builtins;
+use crate::bytecode::{Bytecode, Instruction, Opcode, OperandKind, Value};
+
+// What `prune` removed, by index in the program it was given.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PruneReport {
+    pub functions: Vec<usize>,
+    pub constants: Vec<usize>,
+    // The native that stopped functions from being removed, if one did.
+    pub kept_functions_for: Option<String>,
+}
+
+impl fmt::Display for PruneReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(native) = &self.kept_functions_for {
+            writeln!(
+                f,
+                "kept every function, since '{}' uses function indexes",
+                native
+            )?;
+        }
+        for index in &self.functions {
+            writeln!(f, "removed function {}", index)?;
+        }
+        for index in &self.constants {
+            writeln!(f, "removed constant {}", index)?;
+        }
+        Ok(())
+    }
+}
+
+// Removes the functions that cannot be called from function 0 or from any of
+// `exports`, then the constants that no remaining function uses, and
+// renumbers what is left. A program that passes function indexes around as
+// values, through natives such as `sort_by`, keeps all of its functions,
+// since the indexes are only known when it runs. Exports past the last
+// function are ignored, and the bytecode must have been verified.
+pub fn prune(bytecode: &mut Bytecode, exports: &[usize]) -> PruneReport {
+    let mut report = PruneReport::default();
+    let (functions, constants) = bytecode.parts_mut();
+
+    let mut reachable = vec![false; functions.len()];
+    let mut pending: Vec<usize> = exports.iter().copied().chain([0]).collect();
+    while let Some(index) = pending.pop() {
+        if index >= functions.len() || reachable[index] {
+            continue;
+        }
+        reachable[index] = true;
+        for instruction in &functions[index].instructions {
+            if instruction.opcode().operand_kind() == OperandKind::Function {
+                pending.push(instruction.operand().into());
+            }
+            if instruction.opcode() == Opcode::CallNative {
+                if let Some(Value::Str(name)) = constants.get(usize::from(instruction.operand())) {
+                    if builtins::FUNCTION_INDEX_NATIVES.contains(&&**name) {
+                        report
+                            .kept_functions_for
+                            .get_or_insert_with(|| name.to_string());
+                    }
+                }
+            }
+        }
+    }
+    if report.kept_functions_for.is_some() {
+        reachable.fill(true);
+    }
+    let function_map = renumber(&reachable, &mut report.functions);
+    let mut index = 0;
+    functions.retain(|_| {
+        index += 1;
+        reachable[index - 1]
+    });
+
+    let mut used = vec![false; constants.len()];
+    for function in functions.iter() {
+        let defaults = function.defaults.iter().map(|&constant| constant.into());
+        let operands = function
+            .instructions
+            .iter()
+            .filter_map(Instruction::constant);
+        for constant in defaults.chain(operands) {
+            if let Some(used) = used.get_mut(constant) {
+                *used = true;
+            }
+        }
+    }
+    let constant_map = renumber(&used, &mut report.constants);
+    let mut index = 0;
+    constants.retain(|_| {
+        index += 1;
+        used[index - 1]
+    });
+
+    let moved = |map: &[Option<u16>], index: usize| map.get(index).copied().flatten();
+    for function in functions.iter_mut() {
+        for constant in &mut function.defaults {
+            *constant = moved(&constant_map, (*constant).into()).unwrap_or(*constant);
+        }
+        for instruction in &mut function.instructions {
+            let opcode = instruction.opcode();
+            let operand = match opcode.operand_kind() {
+                OperandKind::Function => moved(&function_map, instruction.operand().into()),
+                OperandKind::Constant => moved(&constant_map, instruction.operand().into()),
+                OperandKind::LocalConstant => {
+                    let (slot, constant) = instruction.local_constant();
+                    moved(&constant_map, constant).map(|constant| (slot as u16) << 8 | constant)
+                }
+                _ => None,
+            };
+            if let Some(operand) = operand {
+                *instruction = Instruction::new(opcode, Some(operand));
+            }
+        }
+    }
+    report
+}
+
+// The new index of each item that is kept, recording the ones that are not.
+fn renumber(kept: &[bool], removed: &mut Vec<usize>) -> Vec<Option<u16>> {
+    let mut next = 0;
+    kept.iter()
+        .enumerate()
+        .map(|(index, &kept)| {
+            if !kept {
+                removed.push(index);
+                return None;
+            }
+            next += 1;
+            Some(next - 1)
+        })
+        .collect()
+}
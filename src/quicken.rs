@@ -0,0 +1,78 @@
+use crate::bytecode::{Bytecode, Opcode};
+
+// The form an arithmetic instruction has been rewritten to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Form {
+    // Not run yet.
+    Unseen,
+    // First run on two numbers, so it works on them directly instead of
+    // dispatching on the types of its operands.
+    Number,
+    // Run on something else, either first or after it was specialized, and
+    // left generic for good so that it can't flip back and forth.
+    Generic,
+}
+
+// The arithmetic instructions of a program, rewritten on their first run
+// into a form for the operands they found, with counts of how often that
+// happened and how often a specialized form met operands it doesn't handle.
+// Modules imported at run time keep their instructions generic.
+#[derive(Clone, Debug)]
+pub struct Quickening {
+    pub(crate) forms: Vec<Vec<Form>>,
+    pub(crate) quickened: u64,
+    pub(crate) deoptimized: u64,
+}
+
+impl Quickening {
+    pub(crate) fn new(bytecode: &Bytecode) -> Quickening {
+        Quickening {
+            forms: bytecode
+                .functions()
+                .iter()
+                .map(|function| vec![Form::Unseen; function.instructions.len()])
+                .collect(),
+            quickened: 0,
+            deoptimized: 0,
+        }
+    }
+
+    // Instructions rewritten into a specialized form.
+    pub fn quickened(&self) -> u64 {
+        self.quickened
+    }
+
+    // Specialized instructions rewritten back into their generic form.
+    pub fn deoptimized(&self) -> u64 {
+        self.deoptimized
+    }
+
+    // Instructions in a specialized form now.
+    pub fn specialized(&self) -> usize {
+        self.forms
+            .iter()
+            .flatten()
+            .filter(|&&form| form == Form::Number)
+            .count()
+    }
+}
+
+// Whether an instruction can be quickened.
+pub(crate) fn is_arithmetic(opcode: Opcode) -> bool {
+    matches!(
+        opcode,
+        Opcode::Add | Opcode::Subtract | Opcode::Multiply | Opcode::Divide | Opcode::Modulo
+    )
+}
+
+// What an arithmetic instruction's number form computes.
+pub(crate) fn number_op(opcode: Opcode, a: f64, b: f64) -> f64 {
+    match opcode {
+        Opcode::Add => a + b,
+        Opcode::Subtract => a - b,
+        Opcode::Multiply => a * b,
+        Opcode::Divide => a / b,
+        Opcode::Modulo => a % b,
+        _ => panic!("Invalid opcode for binary operation."),
+    }
+}
@@ -0,0 +1,347 @@
+// Support library for programs produced by `zircon transpile`. Each function
+// mirrors an instruction of the VM and fails the same way it does.
+
+use std::env;
+use std::io::{self, Write};
+use std::process;
+use std::rc::Rc;
+
+use crate::builtins;
+pub use crate::builtins::Capability;
+pub use crate::bytecode::Value;
+use crate::bytecode::{Bytecode, NumberEquality, NumberFormat, Opcode};
+use crate::vm::{self, VirtualMachine};
+
+pub struct Runtime {
+    constants: Vec<Value>,
+    capabilities: Vec<Capability>,
+    executables: Vec<String>,
+    databases: Vec<String>,
+}
+
+impl Runtime {
+    pub fn new(constants: Vec<Value>) -> Self {
+        Runtime {
+            constants,
+            capabilities: Vec::new(),
+            executables: Vec::new(),
+            databases: Vec::new(),
+        }
+    }
+
+    // Grants the capabilities named by `--allow-<capability>` arguments, the
+    // programs named by `--allow-exec=<program>` arguments, and the databases
+    // named by `--allow-sqlite=<path>` arguments. Runtime errors print as
+    // they do in the VM.
+    pub fn from_args(constants: Vec<Value>) -> Self {
+        vm::report_runtime_errors();
+        let mut runtime = Runtime::new(constants);
+        for arg in env::args().skip(1) {
+            if let Some(name) = arg.strip_prefix("--allow-exec=") {
+                runtime.allow_executable(name);
+            } else if let Some(path) = arg.strip_prefix("--allow-sqlite=") {
+                runtime.allow_database(path);
+            } else if let Some(name) = arg.strip_prefix("--allow-") {
+                match Capability::from_name(name) {
+                    Some(capability) => runtime.grant(capability),
+                    None => {
+                        eprintln!("Unknown capability '{}'", name);
+                        process::exit(1);
+                    }
+                }
+            }
+        }
+        runtime
+    }
+
+    pub fn grant(&mut self, capability: Capability) {
+        if !self.capabilities.contains(&capability) {
+            self.capabilities.push(capability);
+        }
+    }
+
+    pub fn allow_executable(&mut self, name: &str) {
+        if !self.executables.iter().any(|allowed| allowed == name) {
+            self.executables.push(name.to_string());
+        }
+        self.grant(Capability::Exec);
+    }
+
+    pub fn allow_database(&mut self, path: &str) {
+        if !self.databases.iter().any(|allowed| allowed == path) {
+            self.databases.push(path.to_string());
+        }
+        self.grant(Capability::Sqlite);
+    }
+
+    pub fn constant(&self, index: usize) -> Value {
+        self.constants
+            .get(index)
+            .cloned()
+            .unwrap_or_else(|| runtime_error!("Constant index out of range."))
+    }
+
+    // Natives run against an empty program, so the ones that call back into
+    // guest functions, such as `sort_by`, and the reflection natives, such as
+    // `call_depth`, are unavailable.
+    pub fn call_native(&self, name: &str, stack: &mut Vec<Value>) -> Value {
+        let native = builtins::lookup(name)
+            .unwrap_or_else(|| runtime_error!("Unknown native function '{}'.", name));
+        if let Some(capability) = native.capability {
+            if !self.capabilities.contains(&capability) {
+                runtime_error!(
+                    "Native function '{}' requires the '{}' capability.",
+                    native.name,
+                    capability.name()
+                );
+            }
+        }
+        let args = pop_args(stack, native.arity);
+        let bytecode = Bytecode::new();
+        let mut vm = VirtualMachine::new(&bytecode);
+        for capability in &self.capabilities {
+            vm.grant(*capability);
+        }
+        for name in &self.executables {
+            vm.allow_executable(name);
+        }
+        for path in &self.databases {
+            vm.allow_database(path);
+        }
+        native.call(&mut vm, &args)
+    }
+}
+
+pub fn pop(stack: &mut Vec<Value>) -> Value {
+    stack
+        .pop()
+        .unwrap_or_else(|| runtime_error!("Stack underflow."))
+}
+
+// Pops `count` values, returning them in the order they were pushed.
+pub fn pop_args(stack: &mut Vec<Value>, count: usize) -> Vec<Value> {
+    if stack.len() < count {
+        runtime_error!("Stack underflow.");
+    }
+    stack.split_off(stack.len() - count)
+}
+
+pub fn locals(args: Vec<Value>, count: usize) -> Vec<Option<Value>> {
+    let mut locals: Vec<Option<Value>> = args.into_iter().map(Some).collect();
+    locals.resize(count.max(locals.len()), None);
+    locals
+}
+
+pub fn get_local(locals: &[Option<Value>], index: usize) -> Value {
+    locals[index]
+        .clone()
+        .unwrap_or_else(|| runtime_error!("Local variable read before it was set."))
+}
+
+pub fn add(a: Value, b: Value) -> Value {
+    a.add(&b)
+}
+
+pub fn subtract(a: Value, b: Value) -> Value {
+    a.subtract(&b)
+}
+
+pub fn multiply(a: Value, b: Value) -> Value {
+    a.multiply(&b)
+}
+
+pub fn divide(a: Value, b: Value) -> Value {
+    a.divide(&b)
+}
+
+pub fn modulo(a: Value, b: Value) -> Value {
+    a.modulo(&b)
+}
+
+pub fn and(a: Value, b: Value) -> Value {
+    a.logical_and(&b)
+}
+
+pub fn or(a: Value, b: Value) -> Value {
+    a.logical_or(&b)
+}
+
+pub fn equal(a: Value, b: Value) -> Value {
+    Value::Boolean(a == b)
+}
+
+pub fn not_equal(a: Value, b: Value) -> Value {
+    Value::Boolean(a != b)
+}
+
+pub fn identical(a: Value, b: Value) -> Value {
+    Value::Boolean(a.is_identical(&b))
+}
+
+pub fn total_compare(a: Value, b: Value) -> Value {
+    let ordering = a
+        .total_order(&b)
+        .unwrap_or_else(|| runtime_error!("Invalid operand types for total compare."));
+    Value::Number(ordering as i8 as f64)
+}
+
+pub fn less(a: Value, b: Value) -> Value {
+    a.less_than(&b)
+}
+
+pub fn greater(a: Value, b: Value) -> Value {
+    a.greater_than(&b)
+}
+
+pub fn less_or_equal(a: Value, b: Value) -> Value {
+    a.less_or_equal(&b)
+}
+
+pub fn greater_or_equal(a: Value, b: Value) -> Value {
+    a.greater_or_equal(&b)
+}
+
+pub fn compare(a: Value, b: Value) -> Value {
+    a.three_way(&b)
+}
+
+pub fn negate(value: Value) -> Value {
+    value.negate()
+}
+
+pub fn not(value: Value) -> Value {
+    value.logical_not()
+}
+
+pub fn is_true(value: &Value) -> bool {
+    matches!(value, Value::Boolean(true))
+}
+
+pub fn is_false(value: &Value) -> bool {
+    matches!(value, Value::Boolean(false))
+}
+
+pub fn jump_if_equal(a: Value, b: Value) -> bool {
+    a.branches(&b, Opcode::JumpIfEqual, NumberEquality::Ieee)
+}
+
+pub fn jump_if_not_equal(a: Value, b: Value) -> bool {
+    a.branches(&b, Opcode::JumpIfNotEqual, NumberEquality::Ieee)
+}
+
+pub fn jump_if_less(a: Value, b: Value) -> bool {
+    a.branches(&b, Opcode::JumpIfLess, NumberEquality::Ieee)
+}
+
+pub fn jump_if_less_or_equal(a: Value, b: Value) -> bool {
+    a.branches(&b, Opcode::JumpIfLessOrEqual, NumberEquality::Ieee)
+}
+
+pub fn jump_if_greater(a: Value, b: Value) -> bool {
+    a.branches(&b, Opcode::JumpIfGreater, NumberEquality::Ieee)
+}
+
+pub fn jump_if_greater_or_equal(a: Value, b: Value) -> bool {
+    a.branches(&b, Opcode::JumpIfGreaterOrEqual, NumberEquality::Ieee)
+}
+
+pub fn print(value: Value) {
+    println!("{}", value);
+}
+
+pub fn print_raw(value: Value) {
+    print!("{}", value);
+    let _ = io::stdout().flush();
+}
+
+pub fn eprint(value: Value) {
+    eprintln!("{}", value);
+}
+
+pub fn eprint_raw(value: Value) {
+    eprint!("{}", value);
+}
+
+pub fn format(template: Value, args: Vec<Value>) -> Value {
+    match template {
+        Value::Str(s) => Value::Str(vm::format_template(&s, &args, NumberFormat::default()).into()),
+        _ => runtime_error!("Invalid operand type for format."),
+    }
+}
+
+pub fn make_tuple(elements: Vec<Value>) -> Value {
+    Value::Tuple(elements.into())
+}
+
+pub fn tuple_get(tuple: Value, index: usize) -> Value {
+    match tuple {
+        Value::Tuple(elements) => elements
+            .get(index)
+            .cloned()
+            .unwrap_or_else(|| runtime_error!("Tuple index out of range.")),
+        _ => runtime_error!("Invalid operand type for tuple get."),
+    }
+}
+
+pub fn tuple_unpack(tuple: Value, count: usize, stack: &mut Vec<Value>) {
+    let elements = match tuple {
+        Value::Tuple(elements) => elements,
+        _ => runtime_error!("Invalid operand type for tuple unpack."),
+    };
+    if elements.len() != count {
+        runtime_error!("Tuple length mismatch in unpack.");
+    }
+    stack.extend(elements.iter().cloned());
+}
+
+pub fn array_concat(a: Value, b: Value) -> Value {
+    builtins::array::concat(a, b)
+}
+
+pub fn array_slice(array: Value, start: Value, end: Value) -> Value {
+    builtins::array::slice(array, start, end)
+}
+
+pub fn array_push(array: Value, value: Value) -> Value {
+    builtins::array::push(array, value)
+}
+
+pub fn array_pop(array: Value, stack: &mut Vec<Value>) {
+    let (array, last) = builtins::array::pop(array);
+    stack.push(array);
+    stack.push(last);
+}
+
+pub fn set_add(set: Value, value: Value) -> Value {
+    builtins::set::add(set, value)
+}
+
+pub fn set_contains(set: Value, value: Value) -> Value {
+    Value::Boolean(builtins::set::contains(&set, &value))
+}
+
+pub fn set_remove(set: Value, value: Value) -> Value {
+    builtins::set::remove(set, value)
+}
+
+pub fn make_error(payload: Value) -> Value {
+    Value::Error(Rc::new(payload))
+}
+
+pub fn is_error(value: &Value) -> bool {
+    matches!(value, Value::Error(_))
+}
+
+pub fn halt() -> ! {
+    process::exit(0)
+}
+
+#[cfg(feature = "bigint")]
+pub fn bigint(digits: &str) -> Value {
+    Value::BigInt(digits.parse().expect("Invalid BigInt literal."))
+}
+
+#[cfg(feature = "decimal")]
+pub fn decimal(digits: &str) -> Value {
+    Value::Decimal(digits.parse().expect("Invalid Decimal literal."))
+}
@@ -0,0 +1,72 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+// Call stacks sampled at a fixed interval while a program runs, counted by
+// stack. A timer thread raises a flag when a sample is due, and the VM
+// records the stack at the next instruction, so between samples the cost is
+// one atomic load per instruction.
+#[derive(Debug)]
+pub struct SamplingProfile {
+    interval: Duration,
+    pub(crate) stacks: BTreeMap<String, u64>,
+    pub(crate) due: Arc<AtomicBool>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl SamplingProfile {
+    pub(crate) fn start(interval: Duration) -> SamplingProfile {
+        let interval = interval.max(Duration::from_micros(1));
+        let due = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let timer = (Arc::clone(&due), Arc::clone(&stopped));
+        thread::spawn(move || {
+            let (due, stopped) = timer;
+            while !stopped.load(Ordering::Relaxed) {
+                thread::sleep(interval);
+                due.store(true, Ordering::Relaxed);
+            }
+        });
+        SamplingProfile {
+            interval,
+            stacks: BTreeMap::new(),
+            due,
+            stopped,
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub fn samples(&self) -> u64 {
+        self.stacks.values().sum()
+    }
+
+    // How many samples found each stack, with its frames outermost first and
+    // separated by semicolons.
+    pub fn stacks(&self) -> &BTreeMap<String, u64> {
+        &self.stacks
+    }
+}
+
+// The timer thread stops at its next wake-up.
+impl Drop for SamplingProfile {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
+
+// The folded format read by flame graph tools: a line per stack, with the
+// number of samples after it.
+impl fmt::Display for SamplingProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (stack, count) in &self.stacks {
+            writeln!(f, "{} {}", stack, count)?;
+        }
+        Ok(())
+    }
+}
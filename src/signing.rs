@@ -0,0 +1,115 @@
+use std::fmt;
+use std::path::Path;
+
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+
+use crate::bytecode::{self, Bytecode};
+use crate::keys;
+
+// A signed program is the bytecode followed by this marker and a 64-byte
+// Ed25519 signature of the bytecode. Loaders that don't check signatures stop
+// reading after the last section, so they still accept it.
+const SIGNATURE_MAGIC: &[u8; 4] = b"ZSIG";
+const SIGNATURE_SECTION_LEN: usize = SIGNATURE_MAGIC.len() + 64;
+
+#[derive(Debug)]
+pub struct SigningError {
+    pub message: String,
+}
+
+impl fmt::Display for SigningError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for SigningError {}
+
+fn error<T>(message: String) -> Result<T, SigningError> {
+    Err(SigningError { message })
+}
+
+// Generates a key pair from the operating system's random number generator,
+// returning the secret and public keys.
+pub fn generate_signing_key() -> Result<([u8; 32], [u8; 32]), SigningError> {
+    let secret = match keys::random_key() {
+        Ok(secret) => secret,
+        Err(e) => return error(e.to_string()),
+    };
+    let public = SigningKey::from_bytes(&secret).verifying_key().to_bytes();
+    Ok((secret, public))
+}
+
+// Splits a signed program into the bytecode and its signature.
+fn split_signature(bytes: &[u8]) -> Option<(&[u8], &[u8; 64])> {
+    let split = bytes.len().checked_sub(SIGNATURE_SECTION_LEN)?;
+    let (payload, section) = bytes.split_at(split);
+    let signature = section.strip_prefix(SIGNATURE_MAGIC)?;
+    Some((payload, signature.try_into().ok()?))
+}
+
+// Signs the bytecode in `bytes`, replacing any signature it already has.
+pub fn sign_bytecode(bytes: &[u8], secret_key: &[u8; 32]) -> Vec<u8> {
+    let payload = split_signature(bytes).map_or(bytes, |(payload, _)| payload);
+    let signature = SigningKey::from_bytes(secret_key).sign(payload);
+    let mut signed = Vec::with_capacity(payload.len() + SIGNATURE_SECTION_LEN);
+    signed.extend_from_slice(payload);
+    signed.extend_from_slice(SIGNATURE_MAGIC);
+    signed.extend_from_slice(&signature.to_bytes());
+    signed
+}
+
+// Checks that `bytes` was signed with the secret key matching `public_key`,
+// returning the bytecode without its signature.
+pub fn verify_bytecode<'b>(
+    bytes: &'b [u8],
+    public_key: &[u8; 32],
+) -> Result<&'b [u8], SigningError> {
+    let key = match VerifyingKey::from_bytes(public_key) {
+        Ok(key) => key,
+        Err(_) => return error("Invalid public key".to_string()),
+    };
+    let (payload, signature) = match split_signature(bytes) {
+        Some(split) => split,
+        None => return error("Bytecode is not signed".to_string()),
+    };
+    match key.verify_strict(payload, &Signature::from_bytes(signature)) {
+        Ok(()) => Ok(payload),
+        Err(_) => error("Bytecode signature does not match the trusted key".to_string()),
+    }
+}
+
+impl Bytecode {
+    // Loads a program, refusing it unless it is signed with the secret key
+    // matching `public_key`.
+    pub fn from_file_signed<P: AsRef<Path>>(
+        path: P,
+        public_key: &[u8; 32],
+    ) -> Result<Self, SigningError> {
+        let path = path.as_ref();
+        let bytes = match bytecode::read_program_file(path) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                return error(format!(
+                    "Failed to load bytecode from '{}': {}",
+                    path.display(),
+                    e
+                ))
+            }
+        };
+        let mut payload = verify_bytecode(&bytes, public_key).or_else(|e| {
+            error(format!(
+                "Refusing to load bytecode from '{}': {}",
+                path.display(),
+                e
+            ))
+        })?;
+        Bytecode::read(&mut payload).or_else(|e| {
+            error(format!(
+                "Failed to load bytecode from '{}': {}",
+                path.display(),
+                e
+            ))
+        })
+    }
+}
@@ -0,0 +1,77 @@
+use std::io::{self, Write};
+
+use crate::crash::DumpedValue;
+use crate::json::Json;
+
+// How many values from the top of each operand stack a snapshot keeps.
+pub const STACK_SUMMARY: usize = 8;
+
+// A frame of the call stack. The instruction is the one the frame is running,
+// which for a caller is its call, or None for a frame that hasn't started.
+// The module is None for the program itself.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameState {
+    pub function: usize,
+    pub instruction: Option<usize>,
+    pub module: Option<String>,
+    // Slots that have not been set are None.
+    pub locals: Vec<Option<DumpedValue>>,
+    pub stack_depth: usize,
+    // Up to `STACK_SUMMARY` values, the top of the stack first.
+    pub stack_top: Vec<DumpedValue>,
+}
+
+// A snapshot of a VM, with the innermost frame first. Fuel is the number of
+// instructions left before the step limit stops the program, or None
+// without a limit.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VmState {
+    pub running: bool,
+    pub steps: u64,
+    pub fuel: Option<u64>,
+    pub frames: Vec<FrameState>,
+}
+
+impl VmState {
+    pub fn to_json(&self) -> String {
+        let frames = self.frames.iter().map(|frame| {
+            let locals = frame.locals.iter().map(|local| match local {
+                Some(value) => value.to_json(),
+                None => Json::Null,
+            });
+            Json::object(vec![
+                ("function", frame.function.into()),
+                (
+                    "instruction",
+                    frame.instruction.map_or(Json::Null, Json::from),
+                ),
+                (
+                    "module",
+                    frame.module.as_deref().map_or(Json::Null, Json::from),
+                ),
+                ("locals", Json::Array(locals.collect())),
+                ("stack_depth", frame.stack_depth.into()),
+                (
+                    "stack_top",
+                    Json::Array(frame.stack_top.iter().map(DumpedValue::to_json).collect()),
+                ),
+            ])
+        });
+        let fuel = match self.fuel {
+            Some(fuel) => Json::Number(fuel as f64),
+            None => Json::Null,
+        };
+        Json::object(vec![
+            ("running", Json::Bool(self.running)),
+            ("steps", Json::Number(self.steps as f64)),
+            ("fuel", fuel),
+            ("frames", Json::Array(frames.collect())),
+        ])
+        .to_string()
+    }
+
+    // Writes the snapshot as a line of JSON.
+    pub fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "{}", self.to_json())
+    }
+}
@@ -0,0 +1,86 @@
+use std::fmt;
+
+// How often a function was called and the deepest operand stack and call
+// stack it reached.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FunctionStats {
+    pub calls: u64,
+    // The most values on the function's operand stack at once.
+    pub max_stack: usize,
+    // The deepest call stack the function ran at, counting its own frame.
+    pub max_depth: usize,
+}
+
+// Stack usage collected by a VM with stats enabled, overall and for each
+// function.
+#[derive(Clone, Debug)]
+pub struct Stats {
+    pub(crate) max_stack: usize,
+    pub(crate) max_depth: usize,
+    pub(crate) functions: Vec<FunctionStats>,
+}
+
+impl Stats {
+    pub(crate) fn new(num_functions: usize) -> Stats {
+        Stats {
+            max_stack: 0,
+            max_depth: 0,
+            functions: vec![FunctionStats::default(); num_functions],
+        }
+    }
+
+    pub fn max_stack(&self) -> usize {
+        self.max_stack
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    pub fn function(&self, index: usize) -> Option<&FunctionStats> {
+        self.functions.get(index)
+    }
+
+    pub fn functions(&self) -> &[FunctionStats] {
+        &self.functions
+    }
+
+    pub(crate) fn record_call(&mut self, function: usize, depth: usize) {
+        let stats = &mut self.functions[function];
+        stats.calls += 1;
+        stats.max_depth = stats.max_depth.max(depth);
+        self.max_depth = self.max_depth.max(depth);
+    }
+
+    pub(crate) fn record_stack(&mut self, function: usize, len: usize) {
+        let stats = &mut self.functions[function];
+        stats.max_stack = stats.max_stack.max(len);
+        self.max_stack = self.max_stack.max(len);
+    }
+}
+
+// A table with a row for each function that was called, then the overall
+// maximums.
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<10} {:>10} {:>10} {:>10}",
+            "function", "calls", "max_stack", "max_depth"
+        )?;
+        for (index, stats) in self.functions.iter().enumerate() {
+            if stats.calls > 0 {
+                writeln!(
+                    f,
+                    "{:<10} {:>10} {:>10} {:>10}",
+                    index, stats.calls, stats.max_stack, stats.max_depth
+                )?;
+            }
+        }
+        writeln!(
+            f,
+            "{:<10} {:>10} {:>10} {:>10}",
+            "overall", "", self.max_stack, self.max_depth
+        )
+    }
+}
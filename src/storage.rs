@@ -0,0 +1,67 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use crate::bytecode::Value;
+
+// A key-value store supplied by the host with
+// `VirtualMachine::set_storage`, which guest programs read and write with the
+// `kv_*` natives. The host decides where values live and how long they last.
+pub trait Storage {
+    fn get(&mut self, key: &str) -> Option<Value>;
+    fn set(&mut self, key: &str, value: Value);
+    // Returns whether the key was present.
+    fn delete(&mut self, key: &str) -> bool;
+    // Every key in the store, in any order.
+    fn keys(&mut self) -> Vec<String>;
+}
+
+// A store that lives only as long as the value, for tests and for hosts that
+// persist it themselves.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    entries: BTreeMap<String, Value>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        MemoryStorage::default()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn get(&mut self, key: &str) -> Option<Value> {
+        self.entries.get(key).cloned()
+    }
+
+    fn set(&mut self, key: &str, value: Value) {
+        self.entries.insert(key.to_string(), value);
+    }
+
+    fn delete(&mut self, key: &str) -> bool {
+        self.entries.remove(key).is_some()
+    }
+
+    fn keys(&mut self) -> Vec<String> {
+        self.entries.keys().cloned().collect()
+    }
+}
+
+// Lets the host keep a handle to a store it has given to a VM.
+impl<S: Storage> Storage for Rc<RefCell<S>> {
+    fn get(&mut self, key: &str) -> Option<Value> {
+        self.borrow_mut().get(key)
+    }
+
+    fn set(&mut self, key: &str, value: Value) {
+        self.borrow_mut().set(key, value)
+    }
+
+    fn delete(&mut self, key: &str) -> bool {
+        self.borrow_mut().delete(key)
+    }
+
+    fn keys(&mut self) -> Vec<String> {
+        self.borrow_mut().keys()
+    }
+}
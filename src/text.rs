@@ -0,0 +1,198 @@
+use std::borrow::{Borrow, Cow};
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::{Deref, Range};
+use std::rc::{Rc, Weak};
+
+// The contents of a string value. Strings made while a program runs own
+// their text, while the string constants of a loaded program are ranges of
+// one block holding all of them, so loading doesn't allocate per string.
+#[derive(Clone)]
+pub struct Text(Repr);
+
+#[derive(Clone)]
+enum Repr {
+    Owned(Rc<str>),
+    Pooled(Rc<Pool>, u32, u32),
+}
+
+// The string constants of a loaded program, end to end.
+pub(crate) struct Pool(Box<str>);
+
+impl Pool {
+    pub(crate) fn new(text: String) -> Rc<Pool> {
+        Rc::new(Pool(text.into_boxed_str()))
+    }
+}
+
+impl Text {
+    // Panics if the range is not within the pool or splits a character.
+    pub(crate) fn pooled(pool: &Rc<Pool>, range: Range<usize>) -> Text {
+        assert!(
+            pool.0.get(range.clone()).is_some(),
+            "Pooled text is not within its pool."
+        );
+        Text(Repr::Pooled(
+            Rc::clone(pool),
+            range.start as u32,
+            range.end as u32,
+        ))
+    }
+
+    // Copies of the same string value, as opposed to equal strings.
+    pub fn ptr_eq(a: &Text, b: &Text) -> bool {
+        match (&a.0, &b.0) {
+            (Repr::Owned(a), Repr::Owned(b)) => Rc::ptr_eq(a, b),
+            (Repr::Pooled(a, a_start, a_end), Repr::Pooled(b, b_start, b_end)) => {
+                Rc::ptr_eq(a, b) && (a_start, a_end) == (b_start, b_end)
+            }
+            _ => false,
+        }
+    }
+
+    pub fn downgrade(text: &Text) -> WeakText {
+        WeakText(match &text.0 {
+            Repr::Owned(s) => WeakRepr::Owned(Rc::downgrade(s)),
+            Repr::Pooled(pool, start, end) => WeakRepr::Pooled(Rc::downgrade(pool), *start, *end),
+        })
+    }
+
+    // Where the text is, which identifies the value in heap dumps.
+    pub(crate) fn address(&self) -> usize {
+        self.as_ptr() as usize
+    }
+}
+
+impl Deref for Text {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        match &self.0 {
+            Repr::Owned(s) => s,
+            Repr::Pooled(pool, start, end) => &pool.0[*start as usize..*end as usize],
+        }
+    }
+}
+
+impl AsRef<str> for Text {
+    fn as_ref(&self) -> &str {
+        self
+    }
+}
+
+impl Borrow<str> for Text {
+    fn borrow(&self) -> &str {
+        self
+    }
+}
+
+impl From<&str> for Text {
+    fn from(s: &str) -> Text {
+        Text(Repr::Owned(s.into()))
+    }
+}
+
+impl From<String> for Text {
+    fn from(s: String) -> Text {
+        Text(Repr::Owned(s.into()))
+    }
+}
+
+impl From<Cow<'_, str>> for Text {
+    fn from(s: Cow<str>) -> Text {
+        Text(Repr::Owned(s.into()))
+    }
+}
+
+impl From<Rc<str>> for Text {
+    fn from(s: Rc<str>) -> Text {
+        Text(Repr::Owned(s))
+    }
+}
+
+impl PartialEq for Text {
+    fn eq(&self, other: &Text) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for Text {}
+
+impl PartialEq<str> for Text {
+    fn eq(&self, other: &str) -> bool {
+        &**self == other
+    }
+}
+
+impl PartialEq<&str> for Text {
+    fn eq(&self, other: &&str) -> bool {
+        &**self == *other
+    }
+}
+
+impl PartialOrd for Text {
+    fn partial_cmp(&self, other: &Text) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Text {
+    fn cmp(&self, other: &Text) -> Ordering {
+        (**self).cmp(&**other)
+    }
+}
+
+impl Hash for Text {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+impl fmt::Debug for Text {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl fmt::Display for Text {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct WeakText(WeakRepr);
+
+#[derive(Clone, Debug)]
+enum WeakRepr {
+    Owned(Weak<str>),
+    Pooled(Weak<Pool>, u32, u32),
+}
+
+impl WeakText {
+    pub fn upgrade(&self) -> Option<Text> {
+        match &self.0 {
+            WeakRepr::Owned(s) => s.upgrade().map(|s| Text(Repr::Owned(s))),
+            WeakRepr::Pooled(pool, start, end) => pool
+                .upgrade()
+                .map(|pool| Text(Repr::Pooled(pool, *start, *end))),
+        }
+    }
+
+    pub fn ptr_eq(&self, other: &WeakText) -> bool {
+        match (&self.0, &other.0) {
+            (WeakRepr::Owned(a), WeakRepr::Owned(b)) => a.ptr_eq(b),
+            (WeakRepr::Pooled(a, a_start, a_end), WeakRepr::Pooled(b, b_start, b_end)) => {
+                a.ptr_eq(b) && (a_start, a_end) == (b_start, b_end)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Debug for Pool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Pool").field(&self.0.len()).finish()
+    }
+}
@@ -0,0 +1,16 @@
+// Structured logging through the `tracing` crate. Without the `tracing`
+// feature the macro expands to nothing, so its arguments are never evaluated.
+
+#[cfg(feature = "tracing")]
+macro_rules! event {
+    ($level:ident, $($arg:tt)*) => {
+        tracing::event!(tracing::Level::$level, $($arg)*)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! event {
+    ($level:ident, $($arg:tt)*) => {};
+}
+
+pub(crate) use event;
@@ -0,0 +1,337 @@
+use std::fmt::Write;
+
+use super::{error, is_jump, local_count, TranspileError};
+use crate::bytecode::{Bytecode, Function, Instruction, Opcode, Value};
+
+pub(crate) const RUNTIME: &str = include_str!("runtime.c");
+
+// Natives backed by the C standard library. Programs that call any other
+// native cannot be transpiled to C.
+pub(crate) const NATIVES: &[(&str, usize)] = &[
+    ("sqrt", 1),
+    ("pow", 2),
+    ("sin", 1),
+    ("cos", 1),
+    ("floor", 1),
+    ("abs", 1),
+    ("min", 2),
+    ("max", 2),
+];
+
+// The output is a single C99 file with the value runtime included. Each
+// function keeps its own operand stack and locals, and jumps become gotos.
+pub(super) fn emit(bytecode: &Bytecode) -> Result<String, TranspileError> {
+    let mut out = String::new();
+    out.push_str("/* Generated by `zircon transpile`. Build it with a C99 compiler, for\n");
+    out.push_str(" * example `cc -std=c99 program.c -lm`. */\n\n");
+    out.push_str(RUNTIME);
+
+    let constants = bytecode.constants();
+    writeln!(
+        out,
+        "\nstatic zr_value constants[{}];",
+        constants.len().max(1)
+    )
+    .unwrap();
+    out.push_str("\nstatic void init_constants(void) {\n");
+    for (index, constant) in constants.iter().enumerate() {
+        writeln!(
+            out,
+            "    constants[{}] = {};",
+            index,
+            constant_literal(constant)?
+        )
+        .unwrap();
+    }
+    out.push_str("}\n\n");
+
+    for index in 0..bytecode.functions().len() {
+        writeln!(out, "static zr_value function_{}(zr_value *args);", index).unwrap();
+    }
+    for (index, function) in bytecode.functions().iter().enumerate() {
+        out.push('\n');
+        emit_function(bytecode, index, function, &mut out)?;
+    }
+
+    out.push_str("\nint main(void) {\n");
+    out.push_str("    init_constants();\n");
+    if let Some(entry) = bytecode.functions().first() {
+        // The entry point gets no arguments, so its parameters start unset.
+        writeln!(out, "    zr_value args[{}];", entry.num_args.max(1)).unwrap();
+        for slot in 0..entry.num_args {
+            writeln!(out, "    args[{}] = zr_unset();", slot).unwrap();
+        }
+        out.push_str("    zr_release(function_0(args));\n");
+    }
+    out.push_str("    return 0;\n");
+    out.push_str("}\n");
+    Ok(out)
+}
+
+fn constant_literal(constant: &Value) -> Result<String, TranspileError> {
+    let literal = match constant {
+        Value::Number(n) if n.is_nan() => "zr_number(NAN)".to_string(),
+        Value::Number(n) if n.is_infinite() && *n > 0.0 => "zr_number(INFINITY)".to_string(),
+        Value::Number(n) if n.is_infinite() => "zr_number(-INFINITY)".to_string(),
+        Value::Number(n) => format!("zr_number({:?})", n),
+        Value::Boolean(b) => format!("zr_boolean({})", *b as u8),
+        Value::Char(c) => format!("zr_char(0x{:x})", *c as u32),
+        Value::Str(s) => format!("zr_str({}, {})", string_literal(s), s.len()),
+        other => {
+            return error(format!(
+                "Cannot transpile a {} constant to C",
+                other.type_name()
+            ))
+        }
+    };
+    Ok(literal)
+}
+
+// Escapes everything outside printable ASCII so the literal keeps its bytes
+// under any source character set.
+fn string_literal(s: &str) -> String {
+    let mut literal = String::from("\"");
+    for byte in s.bytes() {
+        match byte {
+            b'"' => literal.push_str("\\\""),
+            b'\\' => literal.push_str("\\\\"),
+            // Keeps `??` sequences from being read as trigraphs.
+            b'?' => literal.push_str("\\?"),
+            0x20..=0x7e => literal.push(byte as char),
+            _ => write!(literal, "\\{:03o}", byte).unwrap(),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+fn emit_function(
+    bytecode: &Bytecode,
+    index: usize,
+    function: &Function,
+    out: &mut String,
+) -> Result<(), TranspileError> {
+    let len = function.instructions.len();
+    let locals = local_count(function);
+    let mut targets = vec![false; len];
+    for instruction in &function.instructions {
+        if is_jump(instruction.opcode()) {
+            if let Some(target) = targets.get_mut(usize::from(instruction.operand())) {
+                *target = true;
+            }
+        }
+    }
+
+    writeln!(out, "static zr_value function_{}(zr_value *args) {{", index).unwrap();
+    out.push_str("    zr_stack stack = {NULL, 0, 0};\n");
+    writeln!(out, "    zr_value locals[{}];", locals.max(1)).unwrap();
+    // Keeps -Wunused quiet for functions that take no arguments or locals.
+    if function.num_args == 0 {
+        out.push_str("    (void)args;\n");
+    }
+    if locals == 0 {
+        out.push_str("    (void)locals;\n");
+    }
+    for slot in 0..locals {
+        if slot < function.num_args {
+            writeln!(out, "    locals[{0}] = args[{0}];", slot).unwrap();
+        } else {
+            writeln!(out, "    locals[{}] = zr_unset();", slot).unwrap();
+        }
+    }
+
+    let leave = format!("zr_leave(&stack, locals, {});", locals);
+    for (ip, instruction) in function.instructions.iter().enumerate() {
+        if targets[ip] {
+            writeln!(out, "block_{}:", ip).unwrap();
+        }
+        let code =
+            instruction_code(bytecode, instruction, len, &leave).map_err(|e| TranspileError {
+                message: format!("Function {}, instruction {}: {}", index, ip, e.message),
+            })?;
+        for line in code.lines() {
+            writeln!(out, "    {}", line).unwrap();
+        }
+    }
+    out.push_str("    zr_panic(\"Invalid instruction index\");\n");
+    out.push_str("    return zr_unset();\n");
+    out.push_str("}\n");
+    Ok(())
+}
+
+fn binary(function: &str) -> String {
+    format!(
+        "{{\n    zr_value b = zr_pop(&stack);\n    zr_value a = zr_pop(&stack);\n    zr_push(&stack, zr_{}(a, b));\n}}",
+        function
+    )
+}
+
+fn update_local(slot: usize, function: &str, operand: &str) -> String {
+    format!(
+        "zr_set_local(locals, {}, zr_{}(zr_get_local(locals, {}), {}));",
+        slot, function, slot, operand
+    )
+}
+
+fn unary(function: &str) -> String {
+    format!("zr_push(&stack, zr_{}(zr_pop(&stack)));", function)
+}
+
+fn jump(target: usize, len: usize) -> String {
+    if target < len {
+        format!("goto block_{};", target)
+    } else {
+        "zr_panic(\"Invalid instruction index\");".to_string()
+    }
+}
+
+fn instruction_code(
+    bytecode: &Bytecode,
+    instruction: &Instruction,
+    len: usize,
+    leave: &str,
+) -> Result<String, TranspileError> {
+    let operand = || usize::from(instruction.operand());
+    let code = match instruction.opcode() {
+        Opcode::PushConst if operand() < bytecode.constants().len() => {
+            format!("zr_push(&stack, zr_retain(constants[{}]));", operand())
+        }
+        Opcode::PushConst => "zr_panic(\"Constant index out of range.\");".to_string(),
+        Opcode::Add => binary("add"),
+        Opcode::Subtract => binary("subtract"),
+        Opcode::Multiply => binary("multiply"),
+        Opcode::Divide => binary("divide"),
+        Opcode::Modulo => binary("modulo"),
+        Opcode::And => binary("and"),
+        Opcode::Or => binary("or"),
+        Opcode::Equal => binary("equal"),
+        Opcode::Identical => binary("identical"),
+        Opcode::LessThan => binary("less"),
+        Opcode::GreaterThan => binary("greater"),
+        Opcode::NotEqual => binary("not_equal"),
+        Opcode::LessOrEqual => binary("less_or_equal"),
+        Opcode::GreaterOrEqual => binary("greater_or_equal"),
+        Opcode::Compare => binary("compare"),
+        Opcode::TotalCompare => {
+            return error(
+                "The total_compare instruction is not supported by the C target".to_string(),
+            )
+        }
+        Opcode::Negate => unary("negate"),
+        Opcode::Not => unary("not"),
+        Opcode::Jump => jump(operand(), len),
+        Opcode::JumpIfTrue => format!(
+            "if (zr_is_true(zr_pop(&stack))) {{\n    {}\n}}",
+            jump(operand(), len)
+        ),
+        Opcode::JumpIfFalse => format!(
+            "if (zr_is_false(zr_pop(&stack))) {{\n    {}\n}}",
+            jump(operand(), len)
+        ),
+        Opcode::JumpIfEqual
+        | Opcode::JumpIfNotEqual
+        | Opcode::JumpIfLess
+        | Opcode::JumpIfLessOrEqual
+        | Opcode::JumpIfGreater
+        | Opcode::JumpIfGreaterOrEqual => format!(
+            "{{\n    zr_value b = zr_pop(&stack);\n    zr_value a = zr_pop(&stack);\n    if (zr_{}(a, b)) {{\n        {}\n    }}\n}}",
+            instruction.opcode().name(),
+            jump(operand(), len)
+        ),
+        Opcode::Print | Opcode::PrintRaw | Opcode::EPrint | Opcode::EPrintRaw => {
+            format!("zr_{}(zr_pop(&stack));", instruction.opcode().name())
+        }
+        Opcode::Format => format!(
+            "{{\n    zr_value args[{}];\n    zr_value template;\n    zr_pop_args(&stack, args, {});\n    template = zr_pop(&stack);\n    zr_push(&stack, zr_format(template, args, {}));\n}}",
+            operand().max(1),
+            operand(),
+            operand()
+        ),
+        Opcode::GetLocal => format!("zr_push(&stack, zr_get_local(locals, {}));", operand()),
+        Opcode::SetLocal => format!("zr_set_local(locals, {}, zr_pop(&stack));", operand()),
+        Opcode::IncLocal => update_local(operand(), "add", "zr_number(1)"),
+        Opcode::DecLocal => update_local(operand(), "subtract", "zr_number(1)"),
+        Opcode::AddLocalConst => match instruction.local_constant() {
+            (slot, index) if index < bytecode.constants().len() => {
+                update_local(slot, "add", &format!("zr_retain(constants[{}])", index))
+            }
+            _ => "zr_panic(\"Constant index out of range.\");".to_string(),
+        },
+        Opcode::Call => {
+            let callee = match bytecode.functions().get(operand()) {
+                Some(callee) => callee,
+                None => return error(format!("Invalid function index {}", operand())),
+            };
+            format!(
+                "{{\n    zr_value args[{}];\n    zr_pop_args(&stack, args, {});\n    zr_push(&stack, function_{}(args));\n}}",
+                callee.num_args.max(1),
+                callee.num_args,
+                operand()
+            )
+        }
+        Opcode::Return => format!(
+            "{{\n    zr_value result = stack.len > 0 ? zr_pop(&stack) : zr_boolean(0);\n    {}\n    return result;\n}}",
+            leave
+        ),
+        Opcode::CallNative => {
+            let name = match bytecode.get_constant(operand()) {
+                Some(Value::Str(name)) => name,
+                _ => return error("Native function name must be a string constant".to_string()),
+            };
+            let arity = match NATIVES.iter().find(|(native, _)| *native == &**name) {
+                Some(&(_, arity)) => arity,
+                None => {
+                    return error(format!(
+                        "Native function '{}' is not supported by the C target",
+                        name
+                    ))
+                }
+            };
+            format!(
+                "{{\n    zr_value args[{}];\n    zr_pop_args(&stack, args, {});\n    zr_push(&stack, zr_native_{}(args));\n}}",
+                arity, arity, name
+            )
+        }
+        Opcode::Import => {
+            return error("The import instruction is not supported by the C target".to_string())
+        }
+        Opcode::Match => {
+            return error("The match instruction is not supported by the C target".to_string())
+        }
+        Opcode::ArgCount => {
+            return error("Default arguments are not supported by the C target".to_string())
+        }
+        Opcode::Defer | Opcode::RunDefers => {
+            return error("Deferred calls are not supported by the C target".to_string())
+        }
+        Opcode::Extension(extension) => {
+            return error(format!(
+                "Extension instruction '{}' is not supported by the C target",
+                extension.name()
+            ))
+        }
+        Opcode::MakeTuple => format!("zr_push(&stack, zr_make_tuple(&stack, {}));", operand()),
+        Opcode::TupleGet => format!(
+            "zr_push(&stack, zr_tuple_get(zr_pop(&stack), {}));",
+            operand()
+        ),
+        Opcode::TupleUnpack => format!(
+            "zr_tuple_unpack(&stack, zr_pop(&stack), {});",
+            operand()
+        ),
+        Opcode::ArrayConcat | Opcode::ArraySlice | Opcode::ArrayPush | Opcode::ArrayPop => {
+            return error("Arrays are not supported by the C target".to_string())
+        }
+        Opcode::SetAdd | Opcode::SetContains | Opcode::SetRemove => {
+            return error("Sets are not supported by the C target".to_string())
+        }
+        Opcode::MakeError => unary("make_error"),
+        Opcode::IsError => unary("is_error"),
+        Opcode::Try => format!(
+            "{{\n    zr_value value = zr_pop(&stack);\n    if (value.tag == ZR_ERROR) {{\n        {}\n        return value;\n    }}\n    zr_push(&stack, value);\n}}",
+            leave
+        ),
+        Opcode::Halt => "zr_halt();".to_string(),
+    };
+    Ok(code)
+}
@@ -0,0 +1,92 @@
+use std::fmt;
+
+use crate::bytecode::{Bytecode, Function, Opcode, OperandKind};
+
+pub(crate) mod c;
+mod rust;
+
+#[derive(Debug)]
+pub struct TranspileError {
+    pub message: String,
+}
+
+impl fmt::Display for TranspileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for TranspileError {}
+
+fn error<T>(message: String) -> Result<T, TranspileError> {
+    Err(TranspileError { message })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Target {
+    Rust,
+    C,
+}
+
+impl Target {
+    pub fn from_name(name: &str) -> Option<Target> {
+        match name {
+            "rust" => Some(Target::Rust),
+            "c" => Some(Target::C),
+            _ => None,
+        }
+    }
+}
+
+// Translates a program into source code for the target language, which runs
+// it against the runtime support library for that target.
+pub fn transpile(bytecode: &Bytecode, target: Target) -> Result<String, TranspileError> {
+    match target {
+        Target::Rust => rust::emit(bytecode),
+        Target::C => c::emit(bytecode),
+    }
+}
+
+fn is_jump(opcode: Opcode) -> bool {
+    opcode.operand_kind() == OperandKind::Target
+}
+
+// Ends a block without falling through to the next instruction.
+pub(crate) fn is_terminator(opcode: Opcode) -> bool {
+    matches!(opcode, Opcode::Jump | Opcode::Return | Opcode::Halt)
+}
+
+// Returns the index of the first instruction of each basic block, in order.
+// Blocks start at the entry, at every jump target, and after every jump,
+// return, or halt. Targets past the end of the function are left out, so
+// jumping to one fails at run time as it does in the VM.
+pub(crate) fn block_starts(function: &Function) -> Vec<usize> {
+    let len = function.instructions.len();
+    let mut starts = vec![false; len];
+    if len > 0 {
+        starts[0] = true;
+    }
+    for (i, instruction) in function.instructions.iter().enumerate() {
+        let opcode = instruction.opcode();
+        if is_jump(opcode) {
+            let target: usize = instruction.operand().into();
+            if target < len {
+                starts[target] = true;
+            }
+        }
+        if (is_jump(opcode) || is_terminator(opcode)) && i + 1 < len {
+            starts[i + 1] = true;
+        }
+    }
+    (0..len).filter(|&i| starts[i]).collect()
+}
+
+// The number of local slots a function uses, counting its arguments.
+pub(crate) fn local_count(function: &Function) -> usize {
+    function
+        .instructions
+        .iter()
+        .filter_map(|i| i.local())
+        .map(|slot| slot + 1)
+        .fold(function.num_args, usize::max)
+}
@@ -0,0 +1,784 @@
+/* Value runtime for programs produced by `zircon transpile --target c`. Each
+ * function mirrors an instruction of the VM and fails the same way it does.
+ * Heap values are reference counted; functions that take values consume
+ * them, and functions that return values return a new reference. */
+
+#include <math.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#if defined(__GNUC__)
+#pragma GCC diagnostic ignored "-Wunused-function"
+#endif
+
+enum {
+    ZR_UNSET,
+    ZR_NIL,
+    ZR_NUMBER,
+    ZR_BOOLEAN,
+    ZR_CHAR,
+    ZR_STR,
+    ZR_TUPLE,
+    ZR_ERROR
+};
+
+typedef struct zr_object {
+    long refcount;
+} zr_object;
+
+typedef struct zr_value {
+    int tag;
+    union {
+        double number;
+        int boolean;
+        uint32_t ch;
+        zr_object *object;
+    } as;
+} zr_value;
+
+typedef struct zr_string {
+    zr_object header;
+    size_t len;
+    char data[1];
+} zr_string;
+
+typedef struct zr_tuple {
+    zr_object header;
+    size_t len;
+    zr_value items[1];
+} zr_tuple;
+
+typedef struct zr_error {
+    zr_object header;
+    zr_value payload;
+} zr_error;
+
+typedef struct zr_stack {
+    zr_value *data;
+    size_t len;
+    size_t cap;
+} zr_stack;
+
+typedef struct zr_buffer {
+    char *data;
+    size_t len;
+    size_t cap;
+} zr_buffer;
+
+static void zr_panic(const char *message) {
+    fflush(stdout);
+    fprintf(stderr, "panicked: %s\n", message);
+    exit(101);
+}
+
+static void *zr_alloc(size_t size) {
+    void *p = malloc(size);
+    if (p == NULL) {
+        zr_panic("Out of memory.");
+    }
+    return p;
+}
+
+static int zr_is_object(zr_value v) {
+    return v.tag == ZR_STR || v.tag == ZR_TUPLE || v.tag == ZR_ERROR;
+}
+
+static zr_value zr_retain(zr_value v) {
+    if (zr_is_object(v)) {
+        v.as.object->refcount++;
+    }
+    return v;
+}
+
+static void zr_release(zr_value v) {
+    size_t i;
+    if (!zr_is_object(v) || --v.as.object->refcount > 0) {
+        return;
+    }
+    if (v.tag == ZR_TUPLE) {
+        zr_tuple *t = (zr_tuple *)v.as.object;
+        for (i = 0; i < t->len; i++) {
+            zr_release(t->items[i]);
+        }
+    } else if (v.tag == ZR_ERROR) {
+        zr_release(((zr_error *)v.as.object)->payload);
+    }
+    free(v.as.object);
+}
+
+static zr_value zr_unset(void) {
+    zr_value v;
+    v.tag = ZR_UNSET;
+    v.as.number = 0;
+    return v;
+}
+
+static zr_value zr_number(double n) {
+    zr_value v;
+    v.tag = ZR_NUMBER;
+    v.as.number = n;
+    return v;
+}
+
+static zr_value zr_boolean(int b) {
+    zr_value v;
+    v.tag = ZR_BOOLEAN;
+    v.as.boolean = b != 0;
+    return v;
+}
+
+static zr_value zr_char(uint32_t c) {
+    zr_value v;
+    v.tag = ZR_CHAR;
+    v.as.ch = c;
+    return v;
+}
+
+static zr_value zr_str(const char *data, size_t len) {
+    zr_string *s = zr_alloc(sizeof(zr_string) + len);
+    zr_value v;
+    s->header.refcount = 1;
+    s->len = len;
+    memcpy(s->data, data, len);
+    s->data[len] = '\0';
+    v.tag = ZR_STR;
+    v.as.object = &s->header;
+    return v;
+}
+
+/* Operand stack */
+
+static void zr_push(zr_stack *stack, zr_value v) {
+    if (stack->len == stack->cap) {
+        stack->cap = stack->cap ? stack->cap * 2 : 16;
+        stack->data = realloc(stack->data, stack->cap * sizeof(zr_value));
+        if (stack->data == NULL) {
+            zr_panic("Out of memory.");
+        }
+    }
+    stack->data[stack->len++] = v;
+}
+
+static zr_value zr_pop(zr_stack *stack) {
+    if (stack->len == 0) {
+        zr_panic("Stack underflow.");
+    }
+    return stack->data[--stack->len];
+}
+
+/* Pops `count` values into `out` in the order they were pushed. */
+static void zr_pop_args(zr_stack *stack, zr_value *out, size_t count) {
+    if (stack->len < count) {
+        zr_panic("Stack underflow.");
+    }
+    stack->len -= count;
+    memcpy(out, stack->data + stack->len, count * sizeof(zr_value));
+}
+
+static zr_value zr_get_local(zr_value *locals, size_t index) {
+    if (locals[index].tag == ZR_UNSET) {
+        zr_panic("Local variable read before it was set.");
+    }
+    return zr_retain(locals[index]);
+}
+
+static void zr_set_local(zr_value *locals, size_t index, zr_value v) {
+    zr_release(locals[index]);
+    locals[index] = v;
+}
+
+/* Releases everything a function still holds when it returns. */
+static void zr_leave(zr_stack *stack, zr_value *locals, size_t count) {
+    size_t i;
+    for (i = 0; i < stack->len; i++) {
+        zr_release(stack->data[i]);
+    }
+    free(stack->data);
+    for (i = 0; i < count; i++) {
+        zr_release(locals[i]);
+    }
+}
+
+/* Formatting */
+
+static void zr_buffer_append(zr_buffer *b, const char *data, size_t len) {
+    if (b->len + len + 1 > b->cap) {
+        while (b->len + len + 1 > b->cap) {
+            b->cap = b->cap ? b->cap * 2 : 64;
+        }
+        b->data = realloc(b->data, b->cap);
+        if (b->data == NULL) {
+            zr_panic("Out of memory.");
+        }
+    }
+    memcpy(b->data + b->len, data, len);
+    b->len += len;
+    b->data[b->len] = '\0';
+}
+
+static void zr_buffer_puts(zr_buffer *b, const char *s) {
+    zr_buffer_append(b, s, strlen(s));
+}
+
+/* Writes the shortest digits that read back as `n`, without an exponent. */
+static void zr_format_number(zr_buffer *b, double n) {
+    char text[40];
+    char digits[20];
+    int precision, exponent, count = 0, i;
+    char *p;
+    if (isnan(n)) {
+        zr_buffer_puts(b, "NaN");
+        return;
+    }
+    if (isinf(n)) {
+        zr_buffer_puts(b, n > 0 ? "inf" : "-inf");
+        return;
+    }
+    for (precision = 1; precision < 17; precision++) {
+        sprintf(text, "%.*e", precision - 1, n);
+        if (strtod(text, NULL) == n) {
+            break;
+        }
+    }
+    sprintf(text, "%.*e", precision - 1, n);
+    p = text;
+    if (*p == '-') {
+        zr_buffer_puts(b, "-");
+        p++;
+    }
+    for (; *p != 'e'; p++) {
+        if (*p != '.') {
+            digits[count++] = *p;
+        }
+    }
+    exponent = atoi(p + 1);
+    while (count > 1 && digits[count - 1] == '0') {
+        count--;
+    }
+    if (exponent < 0) {
+        zr_buffer_puts(b, "0.");
+        for (i = 0; i < -exponent - 1; i++) {
+            zr_buffer_puts(b, "0");
+        }
+        zr_buffer_append(b, digits, count);
+    } else if (exponent + 1 >= count) {
+        zr_buffer_append(b, digits, count);
+        for (i = 0; i < exponent + 1 - count; i++) {
+            zr_buffer_puts(b, "0");
+        }
+    } else {
+        zr_buffer_append(b, digits, exponent + 1);
+        zr_buffer_puts(b, ".");
+        zr_buffer_append(b, digits + exponent + 1, count - exponent - 1);
+    }
+}
+
+static void zr_format_char(zr_buffer *b, uint32_t c) {
+    char out[4];
+    size_t len;
+    if (c < 0x80) {
+        out[0] = (char)c;
+        len = 1;
+    } else if (c < 0x800) {
+        out[0] = (char)(0xC0 | (c >> 6));
+        out[1] = (char)(0x80 | (c & 0x3F));
+        len = 2;
+    } else if (c < 0x10000) {
+        out[0] = (char)(0xE0 | (c >> 12));
+        out[1] = (char)(0x80 | ((c >> 6) & 0x3F));
+        out[2] = (char)(0x80 | (c & 0x3F));
+        len = 3;
+    } else {
+        out[0] = (char)(0xF0 | (c >> 18));
+        out[1] = (char)(0x80 | ((c >> 12) & 0x3F));
+        out[2] = (char)(0x80 | ((c >> 6) & 0x3F));
+        out[3] = (char)(0x80 | (c & 0x3F));
+        len = 4;
+    }
+    zr_buffer_append(b, out, len);
+}
+
+static void zr_format_value(zr_buffer *b, zr_value v) {
+    size_t i;
+    switch (v.tag) {
+    case ZR_NIL:
+        zr_buffer_puts(b, "nil");
+        break;
+    case ZR_NUMBER:
+        zr_format_number(b, v.as.number);
+        break;
+    case ZR_BOOLEAN:
+        zr_buffer_puts(b, v.as.boolean ? "true" : "false");
+        break;
+    case ZR_CHAR:
+        zr_format_char(b, v.as.ch);
+        break;
+    case ZR_STR: {
+        zr_string *s = (zr_string *)v.as.object;
+        zr_buffer_append(b, s->data, s->len);
+        break;
+    }
+    case ZR_TUPLE: {
+        zr_tuple *t = (zr_tuple *)v.as.object;
+        zr_buffer_puts(b, "(");
+        for (i = 0; i < t->len; i++) {
+            if (i > 0) {
+                zr_buffer_puts(b, ", ");
+            }
+            zr_format_value(b, t->items[i]);
+        }
+        if (t->len == 1) {
+            zr_buffer_puts(b, ",");
+        }
+        zr_buffer_puts(b, ")");
+        break;
+    }
+    case ZR_ERROR:
+        zr_buffer_puts(b, "error(");
+        zr_format_value(b, ((zr_error *)v.as.object)->payload);
+        zr_buffer_puts(b, ")");
+        break;
+    }
+}
+
+/* Writes a value and `end` to `out`. Standard output is flushed before
+ * writing to standard error, and after output without a newline, so that
+ * everything shows up in order and right away. */
+static void zr_write(FILE *out, zr_value v, const char *end) {
+    zr_buffer b = {NULL, 0, 0};
+    zr_format_value(&b, v);
+    zr_buffer_puts(&b, end);
+    if (out != stdout) {
+        fflush(stdout);
+    }
+    fwrite(b.data, 1, b.len, out);
+    if (*end == '\0') {
+        fflush(out);
+    }
+    free(b.data);
+    zr_release(v);
+}
+
+static void zr_print(zr_value v) {
+    zr_write(stdout, v, "\n");
+}
+
+static void zr_print_raw(zr_value v) {
+    zr_write(stdout, v, "");
+}
+
+static void zr_eprint(zr_value v) {
+    zr_write(stderr, v, "\n");
+}
+
+static void zr_eprint_raw(zr_value v) {
+    zr_write(stderr, v, "");
+}
+
+static zr_value zr_format(zr_value template, zr_value *args, size_t count) {
+    zr_buffer b = {NULL, 0, 0};
+    zr_string *s;
+    zr_value result;
+    size_t i, next = 0;
+    if (template.tag != ZR_STR) {
+        zr_panic("Invalid operand type for format.");
+    }
+    s = (zr_string *)template.as.object;
+    zr_buffer_append(&b, "", 0);
+    for (i = 0; i < s->len; i++) {
+        char c = s->data[i];
+        char d = i + 1 < s->len ? s->data[i + 1] : '\0';
+        if ((c == '{' && d == '{') || (c == '}' && d == '}')) {
+            zr_buffer_append(&b, &c, 1);
+            i++;
+        } else if (c == '{' && d == '}') {
+            if (next == count) {
+                zr_panic("Too few arguments for format.");
+            }
+            zr_format_value(&b, args[next++]);
+            i++;
+        } else {
+            zr_buffer_append(&b, &c, 1);
+        }
+    }
+    if (next < count) {
+        zr_panic("Too many arguments for format.");
+    }
+    result = zr_str(b.data, b.len);
+    free(b.data);
+    zr_release(template);
+    for (i = 0; i < count; i++) {
+        zr_release(args[i]);
+    }
+    return result;
+}
+
+/* Arithmetic and logic */
+
+static void zr_numbers(zr_value a, zr_value b, const char *name) {
+    if (a.tag != ZR_NUMBER || b.tag != ZR_NUMBER) {
+        char message[64];
+        sprintf(message, "Invalid operand types for %s.", name);
+        zr_panic(message);
+    }
+}
+
+static zr_value zr_add(zr_value a, zr_value b) {
+    zr_numbers(a, b, "add");
+    return zr_number(a.as.number + b.as.number);
+}
+
+static zr_value zr_subtract(zr_value a, zr_value b) {
+    zr_numbers(a, b, "subtract");
+    return zr_number(a.as.number - b.as.number);
+}
+
+static zr_value zr_multiply(zr_value a, zr_value b) {
+    zr_numbers(a, b, "multiply");
+    return zr_number(a.as.number * b.as.number);
+}
+
+static zr_value zr_divide(zr_value a, zr_value b) {
+    zr_numbers(a, b, "divide");
+    return zr_number(a.as.number / b.as.number);
+}
+
+static zr_value zr_modulo(zr_value a, zr_value b) {
+    zr_numbers(a, b, "modulo");
+    return zr_number(fmod(a.as.number, b.as.number));
+}
+
+static zr_value zr_negate(zr_value a) {
+    if (a.tag != ZR_NUMBER) {
+        zr_panic("Invalid operand type for negate.");
+    }
+    return zr_number(-a.as.number);
+}
+
+static zr_value zr_and(zr_value a, zr_value b) {
+    if (a.tag != ZR_BOOLEAN || b.tag != ZR_BOOLEAN) {
+        zr_panic("Invalid operand types for logical and.");
+    }
+    return zr_boolean(a.as.boolean && b.as.boolean);
+}
+
+static zr_value zr_or(zr_value a, zr_value b) {
+    if (a.tag != ZR_BOOLEAN || b.tag != ZR_BOOLEAN) {
+        zr_panic("Invalid operand types for logical or.");
+    }
+    return zr_boolean(a.as.boolean || b.as.boolean);
+}
+
+static zr_value zr_not(zr_value a) {
+    if (a.tag != ZR_BOOLEAN) {
+        zr_panic("Invalid operand type for logical not.");
+    }
+    return zr_boolean(!a.as.boolean);
+}
+
+static int zr_values_equal(zr_value a, zr_value b) {
+    size_t i;
+    if (a.tag != b.tag) {
+        return 0;
+    }
+    switch (a.tag) {
+    case ZR_NIL:
+        return 1;
+    case ZR_NUMBER:
+        return a.as.number == b.as.number;
+    case ZR_BOOLEAN:
+        return a.as.boolean == b.as.boolean;
+    case ZR_CHAR:
+        return a.as.ch == b.as.ch;
+    case ZR_STR: {
+        zr_string *x = (zr_string *)a.as.object;
+        zr_string *y = (zr_string *)b.as.object;
+        return x->len == y->len && memcmp(x->data, y->data, x->len) == 0;
+    }
+    case ZR_TUPLE: {
+        zr_tuple *x = (zr_tuple *)a.as.object;
+        zr_tuple *y = (zr_tuple *)b.as.object;
+        if (x->len != y->len) {
+            return 0;
+        }
+        for (i = 0; i < x->len; i++) {
+            if (!zr_values_equal(x->items[i], y->items[i])) {
+                return 0;
+            }
+        }
+        return 1;
+    }
+    case ZR_ERROR:
+        return zr_values_equal(((zr_error *)a.as.object)->payload,
+                               ((zr_error *)b.as.object)->payload);
+    }
+    return 0;
+}
+
+static zr_value zr_equal(zr_value a, zr_value b) {
+    int equal = zr_values_equal(a, b);
+    zr_release(a);
+    zr_release(b);
+    return zr_boolean(equal);
+}
+
+static zr_value zr_not_equal(zr_value a, zr_value b) {
+    int equal = zr_values_equal(a, b);
+    zr_release(a);
+    zr_release(b);
+    return zr_boolean(!equal);
+}
+
+static zr_value zr_identical(zr_value a, zr_value b) {
+    int identical;
+    if (zr_is_object(a) && zr_is_object(b)) {
+        identical = a.tag == b.tag && a.as.object == b.as.object;
+    } else {
+        identical = zr_values_equal(a, b);
+    }
+    zr_release(a);
+    zr_release(b);
+    return zr_boolean(identical);
+}
+
+/* Orders two values of the same type: -1, 0, or 1, 2 for values that are
+ * unordered, which are NaN and tuples whose first differing items are
+ * unordered, or 3 for values that cannot be ordered. */
+static int zr_compare_values(zr_value a, zr_value b) {
+    size_t i, len;
+    int order;
+    if (a.tag != b.tag) {
+        return 3;
+    }
+    switch (a.tag) {
+    case ZR_NUMBER:
+        if (a.as.number < b.as.number) {
+            return -1;
+        }
+        if (a.as.number > b.as.number) {
+            return 1;
+        }
+        return a.as.number == b.as.number ? 0 : 2;
+    case ZR_BOOLEAN:
+        return (a.as.boolean > b.as.boolean) - (a.as.boolean < b.as.boolean);
+    case ZR_CHAR:
+        return (a.as.ch > b.as.ch) - (a.as.ch < b.as.ch);
+    case ZR_STR: {
+        zr_string *x = (zr_string *)a.as.object;
+        zr_string *y = (zr_string *)b.as.object;
+        len = x->len < y->len ? x->len : y->len;
+        order = memcmp(x->data, y->data, len);
+        if (order != 0) {
+            return order < 0 ? -1 : 1;
+        }
+        return (x->len > y->len) - (x->len < y->len);
+    }
+    case ZR_TUPLE: {
+        zr_tuple *x = (zr_tuple *)a.as.object;
+        zr_tuple *y = (zr_tuple *)b.as.object;
+        len = x->len < y->len ? x->len : y->len;
+        for (i = 0; i < len; i++) {
+            order = zr_compare_values(x->items[i], y->items[i]);
+            if (order == 2 || order == 3) {
+                return 2;
+            }
+            if (order != 0) {
+                return order;
+            }
+        }
+        return (x->len > y->len) - (x->len < y->len);
+    }
+    }
+    return 3;
+}
+
+/* Orders two values for the comparison instruction `name`, failing if they
+ * cannot be ordered. */
+static int zr_order(zr_value a, zr_value b, const char *name) {
+    int order = zr_compare_values(a, b);
+    zr_release(a);
+    zr_release(b);
+    if (order == 3) {
+        char message[64];
+        sprintf(message, "Invalid operand types for %s.", name);
+        zr_panic(message);
+    }
+    return order;
+}
+
+static zr_value zr_less(zr_value a, zr_value b) {
+    return zr_boolean(zr_order(a, b, "less") == -1);
+}
+
+static zr_value zr_greater(zr_value a, zr_value b) {
+    return zr_boolean(zr_order(a, b, "greater") == 1);
+}
+
+static zr_value zr_less_or_equal(zr_value a, zr_value b) {
+    int order = zr_order(a, b, "less_or_equal");
+    return zr_boolean(order == -1 || order == 0);
+}
+
+static zr_value zr_greater_or_equal(zr_value a, zr_value b) {
+    int order = zr_order(a, b, "greater_or_equal");
+    return zr_boolean(order == 1 || order == 0);
+}
+
+static zr_value zr_compare(zr_value a, zr_value b) {
+    int order = zr_order(a, b, "compare");
+    if (order == 2) {
+        zr_panic("Cannot compare unordered values.");
+    }
+    return zr_number(order);
+}
+
+static int zr_jump_if_equal(zr_value a, zr_value b) {
+    int equal = zr_values_equal(a, b);
+    zr_release(a);
+    zr_release(b);
+    return equal;
+}
+
+static int zr_jump_if_not_equal(zr_value a, zr_value b) {
+    return !zr_jump_if_equal(a, b);
+}
+
+static int zr_jump_if_less(zr_value a, zr_value b) {
+    return zr_order(a, b, "jump_if_less") == -1;
+}
+
+static int zr_jump_if_less_or_equal(zr_value a, zr_value b) {
+    int order = zr_order(a, b, "jump_if_less_or_equal");
+    return order == -1 || order == 0;
+}
+
+static int zr_jump_if_greater(zr_value a, zr_value b) {
+    return zr_order(a, b, "jump_if_greater") == 1;
+}
+
+static int zr_jump_if_greater_or_equal(zr_value a, zr_value b) {
+    int order = zr_order(a, b, "jump_if_greater_or_equal");
+    return order == 1 || order == 0;
+}
+
+static int zr_is_true(zr_value v) {
+    int result = v.tag == ZR_BOOLEAN && v.as.boolean;
+    zr_release(v);
+    return result;
+}
+
+static int zr_is_false(zr_value v) {
+    int result = v.tag == ZR_BOOLEAN && !v.as.boolean;
+    zr_release(v);
+    return result;
+}
+
+/* Tuples and errors */
+
+static zr_value zr_make_tuple(zr_stack *stack, size_t count) {
+    zr_tuple *t = zr_alloc(sizeof(zr_tuple) + count * sizeof(zr_value));
+    zr_value v;
+    t->header.refcount = 1;
+    t->len = count;
+    zr_pop_args(stack, t->items, count);
+    v.tag = ZR_TUPLE;
+    v.as.object = &t->header;
+    return v;
+}
+
+static zr_value zr_tuple_get(zr_value v, size_t index) {
+    zr_tuple *t;
+    zr_value element;
+    if (v.tag != ZR_TUPLE) {
+        zr_panic("Invalid operand type for tuple get.");
+    }
+    t = (zr_tuple *)v.as.object;
+    if (index >= t->len) {
+        zr_panic("Tuple index out of range.");
+    }
+    element = zr_retain(t->items[index]);
+    zr_release(v);
+    return element;
+}
+
+static void zr_tuple_unpack(zr_stack *stack, zr_value v, size_t count) {
+    zr_tuple *t;
+    size_t i;
+    if (v.tag != ZR_TUPLE) {
+        zr_panic("Invalid operand type for tuple unpack.");
+    }
+    t = (zr_tuple *)v.as.object;
+    if (t->len != count) {
+        zr_panic("Tuple length mismatch in unpack.");
+    }
+    for (i = 0; i < count; i++) {
+        zr_push(stack, zr_retain(t->items[i]));
+    }
+    zr_release(v);
+}
+
+static zr_value zr_make_error(zr_value payload) {
+    zr_error *e = zr_alloc(sizeof(zr_error));
+    zr_value v;
+    e->header.refcount = 1;
+    e->payload = payload;
+    v.tag = ZR_ERROR;
+    v.as.object = &e->header;
+    return v;
+}
+
+static zr_value zr_is_error(zr_value v) {
+    int result = v.tag == ZR_ERROR;
+    zr_release(v);
+    return zr_boolean(result);
+}
+
+static void zr_halt(void) {
+    fflush(stdout);
+    exit(0);
+}
+
+/* Native functions */
+
+static double zr_native_number(zr_value v, const char *name) {
+    if (v.tag != ZR_NUMBER) {
+        char message[64];
+        sprintf(message, "Invalid operand type for %s.", name);
+        zr_panic(message);
+    }
+    return v.as.number;
+}
+
+static zr_value zr_native_sqrt(zr_value *args) {
+    return zr_number(sqrt(zr_native_number(args[0], "sqrt")));
+}
+
+static zr_value zr_native_pow(zr_value *args) {
+    return zr_number(pow(zr_native_number(args[0], "pow"), zr_native_number(args[1], "pow")));
+}
+
+static zr_value zr_native_sin(zr_value *args) {
+    return zr_number(sin(zr_native_number(args[0], "sin")));
+}
+
+static zr_value zr_native_cos(zr_value *args) {
+    return zr_number(cos(zr_native_number(args[0], "cos")));
+}
+
+static zr_value zr_native_floor(zr_value *args) {
+    return zr_number(floor(zr_native_number(args[0], "floor")));
+}
+
+static zr_value zr_native_abs(zr_value *args) {
+    return zr_number(fabs(zr_native_number(args[0], "abs")));
+}
+
+static zr_value zr_native_min(zr_value *args) {
+    return zr_number(fmin(zr_native_number(args[0], "min"), zr_native_number(args[1], "min")));
+}
+
+static zr_value zr_native_max(zr_value *args) {
+    return zr_number(fmax(zr_native_number(args[0], "max"), zr_native_number(args[1], "max")));
+}
@@ -0,0 +1,264 @@
+use std::fmt::Write;
+
+use super::{block_starts, error, is_terminator, local_count, TranspileError};
+use crate::builtins;
+use crate::bytecode::{Bytecode, Function, Instruction, Opcode, Value};
+
+// Each function becomes a Rust function with its own operand stack and
+// locals. Its basic blocks are the arms of a match inside a loop, and a jump
+// selects the next arm to run.
+pub(super) fn emit(bytecode: &Bytecode) -> Result<String, TranspileError> {
+    let mut out = String::new();
+    out.push_str("// Generated by `zircon transpile`. Build it with the zircon crate as a\n");
+    out.push_str("// dependency, which provides the runtime.\n");
+    out.push_str("#![allow(unused_mut, unused_variables, unreachable_code)]\n\n");
+    out.push_str("use zircon::runtime::{self, Runtime, Value};\n\n");
+
+    out.push_str("fn main() {\n");
+    out.push_str("    let rt = Runtime::from_args(vec![\n");
+    for constant in bytecode.constants() {
+        writeln!(out, "        {},", constant_literal(constant)?).unwrap();
+    }
+    out.push_str("    ]);\n");
+    if !bytecode.functions().is_empty() {
+        out.push_str("    function_0(&rt, Vec::new());\n");
+    }
+    out.push_str("}\n");
+
+    for (index, function) in bytecode.functions().iter().enumerate() {
+        out.push('\n');
+        emit_function(bytecode, index, function, &mut out)?;
+    }
+    Ok(out)
+}
+
+fn constant_literal(constant: &Value) -> Result<String, TranspileError> {
+    let literal = match constant {
+        Value::Number(n) if n.is_nan() => "Value::Number(f64::NAN)".to_string(),
+        Value::Number(n) if n.is_infinite() && *n > 0.0 => {
+            "Value::Number(f64::INFINITY)".to_string()
+        }
+        Value::Number(n) if n.is_infinite() => "Value::Number(f64::NEG_INFINITY)".to_string(),
+        Value::Number(n) => format!("Value::Number({:?})", n),
+        Value::Boolean(b) => format!("Value::Boolean({})", b),
+        Value::Char(c) => format!("Value::Char({:?})", c),
+        Value::Str(s) => format!("Value::Str({:?}.into())", s),
+        #[cfg(feature = "bigint")]
+        Value::BigInt(n) => format!("runtime::bigint(\"{}\")", n),
+        #[cfg(feature = "decimal")]
+        Value::Decimal(n) => format!("runtime::decimal(\"{}\")", n),
+        other => return error(format!("Cannot transpile a {} constant", other.type_name())),
+    };
+    Ok(literal)
+}
+
+fn emit_function(
+    bytecode: &Bytecode,
+    index: usize,
+    function: &Function,
+    out: &mut String,
+) -> Result<(), TranspileError> {
+    writeln!(
+        out,
+        "fn function_{}(rt: &Runtime, args: Vec<Value>) -> Value {{",
+        index
+    )
+    .unwrap();
+    out.push_str("    let mut stack: Vec<Value> = Vec::new();\n");
+    writeln!(
+        out,
+        "    let mut locals = runtime::locals(args, {});",
+        local_count(function)
+    )
+    .unwrap();
+    out.push_str("    let mut block = 0;\n");
+    out.push_str("    loop {\n");
+    out.push_str("        match block {\n");
+
+    let starts = block_starts(function);
+    for (n, &start) in starts.iter().enumerate() {
+        let end = starts
+            .get(n + 1)
+            .copied()
+            .unwrap_or(function.instructions.len());
+        writeln!(out, "            {} => {{", start).unwrap();
+        for ip in start..end {
+            let instruction = &function.instructions[ip];
+            let code = instruction_code(bytecode, instruction, ip).map_err(|e| TranspileError {
+                message: format!("Function {}, instruction {}: {}", index, ip, e.message),
+            })?;
+            for line in code.lines() {
+                writeln!(out, "                {}", line).unwrap();
+            }
+        }
+        let last = function.instructions[end - 1].opcode();
+        if !is_terminator(last) && !last.is_branch() {
+            writeln!(out, "                block = {};", end).unwrap();
+        }
+        out.push_str("            }\n");
+    }
+
+    out.push_str("            _ => panic!(\"Invalid instruction index\"),\n");
+    out.push_str("        }\n");
+    out.push_str("    }\n");
+    out.push_str("}\n");
+    Ok(())
+}
+
+fn binary(function: &str) -> String {
+    format!(
+        "{{\n    let b = runtime::pop(&mut stack);\n    let a = runtime::pop(&mut stack);\n    stack.push(runtime::{}(a, b));\n}}",
+        function
+    )
+}
+
+fn update_local(slot: usize, function: &str, operand: &str) -> String {
+    format!(
+        "locals[{}] = Some(runtime::{}(runtime::get_local(&locals, {}), {}));",
+        slot, function, slot, operand
+    )
+}
+
+fn unary(function: &str) -> String {
+    format!(
+        "{{\n    let a = runtime::pop(&mut stack);\n    stack.push(runtime::{}(a));\n}}",
+        function
+    )
+}
+
+fn instruction_code(
+    bytecode: &Bytecode,
+    instruction: &Instruction,
+    ip: usize,
+) -> Result<String, TranspileError> {
+    let operand = || usize::from(instruction.operand());
+    let code = match instruction.opcode() {
+        Opcode::PushConst => format!("stack.push(rt.constant({}));", operand()),
+        Opcode::Add => binary("add"),
+        Opcode::Subtract => binary("subtract"),
+        Opcode::Multiply => binary("multiply"),
+        Opcode::Divide => binary("divide"),
+        Opcode::Modulo => binary("modulo"),
+        Opcode::And => binary("and"),
+        Opcode::Or => binary("or"),
+        Opcode::Equal => binary("equal"),
+        Opcode::Identical => binary("identical"),
+        Opcode::TotalCompare => binary("total_compare"),
+        Opcode::LessThan => binary("less"),
+        Opcode::GreaterThan => binary("greater"),
+        Opcode::NotEqual => binary("not_equal"),
+        Opcode::LessOrEqual => binary("less_or_equal"),
+        Opcode::GreaterOrEqual => binary("greater_or_equal"),
+        Opcode::Compare => binary("compare"),
+        Opcode::Negate => unary("negate"),
+        Opcode::Not => unary("not"),
+        Opcode::Jump => format!("block = {};", operand()),
+        Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+            let test = if instruction.opcode() == Opcode::JumpIfTrue {
+                "is_true"
+            } else {
+                "is_false"
+            };
+            format!(
+                "if runtime::{}(&runtime::pop(&mut stack)) {{\n    block = {};\n}} else {{\n    block = {};\n}}",
+                test,
+                operand(),
+                ip + 1
+            )
+        }
+        Opcode::JumpIfEqual
+        | Opcode::JumpIfNotEqual
+        | Opcode::JumpIfLess
+        | Opcode::JumpIfLessOrEqual
+        | Opcode::JumpIfGreater
+        | Opcode::JumpIfGreaterOrEqual => format!(
+            "{{\n    let b = runtime::pop(&mut stack);\n    let a = runtime::pop(&mut stack);\n    if runtime::{}(a, b) {{\n        block = {};\n    }} else {{\n        block = {};\n    }}\n}}",
+            instruction.opcode().name(),
+            operand(),
+            ip + 1
+        ),
+        Opcode::Print | Opcode::PrintRaw | Opcode::EPrint | Opcode::EPrintRaw => format!(
+            "runtime::{}(runtime::pop(&mut stack));",
+            instruction.opcode().name()
+        ),
+        Opcode::Format => format!(
+            "{{\n    let args = runtime::pop_args(&mut stack, {});\n    let template = runtime::pop(&mut stack);\n    stack.push(runtime::format(template, args));\n}}",
+            operand()
+        ),
+        Opcode::GetLocal => format!("stack.push(runtime::get_local(&locals, {}));", operand()),
+        Opcode::SetLocal => format!("locals[{}] = Some(runtime::pop(&mut stack));", operand()),
+        Opcode::IncLocal => update_local(operand(), "add", "Value::Number(1.0)"),
+        Opcode::DecLocal => update_local(operand(), "subtract", "Value::Number(1.0)"),
+        Opcode::AddLocalConst => {
+            let (slot, index) = instruction.local_constant();
+            update_local(slot, "add", &format!("rt.constant({})", index))
+        }
+        Opcode::Call => {
+            let callee = match bytecode.functions().get(operand()) {
+                Some(callee) => callee,
+                None => return error(format!("Invalid function index {}", operand())),
+            };
+            format!(
+                "{{\n    let args = runtime::pop_args(&mut stack, {});\n    let result = function_{}(rt, args);\n    stack.push(result);\n}}",
+                callee.num_args,
+                operand()
+            )
+        }
+        Opcode::Return => "return stack.pop().unwrap_or(Value::Boolean(false));".to_string(),
+        Opcode::CallNative => {
+            let name = match bytecode.get_constant(operand()) {
+                Some(Value::Str(name)) => name,
+                _ => return error("Native function name must be a string constant".to_string()),
+            };
+            if builtins::lookup(name).is_none() {
+                return error(format!("Unknown native function '{}'", name));
+            }
+            format!(
+                "{{\n    let result = rt.call_native({:?}, &mut stack);\n    stack.push(result);\n}}",
+                name
+            )
+        }
+        Opcode::Import => {
+            return error("The import instruction is not supported by the Rust target".to_string())
+        }
+        Opcode::Match => {
+            return error("The match instruction is not supported by the Rust target".to_string())
+        }
+        Opcode::ArgCount => {
+            return error("Default arguments are not supported by the Rust target".to_string())
+        }
+        Opcode::Defer | Opcode::RunDefers => {
+            return error("Deferred calls are not supported by the Rust target".to_string())
+        }
+        Opcode::Extension(extension) => {
+            return error(format!(
+                "Extension instruction '{}' is not supported by the Rust target",
+                extension.name()
+            ))
+        }
+        Opcode::MakeTuple => format!(
+            "{{\n    let elements = runtime::pop_args(&mut stack, {});\n    stack.push(runtime::make_tuple(elements));\n}}",
+            operand()
+        ),
+        Opcode::TupleGet => format!(
+            "{{\n    let tuple = runtime::pop(&mut stack);\n    stack.push(runtime::tuple_get(tuple, {}));\n}}",
+            operand()
+        ),
+        Opcode::TupleUnpack => format!(
+            "{{\n    let tuple = runtime::pop(&mut stack);\n    runtime::tuple_unpack(tuple, {}, &mut stack);\n}}",
+            operand()
+        ),
+        Opcode::ArrayConcat => binary("array_concat"),
+        Opcode::ArraySlice => "{\n    let end = runtime::pop(&mut stack);\n    let start = runtime::pop(&mut stack);\n    let array = runtime::pop(&mut stack);\n    stack.push(runtime::array_slice(array, start, end));\n}".to_string(),
+        Opcode::ArrayPush => binary("array_push"),
+        Opcode::ArrayPop => "{\n    let array = runtime::pop(&mut stack);\n    runtime::array_pop(array, &mut stack);\n}".to_string(),
+        Opcode::SetAdd => binary("set_add"),
+        Opcode::SetContains => binary("set_contains"),
+        Opcode::SetRemove => binary("set_remove"),
+        Opcode::MakeError => unary("make_error"),
+        Opcode::IsError => "{\n    let value = runtime::pop(&mut stack);\n    stack.push(Value::Boolean(runtime::is_error(&value)));\n}".to_string(),
+        Opcode::Try => "{\n    let value = runtime::pop(&mut stack);\n    if runtime::is_error(&value) {\n        return value;\n    }\n    stack.push(value);\n}".to_string(),
+        Opcode::Halt => "runtime::halt();".to_string(),
+    };
+    Ok(code)
+}
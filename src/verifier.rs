@@ -0,0 +1,220 @@
+use crate::builtins;
+use crate::bytecode::{Bytecode, Count, Function, Instruction, Opcode, Value};
+
+// Checks a program as it is loaded, so that malformed bytecode is refused
+// up front instead of failing partway through a run, and finds the deepest
+// operand stack of each function.
+pub(crate) fn verify(bytecode: &Bytecode) -> Result<Vec<usize>, String> {
+    // Running a program or importing a module starts at function 0.
+    if bytecode.functions().is_empty() {
+        return Err("Program has no functions".to_string());
+    }
+    let mut depths = Vec::with_capacity(bytecode.functions().len());
+    for (index, function) in bytecode.functions().iter().enumerate() {
+        let depth = verify_function(bytecode, function)
+            .map_err(|message| format!("Function {}: {}", index, message))?;
+        depths.push(depth);
+    }
+    Ok(depths)
+}
+
+// The checks of `verify` that don't follow the control flow, which are cheap
+// enough to repeat on a program loaded from a `.zrcv` file.
+pub(crate) fn check_slots(bytecode: &Bytecode) -> Result<(), String> {
+    for (index, function) in bytecode.functions().iter().enumerate() {
+        check_function_slots(bytecode, function)
+            .map_err(|message| format!("Function {}: {}", index, message))?;
+    }
+    Ok(())
+}
+
+fn verify_function(bytecode: &Bytecode, function: &Function) -> Result<usize, String> {
+    check_function_slots(bytecode, function)?;
+    verify_stack(bytecode, function)
+}
+
+// Checks that the arguments fit in the locals, and that default arguments and
+// local operands refer to constants and slots that exist.
+fn check_function_slots(bytecode: &Bytecode, function: &Function) -> Result<(), String> {
+    if function.num_locals < function.num_args {
+        return Err(format!(
+            "{} locals cannot hold {} arguments",
+            function.num_locals, function.num_args
+        ));
+    }
+    for &constant in &function.defaults {
+        if bytecode.get_constant(constant.into()).is_none() {
+            return Err(format!(
+                "default argument constant {} is out of range",
+                constant
+            ));
+        }
+    }
+    for (ip, instruction) in function.instructions.iter().enumerate() {
+        if let Some(slot) = instruction.local() {
+            if slot >= function.num_locals {
+                return Err(format!(
+                    "instruction {}: local {} is out of range for {} locals",
+                    ip, slot, function.num_locals
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+// What is known about a frame before an instruction runs: how many values are
+// on its stack, and the argument count set by a pending `arg_count`.
+#[derive(Clone, Copy, PartialEq)]
+struct State {
+    depth: usize,
+    argument_count: Option<usize>,
+}
+
+// Follows every path through the function, tracking the stack depth, and
+// rejects one that can underflow the stack, that reaches an instruction with
+// different depths along different paths, that returns with more than the
+// return value on the stack, or that runs past the last instruction. Returns
+// the deepest the stack gets.
+fn verify_stack(bytecode: &Bytecode, function: &Function) -> Result<usize, String> {
+    let len = function.instructions.len();
+    if len == 0 {
+        return Err("function has no instructions".to_string());
+    }
+    let mut states: Vec<Option<State>> = vec![None; len];
+    states[0] = Some(State {
+        depth: 0,
+        argument_count: None,
+    });
+    let mut pending = vec![0];
+    let mut deepest = 0;
+    while let Some(ip) = pending.pop() {
+        let state = states[ip].unwrap();
+        let successors = step(bytecode, &function.instructions[ip], ip, state)
+            .map_err(|message| format!("instruction {}: {}", ip, message))?;
+        for (target, next) in successors {
+            if target >= len {
+                return Err(format!(
+                    "instruction {}: continues at {}, past the last instruction",
+                    ip, target
+                ));
+            }
+            match states[target] {
+                None => {
+                    states[target] = Some(next);
+                    pending.push(target);
+                    deepest = deepest.max(next.depth);
+                }
+                Some(existing) if existing == next => {}
+                Some(existing) => {
+                    return Err(format!(
+                        "instruction {} is reached with stack depths {} and {}",
+                        target, existing.depth, next.depth
+                    ));
+                }
+            }
+        }
+    }
+    Ok(deepest)
+}
+
+// The instructions that can run after the one at `ip`, with the state each
+// starts in.
+fn step(
+    bytecode: &Bytecode,
+    instruction: &Instruction,
+    ip: usize,
+    state: State,
+) -> Result<Vec<(usize, State)>, String> {
+    let operand = || usize::from(instruction.operand());
+    // Checks what the operand refers to, and finds the argument count of a
+    // call or the arity of a native.
+    let mut arguments = 0;
+    let mut arity = 0;
+    match instruction.opcode() {
+        Opcode::PushConst | Opcode::Match => {
+            constant(bytecode, operand())?;
+        }
+        Opcode::AddLocalConst => {
+            constant(bytecode, instruction.local_constant().1)?;
+        }
+        Opcode::Jump => return Ok(vec![(operand(), state)]),
+        Opcode::Call | Opcode::Defer => {
+            let callee = match bytecode.functions().get(operand()) {
+                Some(callee) => callee,
+                None => return Err(format!("function {} does not exist", operand())),
+            };
+            arguments = state.argument_count.unwrap_or(callee.num_args);
+            if arguments < callee.required_args() || arguments > callee.num_args {
+                return Err(format!(
+                    "passes {} arguments to function {}, which takes {}",
+                    arguments,
+                    operand(),
+                    callee.num_args
+                ));
+            }
+        }
+        Opcode::ArgCount => {
+            let next = State {
+                argument_count: Some(operand()),
+                ..state
+            };
+            return Ok(vec![(ip + 1, next)]);
+        }
+        Opcode::Return => {
+            if state.depth > 1 {
+                return Err(format!("returns with {} values on the stack", state.depth));
+            }
+            return Ok(Vec::new());
+        }
+        Opcode::CallNative => match constant(bytecode, operand())? {
+            Value::Str(name) => match builtins::lookup(name) {
+                Some(native) => arity = native.arity,
+                None => return Err(format!("unknown native function '{}'", name)),
+            },
+            _ => return Err("native function name must be a string constant".to_string()),
+        },
+        Opcode::Import => match constant(bytecode, operand())? {
+            Value::Str(_) => {}
+            _ => return Err("module name must be a string constant".to_string()),
+        },
+        Opcode::Halt => return Ok(Vec::new()),
+        _ => {}
+    }
+    let count = |count| match count {
+        Count::Fixed(n) => n,
+        Count::Operand(n) => operand() + n,
+        Count::Arguments => arguments,
+        Count::Native => arity,
+        Count::Rest => state.depth,
+    };
+    let effect = instruction.opcode().stack_effect();
+    let (pops, pushes) = (count(effect.pops), count(effect.pushes));
+    if state.depth < pops {
+        return Err(format!(
+            "pops {} values from a stack of {}",
+            pops, state.depth
+        ));
+    }
+    // A call or defer uses up a pending `arg_count`; other instructions keep
+    // it, as the VM does.
+    let argument_count = match instruction.opcode() {
+        Opcode::Call | Opcode::Defer => None,
+        _ => state.argument_count,
+    };
+    let next = State {
+        depth: state.depth - pops + pushes,
+        argument_count,
+    };
+    let mut successors = vec![(ip + 1, next)];
+    if instruction.opcode().is_branch() {
+        successors.push((operand(), next));
+    }
+    Ok(successors)
+}
+
+fn constant(bytecode: &Bytecode, index: usize) -> Result<&Value, String> {
+    bytecode
+        .get_constant(index)
+        .ok_or_else(|| format!("constant {} does not exist", index))
+}
@@ -1,13 +1,52 @@
-use std::collections::HashMap;
+use std::any::Any;
+use std::fmt::{self, Write as _};
+use std::io::{self, Write};
+use std::mem::size_of;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+use std::sync::atomic;
+use std::time::Duration;
 use std::vec::Vec;
 
-use crate::bytecode::{Bytecode, Opcode, Value};
+use crate::builtins::{array, set, Capability};
+use crate::bytecode::{
+    Bytecode, Count, Extension, Function, Instruction, NumberEquality, NumberFormat, Opcode, Value,
+};
+use crate::crash::{self, CrashDump, CrashFrame, DumpedValue};
+use crate::executor::{Executor, Interpreter};
+use crate::heap::{self, Root};
+use crate::memory::{KindUsage, MemoryProfile, MemorySample};
+use crate::metrics::Metrics;
+use crate::pattern;
+use crate::quicken::{self, Form, Quickening};
+use crate::sampler::SamplingProfile;
+use crate::state::{FrameState, VmState, STACK_SUMMARY};
+use crate::stats::Stats;
+use crate::storage::Storage;
+use crate::text::Text;
+use crate::trace;
+
+// The NaN that deterministic float mode produces. NaNs from arithmetic
+// otherwise differ in sign and payload between architectures.
+#[cfg(feature = "deterministic-float")]
+const CANONICAL_NAN: f64 = f64::from_bits(0x7ff8_0000_0000_0000);
 
 struct CallFrame {
     instruction_pointer: usize,
     function_index: usize,
+    // The imported module the function belongs to, or None for the program.
+    module: Option<usize>,
     stack: Vec<Value>,
-    locals: HashMap<usize, Value>,
+    // Slots that have not been set hold None.
+    locals: Vec<Option<Value>>,
+    // Calls registered by `defer`, with their arguments, run in reverse order
+    // when the frame returns.
+    deferred: Vec<(usize, Vec<Value>)>,
+    // The number of arguments the next call passes, set by `arg_count`.
+    argument_count: Option<usize>,
+    // The span for this call, entered while the frame is on the call stack.
+    #[cfg(feature = "tracing")]
+    _span: Option<tracing::span::EnteredSpan>,
 }
 
 impl CallFrame {
@@ -15,8 +54,13 @@ impl CallFrame {
         CallFrame {
             instruction_pointer: 0,
             function_index: func_index,
+            module: None,
             stack: Vec::new(),
-            locals: HashMap::new(),
+            locals: Vec::new(),
+            deferred: Vec::new(),
+            argument_count: None,
+            #[cfg(feature = "tracing")]
+            _span: None,
         }
     }
 
@@ -37,11 +81,14 @@ impl CallFrame {
     }
 
     fn set_local(&mut self, index: usize, value: Value) {
-        self.locals.insert(index, value);
+        if index >= self.locals.len() {
+            self.locals.resize(index + 1, None);
+        }
+        self.locals[index] = Some(value);
     }
 
     fn get_local(&self, index: usize) -> Option<&Value> {
-        self.locals.get(&index)
+        self.locals.get(index)?.as_ref()
     }
 
     fn stack_push(&mut self, value: Value) {
@@ -55,33 +102,798 @@ impl CallFrame {
     fn is_stack_empty(&self) -> bool {
         self.stack.is_empty()
     }
+}
+
+// A module loaded by `import`, with the value its main function returned,
+// which is None while that function runs.
+struct Module {
+    name: Text,
+    bytecode: Rc<Bytecode>,
+    value: Option<Value>,
+}
+
+type ModuleResolver = Box<dyn FnMut(&str) -> Result<Bytecode, String>>;
 
-    // fn debug_stack(&self) {
-    //     for (i, value) in self.stack.iter().enumerate() {
-    //         println!("Stack[{}]: {}", i, value);
-    //     }
-    // }
+// Runs an extension instruction, given its operand and the values it pops,
+// in the order they were pushed. It returns the values to push, which must
+// be as many as the extension declares.
+pub type ExtensionHandler = fn(&mut VirtualMachine, Option<u16>, &[Value]) -> Vec<Value>;
+
+// A value the host retains through `VirtualMachine::create_handle`. A handle
+// is only valid for the VM that created it, until it is dropped.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Handle {
+    index: usize,
+    generation: u32,
+}
+
+// The payload a runtime error panics with, such as an operand of the wrong
+// type or a missing capability, so that a host catching the panic can tell it
+// from a bug in the VM. `runtime_error!` raises one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RuntimeError(pub String);
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
 }
 
-pub(crate) struct VirtualMachine<'a> {
+// A runtime error that an error-tolerant VM carried on after, with the
+// instruction that failed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecoveredError {
+    pub function: usize,
+    pub instruction: usize,
+    pub module: Option<String>,
+    pub message: String,
+}
+
+// Settings applied to a VM by `VirtualMachine::with_options`.
+#[derive(Clone, Debug, Default)]
+pub struct VmOptions {
+    capabilities: Vec<Capability>,
+    executables: Vec<String>,
+    databases: Vec<String>,
+    deterministic: bool,
+    number_format: NumberFormat,
+    number_equality: NumberEquality,
+    error_tolerant: bool,
+    quickening: bool,
+    step_limit: Option<u64>,
+    stack_capacity: Option<usize>,
+    call_depth: usize,
+    backend: Option<Rc<dyn Executor>>,
+}
+
+impl VmOptions {
+    pub fn new() -> Self {
+        VmOptions::default()
+    }
+
+    // A profile under which every run of a program gives the same results on
+    // any host. `now` returns the number of instructions executed so far as
+    // nanoseconds since the Unix epoch instead of reading the clock, natives
+    // that reach outside the VM (the environment, other programs, the network,
+    // and databases) are refused even when granted, and floats are made
+    // deterministic, refusing the math natives that depend on the platform
+    // when the deterministic-float feature is disabled.
+    pub fn deterministic() -> Self {
+        VmOptions {
+            deterministic: true,
+            ..VmOptions::default()
+        }
+    }
+
+    pub fn grant(mut self, capability: Capability) -> Self {
+        if !self.capabilities.contains(&capability) {
+            self.capabilities.push(capability);
+        }
+        self
+    }
+
+    // Lets `exec` run the named program, granting the `exec` capability.
+    pub fn allow_executable(mut self, name: &str) -> Self {
+        if !self.executables.iter().any(|allowed| allowed == name) {
+            self.executables.push(name.to_string());
+        }
+        self.grant(Capability::Exec)
+    }
+
+    // Lets `db_open` open the database at the given path, granting the
+    // `sqlite` capability.
+    pub fn allow_database(mut self, path: &str) -> Self {
+        if !self.databases.iter().any(|allowed| allowed == path) {
+            self.databases.push(path.to_string());
+        }
+        self.grant(Capability::Sqlite)
+    }
+
+    pub fn number_format(mut self, format: NumberFormat) -> Self {
+        self.number_format = format;
+        self
+    }
+
+    pub fn number_equality(mut self, equality: NumberEquality) -> Self {
+        self.number_equality = equality;
+        self
+    }
+
+    pub fn error_tolerant(mut self) -> Self {
+        self.error_tolerant = true;
+        self
+    }
+
+    pub fn quickening(mut self) -> Self {
+        self.quickening = true;
+        self
+    }
+
+    pub fn step_limit(mut self, limit: u64) -> Self {
+        self.step_limit = Some(limit);
+        self
+    }
+
+    // Gives every frame's operand stack room for this many values up front,
+    // instead of the deepest stack the verifier found for its function.
+    pub fn stack_capacity(mut self, capacity: usize) -> Self {
+        self.stack_capacity = Some(capacity);
+        self
+    }
+
+    // Makes room for this many frames up front.
+    pub fn call_depth(mut self, depth: usize) -> Self {
+        self.call_depth = depth;
+        self
+    }
+
+    // Runs the program with another backend than the interpreter.
+    pub fn backend<E: Executor + 'static>(mut self, backend: E) -> Self {
+        self.backend = Some(Rc::new(backend));
+        self
+    }
+}
+
+pub struct VirtualMachine<'a> {
     is_running: bool,
     bytecode: &'a Bytecode,
     frames: Vec<CallFrame>,
+    capabilities: Vec<Capability>,
+    executables: Vec<String>,
+    databases: Vec<String>,
+    metrics: Option<Metrics>,
+    stats: Option<Stats>,
+    memory_profile: Option<MemoryProfile>,
+    sampling_profile: Option<SamplingProfile>,
+    quickening: Option<Quickening>,
+    step_trace: Option<String>,
+    #[cfg(feature = "deterministic-float")]
+    deterministic_float: bool,
+    deterministic: bool,
+    // Instructions executed, which is the clock in deterministic mode.
+    steps: u64,
+    // Nanoseconds `sleep` has added to the clock in deterministic mode.
+    slept: i64,
+    step_limit: Option<u64>,
+    // Room made on each frame's operand stack when it is entered. Without
+    // it, the deepest stack found by the verifier is used, for bytecode that
+    // was loaded.
+    stack_capacity: Option<usize>,
+    number_format: NumberFormat,
+    number_equality: NumberEquality,
+    error_tolerant: bool,
+    recovered_errors: Vec<RecoveredError>,
+    // Values retained by the host, with the generation of each slot, which
+    // goes up each time the slot is reused.
+    handles: Vec<(u32, Option<Value>)>,
+    modules: Vec<Module>,
+    module_resolver: Option<ModuleResolver>,
+    storage: Option<Box<dyn Storage>>,
+    extension_handlers: Vec<(&'static Extension, ExtensionHandler)>,
+    backend: Rc<dyn Executor>,
 }
 
 impl<'a> VirtualMachine<'a> {
-    pub(crate) fn new(bytecode: &'a Bytecode) -> Self {
+    pub fn new(bytecode: &'a Bytecode) -> Self {
         VirtualMachine {
             is_running: true,
             bytecode,
             frames: Vec::new(),
+            capabilities: Vec::new(),
+            executables: Vec::new(),
+            databases: Vec::new(),
+            metrics: None,
+            stats: None,
+            memory_profile: None,
+            sampling_profile: None,
+            quickening: None,
+            step_trace: None,
+            #[cfg(feature = "deterministic-float")]
+            deterministic_float: false,
+            deterministic: false,
+            steps: 0,
+            slept: 0,
+            step_limit: None,
+            stack_capacity: None,
+            number_format: NumberFormat::default(),
+            number_equality: NumberEquality::default(),
+            error_tolerant: false,
+            recovered_errors: Vec::new(),
+            handles: Vec::new(),
+            modules: Vec::new(),
+            module_resolver: None,
+            storage: None,
+            extension_handlers: Vec::new(),
+            backend: Rc::new(Interpreter),
+        }
+    }
+
+    pub fn with_options(bytecode: &'a Bytecode, options: VmOptions) -> Self {
+        let mut vm = VirtualMachine::new(bytecode);
+        for capability in options.capabilities {
+            vm.grant(capability);
+        }
+        vm.executables = options.executables;
+        vm.databases = options.databases;
+        vm.number_format = options.number_format;
+        vm.number_equality = options.number_equality;
+        vm.error_tolerant = options.error_tolerant;
+        if options.quickening {
+            vm.enable_quickening();
+        }
+        vm.step_limit = options.step_limit;
+        vm.stack_capacity = options.stack_capacity;
+        vm.reserve_frames(options.call_depth);
+        if let Some(backend) = options.backend {
+            vm.backend = backend;
+        }
+        if options.deterministic {
+            vm.deterministic = true;
+            #[cfg(feature = "deterministic-float")]
+            vm.enable_deterministic_float();
+        }
+        vm
+    }
+
+    pub fn grant(&mut self, capability: Capability) {
+        if !self.capabilities.contains(&capability) {
+            self.capabilities.push(capability);
+        }
+    }
+
+    // Lets `exec` run the named program, granting the `exec` capability.
+    // Programs are matched by the exact name passed to `exec`.
+    pub fn allow_executable(&mut self, name: &str) {
+        if !self.is_executable_allowed(name) {
+            self.executables.push(name.to_string());
+        }
+        self.grant(Capability::Exec);
+    }
+
+    pub(crate) fn is_executable_allowed(&self, name: &str) -> bool {
+        self.executables.iter().any(|allowed| allowed == name)
+    }
+
+    // Lets `db_open` open the database at the given path, granting the
+    // `sqlite` capability. Paths are matched exactly as passed to `db_open`.
+    pub fn allow_database(&mut self, path: &str) {
+        if !self.databases.iter().any(|allowed| allowed == path) {
+            self.databases.push(path.to_string());
+        }
+        self.grant(Capability::Sqlite);
+    }
+
+    #[cfg(feature = "sqlite")]
+    pub(crate) fn is_database_allowed(&self, path: &str) -> bool {
+        self.databases.iter().any(|allowed| allowed == path)
+    }
+
+    // Starts counting instructions, calls and errors, which costs a branch per
+    // instruction while enabled.
+    pub fn enable_metrics(&mut self) {
+        if self.metrics.is_none() {
+            self.metrics = Some(Metrics::default());
+        }
+    }
+
+    pub fn metrics(&self) -> Option<&Metrics> {
+        self.metrics.as_ref()
+    }
+
+    // Starts tracking the deepest operand stack and call stack reached by each
+    // function.
+    pub fn enable_stats(&mut self) {
+        if self.stats.is_none() {
+            self.stats = Some(Stats::new(self.bytecode.functions().len()));
+        }
+    }
+
+    pub fn stats(&self) -> Option<&Stats> {
+        self.stats.as_ref()
+    }
+
+    // Starts counting the live values of each kind, and the call frames, every
+    // `interval` instructions. Each sample walks everything reachable, so a
+    // short interval slows the program down.
+    pub fn enable_memory_profile(&mut self, interval: u64) {
+        if self.memory_profile.is_none() {
+            self.memory_profile = Some(MemoryProfile::new(interval));
         }
     }
 
+    pub fn memory_profile(&self) -> Option<&MemoryProfile> {
+        self.memory_profile.as_ref()
+    }
+
+    // Starts sampling the call stack every `interval` of wall-clock time, for
+    // flame graphs of long runs. Sampling stops when the VM is dropped.
+    pub fn enable_sampling_profile(&mut self, interval: Duration) {
+        if self.sampling_profile.is_none() {
+            self.sampling_profile = Some(SamplingProfile::start(interval));
+        }
+    }
+
+    pub fn sampling_profile(&self) -> Option<&SamplingProfile> {
+        self.sampling_profile.as_ref()
+    }
+
+    // Lets arithmetic instructions rewrite themselves into a faster form for
+    // numbers the first time they run on numbers, and back to the generic
+    // form the first time they find anything else.
+    pub fn enable_quickening(&mut self) {
+        if self.quickening.is_none() {
+            self.quickening = Some(Quickening::new(self.bytecode));
+        }
+    }
+
+    pub fn quickening(&self) -> Option<&Quickening> {
+        self.quickening.as_ref()
+    }
+
+    // Runs an instruction in the form it was rewritten to, returning false if
+    // its generic form has to run it instead.
+    fn run_quickened(&mut self, function: usize, ip: usize, opcode: Opcode) -> bool {
+        if !quicken::is_arithmetic(opcode) {
+            return false;
+        }
+        let frame = self.frames.last_mut().expect("Call stack is empty.");
+        let numbers = match frame.stack[..] {
+            [.., Value::Number(a), Value::Number(b)] => Some((a, b)),
+            _ => None,
+        };
+        let Some(quickening) = &mut self.quickening else {
+            return false;
+        };
+        let form = &mut quickening.forms[function][ip];
+        match (*form, numbers) {
+            (Form::Number, Some((a, b))) => {
+                frame.stack.truncate(frame.stack.len() - 2);
+                self.push_result(Value::Number(quicken::number_op(opcode, a, b)));
+                return true;
+            }
+            (Form::Number, None) => {
+                *form = Form::Generic;
+                quickening.deoptimized += 1;
+            }
+            (Form::Unseen, Some(_)) => {
+                *form = Form::Number;
+                quickening.quickened += 1;
+            }
+            (Form::Unseen, None) => *form = Form::Generic,
+            (Form::Generic, _) => {}
+        }
+        false
+    }
+
+    fn sample_stack(&mut self) {
+        let mut stack = String::new();
+        for (i, frame) in self.frames.iter().enumerate() {
+            if i > 0 {
+                stack.push(';');
+            }
+            if let Some(module) = frame.module {
+                write!(stack, "{}/", self.modules[module].name).unwrap();
+            }
+            write!(stack, "function_{}", frame.function_index).unwrap();
+        }
+        if let Some(profile) = &mut self.sampling_profile {
+            *profile.stacks.entry(stack).or_insert(0) += 1;
+        }
+    }
+
+    fn sample_memory(&mut self) {
+        let mut kinds: Vec<KindUsage> = heap::census(self.roots())
+            .into_iter()
+            .map(|(kind, count, bytes)| KindUsage { kind, count, bytes })
+            .collect();
+        let frame_bytes = self.frames.iter().map(|frame| {
+            size_of::<CallFrame>()
+                + frame.stack.capacity() * size_of::<Value>()
+                + frame.locals.capacity() * size_of::<Option<Value>>()
+        });
+        kinds.push(KindUsage {
+            kind: "frame",
+            count: self.frames.len(),
+            bytes: frame_bytes.sum(),
+        });
+        kinds.sort_by_key(|usage| usage.kind);
+        let sample = MemorySample {
+            steps: self.steps,
+            kinds,
+        };
+        if let Some(profile) = &mut self.memory_profile {
+            profile.samples.push(sample);
+        }
+    }
+
+    // Starts recording a line for every instruction executed: the function
+    // index, instruction index, mnemonic and operand, and the operand stack
+    // depth before it ran. The text is the same for the same program on every
+    // run and build, so it can be compared against a saved copy.
+    pub fn enable_step_trace(&mut self) {
+        if self.step_trace.is_none() {
+            self.step_trace = Some(String::new());
+        }
+    }
+
+    pub fn step_trace(&self) -> Option<&str> {
+        self.step_trace.as_deref()
+    }
+
+    // Makes float results identical on every platform: math natives use a
+    // portable software implementation instead of the platform's, and every
+    // NaN produced is the same quiet NaN.
+    #[cfg(feature = "deterministic-float")]
+    pub fn enable_deterministic_float(&mut self) {
+        self.deterministic_float = true;
+    }
+
+    #[cfg(feature = "deterministic-float")]
+    pub(crate) fn is_float_deterministic(&self) -> bool {
+        self.deterministic_float
+    }
+
+    // Sets how `print`, `format`, and natives that convert values to strings
+    // write numbers.
+    pub fn set_number_format(&mut self, format: NumberFormat) {
+        self.number_format = format;
+    }
+
+    // Stops the program with a runtime error once it has executed more than
+    // `limit` instructions, counting those run by deferred calls and imports.
+    pub fn set_step_limit(&mut self, limit: u64) {
+        self.step_limit = Some(limit);
+    }
+
+    pub fn set_stack_capacity(&mut self, capacity: usize) {
+        self.stack_capacity = Some(capacity);
+    }
+
+    // The room made on each operand stack, if it was set rather than taken
+    // from the verifier.
+    pub fn stack_capacity(&self) -> Option<usize> {
+        self.stack_capacity
+    }
+
+    // Makes room for this many frames on the call stack, so that calls up to
+    // that depth don't grow it.
+    pub fn reserve_frames(&mut self, depth: usize) {
+        self.frames.reserve(depth.saturating_sub(self.frames.len()));
+    }
+
+    pub(crate) fn number_format(&self) -> NumberFormat {
+        self.number_format
+    }
+
+    // Sets how `equal`, the equality branches, `match` literals, and the
+    // natives that search for a value compare numbers.
+    pub fn set_number_equality(&mut self, equality: NumberEquality) {
+        self.number_equality = equality;
+    }
+
+    pub(crate) fn number_equality(&self) -> NumberEquality {
+        self.number_equality
+    }
+
+    // Keeps running after recoverable runtime errors, such as an operand of
+    // the wrong type or a local read before it is set, each of which becomes
+    // an error value in place of the failed instruction's results.
+    pub fn enable_error_tolerance(&mut self) {
+        self.error_tolerant = true;
+    }
+
+    // The runtime errors an error-tolerant VM has carried on after, in the
+    // order they happened.
+    pub fn recovered_errors(&self) -> &[RecoveredError] {
+        &self.recovered_errors
+    }
+
+    // Sets the function `import` uses to load a module by name. Each module
+    // is loaded the first time it is imported and its main function run; later
+    // imports of the same name return the value that function returned.
+    pub fn set_module_resolver<F>(&mut self, resolver: F)
+    where
+        F: FnMut(&str) -> Result<Bytecode, String> + 'static,
+    {
+        self.module_resolver = Some(Box::new(resolver));
+    }
+
+    // Sets the store the `kv_*` natives read and write. Without one they halt
+    // the VM with an error.
+    pub fn set_storage<S: Storage + 'static>(&mut self, storage: S) {
+        self.storage = Some(Box::new(storage));
+    }
+
+    pub(crate) fn storage(&mut self) -> &mut dyn Storage {
+        match &mut self.storage {
+            Some(storage) => storage.as_mut(),
+            None => runtime_error!("No key-value storage is available."),
+        }
+    }
+
+    // Sets the function that runs an extension instruction. A program that
+    // reaches one without a handler halts with an error.
+    pub fn set_extension_handler(
+        &mut self,
+        extension: &'static Extension,
+        handler: ExtensionHandler,
+    ) {
+        self.extension_handlers.retain(|(e, _)| *e != extension);
+        self.extension_handlers.push((extension, handler));
+    }
+
+    pub(crate) fn is_deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    // The time in deterministic mode: one nanosecond per instruction executed,
+    // plus however long the program has slept.
+    pub(crate) fn virtual_clock(&self) -> i64 {
+        (self.steps as i64).saturating_add(self.slept)
+    }
+
+    pub(crate) fn advance_clock(&mut self, nanos: i64) {
+        self.slept = self.slept.saturating_add(nanos);
+    }
+
+    // The function running when a native is called, and the program or module
+    // it belongs to.
+    pub(crate) fn function_index(&self) -> usize {
+        self.frames
+            .last()
+            .expect("Call stack is empty.")
+            .function_index
+    }
+
+    // The number of frames on the call stack.
+    pub fn call_depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    // Whether the program has yet to halt.
+    pub fn is_running(&self) -> bool {
+        self.is_running
+    }
+
+    // The function, instruction pointer, and module of every frame, innermost
+    // first. The instruction pointer is that of the instruction the frame is
+    // running, such as the call to the frame above it. The placeholder frame
+    // under a host call has run nothing, so it is left out.
+    pub(crate) fn backtrace(&self) -> Vec<(usize, usize, Option<Text>)> {
+        self.frames
+            .iter()
+            .rev()
+            .filter(|frame| frame.instruction_pointer > 0)
+            .map(|frame| {
+                let module = frame.module.map(|m| self.modules[m].name.clone());
+                (frame.function_index, frame.instruction_pointer - 1, module)
+            })
+            .collect()
+    }
+
+    pub(crate) fn current_bytecode(&self) -> &Bytecode {
+        match self.frames.last().expect("Call stack is empty.").module {
+            Some(module) => &self.modules[module].bytecode,
+            None => self.bytecode,
+        }
+    }
+
+    // The call stack, with each frame's locals and operand stack, for a
+    // program that failed with the given message.
+    pub fn crash_dump(&self, message: &str) -> CrashDump {
+        let frames = self
+            .frames
+            .iter()
+            .rev()
+            .filter(|frame| frame.instruction_pointer > 0)
+            .map(|frame| CrashFrame {
+                function: frame.function_index,
+                instruction: frame.instruction_pointer - 1,
+                module: frame.module.map(|m| self.modules[m].name.to_string()),
+                locals: frame
+                    .locals
+                    .iter()
+                    .map(|local| local.as_ref().map(DumpedValue::new))
+                    .collect(),
+                stack: frame.stack.iter().map(DumpedValue::new).collect(),
+            })
+            .collect();
+        CrashDump {
+            message: message.to_string(),
+            program_hash: crash::program_hash(self.bytecode),
+            frames,
+        }
+    }
+
+    // A snapshot of the call stack and the step count, which a native or an
+    // extension handler can take while the program runs.
+    pub fn state(&self) -> VmState {
+        let frames = self
+            .frames
+            .iter()
+            .rev()
+            .map(|frame| FrameState {
+                function: frame.function_index,
+                instruction: frame.instruction_pointer.checked_sub(1),
+                module: frame.module.map(|m| self.modules[m].name.to_string()),
+                locals: frame
+                    .locals
+                    .iter()
+                    .map(|local| local.as_ref().map(DumpedValue::new))
+                    .collect(),
+                stack_depth: frame.stack.len(),
+                stack_top: frame
+                    .stack
+                    .iter()
+                    .rev()
+                    .take(STACK_SUMMARY)
+                    .map(DumpedValue::new)
+                    .collect(),
+            })
+            .collect();
+        VmState {
+            running: self.is_running,
+            steps: self.steps,
+            fuel: self
+                .step_limit
+                .map(|limit| limit.saturating_sub(self.steps)),
+            frames,
+        }
+    }
+
+    pub fn dump_heap<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        heap::dump(out, self.roots())
+    }
+
+    // The constants, and every value on a frame's stack or in its locals.
+    fn roots(&self) -> impl Iterator<Item = (Root, &Value)> {
+        let constants = self
+            .bytecode
+            .constants()
+            .iter()
+            .enumerate()
+            .map(|(index, value)| (Root::Constant(index), value));
+        let frames = self
+            .frames
+            .iter()
+            .enumerate()
+            .flat_map(|(frame_index, frame)| {
+                let stack = frame.stack.iter().enumerate().map(move |(slot, value)| {
+                    let root = Root::Stack {
+                        frame: frame_index,
+                        slot,
+                    };
+                    (root, value)
+                });
+                let locals = frame
+                    .locals
+                    .iter()
+                    .enumerate()
+                    .filter_map(move |(slot, value)| {
+                        let root = Root::Local {
+                            frame: frame_index,
+                            slot,
+                        };
+                        Some((root, value.as_ref()?))
+                    });
+                stack.chain(locals)
+            });
+        let handles = self
+            .handles
+            .iter()
+            .enumerate()
+            .filter_map(|(index, (_, value))| Some((Root::Handle(index), value.as_ref()?)));
+        constants.chain(frames).chain(handles)
+    }
+
+    // Keeps a value alive for the host until the handle is dropped with
+    // `drop_handle`, across any number of calls into the VM. Retained values
+    // are roots of heap dumps and memory profiles like the stacks and locals.
+    pub fn create_handle(&mut self, value: Value) -> Handle {
+        match self.handles.iter().position(|(_, value)| value.is_none()) {
+            Some(index) => {
+                let slot = &mut self.handles[index];
+                slot.0 += 1;
+                slot.1 = Some(value);
+                Handle {
+                    index,
+                    generation: slot.0,
+                }
+            }
+            None => {
+                self.handles.push((0, Some(value)));
+                Handle {
+                    index: self.handles.len() - 1,
+                    generation: 0,
+                }
+            }
+        }
+    }
+
+    pub fn handle_value(&self, handle: &Handle) -> &Value {
+        match self.handles.get(handle.index) {
+            Some((generation, Some(value))) if *generation == handle.generation => value,
+            _ => runtime_error!("Invalid handle."),
+        }
+    }
+
+    // Releases the value, returning it.
+    pub fn drop_handle(&mut self, handle: Handle) -> Value {
+        match self.handles.get_mut(handle.index) {
+            Some((generation, value)) if *generation == handle.generation && value.is_some() => {
+                value.take().unwrap()
+            }
+            _ => runtime_error!("Invalid handle."),
+        }
+    }
+
+    // How many handles are holding a value.
+    pub fn handle_count(&self) -> usize {
+        self.handles
+            .iter()
+            .filter(|(_, value)| value.is_some())
+            .count()
+    }
+
     fn push_frame(&mut self, frame: CallFrame) {
         self.frames.push(frame);
     }
 
+    // Pushes the frame of a call to a guest function.
+    fn enter_frame(&mut self, mut frame: CallFrame) {
+        let function = match frame.module {
+            Some(module) => &self.modules[module].bytecode,
+            None => self.bytecode,
+        }
+        .get_function(frame.function_index);
+        if frame.locals.len() < function.num_locals {
+            frame.locals.resize(function.num_locals, None);
+        }
+        if let Some(capacity) = self.stack_capacity.or(function.max_stack) {
+            frame.stack.reserve(capacity);
+        }
+        #[cfg(feature = "tracing")]
+        let frame = CallFrame {
+            _span: Some(
+                tracing::debug_span!(
+                    "call",
+                    function = frame.function_index,
+                    depth = self.frames.len()
+                )
+                .entered(),
+            ),
+            ..frame
+        };
+        if let Some(metrics) = &mut self.metrics {
+            metrics.calls += 1;
+        }
+        // Function indexes in stats refer to the program, not to modules.
+        if let (Some(stats), None) = (&mut self.stats, frame.module) {
+            stats.record_call(frame.function_index, self.frames.len() + 1);
+        }
+        self.push_frame(frame);
+    }
+
     fn pop_frame(&mut self) {
         if self.frames.is_empty() {
             panic!("Call stack underflow.");
@@ -106,24 +918,73 @@ impl<'a> VirtualMachine<'a> {
     }
 
     fn push_operand(&mut self, value: Value) {
-        self.current_frame().stack_push(value);
+        let frame = self.frames.last_mut().expect("Call stack is empty.");
+        frame.stack_push(value);
+        if let (Some(stats), None) = (&mut self.stats, frame.module) {
+            stats.record_stack(frame.function_index, frame.stack.len());
+        }
     }
 
     fn pop_operand(&mut self) -> Value {
-        self.current_frame().stack_pop().expect("Stack underflow.")
+        self.current_frame()
+            .stack_pop()
+            .unwrap_or_else(|| runtime_error!("Stack underflow."))
+    }
+
+    // Pushes the result of an operation or native call.
+    fn push_result(&mut self, value: Value) {
+        let value = self.canonical(value);
+        self.push_operand(value);
+    }
+
+    // The result of an operation, with NaN made canonical if floats are
+    // deterministic.
+    fn canonical(&self, value: Value) -> Value {
+        #[cfg(feature = "deterministic-float")]
+        let value = match value {
+            Value::Number(n) if self.deterministic_float && n.is_nan() => {
+                Value::Number(CANONICAL_NAN)
+            }
+            value => value,
+        };
+        value
     }
 
     fn get_local(&mut self, index: usize) -> Value {
         self.current_frame()
             .get_local(index)
             .cloned()
-            .expect("Local variable not found.")
+            .unwrap_or_else(|| runtime_error!("Local variable read before it was set."))
     }
 
     fn set_local(&mut self, index: usize, value: Value) {
         self.current_frame().set_local(index, value);
     }
 
+    // Replaces a local with the result of an operation on it, as getting it,
+    // operating on it, and setting it again would.
+    fn update_local(&mut self, index: usize, operation: impl FnOnce(&Value) -> Value) {
+        let result = operation(&self.get_local(index));
+        let result = self.canonical(result);
+        self.set_local(index, result);
+    }
+
+    // Writes a value for one of the print instructions. Output without a
+    // newline is flushed so that it shows up right away.
+    fn print(&self, opcode: Opcode, value: &Value) {
+        let text = value.display(self.number_format);
+        match opcode {
+            Opcode::Print => println!("{}", text),
+            Opcode::PrintRaw => {
+                print!("{}", text);
+                let _ = io::stdout().flush();
+            }
+            Opcode::EPrint => eprintln!("{}", text),
+            Opcode::EPrintRaw => eprint!("{}", text),
+            _ => panic!("Invalid opcode for print."),
+        }
+    }
+
     fn unary_op(&mut self, opcode: Opcode) {
         let val = self.pop_operand();
         let result = match opcode {
@@ -131,7 +992,7 @@ impl<'a> VirtualMachine<'a> {
             Opcode::Negate => val.negate(),
             _ => panic!("Invalid opcode for unary operation."),
         };
-        self.push_operand(result);
+        self.push_result(result);
     }
 
     fn binary_op(&mut self, opcode: Opcode) {
@@ -145,108 +1006,734 @@ impl<'a> VirtualMachine<'a> {
             Opcode::Modulo => val1.modulo(&val2),
             Opcode::And => val1.logical_and(&val2),
             Opcode::Or => val1.logical_or(&val2),
+            Opcode::LessThan => val1.less_than(&val2),
+            Opcode::GreaterThan => val1.greater_than(&val2),
+            Opcode::LessOrEqual => val1.less_or_equal(&val2),
+            Opcode::GreaterOrEqual => val1.greater_or_equal(&val2),
+            Opcode::Compare => val1.three_way(&val2),
             _ => panic!("Invalid opcode for binary operation."),
         };
-        self.push_operand(result);
+        self.push_result(result);
+    }
+
+    fn return_from_frame(&mut self, return_value: Value) {
+        self.run_deferred();
+        if !self.is_running {
+            return;
+        }
+        self.pop_frame();
+        if !self.is_call_stack_empty() {
+            self.push_operand(return_value);
+        }
+    }
+
+    // Pops the arguments of a call, which passes every parameter unless
+    // `arg_count` said otherwise, and fills in the defaults of the rest.
+    fn pop_call_arguments(&mut self, code: &Bytecode, index: usize) -> Vec<Value> {
+        let num_args = code.get_function(index).num_args;
+        let count = self
+            .current_frame()
+            .argument_count
+            .take()
+            .unwrap_or(num_args);
+        let mut args = Vec::with_capacity(num_args);
+        for _ in 0..count {
+            args.push(self.pop_operand());
+        }
+        args.reverse();
+        complete_arguments(code, index, args)
+    }
+
+    // Runs the current frame's deferred calls, most recent first, discarding
+    // their results. A deferred call that halts the VM stops the rest.
+    fn run_deferred(&mut self) {
+        while let Some((index, args)) = self.current_frame().deferred.pop() {
+            let mut frame = CallFrame::new(index);
+            frame.module = self.current_frame().module;
+            for (i, arg) in args.into_iter().enumerate() {
+                frame.set_local(i, arg);
+            }
+            trace::event!(DEBUG, function = index, "deferred call");
+            let depth = self.frames.len();
+            self.enter_frame(frame);
+            self.execute(depth);
+            if !self.is_running {
+                return;
+            }
+            self.pop_operand();
+        }
     }
 
     fn handle_jump(&mut self, target: usize) {
         self.current_frame().set_instruction_pointer(target);
     }
 
-    pub(crate) fn run(&mut self) {
-        self.push_frame(CallFrame::new(0));
-
-        while !self.is_call_stack_empty() && self.is_running {
-            let function_index = self.current_frame().get_function_index();
-            let current_function = self.bytecode.get_function(function_index);
-            let current_frame = self.current_frame();
-            let current_instruction_pointer = current_frame.get_instruction_pointer();
-            let instruction = current_function.get_instruction(current_instruction_pointer);
-
-            // println!("IP: {}", current_instruction_pointer);
-            // current_frame.debug_stack();
-            // println!("Instruction: {:?}", instruction.opcode());
-
-            current_frame.advance_instruction_pointer();
-
-            match instruction.opcode() {
-                Opcode::PushConst => {
-                    let constant = self
-                        .bytecode
-                        .get_constant(instruction.operand().into())
-                        .expect("Constant index out of range.");
-                    self.push_operand(constant.clone());
-                }
-                Opcode::Add
-                | Opcode::Subtract
-                | Opcode::Multiply
-                | Opcode::Divide
-                | Opcode::Modulo
-                | Opcode::And
-                | Opcode::Or => {
-                    self.binary_op(instruction.opcode());
-                }
-                Opcode::Not | Opcode::Negate => {
-                    self.unary_op(instruction.opcode());
-                }
-                Opcode::Equal => {
-                    let val2 = self.pop_operand();
-                    let val1 = self.pop_operand();
-                    self.push_operand(Value::Boolean(val1 == val2));
-                }
-                Opcode::Jump => {
+    pub fn run(&mut self) {
+        if self.bytecode.functions().is_empty() {
+            runtime_error!("Entry function 0 does not exist.");
+        }
+        self.enter_frame(CallFrame::new(0));
+        self.execute(0);
+    }
+
+    // Runs the program starting at another function than function 0, so one
+    // bytecode file can hold several entry points. The function must take no
+    // arguments.
+    pub fn run_from(&mut self, index: usize) {
+        match self.bytecode.functions().get(index) {
+            Some(function) if function.num_args == 0 => {}
+            Some(_) => runtime_error!("Entry function {} must take no arguments.", index),
+            None => runtime_error!("Entry function {} does not exist.", index),
+        }
+        self.enter_frame(CallFrame::new(index));
+        self.execute(0);
+    }
+
+    // Calls a guest function from native or host code, running it to completion
+    // on top of the current call stack and returning its result.
+    // A native called from a module's code calls that module's functions.
+    pub fn call_function(&mut self, index: usize, args: Vec<Value>) -> Value {
+        let module = self.frames.last().and_then(|frame| frame.module);
+        let module_code = module.map(|module| Rc::clone(&self.modules[module].bytecode));
+        let code = module_code.as_deref().unwrap_or(self.bytecode);
+        let args = complete_arguments(code, index, args);
+        let mut frame = CallFrame::new(index);
+        frame.module = module;
+        for (i, arg) in args.into_iter().enumerate() {
+            frame.set_local(i, arg);
+        }
+        // Calls from the host get a placeholder frame to receive the result.
+        let is_host_call = self.is_call_stack_empty();
+        if is_host_call {
+            self.push_frame(CallFrame::new(index));
+        }
+        trace::event!(DEBUG, function = index, host = is_host_call, "host call");
+        if let (true, Some(metrics)) = (is_host_call, &mut self.metrics) {
+            metrics.host_calls += 1;
+        }
+        let depth = self.frames.len();
+        self.enter_frame(frame);
+        self.execute(depth);
+        let result = if self.is_running {
+            self.pop_operand()
+        } else {
+            Value::Nil
+        };
+        if is_host_call {
+            self.pop_frame();
+        }
+        result
+    }
+
+    // Returns the value of the named module, loading it through the resolver
+    // and running its main function the first time it is imported.
+    fn import(&mut self, name: &Text) -> Value {
+        if let Some(index) = self.modules.iter().position(|m| m.name == *name) {
+            return match &self.modules[index].value {
+                Some(value) => value.clone(),
+                None => runtime_error!("Circular import of module '{}'.", name),
+            };
+        }
+        let resolver = match &mut self.module_resolver {
+            Some(resolver) => resolver,
+            None => runtime_error!("Cannot import module '{}' without a module resolver.", name),
+        };
+        let bytecode = resolver(name)
+            .unwrap_or_else(|e| runtime_error!("Failed to import module '{}': {}", name, e));
+        match bytecode.functions().first() {
+            Some(main) if main.num_args == 0 => {}
+            Some(_) => runtime_error!("Main function of module '{}' must take no arguments.", name),
+            None => runtime_error!("Module '{}' has no main function.", name),
+        }
+        trace::event!(DEBUG, module = &**name, "import");
+        let module = self.modules.len();
+        self.modules.push(Module {
+            name: name.clone(),
+            bytecode: Rc::new(bytecode),
+            value: None,
+        });
+        let mut frame = CallFrame::new(0);
+        frame.module = Some(module);
+        let depth = self.frames.len();
+        self.enter_frame(frame);
+        self.execute(depth);
+        let value = if self.is_running {
+            self.pop_operand()
+        } else {
+            Value::Nil
+        };
+        self.modules[module].value = Some(value.clone());
+        value
+    }
+
+    fn record_step(&mut self, function: &Function) {
+        let frame = self.frames.last().expect("Call stack is empty.");
+        let instruction = function.get_instruction(frame.get_instruction_pointer());
+        let mut line = format!(
+            "{} {} {}",
+            frame.function_index,
+            frame.get_instruction_pointer(),
+            instruction.opcode().name()
+        );
+        if let Some(operand) = instruction.optional_operand() {
+            write!(line, " {}", operand).unwrap();
+        }
+        writeln!(line, " [{}]", frame.stack.len()).unwrap();
+        if let Some(trace) = &mut self.step_trace {
+            trace.push_str(&line);
+        }
+    }
+
+    // Sets the backend that runs instructions, which is the interpreter
+    // unless changed.
+    pub fn set_backend<E: Executor + 'static>(&mut self, backend: E) {
+        self.backend = Rc::new(backend);
+    }
+
+    fn execute(&mut self, base_depth: usize) {
+        let backend = Rc::clone(&self.backend);
+        backend.execute(self, base_depth);
+    }
+
+    // Runs the next instruction of the innermost frame.
+    pub fn step(&mut self) {
+        let function_index = self.current_frame().get_function_index();
+        let module = self.current_frame().module;
+        let module_code = module.map(|module| Rc::clone(&self.modules[module].bytecode));
+        let code = module_code.as_deref().unwrap_or(self.bytecode);
+        let current_function = code.get_function(function_index);
+        if self.step_trace.is_some() {
+            self.record_step(current_function);
+        }
+        let current_frame = self.current_frame();
+        let current_instruction_pointer = current_frame.get_instruction_pointer();
+        let instruction = current_function.get_instruction(current_instruction_pointer);
+
+        trace::event!(
+            TRACE,
+            ip = current_instruction_pointer,
+            opcode = ?instruction.opcode(),
+            operand = instruction.optional_operand(),
+            stack = current_frame.stack.len()
+        );
+
+        current_frame.advance_instruction_pointer();
+        self.steps += 1;
+        if let Some(limit) = self.step_limit {
+            if self.steps > limit {
+                runtime_error!("Step limit of {} instructions exceeded.", limit);
+            }
+        }
+        if let Some(metrics) = &mut self.metrics {
+            metrics.record(instruction.opcode());
+        }
+        if let Some(profile) = &self.memory_profile {
+            if self.steps.is_multiple_of(profile.interval) {
+                self.sample_memory();
+            }
+        }
+        if let Some(profile) = &self.sampling_profile {
+            if profile.due.load(atomic::Ordering::Relaxed) {
+                profile.due.store(false, atomic::Ordering::Relaxed);
+                self.sample_stack();
+            }
+        }
+
+        if module.is_none()
+            && self.quickening.is_some()
+            && self.run_quickened(
+                function_index,
+                current_instruction_pointer,
+                instruction.opcode(),
+            )
+        {
+            return;
+        }
+        if self.error_tolerant {
+            self.run_tolerantly(code, module, instruction);
+        } else {
+            self.run_instruction(code, module, instruction);
+        }
+    }
+
+    // Runs an instruction, and if it fails with a runtime error, records the
+    // error and carries on as if the instruction had consumed its operands
+    // and pushed an error value for each of its results. Frames it entered on
+    // the way are left. Instructions whose results can't be counted, such as
+    // calls and returns, fail as usual, and so does the step limit.
+    fn run_tolerantly(
+        &mut self,
+        code: &Bytecode,
+        module: Option<usize>,
+        instruction: &Instruction,
+    ) {
+        let Some((pops, pushes)) = recoverable_effect(code, instruction) else {
+            self.run_instruction(code, module, instruction);
+            return;
+        };
+        let depth = self.frames.len();
+        let height = self.current_frame().stack.len();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            self.run_instruction(code, module, instruction)
+        }));
+        let Err(payload) = result else {
+            return;
+        };
+        // Any other panic is a bug, after which the frames can't be trusted.
+        let Some(RuntimeError(message)) = payload.downcast_ref::<RuntimeError>() else {
+            panic::resume_unwind(payload);
+        };
+        if self.step_limit.is_some_and(|limit| self.steps > limit) {
+            panic::resume_unwind(payload);
+        }
+        let message = message.clone();
+        self.frames.truncate(depth);
+        let frame = self.current_frame();
+        frame.stack.truncate(height.saturating_sub(pops));
+        let function = frame.function_index;
+        let instruction = frame.instruction_pointer - 1;
+        let error = Value::Error(Rc::new(Value::Str(message.as_str().into())));
+        for _ in 0..pushes {
+            self.push_operand(error.clone());
+        }
+        self.recovered_errors.push(RecoveredError {
+            function,
+            instruction,
+            module: module.map(|m| self.modules[m].name.to_string()),
+            message,
+        });
+    }
+
+    fn run_instruction(
+        &mut self,
+        code: &Bytecode,
+        module: Option<usize>,
+        instruction: &Instruction,
+    ) {
+        match instruction.opcode() {
+            Opcode::PushConst => {
+                let constant = code
+                    .get_constant(instruction.operand().into())
+                    .unwrap_or_else(|| runtime_error!("Constant index out of range."));
+                self.push_operand(constant.clone());
+            }
+            Opcode::Add
+            | Opcode::Subtract
+            | Opcode::Multiply
+            | Opcode::Divide
+            | Opcode::Modulo
+            | Opcode::And
+            | Opcode::Or
+            | Opcode::LessThan
+            | Opcode::GreaterThan
+            | Opcode::LessOrEqual
+            | Opcode::GreaterOrEqual
+            | Opcode::Compare => {
+                self.binary_op(instruction.opcode());
+            }
+            Opcode::Not | Opcode::Negate => {
+                self.unary_op(instruction.opcode());
+            }
+            Opcode::Equal => {
+                let val2 = self.pop_operand();
+                let val1 = self.pop_operand();
+                self.push_operand(Value::Boolean(val1.equals(&val2, self.number_equality)));
+            }
+            Opcode::NotEqual => {
+                let val2 = self.pop_operand();
+                let val1 = self.pop_operand();
+                self.push_operand(Value::Boolean(!val1.equals(&val2, self.number_equality)));
+            }
+            Opcode::Identical => {
+                let val2 = self.pop_operand();
+                let val1 = self.pop_operand();
+                self.push_operand(Value::Boolean(val1.is_identical(&val2)));
+            }
+            Opcode::TotalCompare => {
+                let val2 = self.pop_operand();
+                let val1 = self.pop_operand();
+                let ordering = val1
+                    .total_order(&val2)
+                    .unwrap_or_else(|| runtime_error!("Invalid operand types for total compare."));
+                self.push_operand(Value::Number(ordering as i8 as f64));
+            }
+            Opcode::Match => {
+                let pattern = code
+                    .get_constant(instruction.operand().into())
+                    .unwrap_or_else(|| runtime_error!("Constant index out of range."));
+                let numbers = self.number_equality;
+                let frame = self.current_frame();
+                let value = frame
+                    .stack
+                    .last()
+                    .unwrap_or_else(|| runtime_error!("Stack underflow."));
+                let matched = pattern::matches(pattern, value, numbers);
+                self.push_operand(Value::Boolean(matched));
+            }
+            Opcode::Jump => {
+                self.handle_jump(instruction.operand().into());
+            }
+            Opcode::JumpIfTrue => {
+                let val = self.pop_operand();
+                if let Value::Boolean(true) = val {
                     self.handle_jump(instruction.operand().into());
                 }
-                Opcode::JumpIfTrue => {
-                    let val = self.pop_operand();
-                    if let Value::Boolean(true) = val {
-                        self.handle_jump(instruction.operand().into());
-                    }
+            }
+            Opcode::JumpIfFalse => {
+                let val = self.pop_operand();
+                if let Value::Boolean(false) = val {
+                    self.handle_jump(instruction.operand().into());
                 }
-                Opcode::JumpIfFalse => {
-                    let val = self.pop_operand();
-                    if let Value::Boolean(false) = val {
-                        self.handle_jump(instruction.operand().into());
-                    }
+            }
+            Opcode::JumpIfEqual
+            | Opcode::JumpIfNotEqual
+            | Opcode::JumpIfLess
+            | Opcode::JumpIfLessOrEqual
+            | Opcode::JumpIfGreater
+            | Opcode::JumpIfGreaterOrEqual => {
+                let val2 = self.pop_operand();
+                let val1 = self.pop_operand();
+                if val1.branches(&val2, instruction.opcode(), self.number_equality) {
+                    self.handle_jump(instruction.operand().into());
                 }
-                Opcode::Print => {
-                    let val = self.pop_operand();
-                    println!("{}", val);
+            }
+            Opcode::Print | Opcode::PrintRaw | Opcode::EPrint | Opcode::EPrintRaw => {
+                let val = self.pop_operand();
+                self.print(instruction.opcode(), &val);
+            }
+            Opcode::Format => {
+                let num_args = instruction.operand().into();
+                let mut args = Vec::with_capacity(num_args);
+                for _ in 0..num_args {
+                    args.push(self.pop_operand());
                 }
-                Opcode::GetLocal => {
-                    let val = self.get_local(instruction.operand().into());
-                    self.push_operand(val);
+                args.reverse();
+                let template = match self.pop_operand() {
+                    Value::Str(s) => s,
+                    _ => runtime_error!("Invalid operand type for format."),
+                };
+                let result = format_template(&template, &args, self.number_format);
+                self.push_operand(Value::Str(result.into()));
+            }
+            Opcode::GetLocal => {
+                let val = self.get_local(instruction.operand().into());
+                self.push_operand(val);
+            }
+            Opcode::SetLocal => {
+                let val = self.pop_operand();
+                self.set_local(instruction.operand().into(), val);
+            }
+            Opcode::IncLocal => {
+                self.update_local(instruction.operand().into(), |val| {
+                    val.add(&Value::Number(1.0))
+                });
+            }
+            Opcode::DecLocal => {
+                self.update_local(instruction.operand().into(), |val| {
+                    val.subtract(&Value::Number(1.0))
+                });
+            }
+            Opcode::AddLocalConst => {
+                let (slot, index) = instruction.local_constant();
+                let constant = code
+                    .get_constant(index)
+                    .unwrap_or_else(|| runtime_error!("Constant index out of range."));
+                self.update_local(slot, |val| val.add(constant));
+            }
+            Opcode::Call => {
+                let index = instruction.operand().into();
+                let args = self.pop_call_arguments(code, index);
+                let mut new_frame = CallFrame::new(index);
+                new_frame.module = module;
+                for (i, arg) in args.into_iter().enumerate() {
+                    new_frame.set_local(i, arg);
                 }
-                Opcode::SetLocal => {
-                    let val = self.pop_operand();
-                    self.set_local(instruction.operand().into(), val);
-                }
-                Opcode::Call => {
-                    let operand = instruction.operand();
-                    let func_to_call = self.bytecode.get_function(operand.into());
-                    let mut new_frame = CallFrame::new(operand.into());
-                    for i in 0..func_to_call.num_args {
-                        let arg = self.pop_operand();
-                        new_frame.set_local(func_to_call.num_args - i - 1, arg);
+                self.enter_frame(new_frame);
+            }
+            Opcode::Defer => {
+                let index = instruction.operand().into();
+                let args = self.pop_call_arguments(code, index);
+                self.current_frame().deferred.push((index, args));
+            }
+            Opcode::ArgCount => {
+                let count = instruction.operand().into();
+                self.current_frame().argument_count = Some(count);
+            }
+            Opcode::RunDefers => {
+                self.run_deferred();
+            }
+            Opcode::Return => {
+                let return_value = if !self.is_operand_stack_empty() {
+                    self.pop_operand()
+                } else {
+                    Value::Boolean(false)
+                };
+                self.return_from_frame(return_value);
+            }
+            Opcode::CallNative => {
+                let index = instruction.operand().into();
+                let native = match code.get_native(index) {
+                    Some(native) => native,
+                    None => match code.get_constant(index) {
+                        Some(Value::Str(name)) => {
+                            runtime_error!("Unknown native function '{}'.", name)
+                        }
+                        _ => runtime_error!("Native function name must be a string constant."),
+                    },
+                };
+                if let Some(capability) = native.capability {
+                    if !self.capabilities.contains(&capability) {
+                        runtime_error!(
+                            "Native function '{}' requires the '{}' capability.",
+                            native.name,
+                            capability.name()
+                        );
                     }
-                    self.push_frame(new_frame);
                 }
-                Opcode::Return => {
-                    let return_value = if !self.is_operand_stack_empty() {
-                        self.pop_operand()
-                    } else {
-                        Value::Boolean(false)
-                    };
-                    self.pop_frame();
-                    if !self.is_call_stack_empty() {
-                        self.push_operand(return_value);
+                if self.deterministic && !native.is_deterministic() {
+                    runtime_error!(
+                        "Native function '{}' is not available in deterministic mode.",
+                        native.name
+                    );
+                }
+                let mut args = Vec::with_capacity(native.arity);
+                for _ in 0..native.arity {
+                    args.push(self.pop_operand());
+                }
+                args.reverse();
+                trace::event!(DEBUG, native = native.name, "native call");
+                if let Some(metrics) = &mut self.metrics {
+                    metrics.native_calls += 1;
+                }
+                let result = native.call(self, &args);
+                self.push_result(result);
+            }
+            Opcode::Import => {
+                let name = match code.get_constant(instruction.operand().into()) {
+                    Some(Value::Str(name)) => name.clone(),
+                    _ => runtime_error!("Module name must be a string constant."),
+                };
+                let value = self.import(&name);
+                self.push_operand(value);
+            }
+            Opcode::MakeTuple => {
+                let len = instruction.operand().into();
+                let mut elements = Vec::with_capacity(len);
+                for _ in 0..len {
+                    elements.push(self.pop_operand());
+                }
+                elements.reverse();
+                self.push_operand(Value::Tuple(elements.into()));
+            }
+            Opcode::TupleGet => {
+                let index: usize = instruction.operand().into();
+                let element = match self.pop_operand() {
+                    Value::Tuple(elements) => elements
+                        .get(index)
+                        .cloned()
+                        .unwrap_or_else(|| runtime_error!("Tuple index out of range.")),
+                    _ => runtime_error!("Invalid operand type for tuple get."),
+                };
+                self.push_operand(element);
+            }
+            Opcode::TupleUnpack => {
+                let len: usize = instruction.operand().into();
+                let elements = match self.pop_operand() {
+                    Value::Tuple(elements) => elements,
+                    _ => runtime_error!("Invalid operand type for tuple unpack."),
+                };
+                if elements.len() != len {
+                    runtime_error!("Tuple length mismatch in unpack.");
+                }
+                for element in elements.iter() {
+                    self.push_operand(element.clone());
+                }
+            }
+            Opcode::ArrayConcat => {
+                let b = self.pop_operand();
+                let a = self.pop_operand();
+                self.push_operand(array::concat(a, b));
+            }
+            Opcode::ArraySlice => {
+                let end = self.pop_operand();
+                let start = self.pop_operand();
+                let elements = self.pop_operand();
+                self.push_operand(array::slice(elements, start, end));
+            }
+            Opcode::ArrayPush => {
+                let value = self.pop_operand();
+                let elements = self.pop_operand();
+                self.push_operand(array::push(elements, value));
+            }
+            Opcode::ArrayPop => {
+                let (elements, last) = array::pop(self.pop_operand());
+                self.push_operand(elements);
+                self.push_operand(last);
+            }
+            Opcode::SetAdd => {
+                let value = self.pop_operand();
+                let elements = self.pop_operand();
+                self.push_operand(set::add(elements, value));
+            }
+            Opcode::SetContains => {
+                let value = self.pop_operand();
+                let elements = self.pop_operand();
+                self.push_operand(Value::Boolean(set::contains(&elements, &value)));
+            }
+            Opcode::SetRemove => {
+                let value = self.pop_operand();
+                let elements = self.pop_operand();
+                self.push_operand(set::remove(elements, value));
+            }
+            Opcode::MakeError => {
+                let payload = self.pop_operand();
+                if let Some(metrics) = &mut self.metrics {
+                    metrics.errors += 1;
+                }
+                self.push_operand(Value::Error(Rc::new(payload)));
+            }
+            Opcode::IsError => {
+                let val = self.pop_operand();
+                self.push_operand(Value::Boolean(matches!(val, Value::Error(_))));
+            }
+            Opcode::Try => {
+                let val = self.pop_operand();
+                if let Value::Error(_) = val {
+                    trace::event!(
+                        DEBUG,
+                        function = self.current_frame().get_function_index(),
+                        error = %val,
+                        "try returned an error"
+                    );
+                    if let Some(metrics) = &mut self.metrics {
+                        metrics.error_returns += 1;
                     }
+                    self.return_from_frame(val);
+                } else {
+                    self.push_operand(val);
+                }
+            }
+            Opcode::Halt => {
+                trace::event!(DEBUG, "halt");
+                self.is_running = false;
+            }
+            Opcode::Extension(extension) => {
+                let handler = self
+                    .extension_handlers
+                    .iter()
+                    .find(|(e, _)| *e == extension)
+                    .map(|&(_, handler)| handler)
+                    .unwrap_or_else(|| {
+                        runtime_error!(
+                            "No handler is set for extension instruction '{}'.",
+                            extension.name()
+                        )
+                    });
+                let pops = extension.stack_effect().0;
+                let mut args = Vec::with_capacity(pops);
+                for _ in 0..pops {
+                    args.push(self.pop_operand());
+                }
+                args.reverse();
+                let results = handler(self, instruction.optional_operand(), &args);
+                if results.len() != extension.stack_effect().1 {
+                    runtime_error!(
+                        "Extension instruction '{}' returned {} values instead of {}.",
+                        extension.name(),
+                        results.len(),
+                        extension.stack_effect().1
+                    );
                 }
-                Opcode::Halt => {
-                    self.is_running = false;
+                for result in results {
+                    self.push_result(result);
                 }
             }
         }
     }
 }
+
+// How many values an instruction pops and pushes, if it can be recovered
+// from in error-tolerant mode.
+fn recoverable_effect(code: &Bytecode, instruction: &Instruction) -> Option<(usize, usize)> {
+    if let Opcode::Import = instruction.opcode() {
+        return None;
+    }
+    let effect = instruction.opcode().stack_effect();
+    let count = |count| match count {
+        Count::Fixed(n) => Some(n),
+        Count::Operand(n) => Some(usize::from(instruction.operand()) + n),
+        Count::Native => code
+            .get_native(instruction.operand().into())
+            .map(|native| native.arity),
+        Count::Arguments | Count::Rest => None,
+    };
+    Some((count(effect.pops)?, count(effect.pushes)?))
+}
+
+// Replaces the panic hook with one that prints a runtime error's message
+// alone, for programs that halt on one, and passes other panics to the hook it
+// replaces.
+pub fn report_runtime_errors() {
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        match info.payload().downcast_ref::<RuntimeError>() {
+            Some(error) => eprintln!("{}", error),
+            None => hook(info),
+        }
+    }));
+}
+
+// The message a runtime error panicked with.
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(error) = payload.downcast_ref::<RuntimeError>() {
+        error.0.clone()
+    } else if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Unknown error".to_string()
+    }
+}
+
+// Appends the default values of the parameters `args` leaves out.
+fn complete_arguments(code: &Bytecode, index: usize, mut args: Vec<Value>) -> Vec<Value> {
+    let function = code.get_function(index);
+    if args.len() < function.required_args() || args.len() > function.num_args {
+        runtime_error!("Wrong number of arguments for function {}.", index);
+    }
+    let missing = function.num_args - args.len();
+    for &constant in &function.defaults[function.defaults.len() - missing..] {
+        let value = code
+            .get_constant(constant.into())
+            .unwrap_or_else(|| runtime_error!("Constant index out of range."));
+        args.push(value.clone());
+    }
+    args
+}
+
+pub(crate) fn format_template(template: &str, args: &[Value], numbers: NumberFormat) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut args = args.iter();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                chars.next();
+                result.push(c);
+            }
+            ('{', Some('}')) => {
+                chars.next();
+                let arg = args
+                    .next()
+                    .unwrap_or_else(|| runtime_error!("Too few arguments for format."));
+                result.push_str(&arg.display(numbers).to_string());
+            }
+            _ => result.push(c),
+        }
+    }
+    if args.next().is_some() {
+        runtime_error!("Too many arguments for format.");
+    }
+    result
+}
@@ -0,0 +1,58 @@
+use zircon::lint_assembly;
+
+#[test]
+fn assembly_lint_reports_every_problem_with_its_position() {
+    let source = "\
+.func main
+    push_const 9007199254740992
+    push_const 9007199254740993
+    arg_count 3
+    call f
+    call g
+    jump done
+    print
+    halt
+done:
+    jump_if_true nowhere
+done: halt
+.end
+
+.func f 2 1
+    return
+.end
+";
+    let warnings: Vec<String> = lint_assembly(source)
+        .unwrap()
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    assert_eq!(
+        warnings,
+        [
+            "3:16: Literal 9007199254740993 is the same constant as 9007199254740992 on line 2, as both round to 9007199254740992",
+            "5:10: 'f' takes 1 to 2 arguments, but arg_count passes 3",
+            "6:10: Undefined function 'g'",
+            "8:5: Instruction after 'jump' is unreachable",
+            "11:18: Undefined label 'nowhere'",
+            "12:1: Label 'done' is already defined",
+        ]
+    );
+}
+
+// Macro invocations may stand for anything, so code after one is reachable
+// and its arguments are not checked as instructions.
+#[test]
+fn assembly_lint_passes_over_macros() {
+    let source = "\
+%macro leave value
+    push_const %value
+    return
+%end
+
+.func main
+    leave 1
+    halt
+.end
+";
+    assert_eq!(lint_assembly(source).unwrap(), []);
+}
@@ -0,0 +1,36 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+// A bundled executable runs the program appended to it, with no arguments.
+#[test]
+fn bundles_run_their_program() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("bundle");
+    fs::create_dir_all(&dir).unwrap();
+    let source = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/programs/factorial.zr");
+    let bytecode = dir.join("factorial.zrc");
+    let program = dir.join("factorial");
+
+    let zircon = |args: &[&Path]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_zircon"))
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success() && output.stderr.is_empty(),
+            "{:?}",
+            output
+        );
+    };
+    zircon(&[Path::new("compile"), &source, Path::new("-o"), &bytecode]);
+    zircon(&[Path::new("bundle"), &bytecode, Path::new("-o"), &program]);
+
+    let output = Command::new(&program).output().unwrap();
+    let expected = fs::read_to_string(source.with_extension("out")).unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+    assert!(
+        output.status.success() && output.stderr.is_empty(),
+        "{:?}",
+        output
+    );
+}
@@ -0,0 +1,116 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+
+use zircon::{assemble, RuntimeError, Value, VirtualMachine};
+
+const PROGRAM: &str = r#"
+.func main
+    halt
+.end
+
+.func less 2
+    get_local 0
+    get_local 1
+    less
+    return
+.end
+
+.func greater 2
+    get_local 0
+    get_local 1
+    greater
+    return
+.end
+
+.func less_or_equal 2
+    get_local 0
+    get_local 1
+    less_or_equal
+    return
+.end
+
+.func greater_or_equal 2
+    get_local 0
+    get_local 1
+    greater_or_equal
+    return
+.end
+
+.func compare 2
+    get_local 0
+    get_local 1
+    compare
+    return
+.end
+"#;
+
+fn call(function: usize, a: Value, b: Value) -> Value {
+    let bytecode = assemble(PROGRAM).unwrap();
+    let mut vm = VirtualMachine::new(&bytecode);
+    vm.call_function(function, vec![a, b])
+}
+
+fn array(elements: Vec<Value>) -> Value {
+    Value::Array(Rc::new(elements))
+}
+
+fn tuple(elements: Vec<Value>) -> Value {
+    Value::Tuple(Rc::from(elements))
+}
+
+// A NaN inside an array or tuple makes the comparison false both ways, as a
+// bare NaN does, instead of stopping the program.
+#[test]
+fn nan_in_composites_is_unordered() {
+    let nan = || Value::Number(f64::NAN);
+    let one = || Value::Number(1.0);
+    for function in [1, 2, 3, 4] {
+        let arrays = call(function, array(vec![nan()]), array(vec![one()]));
+        assert_eq!(arrays, Value::Boolean(false));
+        let tuples = call(function, tuple(vec![one()]), tuple(vec![nan()]));
+        assert_eq!(tuples, Value::Boolean(false));
+    }
+    let ordered_before_nan = array(vec![Value::Number(0.0), nan()]);
+    let result = call(1, ordered_before_nan, array(vec![one(), one()]));
+    assert_eq!(result, Value::Boolean(true));
+}
+
+// Equal prefixes do not make unordered elements equal, so `<=` and `>=` are
+// false rather than true.
+#[test]
+fn nan_in_composites_is_not_equal_either() {
+    let nan = || Value::Number(f64::NAN);
+    for function in [3, 4] {
+        let arrays = call(function, array(vec![nan()]), array(vec![nan()]));
+        assert_eq!(arrays, Value::Boolean(false));
+        let tuples = call(
+            function,
+            tuple(vec![Value::Number(1.0), nan()]),
+            tuple(vec![Value::Number(1.0), nan()]),
+        );
+        assert_eq!(tuples, Value::Boolean(false));
+    }
+}
+
+// `compare` has no -1, 0, or 1 to give for unordered composites, so it stops
+// with an error rather than picking one.
+#[test]
+fn compare_rejects_unordered_composites() {
+    let nan = || Value::Number(f64::NAN);
+    let one = || Value::Number(1.0);
+    let cases = [
+        (array(vec![nan()]), array(vec![one()])),
+        (tuple(vec![one(), nan()]), tuple(vec![one(), one()])),
+    ];
+    for (a, b) in cases {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| call(5, a, b)));
+        let error = result.unwrap_err().downcast::<RuntimeError>().unwrap();
+        assert_eq!(error.0, "Cannot compare unordered values.");
+    }
+    let ordered = call(
+        5,
+        array(vec![one(), nan()]),
+        array(vec![Value::Number(2.0)]),
+    );
+    assert_eq!(ordered, Value::Number(-1.0));
+}
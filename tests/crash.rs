@@ -0,0 +1,56 @@
+use std::panic::{self, AssertUnwindSafe};
+
+use zircon::{assemble, program_hash, CrashDump, VirtualMachine};
+
+const PROGRAM: &str = r#"
+.func main
+    push_const 5
+    set_local 0
+    push_const "x"
+    push_const 1
+    call nested
+    halt
+.end
+
+.func nested 1
+    push_const 2
+    push_const true
+    add
+    return
+.end
+"#;
+
+// A failed program leaves its frames behind, innermost first, and the dump
+// reads back as it was written.
+#[test]
+fn crash_dump_records_the_failed_frames() {
+    let bytecode = assemble(PROGRAM).unwrap();
+    let mut vm = VirtualMachine::new(&bytecode);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| vm.run()));
+    assert!(result.is_err());
+
+    let dump = vm.crash_dump("Invalid operand types for add.");
+    assert_eq!(dump.program_hash, program_hash(&bytecode));
+    let frames: Vec<_> = dump
+        .frames
+        .iter()
+        .map(|frame| (frame.function, frame.instruction))
+        .collect();
+    assert_eq!(frames, [(1, 2), (0, 4)]);
+    let caller = &dump.frames[1];
+    assert_eq!(caller.locals[0].as_ref().unwrap().text, "5");
+    assert_eq!(caller.stack[0].kind, "string");
+
+    let mut written = Vec::new();
+    dump.write(&mut written).unwrap();
+    let parsed = CrashDump::parse(&String::from_utf8(written).unwrap()).unwrap();
+    assert_eq!(parsed, dump);
+
+    let listing = dump.inspect(Some(&bytecode));
+    assert!(listing.contains("#0 function 1, instruction 2: add\n"));
+    assert!(listing.contains("#1 function 0, instruction 4: call 1\n"));
+    let other = assemble(".func main\n    halt\n.end\n").unwrap();
+    assert!(dump
+        .inspect(Some(&other))
+        .contains("does not match the program hash"));
+}
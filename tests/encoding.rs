@@ -0,0 +1,109 @@
+use std::io::Cursor;
+
+use zircon::{Instruction, Opcode};
+
+// Operands at the edges of the u16 range, plus a spread of others from a
+// fixed xorshift sequence so every run checks the same values.
+fn operands() -> Vec<u16> {
+    let mut operands = vec![
+        0, 1, 0x7f, 0x80, 0xff, 0x100, 0x7fff, 0x8000, 0xfffe, 0xffff,
+    ];
+    let mut state: u32 = 0x2545_f491;
+    for _ in 0..200 {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        operands.push(state as u16);
+    }
+    operands
+}
+
+fn instructions() -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    for &opcode in Opcode::ALL {
+        if opcode.has_operand() {
+            for operand in operands() {
+                instructions.push(Instruction::new(opcode, Some(operand)));
+            }
+        } else {
+            instructions.push(Instruction::new(opcode, None));
+        }
+    }
+    instructions
+}
+
+fn encode(instruction: &Instruction) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    instruction.encode(&mut bytes).unwrap();
+    bytes
+}
+
+#[test]
+fn decode_inverts_encode() {
+    for instruction in instructions() {
+        let bytes = encode(&instruction);
+        let mut reader = Cursor::new(&bytes);
+        assert_eq!(Instruction::decode(&mut reader).unwrap(), instruction);
+        assert_eq!(reader.position() as usize, bytes.len());
+    }
+}
+
+#[test]
+fn decode_inverts_encode_for_a_sequence() {
+    let instructions = instructions();
+    let mut bytes = Vec::new();
+    for instruction in &instructions {
+        instruction.encode(&mut bytes).unwrap();
+    }
+    let mut reader = Cursor::new(&bytes);
+    for instruction in &instructions {
+        assert_eq!(Instruction::decode(&mut reader).unwrap(), *instruction);
+    }
+    assert_eq!(reader.position() as usize, bytes.len());
+}
+
+#[test]
+fn encoding_is_the_opcode_byte_then_a_little_endian_operand() {
+    assert_eq!(encode(&Instruction::new(Opcode::Add, None)), [0x10]);
+    assert_eq!(
+        encode(&Instruction::new(Opcode::PushConst, Some(0x1234))),
+        [0x01, 0x34, 0x12]
+    );
+}
+
+#[test]
+fn opcode_bytes_round_trip() {
+    for &opcode in Opcode::ALL {
+        assert_eq!(Opcode::from_u8(opcode.to_u8()).unwrap(), opcode);
+    }
+    for byte in 0..=u8::MAX {
+        if let Ok(opcode) = Opcode::from_u8(byte) {
+            assert_eq!(opcode.to_u8(), byte);
+            assert!(Opcode::ALL.contains(&opcode));
+        }
+    }
+}
+
+#[test]
+fn unknown_opcodes_are_rejected() {
+    let mut reader = Cursor::new([0x00]);
+    assert!(Instruction::decode(&mut reader).is_err());
+}
+
+#[test]
+fn truncated_operands_are_rejected() {
+    let mut reader = Cursor::new([0x01, 0x34]);
+    assert!(Instruction::decode(&mut reader).is_err());
+}
+
+#[test]
+fn mismatched_operands_are_not_encoded() {
+    let mut bytes = Vec::new();
+    assert!(Instruction::new(Opcode::Add, Some(1))
+        .encode(&mut bytes)
+        .is_err());
+    assert!(Instruction::new(Opcode::PushConst, None)
+        .encode(&mut bytes)
+        .is_err());
+    assert!(bytes.is_empty());
+}
@@ -0,0 +1,69 @@
+use std::env;
+use std::panic::{self, AssertUnwindSafe};
+
+use zircon::{assemble, Bytecode, Capability, RuntimeError, Value, VirtualMachine};
+
+const PROGRAM: &str = r#"
+.func main
+    halt
+.end
+
+.func get 1
+    get_local 0
+    call_native env_get
+    return
+.end
+
+.func cwd
+    call_native cwd
+    return
+.end
+"#;
+
+const GET: usize = 1;
+const CWD: usize = 2;
+
+fn with_env(bytecode: &Bytecode) -> VirtualMachine<'_> {
+    let mut vm = VirtualMachine::new(bytecode);
+    vm.grant(Capability::Env);
+    vm
+}
+
+fn string(s: &str) -> Value {
+    Value::Str(s.into())
+}
+
+#[test]
+fn reads_variables_when_granted() {
+    let bytecode = assemble(PROGRAM).unwrap();
+    let mut vm = with_env(&bytecode);
+    let (name, value) = env::vars_os()
+        .find_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+        .expect("the test has no environment");
+    assert_eq!(vm.call_function(GET, vec![string(&name)]), string(&value));
+
+    let directory = env::current_dir().unwrap();
+    let expected = string(&directory.to_string_lossy());
+    assert_eq!(vm.call_function(CWD, Vec::new()), expected);
+}
+
+#[test]
+fn missing_variables_are_nil() {
+    let bytecode = assemble(PROGRAM).unwrap();
+    let mut vm = with_env(&bytecode);
+    let name = string("ZIRCON_TEST_VARIABLE_THAT_IS_NOT_SET");
+    assert_eq!(vm.call_function(GET, vec![name]), Value::Nil);
+}
+
+#[test]
+fn refuses_without_the_env_capability() {
+    let bytecode = assemble(PROGRAM).unwrap();
+    let mut vm = VirtualMachine::new(&bytecode);
+    let args = vec![string("PATH")];
+    let result = panic::catch_unwind(AssertUnwindSafe(|| vm.call_function(GET, args)));
+    let error = result.unwrap_err().downcast::<RuntimeError>().unwrap();
+    assert_eq!(
+        error.0,
+        "Native function 'env_get' requires the 'env' capability."
+    );
+}
@@ -0,0 +1,90 @@
+use std::panic::{self, AssertUnwindSafe};
+
+use zircon::{
+    assemble, assemble_with_extensions, Extension, OperandKind, Value, VirtualMachine, VmOptions,
+};
+
+const PROGRAM: &str = r#"
+.func main
+    push_const 1
+    push_const "a"
+    add
+    set_local 0
+    push_const 5
+    call_native length
+    set_local 1
+    get_local 7
+    set_local 2
+    push_const "done"
+    set_local 3
+    halt
+.end
+"#;
+
+// Each failed instruction leaves an error value in place of its result, and
+// the program runs to the end.
+#[test]
+fn recoverable_errors_are_collected() {
+    let bytecode = assemble(PROGRAM).unwrap();
+    let mut vm = VirtualMachine::with_options(&bytecode, VmOptions::new().error_tolerant());
+    vm.run();
+
+    let errors: Vec<_> = vm
+        .recovered_errors()
+        .iter()
+        .map(|error| (error.function, error.instruction, error.message.as_str()))
+        .collect();
+    assert_eq!(
+        errors,
+        [
+            (0, 2, "Invalid operand types for add."),
+            (0, 5, "Invalid operand type for length."),
+            (0, 7, "Local variable read before it was set."),
+        ]
+    );
+    let state = vm.state();
+    let locals = &state.frames[0].locals;
+    assert_eq!(locals[0].as_ref().unwrap().kind, "error");
+    assert_eq!(locals[3].as_ref().unwrap().text, "done");
+    assert_eq!(state.frames[0].stack_depth, 0);
+}
+
+// The step limit still stops the program.
+#[test]
+fn step_limit_is_not_recovered() {
+    let bytecode = assemble(PROGRAM).unwrap();
+    let mut vm =
+        VirtualMachine::with_options(&bytecode, VmOptions::new().error_tolerant().step_limit(3));
+    let result = panic::catch_unwind(AssertUnwindSafe(|| vm.run()));
+    assert!(result.is_err());
+    assert_eq!(vm.recovered_errors().len(), 1);
+}
+
+#[test]
+fn errors_are_fatal_by_default() {
+    let bytecode = assemble(PROGRAM).unwrap();
+    let mut vm = VirtualMachine::new(&bytecode);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| vm.run()));
+    assert!(result.is_err());
+    assert!(vm.recovered_errors().is_empty());
+}
+
+static BUGGY: Extension = Extension::new(0xE0, "buggy", OperandKind::None, 0, 1);
+
+fn buggy(_: &mut VirtualMachine, _: Option<u16>, _: &[Value]) -> Vec<Value> {
+    let empty: Vec<Value> = Vec::new();
+    vec![empty[0].clone()]
+}
+
+// A panic that isn't a runtime error is a bug, so it is not turned into an
+// error value.
+#[test]
+fn other_panics_are_not_recovered() {
+    let source = ".func main\n    buggy\n    set_local 0\n    halt\n.end\n";
+    let bytecode = assemble_with_extensions(source, &[&BUGGY]).unwrap();
+    let mut vm = VirtualMachine::with_options(&bytecode, VmOptions::new().error_tolerant());
+    vm.set_extension_handler(&BUGGY, buggy);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| vm.run()));
+    assert!(result.is_err());
+    assert!(vm.recovered_errors().is_empty());
+}
@@ -0,0 +1,40 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use zircon::{assemble, Executor, Value, VirtualMachine, VmOptions};
+
+// A backend that counts the instructions it hands to the interpreter.
+#[derive(Debug, Default)]
+struct Counting {
+    steps: Rc<Cell<u64>>,
+}
+
+impl Executor for Counting {
+    fn execute(&self, vm: &mut VirtualMachine, base_depth: usize) {
+        while vm.call_depth() > base_depth && vm.is_running() {
+            self.steps.set(self.steps.get() + 1);
+            vm.step();
+        }
+    }
+}
+
+// Another backend runs the same instructions to the same result.
+#[test]
+fn backends_run_the_same_program() {
+    let source = include_str!("programs/recursion.zasm");
+    let bytecode = assemble(source).unwrap();
+    let mut interpreter = VirtualMachine::new(&bytecode);
+    interpreter.enable_step_trace();
+    let expected = interpreter.call_function(1, vec![Value::Number(10.0)]);
+
+    let backend = Counting::default();
+    let steps = Rc::clone(&backend.steps);
+    let mut vm = VirtualMachine::with_options(&bytecode, VmOptions::new().backend(backend));
+    vm.enable_step_trace();
+    assert_eq!(vm.call_function(1, vec![Value::Number(10.0)]), expected);
+    assert_eq!(vm.step_trace(), interpreter.step_trace());
+    assert_eq!(
+        steps.get() as usize,
+        vm.step_trace().unwrap().lines().count()
+    );
+}
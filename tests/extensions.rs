@@ -0,0 +1,51 @@
+use std::panic;
+
+use zircon::{
+    assemble_with_extensions, Bytecode, Extension, LoadOptions, OperandKind, RuntimeError, Value,
+    VirtualMachine,
+};
+
+static SCALE: Extension = Extension::new(0xE0, "scale", OperandKind::Count, 1, 1);
+
+// Multiplies a number by the instruction's operand.
+fn scale(_: &mut VirtualMachine, operand: Option<u16>, args: &[Value]) -> Vec<Value> {
+    match (operand, &args[0]) {
+        (Some(factor), Value::Number(n)) => vec![Value::Number(n * f64::from(factor))],
+        _ => panic::panic_any(RuntimeError("Invalid operand type for scale.".to_string())),
+    }
+}
+
+const SOURCE: &str = r#"
+    .func main 0
+        push_const 7
+        scale 6
+        return
+    .end
+"#;
+
+// An extension instruction assembles by name, survives a round trip through
+// the file format, and runs the handler the embedder sets.
+#[test]
+fn extension_instructions_run_their_handler() {
+    let bytecode = assemble_with_extensions(SOURCE, &[&SCALE]).unwrap();
+    let mut bytes = Vec::new();
+    bytecode.write(&mut bytes).unwrap();
+    let options = LoadOptions::new().extension(&SCALE);
+    let loaded = Bytecode::from_bytes_with_options(&bytes, &options).unwrap();
+
+    let mut vm = VirtualMachine::new(&loaded);
+    vm.set_extension_handler(&SCALE, scale);
+    assert_eq!(vm.call_function(0, Vec::new()), Value::Number(42.0));
+}
+
+// A program that uses an extension the loader wasn't given doesn't load.
+#[test]
+fn unknown_extensions_are_rejected() {
+    let bytecode = assemble_with_extensions(SOURCE, &[&SCALE]).unwrap();
+    let mut bytes = Vec::new();
+    bytecode.write(&mut bytes).unwrap();
+    match Bytecode::from_bytes(&bytes) {
+        Ok(_) => panic!("Loaded a program with an unknown extension."),
+        Err(e) => assert_eq!(e.to_string(), "Unknown opcode"),
+    }
+}
@@ -0,0 +1,74 @@
+use std::fs;
+use std::path::Path;
+
+use zircon::{assemble, format_assembly, Bytecode};
+
+#[test]
+fn assembly_is_laid_out_canonically() {
+    let source = "\
+; Counts down.
+.func main   ; entry
+  push_const 3
+     set_local 0
+top: get_local 0
+print   ; show it
+
+
+  dec_local 0
+  get_local 0
+  push_const 0
+  jump_if_greater   top
+  halt
+.end
+.func other 1 \"x\"
+  return
+.end
+";
+    let expected = "\
+; Counts down.
+.func main ; entry
+    push_const      3
+    set_local       0
+top:
+    get_local       0
+    print ; show it
+
+    dec_local       0
+    get_local       0
+    push_const      0
+    jump_if_greater top
+    halt
+.end
+
+.func other 1 \"x\"
+    return
+.end
+";
+    assert_eq!(format_assembly(source).unwrap(), expected);
+}
+
+fn bytes(bytecode: &Bytecode) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytecode.write(&mut bytes).unwrap();
+    bytes
+}
+
+// Formatting the test programs changes none of them, and formatting again
+// changes nothing more.
+#[test]
+fn formatting_keeps_the_program() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/programs");
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_none_or(|e| e != "zasm") {
+            continue;
+        }
+        let source = fs::read_to_string(&path).unwrap();
+        let formatted = format_assembly(&source).unwrap();
+        assert_eq!(format_assembly(&formatted).unwrap(), formatted);
+        if let Ok(bytecode) = assemble(&source) {
+            let reformatted = assemble(&formatted).unwrap();
+            assert_eq!(bytes(&reformatted), bytes(&bytecode), "{}", path.display());
+        }
+    }
+}
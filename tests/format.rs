@@ -0,0 +1,451 @@
+use proptest::prelude::*;
+
+use zircon::{Bytecode, Instruction, Opcode};
+
+// A program described just closely enough to write it in the file format.
+// Every one the strategies produce should load.
+#[derive(Debug, Clone)]
+enum Constant {
+    Number(f64),
+    Boolean(bool),
+    Str(String),
+    Char(char),
+    Tuple(Vec<Constant>),
+}
+
+#[derive(Debug, Clone)]
+struct Header {
+    num_args: usize,
+    num_locals: usize,
+    defaults: Vec<u16>,
+}
+
+// A run of instructions that leaves the stack as it found it, so that every
+// statement starts at depth zero and any of them can be a jump target.
+#[derive(Debug, Clone)]
+enum Statement {
+    Print(u16),
+    Store(u16, u16),
+    Load(u16),
+    Add(u16, u16),
+    Tuple(Vec<u16>),
+    Call(usize, usize),
+    Defer(usize, usize),
+    Jump(usize),
+    Branch(u16, usize, bool),
+}
+
+#[derive(Debug, Clone)]
+struct Program {
+    constants: Vec<Constant>,
+    functions: Vec<(Header, Vec<Statement>, bool)>,
+    sections: Vec<(u8, Vec<u8>)>,
+}
+
+fn constant() -> impl Strategy<Value = Constant> {
+    let leaf = prop_oneof![
+        any::<f64>().prop_map(Constant::Number),
+        any::<bool>().prop_map(Constant::Boolean),
+        ".{0,12}".prop_map(Constant::Str),
+        any::<char>().prop_map(Constant::Char),
+    ];
+    leaf.prop_recursive(3, 16, 4, |inner| {
+        prop::collection::vec(inner, 0..4).prop_map(Constant::Tuple)
+    })
+}
+
+fn header(constants: usize) -> impl Strategy<Value = Header> {
+    (0usize..4, 0usize..4).prop_flat_map(move |(num_args, extra)| {
+        let index = 0..constants as u16;
+        prop::collection::vec(index, 0..=num_args).prop_map(move |defaults| Header {
+            num_args,
+            num_locals: num_args + extra,
+            defaults,
+        })
+    })
+}
+
+// Statement operands are drawn as raw numbers and brought into range when the
+// program is encoded, since the valid range depends on the function.
+fn statement() -> impl Strategy<Value = Statement> {
+    let n = any::<u16>();
+    let i = any::<usize>();
+    prop_oneof![
+        n.prop_map(Statement::Print),
+        (n, n).prop_map(|(c, l)| Statement::Store(c, l)),
+        n.prop_map(Statement::Load),
+        (n, n).prop_map(|(a, b)| Statement::Add(a, b)),
+        prop::collection::vec(n, 0..4).prop_map(Statement::Tuple),
+        (i, i).prop_map(|(f, c)| Statement::Call(f, c)),
+        (i, i).prop_map(|(f, c)| Statement::Defer(f, c)),
+        i.prop_map(Statement::Jump),
+        (n, i, any::<bool>()).prop_map(|(c, t, b)| Statement::Branch(c, t, b)),
+    ]
+}
+
+fn program() -> impl Strategy<Value = Program> {
+    prop::collection::vec(constant(), 1..8).prop_flat_map(|constants| {
+        let count = constants.len();
+        let function = (
+            header(count),
+            prop::collection::vec(statement(), 0..12),
+            any::<bool>(),
+        );
+        // IDs 1 and 2 are the constants and functions.
+        let section = (3u8.., prop::collection::vec(any::<u8>(), 0..16));
+        (
+            prop::collection::vec(function, 1..5),
+            prop::collection::vec(section, 0..3),
+        )
+            .prop_map(move |(functions, sections)| Program {
+                constants: constants.clone(),
+                functions,
+                sections,
+            })
+    })
+}
+
+// The byte order of the numbers in a file.
+#[derive(Clone, Copy)]
+enum Order {
+    Little,
+    Big,
+}
+
+impl Order {
+    fn u16(self, n: u16) -> [u8; 2] {
+        match self {
+            Order::Little => n.to_le_bytes(),
+            Order::Big => n.to_be_bytes(),
+        }
+    }
+
+    fn u32(self, n: u32) -> [u8; 4] {
+        match self {
+            Order::Little => n.to_le_bytes(),
+            Order::Big => n.to_be_bytes(),
+        }
+    }
+
+    fn f64(self, n: f64) -> [u8; 8] {
+        match self {
+            Order::Little => n.to_le_bytes(),
+            Order::Big => n.to_be_bytes(),
+        }
+    }
+}
+
+fn write_constant(out: &mut Vec<u8>, constant: &Constant, order: Order) {
+    match constant {
+        Constant::Number(n) => {
+            out.push(0x01);
+            out.extend(order.f64(*n));
+        }
+        Constant::Boolean(b) => out.extend([0x02, *b as u8]),
+        Constant::Str(s) => {
+            out.push(0x03);
+            out.extend(order.u16(s.len() as u16));
+            out.extend(s.as_bytes());
+        }
+        Constant::Char(c) => {
+            out.push(0x06);
+            out.extend(order.u32(*c as u32));
+        }
+        Constant::Tuple(elements) => {
+            out.push(0x07);
+            out.extend(order.u16(elements.len() as u16));
+            for element in elements {
+                write_constant(out, element, order);
+            }
+        }
+    }
+}
+
+fn op(opcode: Opcode, operand: usize) -> Instruction {
+    Instruction::new(opcode, Some(operand as u16))
+}
+
+fn bare(opcode: Opcode) -> Instruction {
+    Instruction::new(opcode, None)
+}
+
+fn encode_function(
+    program: &Program,
+    header: &Header,
+    statements: &[Statement],
+    halts: bool,
+) -> Vec<Instruction> {
+    let constants = program.constants.len();
+    let constant = |c: u16| op(Opcode::PushConst, usize::from(c) % constants);
+    // Each statement's instructions, with jump targets still given as
+    // statement numbers. The final statement returns or halts.
+    let targets = statements.len() + 1;
+    let mut blocks: Vec<Vec<(Instruction, Option<usize>)>> = Vec::new();
+    for statement in statements {
+        let mut block = Vec::new();
+        match statement {
+            Statement::Print(c) => {
+                block.push((constant(*c), None));
+                block.push((bare(Opcode::Print), None));
+            }
+            // A function without locals prints the value instead.
+            Statement::Store(c, _) | Statement::Load(c) if header.num_locals == 0 => {
+                block.push((constant(*c), None));
+                block.push((bare(Opcode::Print), None));
+            }
+            Statement::Store(c, l) => {
+                block.push((constant(*c), None));
+                let slot = usize::from(*l) % header.num_locals;
+                block.push((op(Opcode::SetLocal, slot), None));
+            }
+            Statement::Load(l) => {
+                let slot = usize::from(*l) % header.num_locals;
+                block.push((op(Opcode::GetLocal, slot), None));
+                block.push((bare(Opcode::Print), None));
+            }
+            Statement::Add(a, b) => {
+                block.push((constant(*a), None));
+                block.push((constant(*b), None));
+                block.push((bare(Opcode::Add), None));
+                block.push((bare(Opcode::Print), None));
+            }
+            Statement::Tuple(elements) => {
+                for &c in elements {
+                    block.push((constant(c), None));
+                }
+                block.push((op(Opcode::MakeTuple, elements.len()), None));
+                block.push((bare(Opcode::Print), None));
+            }
+            Statement::Call(f, c) | Statement::Defer(f, c) => {
+                let index = f % program.functions.len();
+                let callee = &program.functions[index].0;
+                let required = callee.num_args - callee.defaults.len();
+                let count = required + c % (callee.defaults.len() + 1);
+                for _ in 0..count {
+                    block.push((constant(0), None));
+                }
+                if count != callee.num_args {
+                    block.push((op(Opcode::ArgCount, count), None));
+                }
+                if let Statement::Call(_, _) = statement {
+                    block.push((op(Opcode::Call, index), None));
+                    block.push((bare(Opcode::Print), None));
+                } else {
+                    block.push((op(Opcode::Defer, index), None));
+                }
+            }
+            Statement::Jump(t) => block.push((op(Opcode::Jump, 0), Some(t % targets))),
+            Statement::Branch(c, t, b) => {
+                block.push((constant(*c), None));
+                let opcode = if *b {
+                    Opcode::JumpIfTrue
+                } else {
+                    Opcode::JumpIfFalse
+                };
+                block.push((op(opcode, 0), Some(t % targets)));
+            }
+        }
+        blocks.push(block);
+    }
+    if halts {
+        blocks.push(vec![(bare(Opcode::Halt), None)]);
+    } else {
+        blocks.push(vec![(constant(0), None), (bare(Opcode::Return), None)]);
+    }
+
+    let mut starts = Vec::new();
+    let mut ip = 0;
+    for block in &blocks {
+        starts.push(ip);
+        ip += block.len();
+    }
+    blocks
+        .into_iter()
+        .flatten()
+        .map(|(instruction, target)| match target {
+            Some(target) => op(instruction.opcode(), starts[target]),
+            None => instruction,
+        })
+        .collect()
+}
+
+fn constants(program: &Program, order: Order) -> Vec<u8> {
+    let mut out = order.u32(program.constants.len() as u32).to_vec();
+    for constant in &program.constants {
+        write_constant(&mut out, constant, order);
+    }
+    out
+}
+
+fn functions(program: &Program, order: Order) -> Vec<u8> {
+    let mut out = order.u32(program.functions.len() as u32).to_vec();
+    for (header, statements, halts) in &program.functions {
+        let instructions = encode_function(program, header, statements, *halts);
+        out.extend(order.u32(instructions.len() as u32));
+        out.extend(order.u32(header.num_args as u32));
+        out.extend(order.u32(header.num_locals as u32));
+        out.extend(order.u16(header.defaults.len() as u16));
+        for &default in &header.defaults {
+            out.extend(order.u16(default));
+        }
+        for instruction in instructions {
+            match order {
+                Order::Little => instruction.encode(&mut out).unwrap(),
+                Order::Big => {
+                    out.push(instruction.opcode().to_u8());
+                    if let Some(operand) = instruction.optional_operand() {
+                        out.extend(order.u16(operand));
+                    }
+                }
+            }
+        }
+    }
+    out
+}
+
+// Version 6: the byte order, a section table, then the contents in the same
+// order, each starting at a multiple of 8.
+fn encode_in(program: &Program, order: Order) -> Vec<u8> {
+    let mut sections = vec![
+        (0x01, constants(program, order)),
+        (0x02, functions(program, order)),
+    ];
+    sections.extend(program.sections.iter().cloned());
+    let mut out = b"ZRCN".to_vec();
+    out.push(6);
+    out.push(match order {
+        Order::Little => 0x01,
+        Order::Big => 0x02,
+    });
+    out.extend(order.u16(sections.len() as u16));
+    let mut offset = 8 + 9 * sections.len();
+    let mut offsets = Vec::new();
+    for (id, contents) in &sections {
+        offset = offset.next_multiple_of(8);
+        offsets.push(offset);
+        out.push(*id);
+        out.extend(order.u32(offset as u32));
+        out.extend(order.u32(contents.len() as u32));
+        offset += contents.len();
+    }
+    for ((_, contents), offset) in sections.iter().zip(offsets) {
+        out.resize(offset, 0);
+        out.extend(contents);
+    }
+    out
+}
+
+fn encode(program: &Program) -> Vec<u8> {
+    encode_in(program, Order::Little)
+}
+
+// Version 4: the constants and functions, then the other sections.
+fn encode_version_4(program: &Program) -> Vec<u8> {
+    let mut out = b"ZRCN".to_vec();
+    out.push(4);
+    out.extend(constants(program, Order::Little));
+    out.extend(functions(program, Order::Little));
+    out.extend((program.sections.len() as u16).to_le_bytes());
+    for (id, contents) in &program.sections {
+        out.push(*id);
+        out.extend((contents.len() as u32).to_le_bytes());
+        out.extend(contents);
+    }
+    out
+}
+
+fn write(bytecode: &Bytecode) -> Vec<u8> {
+    let mut out = Vec::new();
+    bytecode.write(&mut out).unwrap();
+    out
+}
+
+proptest! {
+    #[test]
+    fn generated_programs_load(program in program()) {
+        let bytes = encode(&program);
+        if let Err(e) = Bytecode::read(&mut bytes.as_slice()) {
+            prop_assert!(false, "{}", e);
+        }
+    }
+
+    #[test]
+    fn writing_reproduces_the_bytes_read(program in program()) {
+        let bytes = encode(&program);
+        let bytecode = Bytecode::read(&mut bytes.as_slice()).unwrap();
+        prop_assert_eq!(write(&bytecode), bytes);
+    }
+
+    #[test]
+    fn loading_from_memory_matches_reading(program in program()) {
+        let bytes = encode(&program);
+        let bytecode = Bytecode::from_bytes(&bytes).unwrap();
+        prop_assert_eq!(write(&bytecode), bytes);
+    }
+
+    #[test]
+    fn written_programs_load_again(program in program()) {
+        let bytes = encode(&program);
+        let bytecode = Bytecode::read(&mut bytes.as_slice()).unwrap();
+        let written = write(&bytecode);
+        let reloaded = Bytecode::read(&mut written.as_slice()).unwrap();
+        prop_assert_eq!(write(&reloaded), written);
+    }
+
+    #[test]
+    fn version_4_programs_load_the_same(program in program()) {
+        let bytes = encode_version_4(&program);
+        let bytecode = Bytecode::read(&mut bytes.as_slice()).unwrap();
+        prop_assert_eq!(write(&bytecode), encode(&program));
+    }
+
+    #[test]
+    fn programs_convert_to_version_4(program in program()) {
+        let bytes = encode(&program);
+        let bytecode = Bytecode::read(&mut bytes.as_slice()).unwrap();
+        let mut converted = Vec::new();
+        bytecode.write_version(&mut converted, 4).unwrap();
+        prop_assert_eq!(converted, encode_version_4(&program));
+    }
+
+    #[test]
+    fn converted_programs_load_the_same(program in program(), version in 1u8..=6) {
+        let bytes = encode(&program);
+        let bytecode = Bytecode::read(&mut bytes.as_slice()).unwrap();
+        let mut converted = Vec::new();
+        match bytecode.write_version(&mut converted, version) {
+            Ok(()) => {
+                let reloaded = Bytecode::read(&mut converted.as_slice()).unwrap();
+                // Before version 3, the local counts are worked out again.
+                if version >= 3 {
+                    prop_assert_eq!(write(&reloaded), bytes);
+                }
+            }
+            // Only a version that can't hold the program refuses it.
+            Err(_) => prop_assert!(version < 4),
+        }
+    }
+
+    #[test]
+    fn big_endian_programs_load_the_same(program in program()) {
+        let bytes = encode_in(&program, Order::Big);
+        let bytecode = Bytecode::read(&mut bytes.as_slice()).unwrap();
+        prop_assert_eq!(write(&bytecode), encode(&program));
+        let bytecode = Bytecode::from_bytes(&bytes).unwrap();
+        prop_assert_eq!(write(&bytecode), encode(&program));
+    }
+
+    #[test]
+    fn unknown_sections_are_kept(program in program()) {
+        let bytes = encode(&program);
+        let bytecode = Bytecode::read(&mut bytes.as_slice()).unwrap();
+        prop_assert_eq!(bytecode.unknown_sections(), &program.sections[..]);
+    }
+
+    #[test]
+    fn truncated_programs_are_rejected(program in program(), cut in any::<prop::sample::Index>()) {
+        let bytes = encode(&program);
+        let len = cut.index(bytes.len());
+        prop_assert!(Bytecode::read(&mut &bytes[..len]).is_err());
+    }
+}
@@ -0,0 +1,55 @@
+use std::panic::{self, AssertUnwindSafe};
+
+use zircon::{assemble, Value, VirtualMachine};
+
+const PROGRAM: &str = r#"
+.func main
+    halt
+.end
+
+.func make 1
+    get_local 0
+    push_const "!"
+    make_tuple 2
+    return
+.end
+"#;
+
+// A value returned from one call is retained across others, shows up as a
+// root of a heap dump, and can't be used once it is dropped.
+#[test]
+fn handles_retain_values_across_calls() {
+    let bytecode = assemble(PROGRAM).unwrap();
+    let mut vm = VirtualMachine::new(&bytecode);
+    let first = vm.call_function(1, vec![Value::Number(1.0)]);
+    let handle = vm.create_handle(first);
+    let second = vm.call_function(1, vec![Value::Number(2.0)]);
+    let other = vm.create_handle(second);
+    assert_eq!(vm.handle_value(&handle).to_string(), "(1, !)");
+    assert_eq!(vm.handle_count(), 2);
+
+    let mut dump = Vec::new();
+    vm.dump_heap(&mut dump).unwrap();
+    let dump = String::from_utf8(dump).unwrap();
+    assert!(dump.contains(r#""kind": "handle", "index": 0"#));
+    assert!(dump.contains(r#""kind": "handle", "index": 1"#));
+
+    let value = vm.drop_handle(handle);
+    assert_eq!(value.to_string(), "(1, !)");
+    assert_eq!(vm.handle_count(), 1);
+    let reused = vm.create_handle(Value::Nil);
+    assert_eq!(vm.handle_value(&reused), &Value::Nil);
+    assert_eq!(vm.handle_value(&other).to_string(), "(2, !)");
+}
+
+// Handles aren't shared between VMs.
+#[test]
+fn handles_belong_to_their_vm() {
+    let bytecode = assemble(PROGRAM).unwrap();
+    let mut vm = VirtualMachine::new(&bytecode);
+    let handle = vm.create_handle(Value::Number(1.0));
+    let other = VirtualMachine::new(&bytecode);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| other.handle_value(&handle).clone()));
+    assert!(result.is_err());
+    assert_eq!(vm.drop_handle(handle), Value::Number(1.0));
+}
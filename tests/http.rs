@@ -0,0 +1,146 @@
+#![cfg(feature = "http")]
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::rc::Rc;
+use std::thread::{self, JoinHandle};
+
+use zircon::{assemble, Capability, Value, VirtualMachine};
+
+const PROGRAM: &str = r#"
+.func main
+    halt
+.end
+
+.func get 1
+    get_local 0
+    call_native http_get
+    return
+.end
+
+.func post 3
+    get_local 0
+    get_local 1
+    get_local 2
+    call_native http_post
+    return
+.end
+"#;
+
+const GET: usize = 1;
+const POST: usize = 2;
+
+// Answers one request on a local port with `response`, returning the URL to
+// request and the thread, which yields the request it read.
+fn serve(response: Vec<u8>) -> (String, JoinHandle<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/path", listener.local_addr().unwrap());
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut request = String::new();
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if let Some(value) = line.strip_prefix("Content-Length: ") {
+                length = value.trim().parse().unwrap();
+            }
+            request.push_str(&line);
+            if line == "\r\n" {
+                break;
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        request.push_str(&String::from_utf8(body).unwrap());
+        // The client stops reading a response that is too large.
+        let _ = reader.get_mut().write_all(&response);
+        request
+    });
+    (url, server)
+}
+
+fn call(function: usize, args: Vec<Value>) -> Value {
+    let bytecode = assemble(PROGRAM).unwrap();
+    let mut vm = VirtualMachine::new(&bytecode);
+    vm.grant(Capability::Net);
+    vm.call_function(function, args)
+}
+
+fn string(s: &str) -> Value {
+    Value::Str(s.into())
+}
+
+fn header(name: &str, value: &str) -> Value {
+    Value::Array(Rc::new(vec![string(name), string(value)]))
+}
+
+#[test]
+fn gets_a_response() {
+    let response = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello".to_vec();
+    let (url, server) = serve(response);
+    let result = call(GET, vec![string(&url)]);
+    let expected = Value::Tuple(Rc::from([
+        Value::Number(200.0),
+        Value::Array(Rc::new(vec![header("content-length", "5")])),
+        string("hello"),
+    ]));
+    assert_eq!(result, expected);
+    assert!(server.join().unwrap().starts_with("GET /path HTTP/1.1\r\n"));
+}
+
+#[test]
+fn posts_a_body() {
+    let response = b"HTTP/1.1 201 Created\r\nContent-Length: 0\r\n\r\n".to_vec();
+    let (url, server) = serve(response);
+    let args = vec![string(&url), string("text/plain"), string("data")];
+    match call(POST, args) {
+        Value::Tuple(response) => assert_eq!(response[0], Value::Number(201.0)),
+        other => panic!("expected a response, got {}", other),
+    }
+    let request = server.join().unwrap();
+    assert!(request.starts_with("POST /path HTTP/1.1\r\n"));
+    assert!(request.contains("Content-Type: text/plain\r\n"));
+    assert!(request.ends_with("\r\n\r\ndata"));
+}
+
+#[test]
+fn refuses_responses_that_are_too_large() {
+    let mut response = b"HTTP/1.1 200 OK\r\n\r\n".to_vec();
+    response.resize(17 * 1024 * 1024, b'x');
+    let (url, server) = serve(response);
+    let result = call(GET, vec![string(&url)]);
+    let expected = Value::Error(Rc::new(string("HTTP response is too large")));
+    assert_eq!(result, expected);
+    server.join().unwrap();
+}
+
+fn body(response: &[u8]) -> Value {
+    let (url, server) = serve(response.to_vec());
+    let result = call(GET, vec![string(&url)]);
+    server.join().unwrap();
+    match result {
+        Value::Tuple(response) => response[2].clone(),
+        other => other,
+    }
+}
+
+// Chunked wins over Content-Length when it is the last transfer coding.
+#[test]
+fn chunked_bodies_ignore_content_length() {
+    let chunked =
+        b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\n";
+    assert_eq!(body(chunked), string("hello"));
+    let coded = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: gzip, chunked\r\n\r\n3\r\nabc\r\n0\r\n\r\n";
+    assert_eq!(body(coded), string("abc"));
+}
+
+#[test]
+fn content_lengths_must_agree() {
+    let repeated = b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\nContent-Length: 3\r\n\r\nabcdef";
+    assert_eq!(body(repeated), string("abc"));
+    let conflicting = b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\nContent-Length: 5\r\n\r\nabcdef";
+    let malformed = Value::Error(Rc::new(string("Malformed HTTP response")));
+    assert_eq!(body(conflicting), malformed);
+}
@@ -0,0 +1,72 @@
+use std::panic::{self, AssertUnwindSafe};
+
+use zircon::{assemble, Bytecode, RuntimeError, Value, VirtualMachine};
+
+const PROGRAM: &str = r#"
+.func main
+    halt
+.end
+
+.func hypotenuse 2
+    get_local 0
+    push_const 2
+    call_native pow
+    get_local 1
+    push_const 2
+    call_native pow
+    add
+    call_native sqrt
+    return
+.end
+"#;
+
+// Natives are looked up once when bytecode loads, and in-memory bytecode
+// looks them up by name as it runs, with the same results.
+#[test]
+fn natives_are_called_in_loaded_and_assembled_bytecode() {
+    let assembled = assemble(PROGRAM).unwrap();
+    let mut bytes = Vec::new();
+    assembled.write(&mut bytes).unwrap();
+    let loaded = Bytecode::read(&mut bytes.as_slice()).unwrap();
+    for bytecode in [&assembled, &loaded] {
+        let mut vm = VirtualMachine::new(bytecode);
+        let args = vec![Value::Number(3.0), Value::Number(4.0)];
+        assert_eq!(vm.call_function(1, args), Value::Number(5.0));
+    }
+}
+
+// Local slots are 16-bit, so a larger count in a file is refused when it
+// loads instead of allocating that many locals on the first call.
+#[test]
+fn huge_local_counts_are_refused() {
+    let mut bytes = Vec::new();
+    assemble(PROGRAM).unwrap().write(&mut bytes).unwrap();
+    // The header of `hypotenuse`: 9 instructions, 2 arguments, and 2 locals.
+    let header = [9u32, 2, 2].map(u32::to_le_bytes).concat();
+    let at = bytes
+        .windows(header.len())
+        .position(|window| window == header)
+        .unwrap();
+    bytes[at + 8..at + 12].copy_from_slice(&u32::MAX.to_le_bytes());
+    let error = Bytecode::read(&mut bytes.as_slice()).err().unwrap();
+    assert_eq!(error.to_string(), "Functions have at most 65536 locals");
+
+    bytes[at + 8..at + 12].copy_from_slice(&65536u32.to_le_bytes());
+    assert!(Bytecode::read(&mut bytes.as_slice()).is_ok());
+}
+
+// Running a program starts at function 0, so a file without one doesn't
+// load, and running one built in memory is a runtime error.
+#[test]
+fn programs_without_functions_are_refused() {
+    let empty = assemble("").unwrap();
+    let mut bytes = Vec::new();
+    empty.write(&mut bytes).unwrap();
+    let error = Bytecode::read(&mut bytes.as_slice()).err().unwrap();
+    assert_eq!(error.to_string(), "Program has no functions");
+
+    let mut vm = VirtualMachine::new(&empty);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| vm.run()));
+    let error = result.unwrap_err().downcast::<RuntimeError>().unwrap();
+    assert_eq!(error.0, "Entry function 0 does not exist.");
+}
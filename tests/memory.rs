@@ -0,0 +1,52 @@
+use zircon::{assemble, VirtualMachine};
+
+// A program that builds a chain of tuples shows the chain growing from one
+// sample to the next, with a frame for `main` throughout.
+#[test]
+fn memory_profile_tracks_live_values() {
+    let bytecode = assemble(
+        r#"
+        .func main
+            push_const 0
+            set_local 0
+            push_const "start"
+            set_local 1
+        loop:
+            get_local 1
+            get_local 0
+            make_tuple 2
+            set_local 1
+            inc_local 0
+            get_local 0
+            push_const 100
+            jump_if_less loop
+            halt
+        .end
+        "#,
+    )
+    .unwrap();
+    let mut vm = VirtualMachine::new(&bytecode);
+    vm.enable_memory_profile(70);
+    vm.run();
+
+    let profile = vm.memory_profile().unwrap();
+    assert_eq!(profile.samples().len(), 11);
+    let tuples: Vec<usize> = profile
+        .samples()
+        .iter()
+        .map(|sample| {
+            let tuple = sample.kinds.iter().find(|usage| usage.kind == "tuple");
+            tuple.map_or(0, |usage| usage.count)
+        })
+        .collect();
+    assert!(
+        tuples.windows(2).all(|pair| pair[0] < pair[1]),
+        "{:?}",
+        tuples
+    );
+    let peaks = profile.peaks();
+    let kinds: Vec<&str> = peaks.iter().map(|usage| usage.kind).collect();
+    assert_eq!(kinds, ["frame", "string", "tuple"]);
+    assert_eq!(peaks[0].count, 1);
+    assert_eq!(peaks[2].count, *tuples.last().unwrap());
+}
@@ -0,0 +1,166 @@
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+
+use zircon::{assemble, Bytecode, Capability, RuntimeError, Value, VirtualMachine};
+
+const PROGRAM: &str = r#"
+.func main
+    halt
+.end
+
+.func connect 1
+    get_local 0
+    call_native tcp_connect
+    return
+.end
+
+.func listen 1
+    get_local 0
+    call_native tcp_listen
+    return
+.end
+
+.func accept 1
+    get_local 0
+    call_native tcp_accept
+    return
+.end
+
+.func read 2
+    get_local 0
+    get_local 1
+    call_native tcp_read
+    return
+.end
+
+.func write 2
+    get_local 0
+    get_local 1
+    call_native tcp_write
+    return
+.end
+
+.func set_timeout 2
+    get_local 0
+    get_local 1
+    call_native tcp_set_timeout
+    return
+.end
+
+.func local_address 1
+    get_local 0
+    call_native tcp_local_address
+    return
+.end
+"#;
+
+const CONNECT: usize = 1;
+const LISTEN: usize = 2;
+const ACCEPT: usize = 3;
+const READ: usize = 4;
+const WRITE: usize = 5;
+const SET_TIMEOUT: usize = 6;
+const LOCAL_ADDRESS: usize = 7;
+
+fn networked(bytecode: &Bytecode) -> VirtualMachine<'_> {
+    let mut vm = VirtualMachine::new(bytecode);
+    vm.grant(Capability::Net);
+    vm
+}
+
+fn string(s: &str) -> Value {
+    Value::Str(s.into())
+}
+
+fn timed_out() -> Value {
+    Value::Error(Rc::new(string("Timed out")))
+}
+
+#[test]
+fn connects_reads_and_writes() {
+    let bytecode = assemble(PROGRAM).unwrap();
+    let mut vm = networked(&bytecode);
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+
+    let socket = vm.call_function(CONNECT, vec![string(&address)]);
+    assert!(matches!(socket, Value::Socket(_)));
+    let (mut peer, _) = listener.accept().unwrap();
+
+    peer.write_all(b"ping").unwrap();
+    let read = vm.call_function(READ, vec![socket.clone(), Value::Number(16.0)]);
+    assert_eq!(read, Value::Bytes(b"ping"[..].into()));
+
+    let written = vm.call_function(WRITE, vec![socket, string("pong")]);
+    assert_eq!(written, Value::Nil);
+    let mut buffer = [0; 4];
+    peer.read_exact(&mut buffer).unwrap();
+    assert_eq!(&buffer, b"pong");
+}
+
+// A read asking for more than the runtime reads at once still returns what
+// has arrived, without allocating the whole count.
+#[test]
+fn large_reads_are_capped() {
+    let bytecode = assemble(PROGRAM).unwrap();
+    let mut vm = networked(&bytecode);
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let socket = vm.call_function(CONNECT, vec![string(&address)]);
+    let (mut peer, _) = listener.accept().unwrap();
+
+    peer.write_all(b"data").unwrap();
+    let read = vm.call_function(READ, vec![socket, Value::Number(1e15)]);
+    assert_eq!(read, Value::Bytes(b"data"[..].into()));
+}
+
+#[test]
+fn accepts_connections() {
+    let bytecode = assemble(PROGRAM).unwrap();
+    let mut vm = networked(&bytecode);
+    let listener = vm.call_function(LISTEN, vec![string("127.0.0.1:0")]);
+    let address = match vm.call_function(LOCAL_ADDRESS, vec![listener.clone()]) {
+        Value::Str(address) => address.to_string(),
+        other => panic!("expected an address, got {}", other),
+    };
+    let mut peer = std::net::TcpStream::connect(address).unwrap();
+
+    let socket = vm.call_function(ACCEPT, vec![listener]);
+    peer.write_all(b"hello").unwrap();
+    let read = vm.call_function(READ, vec![socket, Value::Number(5.0)]);
+    assert_eq!(read, Value::Bytes(b"hello"[..].into()));
+}
+
+#[test]
+fn reads_and_accepts_time_out() {
+    let bytecode = assemble(PROGRAM).unwrap();
+    let mut vm = networked(&bytecode);
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let socket = vm.call_function(CONNECT, vec![string(&address)]);
+    let _peer = listener.accept().unwrap();
+
+    let timeout = Value::Number(0.05);
+    vm.call_function(SET_TIMEOUT, vec![socket.clone(), timeout.clone()]);
+    let read = vm.call_function(READ, vec![socket, Value::Number(1.0)]);
+    assert_eq!(read, timed_out());
+
+    let listener = vm.call_function(LISTEN, vec![string("127.0.0.1:0")]);
+    vm.call_function(SET_TIMEOUT, vec![listener.clone(), timeout]);
+    assert_eq!(vm.call_function(ACCEPT, vec![listener]), timed_out());
+}
+
+#[test]
+fn refuses_without_the_net_capability() {
+    let bytecode = assemble(PROGRAM).unwrap();
+    let mut vm = VirtualMachine::new(&bytecode);
+    let args = vec![string("127.0.0.1:1")];
+    let result = panic::catch_unwind(AssertUnwindSafe(|| vm.call_function(CONNECT, args)));
+    let error = result.unwrap_err().downcast::<RuntimeError>().unwrap();
+    assert_eq!(
+        error.0,
+        "Native function 'tcp_connect' requires the 'net' capability."
+    );
+}
@@ -0,0 +1,86 @@
+use zircon::{assemble, NumberEquality, Value, VirtualMachine, VmOptions};
+
+const PROGRAM: &str = r#"
+.func main
+    halt
+.end
+
+.func equal 2
+    get_local 0
+    get_local 1
+    equal
+    return
+.end
+
+.func equal_in_tuple 2
+    get_local 0
+    make_tuple 1
+    get_local 1
+    make_tuple 1
+    equal
+    return
+.end
+
+.func total_compare 2
+    get_local 0
+    get_local 1
+    total_compare
+    return
+.end
+
+.func not_equal 2
+    get_local 0
+    get_local 1
+    not_equal
+    return
+.end
+"#;
+
+fn call(equality: NumberEquality, function: usize, a: f64, b: f64) -> Value {
+    let bytecode = assemble(PROGRAM).unwrap();
+    let options = VmOptions::new().number_equality(equality);
+    let mut vm = VirtualMachine::with_options(&bytecode, options);
+    vm.call_function(function, vec![Value::Number(a), Value::Number(b)])
+}
+
+#[test]
+fn ieee_equality_is_the_default() {
+    let bytecode = assemble(PROGRAM).unwrap();
+    let mut vm = VirtualMachine::new(&bytecode);
+    let nan = vm.call_function(1, vec![Value::Number(f64::NAN), Value::Number(f64::NAN)]);
+    assert_eq!(nan, Value::Boolean(false));
+    let zero = vm.call_function(1, vec![Value::Number(0.0), Value::Number(-0.0)]);
+    assert_eq!(zero, Value::Boolean(true));
+}
+
+#[test]
+fn total_equality_follows_total_order() {
+    for function in [1, 2] {
+        let nan = call(NumberEquality::Total, function, f64::NAN, f64::NAN);
+        assert_eq!(nan, Value::Boolean(true));
+        let zero = call(NumberEquality::Total, function, 0.0, -0.0);
+        assert_eq!(zero, Value::Boolean(false));
+        let one = call(NumberEquality::Total, function, 1.0, 1.0);
+        assert_eq!(one, Value::Boolean(true));
+    }
+}
+
+#[test]
+fn total_compare_orders_every_number() {
+    let order = |a, b| call(NumberEquality::Ieee, 3, a, b);
+    assert_eq!(order(-0.0, 0.0), Value::Number(-1.0));
+    assert_eq!(order(f64::INFINITY, f64::NAN), Value::Number(-1.0));
+    assert_eq!(order(-f64::NAN, f64::NEG_INFINITY), Value::Number(-1.0));
+    assert_eq!(order(2.0, 1.0), Value::Number(1.0));
+    assert_eq!(order(f64::NAN, f64::NAN), Value::Number(0.0));
+}
+
+#[test]
+fn not_equal_follows_the_policy() {
+    let nan = |equality| call(equality, 4, f64::NAN, f64::NAN);
+    assert_eq!(nan(NumberEquality::Ieee), Value::Boolean(true));
+    assert_eq!(nan(NumberEquality::Total), Value::Boolean(false));
+    let zero = |equality| call(equality, 4, 0.0, -0.0);
+    assert_eq!(zero(NumberEquality::Ieee), Value::Boolean(false));
+    assert_eq!(zero(NumberEquality::Total), Value::Boolean(true));
+}
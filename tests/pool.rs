@@ -0,0 +1,70 @@
+use std::time::Duration;
+
+use zircon::{assemble, Job, JobError, VmPool};
+
+const PROGRAM: &str = r#"
+.func main
+    push_const 6
+    push_const 7
+    multiply
+    return
+.end
+
+.func spin
+top:
+    jump top
+.end
+
+.func fail
+    push_const 1
+    push_const true
+    add
+    return
+.end
+"#;
+
+fn pool() -> (VmPool, zircon::ProgramId) {
+    let mut bytes = Vec::new();
+    assemble(PROGRAM).unwrap().write(&mut bytes).unwrap();
+    let mut pool = VmPool::new(2);
+    let program = pool.add_program(&bytes).unwrap();
+    (pool, program)
+}
+
+#[test]
+fn jobs_return_their_results() {
+    let (pool, program) = pool();
+    let results: Vec<_> = (0..8).map(|_| pool.submit(Job::new(program))).collect();
+    for result in results {
+        let output = result.recv().unwrap().unwrap();
+        assert_eq!(output.value.kind, "number");
+        assert_eq!(output.value.text, "42");
+        assert_eq!(output.steps, 4);
+    }
+}
+
+#[test]
+fn jobs_stop_at_their_limits() {
+    let (pool, program) = pool();
+    let limited = pool.submit(Job::new(program).entry(1).step_limit(100));
+    let timed = pool.submit(
+        Job::new(program)
+            .entry(1)
+            .timeout(Duration::from_millis(20)),
+    );
+    let failed = pool.submit(Job::new(program).entry(2));
+    assert_eq!(
+        limited.recv().unwrap(),
+        Err(JobError::Failed(
+            "Step limit of 100 instructions exceeded.".to_string()
+        ))
+    );
+    assert_eq!(timed.recv().unwrap(), Err(JobError::TimedOut));
+    assert!(matches!(failed.recv().unwrap(), Err(JobError::Failed(_))));
+}
+
+#[test]
+fn invalid_programs_are_refused() {
+    let mut pool = VmPool::new(1);
+    assert!(pool.add_program(b"not bytecode").is_err());
+}
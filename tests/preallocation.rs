@@ -0,0 +1,60 @@
+use zircon::{assemble, Bytecode, Value, VirtualMachine, VmOptions};
+
+const PROGRAM: &str = r#"
+.func main
+    halt
+.end
+
+.func sum 1
+    get_local 0
+    push_const 0
+    jump_if_equal done
+    get_local 0
+    get_local 0
+    push_const 1
+    subtract
+    call sum
+    add
+    return
+done:
+    push_const 0
+    return
+.end
+"#;
+
+// Bytecode that went through a file, so the verifier has sized its stacks.
+fn loaded() -> Bytecode {
+    let mut bytes = Vec::new();
+    assemble(PROGRAM).unwrap().write(&mut bytes).unwrap();
+    Bytecode::read(&mut bytes.as_slice()).unwrap()
+}
+
+#[test]
+fn preallocation_changes_no_results() {
+    let bytecode = loaded();
+    for options in [
+        VmOptions::new(),
+        VmOptions::new().stack_capacity(0),
+        VmOptions::new().stack_capacity(64).call_depth(128),
+    ] {
+        let mut vm = VirtualMachine::with_options(&bytecode, options);
+        assert_eq!(
+            vm.call_function(1, vec![Value::Number(100.0)]),
+            Value::Number(5050.0)
+        );
+    }
+}
+
+#[test]
+fn stack_capacity_can_be_set_on_a_vm() {
+    let bytecode = assemble(PROGRAM).unwrap();
+    let mut vm = VirtualMachine::new(&bytecode);
+    assert_eq!(vm.stack_capacity(), None);
+    vm.set_stack_capacity(16);
+    vm.reserve_frames(8);
+    assert_eq!(vm.stack_capacity(), Some(16));
+    assert_eq!(
+        vm.call_function(1, vec![Value::Number(4.0)]),
+        Value::Number(10.0)
+    );
+}
@@ -0,0 +1,44 @@
+use std::rc::Rc;
+
+use zircon::Value;
+
+fn array(elements: Vec<Value>) -> Value {
+    Value::Array(Rc::new(elements))
+}
+
+#[test]
+fn short_values_stay_on_one_line() {
+    let value = array(vec![
+        Value::Number(1.0),
+        Value::Tuple(Rc::from([Value::Boolean(true)])),
+        array(vec![]),
+    ]);
+    assert_eq!(value.pretty(4), "[1, (true,), []]");
+}
+
+#[test]
+fn long_and_deep_values_are_laid_out_and_cut_short() {
+    let words = array((0..12).map(|_| Value::Str("sixteen".into())).collect());
+    let deep = array(vec![array(vec![array(vec![Value::Nil])])]);
+    let value = array(vec![words, deep, Value::Error(Rc::new(Value::Number(2.0)))]);
+    let expected = "\
+[
+  [
+    sixteen,
+    sixteen,
+    sixteen,
+    sixteen,
+    sixteen,
+    sixteen,
+    sixteen,
+    sixteen,
+    sixteen,
+    sixteen,
+    sixteen,
+    sixteen,
+  ],
+  [[...]],
+  error(2),
+]";
+    assert_eq!(value.pretty(2), expected);
+}
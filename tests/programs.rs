@@ -0,0 +1,105 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// Each case in tests/programs is an assembly (`.zasm`) or source (`.zr`) file,
+// or a directory of assembly files, next to a `.out` file holding what the
+// program prints. The case is built and run with the zircon binary, and its
+// standard output must match. Running with ZIRCON_BLESS=1 writes the actual
+// output to the `.out` files instead, for adding a case or accepting a change.
+#[test]
+fn programs() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/programs");
+    let build = Path::new(env!("CARGO_TARGET_TMPDIR")).join("programs");
+    fs::create_dir_all(&build).unwrap();
+    let bless = env::var_os("ZIRCON_BLESS").is_some();
+
+    let mut cases: Vec<PathBuf> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_dir() || matches!(extension(path), "zasm" | "zr"))
+        .collect();
+    cases.sort();
+    assert!(!cases.is_empty(), "No programs in {}", dir.display());
+
+    let mut failures = Vec::new();
+    for source in &cases {
+        let name = source.file_name().unwrap().to_string_lossy();
+        let bytecode = build.join(source.with_extension("zrc").file_name().unwrap());
+        let expected_path = source.with_extension("out");
+        let actual = match run(source, &bytecode) {
+            Ok(actual) => actual,
+            Err(e) => {
+                failures.push(format!("{}: {}", name, e));
+                continue;
+            }
+        };
+        if bless {
+            fs::write(&expected_path, &actual).unwrap();
+            continue;
+        }
+        match fs::read_to_string(&expected_path) {
+            Ok(expected) if expected == actual => {}
+            Ok(expected) => failures.push(format!("{}:\n{}", name, diff(&expected, &actual))),
+            Err(e) => failures.push(format!("{}: cannot read expected output: {}", name, e)),
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} of {} programs failed\n\n{}",
+        failures.len(),
+        cases.len(),
+        failures.join("\n\n")
+    );
+}
+
+fn extension(path: &Path) -> &str {
+    path.extension().and_then(|e| e.to_str()).unwrap_or("")
+}
+
+fn zircon(args: &[&Path]) -> Result<String, String> {
+    let output = Command::new(env!("CARGO_BIN_EXE_zircon"))
+        .args(args)
+        .output()
+        .map_err(|e| e.to_string())?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() || !stderr.is_empty() {
+        return Err(format!("{}\n{}", output.status, stderr.trim_end()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn run(source: &Path, bytecode: &Path) -> Result<String, String> {
+    let command = if source.is_dir() {
+        "build"
+    } else if extension(source) == "zr" {
+        "compile"
+    } else {
+        "assemble"
+    };
+    zircon(&[Path::new(command), source, Path::new("-o"), bytecode])?;
+    zircon(&[bytecode])
+}
+
+// A line-by-line comparison, marking expected lines with `-` and actual ones
+// with `+`.
+fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    let mut out = String::new();
+    for i in 0..expected.len().max(actual.len()) {
+        match (expected.get(i), actual.get(i)) {
+            (Some(e), Some(a)) if e == a => out.push_str(&format!("  {}\n", e)),
+            (e, a) => {
+                if let Some(e) = e {
+                    out.push_str(&format!("- {}\n", e));
+                }
+                if let Some(a) = a {
+                    out.push_str(&format!("+ {}\n", a));
+                }
+            }
+        }
+    }
+    out
+}
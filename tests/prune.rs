@@ -0,0 +1,74 @@
+use zircon::{assemble, prune, Value, VirtualMachine};
+
+// Functions nothing reaches are removed with the constants only they use, and
+// the functions that remain still call each other and read the right values.
+#[test]
+fn prune_removes_unreachable_functions() {
+    let mut bytecode = assemble(
+        r#"
+        .func main 0
+            push_const 2
+            call double
+            return
+        .end
+
+        .func unused 0
+            push_const "never"
+            call double
+            return
+        .end
+
+        .func double 1
+            get_local 0
+            add_local_const 0 10
+            get_local 0
+            push_const 2
+            multiply
+            return
+        .end
+
+        .func tool 0
+            push_const 21
+            call double
+            return
+        .end
+        "#,
+    )
+    .unwrap();
+
+    let report = prune(&mut bytecode, &[3]);
+    assert_eq!(report.functions, [1]);
+    assert_eq!(report.constants, [1]);
+    assert_eq!(
+        report.to_string(),
+        "removed function 1\nremoved constant 1\n"
+    );
+
+    let mut vm = VirtualMachine::new(&bytecode);
+    assert_eq!(vm.call_function(0, Vec::new()), Value::Number(24.0));
+    assert_eq!(vm.call_function(2, Vec::new()), Value::Number(62.0));
+}
+
+// A program that uses function indexes as values keeps every function.
+#[test]
+fn prune_keeps_functions_used_by_index() {
+    let mut bytecode = assemble(
+        r#"
+        .func main 0
+            push_const 1
+            call_native function_arity
+            return
+        .end
+
+        .func unused 0
+            push_const "unused"
+            return
+        .end
+        "#,
+    )
+    .unwrap();
+
+    let report = prune(&mut bytecode, &[]);
+    assert!(report.functions.is_empty() && report.constants.is_empty());
+    assert_eq!(report.kept_functions_for.as_deref(), Some("function_arity"));
+}
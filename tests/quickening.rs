@@ -0,0 +1,68 @@
+use zircon::{assemble, Value, VirtualMachine, VmOptions};
+
+const PROGRAM: &str = r#"
+.func main
+    halt
+.end
+
+.func add 2
+    get_local 0
+    get_local 1
+    add
+    return
+.end
+
+.func triangle 1
+    push_const 0
+    set_local 1
+top:
+    get_local 0
+    push_const 0
+    jump_if_equal done
+    get_local 1
+    get_local 0
+    add
+    set_local 1
+    get_local 0
+    push_const 1
+    subtract
+    set_local 0
+    jump top
+done:
+    get_local 1
+    return
+.end
+"#;
+
+fn quickening_vm(bytecode: &zircon::Bytecode) -> VirtualMachine<'_> {
+    VirtualMachine::with_options(bytecode, VmOptions::new().quickening())
+}
+
+#[test]
+fn arithmetic_on_numbers_is_rewritten_once() {
+    let bytecode = assemble(PROGRAM).unwrap();
+    let mut vm = quickening_vm(&bytecode);
+    assert_eq!(
+        vm.call_function(2, vec![Value::Number(100.0)]),
+        Value::Number(5050.0)
+    );
+    let quickening = vm.quickening().unwrap();
+    assert_eq!(quickening.quickened(), 2);
+    assert_eq!(quickening.deoptimized(), 0);
+    assert_eq!(quickening.specialized(), 2);
+}
+
+#[test]
+fn other_operands_rewrite_it_back() {
+    let bytecode = assemble(PROGRAM).unwrap();
+    let mut vm = quickening_vm(&bytecode);
+    let numbers = vec![Value::Number(1.0), Value::Number(2.0)];
+    assert_eq!(vm.call_function(1, numbers.clone()), Value::Number(3.0));
+    let durations = vec![Value::Duration(5), Value::Duration(7)];
+    assert_eq!(vm.call_function(1, durations), Value::Duration(12));
+    assert_eq!(vm.call_function(1, numbers), Value::Number(3.0));
+    let quickening = vm.quickening().unwrap();
+    assert_eq!(quickening.quickened(), 1);
+    assert_eq!(quickening.deoptimized(), 1);
+    assert_eq!(quickening.specialized(), 0);
+}
@@ -0,0 +1,58 @@
+use std::time::{Duration, Instant};
+
+use zircon::{assemble, Executor, VirtualMachine, VmOptions};
+
+// Calls a function that counts down from its argument, over and over.
+const PROGRAM: &str = r#"
+.func main
+top:
+    push_const 1000
+    call count
+    set_local 0
+    jump top
+.end
+
+.func count 1
+loop:
+    dec_local 0
+    get_local 0
+    push_const 0
+    jump_if_greater loop
+    push_const 0
+    return
+.end
+"#;
+
+// Runs until the profile has enough samples, as the program never ends.
+#[derive(Debug)]
+struct UntilSampled(u64);
+
+impl Executor for UntilSampled {
+    fn execute(&self, vm: &mut VirtualMachine, _base_depth: usize) {
+        let start = Instant::now();
+        while vm.sampling_profile().unwrap().samples() < self.0
+            && start.elapsed() < Duration::from_secs(30)
+        {
+            vm.step();
+        }
+    }
+}
+
+#[test]
+fn samples_are_folded_by_stack() {
+    let bytecode = assemble(PROGRAM).unwrap();
+    let options = VmOptions::new().backend(UntilSampled(20));
+    let mut vm = VirtualMachine::with_options(&bytecode, options);
+    vm.enable_sampling_profile(Duration::from_micros(200));
+    vm.run();
+
+    let profile = vm.sampling_profile().unwrap();
+    assert!(profile.samples() >= 20);
+    for stack in profile.stacks().keys() {
+        assert!(["function_0", "function_0;function_1"].contains(&stack.as_str()));
+    }
+    for line in profile.to_string().lines() {
+        let (stack, count) = line.rsplit_once(' ').unwrap();
+        assert_eq!(profile.stacks()[stack], count.parse::<u64>().unwrap());
+    }
+}
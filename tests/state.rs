@@ -0,0 +1,51 @@
+use std::panic::{self, AssertUnwindSafe};
+
+use zircon::{assemble, VirtualMachine};
+
+const PROGRAM: &str = r#"
+.func main
+    push_const "x"
+    set_local 0
+    push_const 1
+    push_const 2
+    call add
+    halt
+.end
+
+.func add 2
+    get_local 0
+    get_local 1
+    add
+    return
+.end
+"#;
+
+// A program stopped by its step limit inside a call shows both frames, the
+// innermost first, and no fuel left.
+#[test]
+fn state_shows_the_stopped_frames() {
+    let bytecode = assemble(PROGRAM).unwrap();
+    let mut vm = VirtualMachine::new(&bytecode);
+    vm.set_step_limit(7);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| vm.run()));
+    assert!(result.is_err());
+
+    let state = vm.state();
+    assert_eq!(state.steps, 8);
+    assert_eq!(state.fuel, Some(0));
+    let frames: Vec<_> = state
+        .frames
+        .iter()
+        .map(|frame| (frame.function, frame.instruction, frame.stack_depth))
+        .collect();
+    assert_eq!(frames, [(1, Some(2), 2), (0, Some(4), 0)]);
+    let callee = &state.frames[0];
+    assert_eq!(callee.stack_top[0].text, "2");
+    assert_eq!(callee.locals[1].as_ref().unwrap().kind, "number");
+    assert_eq!(state.frames[1].locals[0].as_ref().unwrap().text, "x");
+
+    let json = state.to_json();
+    assert!(json.starts_with(r#"{"running":"#));
+    assert!(json.contains(r#""steps":8,"fuel":0,"#));
+    assert!(json.contains(r#""stack_top":[{"type":"number","value":"2"},"#));
+}
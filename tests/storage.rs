@@ -0,0 +1,52 @@
+use std::cell::RefCell;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+
+use zircon::{compile, MemoryStorage, RuntimeError, Storage, Value, VirtualMachine, VmOptions};
+
+// A guest program reads and writes the host's store, and the host sees the
+// changes once the program has finished.
+#[test]
+fn programs_share_the_host_store() {
+    let bytecode = compile(
+        r#"
+        kv_set("count", kv_get("count") + 1);
+        kv_set("missing", kv_get("missing"));
+        kv_set("scratch", true);
+        kv_delete("scratch");
+        kv_set("keys", kv_keys());
+        "#,
+    )
+    .unwrap();
+    let storage = Rc::new(RefCell::new(MemoryStorage::new()));
+    storage.borrow_mut().set("count", Value::Number(41.0));
+
+    let mut vm = VirtualMachine::new(&bytecode);
+    vm.set_storage(storage.clone());
+    vm.run();
+
+    let mut storage = storage.borrow_mut();
+    assert_eq!(storage.get("count"), Some(Value::Number(42.0)));
+    assert_eq!(storage.get("missing"), Some(Value::Nil));
+    assert_eq!(storage.get("scratch"), None);
+    assert_eq!(storage.keys(), ["count", "keys", "missing"]);
+    let keys = ["count", "missing"].map(|key| Value::Str(key.into()));
+    assert_eq!(
+        storage.get("keys"),
+        Some(Value::Array(Rc::new(keys.to_vec())))
+    );
+}
+
+// The store outlives a run, so a deterministic VM refuses to read or change
+// it.
+#[test]
+fn deterministic_programs_cannot_use_the_store() {
+    for source in ["kv_get(\"count\");", "kv_set(\"count\", 1);", "kv_keys();"] {
+        let bytecode = compile(source).unwrap();
+        let mut vm = VirtualMachine::with_options(&bytecode, VmOptions::deterministic());
+        vm.set_storage(Rc::new(RefCell::new(MemoryStorage::new())));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| vm.run()));
+        let error = result.unwrap_err().downcast::<RuntimeError>().unwrap();
+        assert!(error.0.ends_with("is not available in deterministic mode."));
+    }
+}
@@ -0,0 +1,95 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use zircon::{Bytecode, VirtualMachine};
+
+// Runs every program in tests/programs with a step trace, which records each
+// instruction executed and the stack depth before it, and compares the trace
+// with the snapshot of the same name in tests/traces. A change to how the VM
+// dispatches instructions should leave every snapshot as it is. Running with
+// ZIRCON_BLESS=1 writes the snapshots instead.
+#[test]
+fn traces() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let snapshots = root.join("traces");
+    let bless = env::var_os("ZIRCON_BLESS").is_some();
+    if bless {
+        fs::create_dir_all(&snapshots).unwrap();
+    }
+
+    let mut cases: Vec<PathBuf> = fs::read_dir(root.join("programs"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| build(path).is_some())
+        .collect();
+    cases.sort();
+
+    let mut failures = Vec::new();
+    for source in &cases {
+        let name = source.file_stem().unwrap().to_string_lossy();
+        let snapshot = snapshots.join(format!("{}.trace", name));
+        let bytecode = match build(source).unwrap() {
+            Ok(bytecode) => bytecode,
+            Err(e) => {
+                failures.push(format!("{}: {}", name, e));
+                continue;
+            }
+        };
+        let mut vm = VirtualMachine::new(&bytecode);
+        vm.enable_step_trace();
+        vm.run();
+        let actual = vm.step_trace().unwrap();
+        if bless {
+            fs::write(&snapshot, actual).unwrap();
+            continue;
+        }
+        match fs::read_to_string(&snapshot) {
+            Ok(expected) if expected == actual => {}
+            Ok(expected) => {
+                let (line, expected, actual) = first_difference(&expected, actual);
+                failures.push(format!(
+                    "{}: step {} differs\n- {}\n+ {}",
+                    name, line, expected, actual
+                ));
+            }
+            Err(e) => failures.push(format!("{}: cannot read snapshot: {}", name, e)),
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} of {} traces differ\n\n{}",
+        failures.len(),
+        cases.len(),
+        failures.join("\n\n")
+    );
+}
+
+// Assembles or compiles a program, or returns None for a file that is not one.
+fn build(path: &Path) -> Option<Result<Bytecode, String>> {
+    if path.is_dir() {
+        return Some(zircon::assemble_dir(path).map_err(|e| e.to_string()));
+    }
+    match path.extension()?.to_str()? {
+        "zasm" => Some(zircon::assemble_file(path).map_err(|e| e.to_string())),
+        "zr" => Some(
+            fs::read_to_string(path)
+                .map_err(|e| e.to_string())
+                .and_then(|source| zircon::compile(&source).map_err(|e| e.to_string())),
+        ),
+        _ => None,
+    }
+}
+
+// The 1-based number of the first line that differs, and both versions of it.
+fn first_difference<'t>(expected: &'t str, actual: &'t str) -> (usize, &'t str, &'t str) {
+    let mut expected = expected.lines();
+    let mut actual = actual.lines();
+    let mut line = 1;
+    loop {
+        match (expected.next(), actual.next()) {
+            (Some(e), Some(a)) if e == a => line += 1,
+            (e, a) => return (line, e.unwrap_or("(end)"), a.unwrap_or("(end)")),
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use zircon::{assemble, Bytecode, Value, VirtualMachine};
+
+fn program(result: f64) -> Bytecode {
+    assemble(&format!(
+        ".func main\n    halt\n.end\n\n.func answer\n    push_const {}\n    return\n.end\n",
+        result
+    ))
+    .unwrap()
+}
+
+fn answer(bytecode: &Bytecode) -> Value {
+    VirtualMachine::new(bytecode).call_function(1, Vec::new())
+}
+
+// A bytecode file in a directory of its own, with the bytes written to it.
+fn bytecode_file(name: &str, bytecode: &Bytecode) -> (PathBuf, Vec<u8>) {
+    let directory = std::env::temp_dir().join(format!("zircon-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    let path = directory.join("program.zrc");
+    let mut bytes = Vec::new();
+    bytecode.write(&mut bytes).unwrap();
+    fs::write(&path, &bytes).unwrap();
+    (path, bytes)
+}
+
+fn write_cache(path: &Path, bytecode: &Bytecode, source: &[u8]) {
+    let mut cache = Vec::new();
+    bytecode.write_verified(&mut cache, source).unwrap();
+    fs::write(path.with_extension("zrcv"), cache).unwrap();
+}
+
+// The cache is loaded in place of the bytecode file it was written for, so a
+// cache holding another program shows which one `from_file` read.
+#[test]
+fn loads_the_cache_for_an_unchanged_file() {
+    let (path, source) = bytecode_file("fresh-cache", &program(1.0));
+    write_cache(&path, &program(2.0), &source);
+    assert_eq!(
+        answer(&Bytecode::from_file(&path).unwrap()),
+        Value::Number(2.0)
+    );
+    fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn ignores_a_stale_or_damaged_cache() {
+    let (path, source) = bytecode_file("stale-cache", &program(1.0));
+    write_cache(&path, &program(2.0), &source);
+    let (path, _) = bytecode_file("stale-cache", &program(3.0));
+    assert_eq!(
+        answer(&Bytecode::from_file(&path).unwrap()),
+        Value::Number(3.0)
+    );
+
+    fs::write(path.with_extension("zrcv"), b"ZRCV").unwrap();
+    assert_eq!(
+        answer(&Bytecode::from_file(&path).unwrap()),
+        Value::Number(3.0)
+    );
+    fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+// Changing any byte after the digest, such as a stored depth or an
+// instruction, makes the cache stale even though the bytecode file is the
+// same.
+#[test]
+fn ignores_a_cache_whose_body_changed() {
+    let (path, source) = bytecode_file("changed-cache", &program(1.0));
+    let mut cache = Vec::new();
+    program(2.0).write_verified(&mut cache, &source).unwrap();
+    // The magic and digest come first, then the function count and the depth
+    // of function 0.
+    for offset in [16, cache.len() - 1] {
+        let mut changed = cache.clone();
+        changed[offset] ^= 0xFF;
+        fs::write(path.with_extension("zrcv"), changed).unwrap();
+        assert_eq!(
+            answer(&Bytecode::from_file(&path).unwrap()),
+            Value::Number(1.0)
+        );
+    }
+    fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn refuses_to_cache_encrypted_programs() {
+    let mut cache = Vec::new();
+    let result = program(1.0).write_verified(&mut cache, b"ZRCE");
+    assert!(result.is_err());
+    assert!(cache.is_empty());
+}